@@ -0,0 +1,9 @@
+#![no_main]
+
+use game::drawing::sff::{data::BufferReader, lz5::decode_lz5};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let data = data.to_vec();
+    let _ = decode_lz5(&mut BufferReader::new(&data));
+});
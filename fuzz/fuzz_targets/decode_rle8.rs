@@ -0,0 +1,9 @@
+#![no_main]
+
+use game::drawing::sff::{data::BufferReader, rle5::decode_rle8};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let data = data.to_vec();
+    let _ = decode_rle8(&mut BufferReader::new(&data));
+});
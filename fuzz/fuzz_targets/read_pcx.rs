@@ -0,0 +1,9 @@
+#![no_main]
+
+use game::drawing::sff::{data::BufferReader, pcx::read_pcx};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let data = data.to_vec();
+    let _ = read_pcx(&mut BufferReader::new(&data));
+});
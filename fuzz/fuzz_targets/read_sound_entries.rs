@@ -0,0 +1,10 @@
+#![no_main]
+
+use game::audio::snd_parser::read_sound_entries;
+use game::drawing::sff::data::BufferReader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let data = data.to_vec();
+    let _ = read_sound_entries(&mut BufferReader::new(&data));
+});
@@ -0,0 +1,171 @@
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Error, Expr, Fields, GenericArgument, Lit, Meta, NestedMeta, PathArguments, Result, Type};
+
+#[proc_macro_derive(FromTextSection, attributes(text_section))]
+pub fn derive_from_text_section(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand_from_text_section(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+#[derive(Default)]
+struct Options {
+    key: Option<String>,
+    prefix: Option<String>,
+    default: Option<Expr>,
+    keys: Vec<String>,
+    required: bool,
+    skip: bool,
+}
+
+fn parse_options(attributes: &[Attribute]) -> Result<Options> {
+    let mut options = Options::default();
+
+    for attribute in attributes.iter().filter(|attribute| attribute.path.is_ident("text_section")) {
+        let list = match attribute.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(Error::new_spanned(meta, "expected #[text_section(...)]")),
+        };
+
+        for nested in list.nested.iter() {
+            match nested {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("required") => options.required = true,
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => options.skip = true,
+                NestedMeta::Meta(Meta::NameValue(name_value)) => {
+                    let value = match &name_value.lit {
+                        Lit::Str(value) => value,
+                        lit => return Err(Error::new_spanned(lit, "expected a string literal")),
+                    };
+
+                    if name_value.path.is_ident("key") {
+                        options.key = Some(value.value());
+                    } else if name_value.path.is_ident("prefix") {
+                        options.prefix = Some(value.value());
+                    } else if name_value.path.is_ident("default") {
+                        options.default = Some(value.parse()?);
+                    } else if name_value.path.is_ident("keys") {
+                        options.keys.extend(value.value().split(',').map(|key| key.trim().to_string()).filter(|key| !key.is_empty()));
+                    } else {
+                        return Err(Error::new_spanned(&name_value.path, "unknown text_section option"));
+                    }
+                }
+                nested => return Err(Error::new_spanned(nested, "unknown text_section option")),
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+fn is_option(ty: &Type) -> bool {
+    if let Type::Path(type_path) = ty {
+        if let Some(segment) = type_path.path.segments.last() {
+            if segment.ident == "Option" {
+                if let PathArguments::AngleBracketed(arguments) = &segment.arguments {
+                    return matches!(arguments.args.first(), Some(GenericArgument::Type(_)));
+                }
+            }
+        }
+    }
+
+    false
+}
+
+fn expand_from_text_section(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let struct_options = parse_options(&input.attrs)?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new_spanned(name, "FromTextSection requires named fields")),
+        },
+        _ => return Err(Error::new_spanned(name, "FromTextSection can only be derived for structs")),
+    };
+
+    let mut initializers = Vec::new();
+    let mut expected_keys = Vec::new();
+
+    for field in fields.iter() {
+        let ident = field.ident.as_ref().expect("named field");
+        let ty = &field.ty;
+        let options = parse_options(&field.attrs)?;
+
+        if options.required && options.default.is_some() {
+            return Err(Error::new_spanned(ident, "a field can not be both required and have a default"));
+        }
+
+        if options.skip {
+            let value = match &options.default {
+                Some(default) => quote! { #default },
+                None => quote! { ::std::default::Default::default() },
+            };
+
+            initializers.push(quote! { #ident: #value });
+            continue;
+        }
+
+        if let Some(prefix) = &options.prefix {
+            initializers.push(quote! {
+                #ident: <#ty as crate::io::from_text_section::FromTextSection>::from_text_section(
+                    section,
+                    &crate::io::from_text_section::join_key(prefix, #prefix)
+                )?
+            });
+            expected_keys.push(quote! {
+                keys.extend(<#ty as crate::io::from_text_section::FromTextSection>::expected_keys(
+                    &crate::io::from_text_section::join_key(prefix, #prefix)
+                ));
+            });
+            continue;
+        }
+
+        let key = options.key.clone().unwrap_or_else(|| ident.to_string());
+        let full_key = quote! { &crate::io::from_text_section::join_key(prefix, #key) };
+
+        let value = if options.required {
+            quote! { section.get_attribute_or_fail(#full_key)? }
+        } else if let Some(default) = &options.default {
+            quote! { section.get_attribute_or(#full_key, #default) }
+        } else if is_option(ty) {
+            quote! { section.get_attribute(#full_key) }
+        } else {
+            quote! { section.get_attribute_or_default(#full_key) }
+        };
+
+        initializers.push(quote! { #ident: #value });
+        expected_keys.push(quote! {
+            keys.push(crate::io::from_text_section::join_key(prefix, #key));
+        });
+    }
+
+    let extra_keys = struct_options.keys.iter();
+
+    Ok(quote! {
+        impl #impl_generics crate::io::from_text_section::FromTextSection for #name #type_generics #where_clause {
+            fn from_text_section(
+                section: &crate::io::text_section::TextSection,
+                prefix: &str,
+            ) -> ::std::result::Result<Self, crate::core::error::DataError> {
+                Ok(#name {
+                    #(#initializers,)*
+                })
+            }
+
+            fn expected_keys(prefix: &str) -> ::std::vec::Vec<::std::string::String> {
+                let mut keys = ::std::vec::Vec::new();
+                #(#expected_keys)*
+                #(keys.push(crate::io::from_text_section::join_key(prefix, #extra_keys));)*
+                keys
+            }
+        }
+    })
+}
@@ -5,16 +5,33 @@ uniform int use_palette = 0;
 uniform int blend_type = 0;
 uniform float blend_source = 1;
 uniform float blend_destination = 1;
+uniform vec3 palfx_add = vec3(0.0);
+uniform vec3 palfx_mul = vec3(1.0);
+uniform float palfx_color = 1.0;
+uniform int palfx_invertall = 0;
 
 void fragment() {
-    float r = texture(TEXTURE, UV).r;
-    ivec2 pallete_size = textureSize(palette, 0);
     vec4 source_color = texture(TEXTURE, UV);
 
     if (use_palette > 0) {
-        source_color = texture(palette, vec2(r * 255.0 / float(pallete_size.x), 0), 0);
+        int index = int(source_color.r * 255.0 + 0.5);
+        source_color = texelFetch(palette, ivec2(index, 0), 0);
     }
 
+    // Vertex color carries text colors and canvas item modulation
+    source_color *= COLOR;
+
+    // Same steps as PalFxState::apply_color
+    vec3 palfx_rgb = source_color.rgb;
+
+    if (palfx_invertall > 0) {
+        palfx_rgb = vec3(1.0) - palfx_rgb;
+    }
+
+    float gray = (palfx_rgb.r + palfx_rgb.g + palfx_rgb.b) / 3.0;
+    palfx_rgb = vec3(gray) + (palfx_rgb - vec3(gray)) * palfx_color;
+    source_color.rgb = clamp((palfx_rgb + palfx_add) * palfx_mul, 0.0, 1.0);
+
     COLOR = source_color;
 
     if (blend_type == 1 || blend_type == 2) {
@@ -1,9 +1,12 @@
 use std::fmt::Display;
+use std::sync::Arc;
 
-use gdnative::core_types::Vector2;
-use crate::core::{blending::Blending, enumerations::SpriteEffects, error::DataError, sprite_id::SpriteId};
+use gdnative::core_types::{Rect2, Vector2};
+use crate::core::{blending::Blending, enumerations::{AnimationInterpolation, Facing, SpriteEffects}, error::DataError, sprite_id::SpriteId};
 
-#[derive(Copy, Clone, PartialEq)]
+use super::clsn::Clsn;
+
+#[derive(Clone, PartialEq)]
 pub struct AnimationElement {
     pub id: usize,
     pub gameticks: i32,
@@ -12,6 +15,20 @@ pub struct AnimationElement {
     pub flip: SpriteEffects,
     pub blending: Blending,
     pub start_tick: i32,
+    pub clsn1: Arc<Vec<Clsn>>,
+    pub clsn2: Arc<Vec<Clsn>>,
+    pub scale: Vector2,
+    pub angle: f32,
+    // Values that move towards the next element while this one is shown
+    pub interpolation: AnimationInterpolation,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct InterpolatedElement {
+    pub offset: Vector2,
+    pub scale: Vector2,
+    pub angle: f32,
+    pub blending: Blending,
 }
 
 impl AnimationElement {
@@ -23,6 +40,10 @@ impl AnimationElement {
         flip: SpriteEffects,
         blending: Blending,
         start_tick: i32,
+        clsn1: Arc<Vec<Clsn>>,
+        clsn2: Arc<Vec<Clsn>>,
+        scale: Vector2,
+        angle: f32,
     ) -> Self {
         AnimationElement {
             id: id,
@@ -32,7 +53,47 @@ impl AnimationElement {
             flip: flip,
             blending: blending,
             start_tick: start_tick,
+            clsn1: clsn1,
+            clsn2: clsn2,
+            scale: scale,
+            angle: angle,
+            interpolation: AnimationInterpolation::None,
+        }
+    }
+
+    pub fn interpolate(&self, next: &AnimationElement, amount: f32) -> InterpolatedElement {
+        let mut result = InterpolatedElement {
+            offset: self.offset,
+            scale: self.scale,
+            angle: self.angle,
+            blending: self.blending,
+        };
+
+        if self.interpolation.contains(AnimationInterpolation::Offset) {
+            result.offset = self.offset.lerp(next.offset, amount);
+        }
+
+        if self.interpolation.contains(AnimationInterpolation::Scale) {
+            result.scale = self.scale.lerp(next.scale, amount);
+        }
+
+        if self.interpolation.contains(AnimationInterpolation::Angle) {
+            result.angle = self.angle + (next.angle - self.angle) * amount;
+        }
+
+        if self.interpolation.contains(AnimationInterpolation::Blend) {
+            result.blending = self.blending.lerp(&next.blending, amount);
         }
+
+        result
+    }
+
+    pub fn get_attack_rects(&self, position: Vector2, facing: Facing, scale: Vector2) -> Vec<Rect2> {
+        Clsn::get_rects(&self.clsn1, position, facing, scale)
+    }
+
+    pub fn get_hurt_rects(&self, position: Vector2, facing: Facing, scale: Vector2) -> Vec<Rect2> {
+        Clsn::get_rects(&self.clsn2, position, facing, scale)
     }
 }
 
@@ -86,10 +147,50 @@ impl Animation {
         let next_element_number = elementnumber + 1;
 
         if next_element_number < self.elements.len() {
-            return Some(self.elements[next_element_number]);
+            return Some(self.elements[next_element_number].clone());
+        }
+
+        Some(self.elements[self.loopstart].clone())
+    }
+
+    // End of the first pass. With a -1 frame the animation ends where that frame starts
+    pub fn get_end_time(&self) -> i32 {
+        if self.totaltime != -1 {
+            return self.totaltime;
+        }
+
+        self.elements.iter()
+            .find(|element| element.gameticks == -1)
+            .map_or(0, |element| element.start_tick)
+    }
+
+    // Maps a time since the start of the action into the current pass, following loopstart
+    pub fn get_looped_time(&self, time: i32) -> i32 {
+        if self.totaltime == -1 || time < self.totaltime {
+            return time;
+        }
+
+        let loopstarttime = self.elements[self.loopstart].start_tick;
+        let looptime = self.totaltime - loopstarttime;
+
+        if looptime <= 0 {
+            return loopstarttime;
+        }
+
+        loopstarttime + (time - self.totaltime) % looptime
+    }
+
+    pub fn get_interpolated_element(&self, element: &AnimationElement, elapsedticks: i32) -> InterpolatedElement {
+        if element.gameticks <= 0 {
+            return element.interpolate(element, 0.0);
         }
 
-        Some(self.elements[self.loopstart])
+        let amount = elapsedticks.clamp(0, element.gameticks) as f32 / element.gameticks as f32;
+
+        match self.get_next_element(element.id) {
+            Some(next) => element.interpolate(&next, amount),
+            None => element.interpolate(element, 0.0),
+        }
     }
 
     pub fn get_element_from_time(&self, time: i32) -> Result<AnimationElement, DataError> {
@@ -97,8 +198,9 @@ impl Animation {
             return Err(DataError::new(format!("Invalid animation time: {}", time)));
         }
 
-        let mut element_option = Some(self.elements[0]);
+        let mut element_option = Some(self.elements[0].clone());
         let mut current_time = time;
+        let mut steps = 0;
 
         while let Some(element) = element_option {
             if element.gameticks == -1 {
@@ -106,8 +208,10 @@ impl Animation {
             }
 
             current_time -= element.gameticks;
+            steps += 1;
 
-            if current_time < 0 {
+            // A loop of 0 tick elements never uses up the time, one pass over the elements is enough
+            if current_time < 0 || steps >= self.elements.len() {
                 return Ok(element);
             }
 
@@ -1,16 +1,18 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use gdnative::core_types::{Point2, Rect2, Size2, Vector2};
-use gdnative::{godot_error, godot_warn};
 
-use crate::core::attribute_value::AttributeValue;
+use crate::core::attribute_value::{AttributeValue, ParseAttributeValue};
 use crate::core::blending::Blending;
-use crate::core::enumerations::SpriteEffects;
+use crate::core::diagnostics;
+use crate::core::enumerations::{AnimationInterpolation, SpriteEffects};
 use crate::core::error::DataError;
 use crate::core::sprite_id::SpriteId;
 use crate::core::{enumerations::ClsnType};
 use crate::core::regex::{RegEx, RegExFlags};
 use crate::io::file_system;
+use crate::io::text_file::TextFile;
 use crate::io::text_section::TextSection;
 
 use super::{animation::{Animation, AnimationElement}, clsn::Clsn};
@@ -20,6 +22,7 @@ pub struct AnimationLoader {
     clsnregex: RegEx,
     clsnlineregex: RegEx,
     elementregex: RegEx,
+    interpolationregex: RegEx,
 }
 
 impl AnimationLoader {
@@ -28,17 +31,24 @@ impl AnimationLoader {
         let clsnregex = RegEx::new(r"clsn([12])(default)?:\s*(\d+)", RegExFlags::IgnoreCase);
         let clsnlineregex = RegEx::new(r"clsn([12])?\[(-?\d+)\]\s*=\s*(-?\d+)\s*,\s*(-?\d+)\s*,\s*(-?\d+)\s*,\s*(-?\d+)", RegExFlags::IgnoreCase);
         let elementregex = RegEx::new(r"\s*,\s*", RegExFlags::IgnoreCase);
+        let interpolationregex = RegEx::new(r"^\s*interpolate\s+(offset|blend|scale|angle)\s*$", RegExFlags::IgnoreCase);
 
         AnimationLoader {
             animationtitleregex: animationtitleregex,
             clsnregex: clsnregex,
             clsnlineregex: clsnlineregex,
-            elementregex: elementregex
+            elementregex: elementregex,
+            interpolationregex: interpolationregex
         }
     }
 
     pub fn load_animations(&self, path: &str) -> Result<HashMap<i32, Animation>, DataError> {
         let text_file = file_system::open_text_file(path)?;
+
+        Ok(self.read_animations(&text_file))
+    }
+
+    pub fn read_animations(&self, text_file: &TextFile) -> HashMap<i32, Animation> {
         let mut animations = HashMap::new();
 
         for section in text_file.sections.iter() {
@@ -49,18 +59,21 @@ impl AnimationLoader {
                     if !animations.contains_key(&animation.number) {
                         animations.insert(animation.number, animation);
                     } else {
-                        godot_warn!("Invalid duplicated animation: {}", section.title)
+                        diagnostics::warn(section.locate_error(
+                            DataError::invalid_attribute(format!("Invalid duplicated animation: {}", section.title)),
+                            section.line
+                        ));
                     }
                 },
                 Err(error) => {
                     if error.message != "No match" {
-                        godot_error!("{}", error.message);
+                        diagnostics::error(section.locate_error(error, section.line));
                     }
                 }
             }
         }
 
-        Ok(animations)
+        animations
     }
 
     fn create_animation(&self, section: &TextSection) -> Result<Animation, DataError> {
@@ -74,8 +87,8 @@ impl AnimationLoader {
 
         let mut loading_type1 = Vec::<Clsn>::new();
         let mut loading_type2 = Vec::<Clsn>::new();
-        let mut default_type1 = Vec::<Clsn>::new();
-        let mut default_type2 = Vec::<Clsn>::new();
+        let mut default_type1 = Arc::new(Vec::<Clsn>::new());
+        let mut default_type2 = Arc::new(Vec::<Clsn>::new());
 
         let mut loaddefault = false;
         let mut loadtype = ClsnType::None;
@@ -90,10 +103,10 @@ impl AnimationLoader {
                 if let Some(clsn) = clsn_option {
                     if loaddefault {
                         if loadtype == ClsnType::Type1Attack {
-                            default_type1.push(clsn);
+                            Arc::make_mut(&mut default_type1).push(clsn);
                         }
                         if loadtype == ClsnType::Type2Normal {
-                            default_type2.push(clsn);
+                            Arc::make_mut(&mut default_type2).push(clsn);
                         }
                     } else {
                         if loadtype == ClsnType::Type1Attack {
@@ -104,7 +117,10 @@ impl AnimationLoader {
                         }
                     }
                 } else {
-                    godot_warn!("Could not create Clsn from line: {}", line.to_string());
+                    diagnostics::warn(section.locate_error(
+                        DataError::invalid_attribute(format!("Could not create Clsn from line: {}", line.to_string())),
+                        line.line()
+                    ));
                 }
 
                 loadcount = loadcount - 1;
@@ -127,11 +143,12 @@ impl AnimationLoader {
                 let isdefault = clsn_match.get_string(2).to_lowercase() == "default";
 
                 if isdefault {
+                    // Elements already loaded keep sharing the previous defaults
                     if clsntype == ClsnType::Type1Attack {
-                        default_type1.clear();
+                        default_type1 = Arc::new(Vec::new());
                     }
                     if clsntype == ClsnType::Type2Normal {
-                        default_type2.clear();
+                        default_type2 = Arc::new(Vec::new());
                     }
                 }
 
@@ -146,36 +163,67 @@ impl AnimationLoader {
                 continue;
             }
 
+            if let Some(interpolation_match) = self.interpolationregex.search(&line_string) {
+                let interpolation = match interpolation_match.get_string(1).to_lowercase().as_str() {
+                    "offset" => AnimationInterpolation::Offset,
+                    "blend" => AnimationInterpolation::Blend,
+                    "scale" => AnimationInterpolation::Scale,
+                    _ => AnimationInterpolation::Angle,
+                };
+
+                // Interpolation runs from the element before the line to the one after it
+                if let Some(element) = elements.last_mut() {
+                    element.interpolation |= interpolation;
+                } else {
+                    diagnostics::warn(section.locate_error(
+                        DataError::invalid_attribute(format!("Interpolation before the first element: {}", line_string)),
+                        line.line()
+                    ));
+                }
+
+                continue;
+            }
+
             let element_result = self.create_element(
+                section,
                 line,
                 elements.len(),
                 starttick,
-                default_type1.clone(),
-                default_type2.clone(),
-                loading_type1.clone(),
-                loading_type2.clone()
+                &default_type1,
+                &default_type2,
+                &loading_type1,
+                &loading_type2
             );
 
             match element_result {
                 Ok(element) => {
-                    if element.gameticks == -1 {
+                    // Elements after a -1 frame are never reached
+                    if element.gameticks == -1 || starttick == -1 {
                         starttick = -1;
                     } else {
                         starttick += element.gameticks;
                     }
 
-                    elements.push(element.clone());
+                    elements.push(element);
                     loading_type1.clear();
                     loading_type2.clear();
                 },
                 Err(error) => {
-                    godot_error!("Invalid animation element. Anim No: {}, Line: {}, Detail: {}", animation_number, line.to_string(), error.message.to_string());
+                    diagnostics::error(section.locate_error(
+                        DataError::invalid_attribute(format!(
+                            "Invalid animation element. Anim No: {}, Line: {}, Detail: {}",
+                            animation_number,
+                            line.to_string(),
+                            error.message.to_string()
+                        )),
+                        line.line()
+                    ));
                 }
             }
         }
 
         if elements.len() == 0 {
-            return Err(DataError::new(format!("Invalid animation {}, no elements", animation_number)));
+            return Err(DataError::invalid_attribute(format!("Invalid animation {}, no elements", animation_number)));
         }
 
         if loopstart == elements.len() {
@@ -218,13 +266,14 @@ impl AnimationLoader {
 
     fn create_element(
         &self,
+        section: &TextSection,
         line: &AttributeValue,
         elementid: usize,
         starttick: i32,
-        default_type1: Vec<Clsn>,
-        default_type2: Vec<Clsn>,
-        loading_type1: Vec<Clsn>,
-        loading_type2: Vec<Clsn>
+        default_type1: &Arc<Vec<Clsn>>,
+        default_type2: &Arc<Vec<Clsn>>,
+        loading_type1: &[Clsn],
+        loading_type2: &[Clsn]
     ) -> Result<AnimationElement, DataError> {
         let line_string = line.to_string();
         let elements = self.elementregex.split(&line_string).ok_or_else(|| DataError::new("No match".to_string()))?;
@@ -256,12 +305,31 @@ impl AnimationLoader {
         let mut blending = Blending::default();
 
         if elements.len() >= 7 {
-            blending = Blending::from(elements[6]);
+            blending = Blending::parse_attribute_value(AttributeValue::new(elements[6]))
+                .unwrap_or_else(|error| {
+                    diagnostics::warn(section.locate_error(error, line.line()));
+                    Blending::default()
+                });
+        }
+
+        let mut scale = Vector2::new(1.0, 1.0);
+
+        if elements.len() >= 8 {
+            scale.x = elements[7].parse::<f32>().unwrap_or(1.0);
+        }
+
+        if elements.len() >= 9 {
+            scale.y = elements[8].parse::<f32>().unwrap_or(1.0);
+        }
+
+        let mut angle = 0.0;
+
+        if elements.len() >= 10 {
+            angle = elements[9].parse::<f32>().unwrap_or(0.0);
         }
 
-        let mut clsn = Vec::<Clsn>::new();
-        clsn.extend(if loading_type1.len() != 0 { loading_type1.clone() } else { default_type1.clone() });
-        clsn.extend(if loading_type2.len() != 0 { loading_type2.clone() } else { default_type2.clone() });
+        let clsn1 = if !loading_type1.is_empty() { Arc::new(loading_type1.to_vec()) } else { default_type1.clone() };
+        let clsn2 = if !loading_type2.is_empty() { Arc::new(loading_type2.to_vec()) } else { default_type2.clone() };
 
         let element = AnimationElement::new(
             elementid,
@@ -270,7 +338,11 @@ impl AnimationLoader {
             Vector2::new(offset_x as f32, offset_y as f32),
             flip,
             blending,
-            starttick
+            starttick,
+            clsn1,
+            clsn2,
+            scale,
+            angle
         );
 
         Ok(element)
@@ -282,3 +354,182 @@ impl Default for AnimationLoader {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::core::enumerations::{BlendType, Facing};
+
+    use super::*;
+
+    fn load(text: &str) -> Animation {
+        let text_file = TextFile::from_string("test.air".to_string(), text.to_string());
+
+        AnimationLoader::new().create_animation(&text_file.sections[0]).unwrap()
+    }
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> Rect2 {
+        Rect2::new(Point2::new(x, y), Size2::new(width, height))
+    }
+
+    fn rects(boxes: &[Clsn]) -> Vec<Rect2> {
+        boxes.iter().map(|clsn| clsn.rect).collect()
+    }
+
+    #[test]
+    fn clsn2_default_carries_over_to_every_element() {
+        let animation = load(
+            "[Begin Action 0]\n\
+             Clsn2Default: 2\n\
+             \x20Clsn2[0] = -10, 0, 10, -80\n\
+             \x20Clsn2[1] = -5, -80, 5, -95\n\
+             0,0, 0,0, 5\n\
+             0,1, 0,0, 5\n\
+             0,2, 0,0, 5\n"
+        );
+
+        assert_eq!(animation.elements.len(), 3);
+
+        for element in animation.elements.iter() {
+            assert!(element.clsn1.is_empty());
+            assert_eq!(rects(&element.clsn2), vec![rect(-10.0, -80.0, 20.0, 80.0), rect(-5.0, -95.0, 10.0, 15.0)]);
+            assert!(element.clsn2.iter().all(|clsn| clsn.clsn_type == ClsnType::Type2Normal));
+            assert!(Arc::ptr_eq(&element.clsn2, &animation.elements[0].clsn2));
+        }
+    }
+
+    #[test]
+    fn per_frame_boxes_override_only_their_element() {
+        let animation = load(
+            "[Begin Action 200]\n\
+             Clsn2Default: 1\n\
+             \x20Clsn2[0] = -10, 0, 10, -80\n\
+             200,0, 0,0, 3\n\
+             Clsn1: 1\n\
+             \x20Clsn1[0] = 15, -60, 50, -50\n\
+             Clsn2: 1\n\
+             \x20Clsn2[0] = -10, 0, 20, -75\n\
+             200,1, 0,0, 4\n\
+             200,2, 0,0, 3\n"
+        );
+
+        let elements = &animation.elements;
+
+        assert!(elements[0].clsn1.is_empty());
+        assert_eq!(rects(&elements[1].clsn1), vec![rect(15.0, -60.0, 35.0, 10.0)]);
+        assert!(elements[1].clsn1[0].clsn_type == ClsnType::Type1Attack);
+        assert!(elements[2].clsn1.is_empty());
+
+        assert_eq!(rects(&elements[1].clsn2), vec![rect(-10.0, -75.0, 30.0, 75.0)]);
+        assert_eq!(rects(&elements[2].clsn2), vec![rect(-10.0, -80.0, 20.0, 80.0)]);
+        assert!(Arc::ptr_eq(&elements[0].clsn2, &elements[2].clsn2));
+    }
+
+    #[test]
+    fn new_default_does_not_change_earlier_elements() {
+        let animation = load(
+            "[Begin Action 5]\n\
+             Clsn2Default: 1\n\
+             \x20Clsn2[0] = 0, 0, 10, 10\n\
+             5,0, 0,0, 2\n\
+             Clsn2Default: 1\n\
+             \x20Clsn2[0] = 0, 0, 20, 20\n\
+             5,1, 0,0, 2\n\
+             Clsn1Default: 1\n\
+             \x20Clsn1[0] = 0, 0, 5, 5\n\
+             5,2, 0,0, 2\n"
+        );
+
+        let elements = &animation.elements;
+
+        assert_eq!(rects(&elements[0].clsn2), vec![rect(0.0, 0.0, 10.0, 10.0)]);
+        assert_eq!(rects(&elements[1].clsn2), vec![rect(0.0, 0.0, 20.0, 20.0)]);
+        assert!(elements[1].clsn1.is_empty());
+        assert_eq!(rects(&elements[2].clsn1), vec![rect(0.0, 0.0, 5.0, 5.0)]);
+        assert!(Arc::ptr_eq(&elements[1].clsn2, &elements[2].clsn2));
+    }
+
+    #[test]
+    fn world_rects_follow_position_facing_and_scale() {
+        let clsn = Clsn::new(ClsnType::Type1Attack, rect(10.0, -60.0, 30.0, 20.0));
+        let position = Vector2::new(100.0, 200.0);
+
+        assert_eq!(clsn.get_rect(position, Facing::Right, Vector2::new(1.0, 1.0)), rect(110.0, 140.0, 30.0, 20.0));
+        assert_eq!(clsn.get_rect(position, Facing::Left, Vector2::new(1.0, 1.0)), rect(60.0, 140.0, 30.0, 20.0));
+        assert_eq!(clsn.get_rect(position, Facing::Right, Vector2::new(2.0, 0.5)), rect(120.0, 170.0, 60.0, 10.0));
+        assert_eq!(clsn.get_rect(position, Facing::Left, Vector2::new(2.0, 0.5)), rect(20.0, 170.0, 60.0, 10.0));
+    }
+
+    #[test]
+    fn air_1_1_columns_are_parsed() {
+        let animation = load(
+            "[Begin Action 10]\n\
+             10,0, 5,-3, 4, H, AS256D128, 1.5, 0.5, 45\n\
+             10,1, 0,0, 4, , , 2\n\
+             10,2, 0,0, 4\n"
+        );
+
+        let elements = &animation.elements;
+
+        assert!(elements[0].blending == Blending::new(BlendType::Add, 255, 128));
+        assert_eq!(elements[0].scale, Vector2::new(1.5, 0.5));
+        assert_eq!(elements[0].angle, 45.0);
+        assert!(elements[1].blending == Blending::default());
+        assert_eq!(elements[1].scale, Vector2::new(2.0, 1.0));
+        assert_eq!(elements[2].scale, Vector2::new(1.0, 1.0));
+        assert_eq!(elements[2].angle, 0.0);
+    }
+
+    #[test]
+    fn interpolation_lines_flag_the_previous_element() {
+        let animation = load(
+            "[Begin Action 20]\n\
+             Interpolate Angle\n\
+             20,0, 0,0, 4, , AS0D0, 1, 1, 0\n\
+             Interpolate Offset\n\
+             interpolate scale\n\
+             Interpolate Blend\n\
+             20,1, 8,-4, 4, , AS200D100, 3, 2, 90\n\
+             20,2, 0,0, 4\n"
+        );
+
+        let elements = &animation.elements;
+        let flags = AnimationInterpolation::Offset | AnimationInterpolation::Scale | AnimationInterpolation::Blend;
+
+        assert!(elements[0].interpolation == flags);
+        assert!(elements[1].interpolation == AnimationInterpolation::None);
+        assert!(elements[2].interpolation == AnimationInterpolation::None);
+
+        let start = animation.get_interpolated_element(&elements[0], 0);
+        assert_eq!(start.offset, Vector2::new(0.0, 0.0));
+        assert_eq!(start.scale, Vector2::new(1.0, 1.0));
+
+        let middle = animation.get_interpolated_element(&elements[0], 2);
+        assert_eq!(middle.offset, Vector2::new(4.0, -2.0));
+        assert_eq!(middle.scale, Vector2::new(2.0, 1.5));
+        assert_eq!(middle.angle, 0.0);
+        assert!(middle.blending == Blending::new(BlendType::Add, 100, 50));
+
+        let held = animation.get_interpolated_element(&elements[1], 2);
+        assert_eq!(held.offset, Vector2::new(8.0, -4.0));
+        assert_eq!(held.angle, 90.0);
+    }
+
+    #[test]
+    fn interpolation_wraps_to_the_loop_start() {
+        let animation = load(
+            "[Begin Action 30]\n\
+             30,0, 0,0, 2\n\
+             Loopstart\n\
+             30,1, 0,0, 4, , , 1, 1, 0\n\
+             30,2, 0,0, 4, , , 1, 1, 180\n\
+             Interpolate Angle\n"
+        );
+
+        let middle = animation.get_interpolated_element(&animation.elements[2], 2);
+        assert_eq!(middle.angle, 90.0);
+
+        let frozen = animation.get_interpolated_element(&animation.elements[0], 1);
+        assert_eq!(frozen.angle, 0.0);
+    }
+
+}
@@ -4,7 +4,7 @@ use gdnative::godot_error;
 
 use crate::core::error::DataError;
 
-use super::animation::{ Animation, AnimationElement };
+use super::animation::{ Animation, AnimationElement, InterpolatedElement };
 
 #[derive(Clone)]
 pub struct  AnimationManager {
@@ -14,6 +14,7 @@ pub struct  AnimationManager {
     pub currentelement: Option<AnimationElement>,
     pub finishedanimation: bool,
     pub animationtime: i32,
+    animationstarttime: i32,
     animations: HashMap<i32, Animation>,
     animationinloop: bool,
     elementswitchtime: i32,
@@ -28,6 +29,7 @@ impl AnimationManager {
             currentelement: None,
             finishedanimation: false,
             animationtime: 0,
+            animationstarttime: 0,
             animations: animations.clone(),
             animationinloop: false,
             elementswitchtime: 0,
@@ -80,9 +82,73 @@ impl AnimationManager {
         self.finishedanimation = false;
         self.animationinloop = false;
         self.animationtime = animation.get_element_start_time(element.id);
+        self.animationstarttime = self.animationtime;
         self.elementswitchtime = element.gameticks;
     }
 
+    pub fn get_interpolated_element(&self) -> Option<InterpolatedElement> {
+        let currentanimation = self.currentanimation.as_ref()?;
+        let currentelement = self.currentelement.as_ref()?;
+
+        Some(currentanimation.get_interpolated_element(
+            currentelement,
+            currentelement.gameticks - self.elementswitchtime
+        ))
+    }
+
+    // Trigger queries. Element numbers are 1-based as in MUGEN, and None stands for an invalid
+    // query. Times count the ticks since the action started, so the tick an animation is set
+    // reads as time 0 of its starting element.
+
+    pub fn anim_exist(&self, number: i32) -> bool {
+        self.has_animation(number)
+    }
+
+    // Negative until the end of the first pass, 0 when it ends, then keeps counting through loops
+    pub fn anim_time(&self) -> Option<i32> {
+        let currentanimation = self.currentanimation.as_ref()?;
+
+        Some(self.animationtime - currentanimation.get_end_time())
+    }
+
+    // Measured within the current pass, so elements restart their time every loop
+    pub fn anim_elem_time(&self, elementnumber: i32) -> Option<i32> {
+        let currentanimation = self.currentanimation.as_ref()?;
+
+        if elementnumber < 1 || elementnumber as usize > currentanimation.elements.len() {
+            return None;
+        }
+
+        let element = &currentanimation.elements[elementnumber as usize - 1];
+
+        // Elements after a -1 frame are never reached
+        if element.start_tick == -1 {
+            return None;
+        }
+
+        Some(currentanimation.get_looped_time(self.animationtime) - element.start_tick)
+    }
+
+    pub fn anim_elem(&self, elementnumber: i32) -> bool {
+        self.anim_elem_time(elementnumber) == Some(0)
+    }
+
+    // Element shown the given ticks away from now, which can't go back past the set tick
+    pub fn anim_elem_no(&self, time: i32) -> Option<i32> {
+        let currentanimation = self.currentanimation.as_ref()?;
+        let checktime = self.animationtime + time;
+
+        if checktime < self.animationstarttime {
+            return None;
+        }
+
+        let element = currentanimation
+            .get_element_from_time(currentanimation.get_looped_time(checktime))
+            .ok()?;
+
+        Some(element.id as i32 + 1)
+    }
+
     pub fn update(&mut self) -> Result<(), DataError> {
         self.finishedanimation = false;
         self.animationtime += 1;
@@ -120,3 +186,186 @@ impl AnimationManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{animations::animation_loader::AnimationLoader, io::text_file::TextFile};
+
+    use super::*;
+
+    const AIR: &str = "\
+; Three elements without loopstart, 12 ticks
+[Begin Action 0]
+0,0, 0,0, 3
+0,1, 0,0, 4
+0,2, 0,0, 5
+
+; Loops back to the second element, 9 ticks with a 7 tick loop
+[Begin Action 1]
+1,0, 0,0, 2
+Loopstart
+1,1, 0,0, 3
+1,2, 0,0, 4
+
+; Holds on a -1 frame, the last element is never shown
+[Begin Action 2]
+2,0, 0,0, 2
+2,1, 0,0, 3
+2,2, 0,0, -1
+2,3, 0,0, 5
+
+[Begin Action 3]
+3,0, 0,0, -1
+
+; The loop takes no time
+[Begin Action 4]
+4,0, 0,0, 5
+Loopstart
+4,1, 0,0, 0
+
+[Begin Action 5]
+5,0, 0,0, 0
+5,1, 0,0, 0
+";
+
+    // Named after the MUGEN triggers
+    #[allow(clippy::enum_variant_names)]
+    #[derive(Clone, Copy)]
+    enum Query {
+        AnimTime,
+        AnimElem(i32),
+        AnimElemTime(i32),
+        AnimElemNo(i32),
+    }
+
+    use Query::*;
+
+    // (action, starting element index, ticks since set, query, expected). AnimElem reads as 1 or 0.
+    const CONFORMANCE: &[(i32, usize, i32, Query, Option<i32>)] = &[
+        // The set tick is time 0 of the first element
+        (0, 0, 0, AnimTime, Some(-12)),
+        (0, 0, 0, AnimElem(1), Some(1)),
+        (0, 0, 0, AnimElemTime(1), Some(0)),
+        (0, 0, 0, AnimElemTime(2), Some(-3)),
+        (0, 0, 0, AnimElemNo(0), Some(1)),
+        (0, 0, 0, AnimElemNo(-1), None),
+        (0, 0, 0, AnimElemTime(0), None),
+        (0, 0, 0, AnimElemTime(4), None),
+        // Element switches land on the tick after the previous element's last one
+        (0, 0, 2, AnimElem(2), Some(0)),
+        (0, 0, 2, AnimElemNo(0), Some(1)),
+        (0, 0, 3, AnimElem(2), Some(1)),
+        (0, 0, 3, AnimElemTime(1), Some(3)),
+        (0, 0, 3, AnimElemNo(0), Some(2)),
+        (0, 0, 3, AnimElemNo(-1), Some(1)),
+        (0, 0, 3, AnimElemNo(8), Some(3)),
+        (0, 0, 3, AnimElemNo(9), Some(1)),
+        (0, 0, 11, AnimTime, Some(-1)),
+        (0, 0, 11, AnimElemTime(3), Some(4)),
+        // AnimTime is 0 on the tick the animation loops and keeps counting after it
+        (0, 0, 12, AnimTime, Some(0)),
+        (0, 0, 12, AnimElem(1), Some(1)),
+        (0, 0, 12, AnimElemNo(0), Some(1)),
+        (0, 0, 12, AnimElemTime(3), Some(-7)),
+        (0, 0, 15, AnimTime, Some(3)),
+        (0, 0, 15, AnimElem(2), Some(1)),
+        // Starting on a later element keeps the action's timeline, but not the ticks before the set
+        (0, 1, 0, AnimElemNo(0), Some(2)),
+        (0, 1, 0, AnimElem(2), Some(1)),
+        (0, 1, 0, AnimElemTime(1), Some(3)),
+        (0, 1, 0, AnimTime, Some(-9)),
+        (0, 1, 0, AnimElemNo(-1), None),
+        (0, 1, 1, AnimElemNo(-1), Some(2)),
+        // Loopstart
+        (1, 0, 8, AnimElemNo(0), Some(3)),
+        (1, 0, 8, AnimTime, Some(-1)),
+        (1, 0, 9, AnimTime, Some(0)),
+        (1, 0, 9, AnimElemNo(0), Some(2)),
+        (1, 0, 9, AnimElem(2), Some(1)),
+        (1, 0, 9, AnimElem(1), Some(0)),
+        (1, 0, 9, AnimElemTime(1), Some(2)),
+        (1, 0, 9, AnimElemNo(-9), Some(1)),
+        (1, 0, 9, AnimElemNo(-10), None),
+        (1, 0, 12, AnimElem(3), Some(1)),
+        (1, 0, 16, AnimElem(2), Some(1)),
+        (1, 0, 16, AnimTime, Some(7)),
+        (1, 0, 23, AnimElemNo(0), Some(2)),
+        // -1 frames end the animation where they start and never advance
+        (2, 0, 0, AnimTime, Some(-5)),
+        (2, 0, 5, AnimTime, Some(0)),
+        (2, 0, 5, AnimElem(3), Some(1)),
+        (2, 0, 50, AnimTime, Some(45)),
+        (2, 0, 50, AnimElemNo(0), Some(3)),
+        (2, 0, 50, AnimElemNo(100), Some(3)),
+        (2, 0, 50, AnimElemTime(3), Some(45)),
+        (2, 0, 50, AnimElemTime(4), None),
+        (3, 0, 0, AnimTime, Some(0)),
+        (3, 0, 0, AnimElem(1), Some(1)),
+        (3, 0, 5, AnimElemTime(1), Some(5)),
+        // Loops without ticks stop at the last element of a single pass
+        (4, 0, 0, AnimElemNo(0), Some(1)),
+        (4, 0, 5, AnimElemNo(0), Some(2)),
+        (4, 0, 20, AnimElemNo(0), Some(2)),
+        (4, 0, 20, AnimElemNo(-3), Some(2)),
+        (4, 0, 20, AnimTime, Some(15)),
+        (5, 0, 0, AnimElemNo(0), Some(2)),
+        (5, 0, 3, AnimElemNo(0), Some(2)),
+        (5, 0, 3, AnimTime, Some(3)),
+    ];
+
+    fn manager() -> AnimationManager {
+        let text_file = TextFile::from_string("test.air".to_string(), AIR.to_string());
+
+        AnimationManager::new("test.air", AnimationLoader::new().read_animations(&text_file))
+    }
+
+    fn evaluate(manager: &AnimationManager, query: Query) -> Option<i32> {
+        match query {
+            AnimTime => manager.anim_time(),
+            AnimElem(elementnumber) => Some(manager.anim_elem(elementnumber) as i32),
+            AnimElemTime(elementnumber) => manager.anim_elem_time(elementnumber),
+            AnimElemNo(time) => manager.anim_elem_no(time),
+        }
+    }
+
+    #[test]
+    fn timing_queries_match_conformance_table() {
+        for (row, &(action, element, ticks, query, expected)) in CONFORMANCE.iter().enumerate() {
+            let mut manager = manager();
+            manager.set_local_animation(action, element).unwrap();
+
+            for _ in 0..ticks {
+                manager.update().unwrap();
+            }
+
+            assert_eq!(evaluate(&manager, query), expected, "conformance row {}", row);
+        }
+    }
+
+    #[test]
+    fn anim_elem_no_follows_the_displayed_element() {
+        for action in 0..4 {
+            let mut manager = manager();
+            manager.set_local_animation(action, 0).unwrap();
+
+            for tick in 0..40 {
+                let currentelement = manager.currentelement.as_ref().unwrap();
+
+                assert_eq!(manager.anim_elem_no(0), Some(currentelement.id as i32 + 1), "action {}, tick {}", action, tick);
+
+                manager.update().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn queries_without_animation_are_invalid() {
+        let manager = manager();
+
+        assert!(manager.anim_exist(2));
+        assert!(!manager.anim_exist(99));
+        assert_eq!(manager.anim_time(), None);
+        assert_eq!(manager.anim_elem_time(1), None);
+        assert_eq!(manager.anim_elem_no(0), None);
+    }
+}
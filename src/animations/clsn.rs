@@ -1,4 +1,4 @@
-use gdnative::core_types::{Rect2, Vector2};
+use gdnative::core_types::{Point2, Rect2, Size2, Vector2};
 
 use crate::core::enumerations::{ClsnType, Facing};
 
@@ -15,4 +15,25 @@ impl Clsn {
             rect: rect,
         }
     }
+
+    pub fn get_rect(&self, position: Vector2, facing: Facing, scale: Vector2) -> Rect2 {
+        let width = self.rect.size.width * scale.x;
+        let height = self.rect.size.height * scale.y;
+        let top = position.y + self.rect.origin.y * scale.y;
+
+        // Boxes are authored facing right, so mirror them around the axis when facing left
+        let left = match facing {
+            Facing::Right => position.x + self.rect.origin.x * scale.x,
+            Facing::Left => position.x - self.rect.origin.x * scale.x - width,
+        };
+
+        Rect2::new(Point2::new(left, top), Size2::new(width, height))
+    }
+
+    pub fn get_rects(boxes: &[Clsn], position: Vector2, facing: Facing, scale: Vector2) -> Vec<Rect2> {
+        boxes
+            .iter()
+            .map(|clsn| clsn.get_rect(position, facing, scale))
+            .collect()
+    }
 }
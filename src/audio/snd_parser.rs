@@ -2,34 +2,59 @@ use crate::core::error::DataError;
 use crate::core::sound_id::SoundId;
 use crate::drawing::sff::data::{FileReader, DataReader, BufferReader};
 
-use super::structs::{FileHeader, SubHeader, WavHeader, WavSound};
-use gdnative::api::file::File;
+use super::structs::{FileHeader, SubHeader, WavHeader, WavSound, SoundData};
 use gdnative::api::audio_stream_sample::AudioStreamSample;
 use gdnative::prelude::*;
 
 pub fn read_sounds(path: &str) -> Result<Vec<WavSound>, DataError> {
-    let file = File::new();
-    let open_result = file.open(path, File::READ);
+    Ok(create_sounds(&read_sound_data(path)?))
+}
 
-    if let Err(detail) = open_result {
-        return Result::Err(DataError::new(format!(
-            "Error opening snd file: {}",
-            detail
-        )));
+// Creates the Godot streams, must run on the main thread
+pub fn create_sounds(sound_data: &[SoundData]) -> Vec<WavSound> {
+    let mut result = Vec::new();
+
+    for sound_data in sound_data.iter() {
+        let wav_header = &sound_data.header;
+        let stream = AudioStreamSample::new();
+        stream.set_data(to_signed(&sound_data.data));
+        stream.set_mix_rate(wav_header.sample_rate as i64);
+        stream.set_stereo(wav_header.num_channels == 2);
+        match wav_header.bits_per_sample {
+            8 => stream.set_format(AudioStreamSample::FORMAT_8_BITS),
+            16 => stream.set_format(AudioStreamSample::FORMAT_16_BITS),
+            _ => {
+                godot_warn!("invalid bits_per_sample: {}", wav_header.bits_per_sample);
+            }
+        };
+
+        result.push(WavSound {
+            soundid: sound_data.soundid,
+            stream: stream.into_shared()
+        });
     }
 
-    let mut reader = FileReader::new(&file);
-    let head = FileHeader::read(&mut reader);
+    result
+}
+
+pub fn read_sound_data(path: &str) -> Result<Vec<SoundData>, DataError> {
+    let mut reader = FileReader::open(path)?;
+
+    read_sound_entries(&mut reader).map_err(|error| error.with_path(path))
+}
+
+// Walks the subheader list, kept free of Godot calls so it can be fuzzed
+pub fn read_sound_entries(reader: &mut dyn DataReader) -> Result<Vec<SoundData>, DataError> {
+    let head = FileHeader::read(reader)?;
 
     if head.signature != "ElecbyteSnd" {
-        file.close();
-        return Result::Err(DataError::new(format!(
+        return Result::Err(DataError::decode_failure(format!(
             "Snd invalid signature: {}",
             head.signature
         )));
     }
 
-    file.seek(head.subheader_offset as i64);
+    reader.seek(head.subheader_offset as usize);
 
     let mut result = Vec::new();
 
@@ -38,49 +63,147 @@ pub fn read_sounds(path: &str) -> Result<Vec<WavSound>, DataError> {
             break;
         }
 
-        let subheader = SubHeader::read(&mut reader);
+        let subheader_offset = reader.pos();
+        let subheader = SubHeader::read(reader)?;
 
         if subheader.length == 0 {
             break;
         }
 
-        let tmp_arr = reader.get_buffer(subheader.length as usize);
+        let tmp_arr = reader.get_buffer(subheader.length as usize).map_err(|error| DataError::decode_failure(format!(
+            "Sound {},{} is truncated: {}",
+            subheader.groupno, subheader.soundno, error.message
+        )))?;
         let mut tmp_arr_reader = BufferReader::new(&tmp_arr);
-        let wav_header = WavHeader::read(&mut tmp_arr_reader);
-
-        let stream = AudioStreamSample::new();
-        stream.set_data(to_signed(&tmp_arr));
-        stream.set_mix_rate(wav_header.sample_rate as i64);
-        stream.set_stereo(wav_header.num_channels == 2);
-        match wav_header.bits_per_sample {
-            8 => stream.set_format(AudioStreamSample::FORMAT_8_BITS),
-            16 => stream.set_format(AudioStreamSample::FORMAT_16_BITS),
-            _ => {
-                godot_warn!("invalid bits_per_sample: {}", wav_header.bits_per_sample);
-            }
-        };
+        let wav_header = WavHeader::read(&mut tmp_arr_reader).map_err(|error| DataError::decode_failure(format!(
+            "Invalid wav header in sound {},{}: {}",
+            subheader.groupno, subheader.soundno, error.message
+        )))?;
 
-        result.push(WavSound {
+        result.push(SoundData {
             soundid: SoundId::new(subheader.groupno as i16, subheader.soundno as i16),
-            stream: stream.into_shared()
+            header: wav_header,
+            data: tmp_arr,
         });
 
-        if subheader.next > 0 && subheader.next < file.get_len() as u32 {
-            file.seek(subheader.next as i64);
+        // Subheaders only point forward, a link back would read the same sounds again
+        if subheader.next as usize > subheader_offset && (subheader.next as usize) < reader.size() {
+            reader.seek(subheader.next as usize);
         } else {
             break;
         }
     }
 
-    file.close();
-
     Result::Ok(result)
 }
 
-
 fn to_signed(source: &[u8]) -> ByteArray {
     source
         .iter()
         .map(|value| ((*value as i16) - 128) as u8)
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUBHEADER_OFFSET: usize = 512;
+
+    fn wav(samples: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        data.extend_from_slice(b"RIFF");
+        data.extend_from_slice(&(36 + samples.len() as u32).to_le_bytes());
+        data.extend_from_slice(b"WAVEfmt ");
+        data.extend_from_slice(&16u32.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&11025u32.to_le_bytes());
+        data.extend_from_slice(&11025u32.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&8u16.to_le_bytes());
+        data.extend_from_slice(b"data");
+        data.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+        data.extend_from_slice(samples);
+        data
+    }
+
+    // Sounds are chained in order, `last_next` overrides the link of the last subheader
+    fn snd(sounds: &[(u32, u32, Vec<u8>)], last_next: Option<u32>) -> Vec<u8> {
+        let mut data = b"ElecbyteSnd\0".to_vec();
+
+        data.extend_from_slice(&[0, 1, 0, 0]);
+        data.extend_from_slice(&(sounds.len() as u32).to_le_bytes());
+        data.extend_from_slice(&(SUBHEADER_OFFSET as u32).to_le_bytes());
+        data.resize(SUBHEADER_OFFSET, 0);
+
+        for (index, (groupno, soundno, wav)) in sounds.iter().enumerate() {
+            let next = if index + 1 < sounds.len() {
+                (data.len() + 16 + wav.len()) as u32
+            } else {
+                last_next.unwrap_or(0)
+            };
+
+            data.extend_from_slice(&next.to_le_bytes());
+            data.extend_from_slice(&(wav.len() as u32).to_le_bytes());
+            data.extend_from_slice(&groupno.to_le_bytes());
+            data.extend_from_slice(&soundno.to_le_bytes());
+            data.extend_from_slice(wav);
+        }
+
+        data
+    }
+
+    fn sounds() -> Vec<(u32, u32, Vec<u8>)> {
+        vec![(0, 0, wav(&[128, 130, 126])), (5, 2, wav(&[1, 2, 3, 4, 5]))]
+    }
+
+    fn read(data: &[u8]) -> Result<Vec<SoundData>, DataError> {
+        read_sound_entries(&mut BufferReader::new(&data.to_vec()))
+    }
+
+    #[test]
+    fn reads_every_sound() {
+        let sounds = read(&snd(&sounds(), None)).unwrap();
+
+        assert_eq!(sounds.len(), 2);
+        assert!(sounds[1].soundid == SoundId::new(5, 2));
+        assert_eq!(sounds[1].header.sample_rate, 11025);
+        assert_eq!(sounds[1].data.len(), 44 + 5);
+    }
+
+    #[test]
+    fn stops_at_links_back_to_earlier_subheaders() {
+        let first = read(&snd(&sounds(), Some(SUBHEADER_OFFSET as u32))).unwrap();
+        let last_offset = (SUBHEADER_OFFSET + 16 + sounds()[0].2.len()) as u32;
+        let itself = read(&snd(&sounds(), Some(last_offset))).unwrap();
+
+        assert_eq!(first.len(), 2);
+        assert_eq!(itself.len(), 2);
+    }
+
+    #[test]
+    fn rejects_truncated_sounds() {
+        let data = snd(&sounds(), None);
+
+        assert!(read(&data[..data.len() - 2]).is_err());
+        assert!(read(&data[..SUBHEADER_OFFSET + 30]).is_err());
+        assert!(read(&data[..100]).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_wav_headers() {
+        let data = snd(&[(0, 0, vec![0; 20])], None);
+
+        assert!(read(&data).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_signatures() {
+        let mut data = snd(&sounds(), None);
+        data[0] = b'X';
+
+        assert!(read(&data).is_err());
+    }
+}
@@ -2,23 +2,30 @@ use std::collections::HashMap;
 
 use crate::core::{sound_id::SoundId, error::DataError};
 
-use super::{structs::WavSound, snd_parser::read_sounds};
+use super::{structs::{SoundData, WavSound}, snd_parser::{create_sounds, read_sounds}};
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct SoundManager {
     sound_map: HashMap<SoundId, WavSound>,
 }
 
 impl SoundManager {
     pub fn load(path: &str) -> Result<Self, DataError> {
-        let sounds = read_sounds(path)?;
+        Ok(SoundManager::from_sounds(read_sounds(path)?))
+    }
+
+    pub fn from_sound_data(sound_data: &[SoundData]) -> Self {
+        SoundManager::from_sounds(create_sounds(sound_data))
+    }
+
+    fn from_sounds(sounds: Vec<WavSound>) -> Self {
         let mut sound_map = HashMap::new();
 
         for sound in sounds.iter() {
             sound_map.insert(sound.soundid, sound.clone());
         }
 
-        Ok(SoundManager { sound_map })
+        SoundManager { sound_map }
     }
 
     pub fn get_sound(&self, soundid: SoundId) -> Option<&WavSound> {
@@ -1,6 +1,6 @@
 use gdnative::{Ref, api::AudioStreamSample};
 
-use crate::{drawing::sff::data::DataReader, core::sound_id::SoundId};
+use crate::{drawing::sff::data::DataReader, core::{error::DataError, sound_id::SoundId}};
 
 #[derive(Clone)]
 pub struct WavSound {
@@ -8,6 +8,12 @@ pub struct WavSound {
     pub stream: Ref<AudioStreamSample>
 }
 
+pub struct SoundData {
+    pub soundid: SoundId,
+    pub header: WavHeader,
+    pub data: Vec<u8>,
+}
+
 pub struct FileHeader {
     pub signature: String, // 12
     pub verlo3: u8,
@@ -43,47 +49,47 @@ pub struct WavHeader {
 }
 
 impl FileHeader {
-    pub fn read(reader: &mut dyn DataReader) -> FileHeader {
-        FileHeader {
-            signature: reader.get_text(12),
-            verlo3: reader.get_u8(),
-            verlo2: reader.get_u8(),
-            verlo1: reader.get_u8(),
-            verhi: reader.get_u8(),
-            total_sounds: reader.get_u32(),
-            subheader_offset: reader.get_u32(),
-            unused: reader.get_buffer(488),
-        }
+    pub fn read(reader: &mut dyn DataReader) -> Result<FileHeader, DataError> {
+        Ok(FileHeader {
+            signature: reader.get_text(12)?,
+            verlo3: reader.get_u8()?,
+            verlo2: reader.get_u8()?,
+            verlo1: reader.get_u8()?,
+            verhi: reader.get_u8()?,
+            total_sounds: reader.get_u32()?,
+            subheader_offset: reader.get_u32()?,
+            unused: reader.get_buffer(488)?,
+        })
     }
 }
 
 impl SubHeader {
-    pub fn read(reader: &mut dyn DataReader) -> SubHeader {
-        SubHeader {
-            next: reader.get_u32(),
-            length: reader.get_u32(),
-            groupno: reader.get_u32(),
-            soundno: reader.get_u32(),
-        }
+    pub fn read(reader: &mut dyn DataReader) -> Result<SubHeader, DataError> {
+        Ok(SubHeader {
+            next: reader.get_u32()?,
+            length: reader.get_u32()?,
+            groupno: reader.get_u32()?,
+            soundno: reader.get_u32()?,
+        })
     }
 }
 
 impl WavHeader {
-    pub fn read(reader: &mut dyn DataReader) -> WavHeader {
-        WavHeader {
-            riff: reader.get_buffer(4),
-            chunk_size: reader.get_u32(),
-            format: reader.get_buffer(4),
-            subchunk1_id: reader.get_buffer(4),
-            subchunk1_size: reader.get_u32(),
-            audio_format: reader.get_u16(),
-            num_channels: reader.get_u16(),
-            sample_rate: reader.get_u32(),
-            byte_rate: reader.get_u32(),
-            block_align: reader.get_u16(),
-            bits_per_sample: reader.get_u16(),
-            subchunk2_id: reader.get_buffer(4),
-            subchunk2_size: reader.get_u32(),
-        }
+    pub fn read(reader: &mut dyn DataReader) -> Result<WavHeader, DataError> {
+        Ok(WavHeader {
+            riff: reader.get_buffer(4)?,
+            chunk_size: reader.get_u32()?,
+            format: reader.get_buffer(4)?,
+            subchunk1_id: reader.get_buffer(4)?,
+            subchunk1_size: reader.get_u32()?,
+            audio_format: reader.get_u16()?,
+            num_channels: reader.get_u16()?,
+            sample_rate: reader.get_u32()?,
+            byte_rate: reader.get_u32()?,
+            block_align: reader.get_u16()?,
+            bits_per_sample: reader.get_u16()?,
+            subchunk2_id: reader.get_buffer(4)?,
+            subchunk2_size: reader.get_u32()?,
+        })
     }
 }
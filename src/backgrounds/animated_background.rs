@@ -0,0 +1,73 @@
+use std::sync::{Arc, RwLock};
+
+use bevy_ecs::prelude::*;
+use bevy_transform::hierarchy::ChildBuilder;
+use gdnative::core_types::Transform2D;
+
+use crate::{animations::animation_manager::AnimationManager, core::{configuration::Configuration, constants::{BG_LAYER_BACK_Z_INDEX_MAX, BG_LAYER_FRONT_Z_INDEX_MAX}, diagnostics, enumerations::BackgroundLayer, error::DataError}, drawing::sprite_file::SpriteFile, elements::animated_image::{AnimatedSprite, AnimatedSpriteBundle}, io::text_section::TextSection};
+
+use super::base_background::BaseBackground;
+
+// Plays an action of the motif's AIR file, title logos are usually drawn this way
+#[derive(Clone)]
+pub struct AnimatedBackground {
+    pub base_background: BaseBackground,
+    pub actionno: i32,
+    animation_manager: AnimationManager,
+    sprite_file: Arc<RwLock<SpriteFile>>,
+}
+
+impl AnimatedBackground {
+    pub fn build(
+        configuration: &Configuration,
+        textsection: &TextSection,
+        sprite_file: &Arc<RwLock<SpriteFile>>,
+        animation_manager: &AnimationManager
+    ) -> Result<Self, DataError> {
+        let actionno: i32 = textsection.get_attribute_or("actionno", -1);
+
+        if !animation_manager.has_animation(actionno) {
+            return Err(DataError::invalid_attribute(format!("Action not found: {}", actionno))
+                .with_path(&textsection.filepath)
+                .with_section(&textsection.title));
+        }
+
+        Ok(AnimatedBackground {
+            base_background: BaseBackground::build(configuration, textsection)?,
+            actionno,
+            animation_manager: animation_manager.clone(),
+            sprite_file: sprite_file.clone(),
+        })
+    }
+
+    pub fn render(&self, commands: &mut ChildBuilder, configuration: &Res<Configuration>, z_index: i32) -> Entity {
+        let shader = configuration.sprite_shader.clone();
+        let animated_sprite = AnimatedSprite::new(
+            self.animation_manager.clone(),
+            self.sprite_file.clone(),
+            shader.clone(),
+            self.actionno
+        );
+
+        let animated_sprite = match animated_sprite {
+            Ok(animated_sprite) => animated_sprite,
+            Err(error) => {
+                diagnostics::warn(error);
+                return commands.spawn().id();
+            }
+        };
+
+        let max_z_index = match self.base_background.layer {
+            BackgroundLayer::Back => BG_LAYER_BACK_Z_INDEX_MAX,
+            BackgroundLayer::Front => BG_LAYER_FRONT_Z_INDEX_MAX,
+        };
+        let startlocation = self.base_background.startlocation;
+        let mut bundle = AnimatedSpriteBundle::new(animated_sprite, shader);
+
+        bundle.sprite_bundle.transform = Transform2D::translation(startlocation.x, startlocation.y);
+        bundle.sprite_bundle.clip_rect = self.base_background.get_window_clip_rect();
+        bundle.sprite_bundle.z_index = i32::min(z_index, max_z_index).into();
+
+        commands.spawn_bundle(bundle).id()
+    }
+}
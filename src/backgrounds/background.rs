@@ -1,14 +1,17 @@
+use std::sync::{Arc, RwLock};
+
 use bevy_ecs::prelude::*;
 use bevy_transform::hierarchy::ChildBuilder;
 
-use crate::{animations::animation_manager::AnimationManager, core::{configuration::Configuration, error::DataError, enumerations::BackgroundLayer}, drawing::{sprite_file::SpriteFile}, io::text_section::TextSection};
+use crate::{animations::animation_manager::AnimationManager, core::{configuration::Configuration, error::DataError, enumerations::BackgroundLayer}, drawing::{sprite_file::SpriteFile}, io::{from_text_section::{FromTextSection, warn_unknown_keys}, text_section::TextSection}, systems::visual_server::palette::PaletteCache};
 
-use super::{background_type::BackgroundType, static_background::StaticBackground};
+use super::{animated_background::AnimatedBackground, background_type::BackgroundType, base_background::BaseBackground, static_background::StaticBackground};
 
 #[derive(Clone)]
 pub enum Background {
     None,
     Static(StaticBackground),
+    Animated(AnimatedBackground),
 }
 
 struct Empty;
@@ -16,15 +19,21 @@ struct Empty;
 pub fn build_background(
     configuration: &Configuration,
     textsection: &TextSection,
-    sprite_file: &mut SpriteFile,
+    sprite_file: &Arc<RwLock<SpriteFile>>,
+    palette_cache: &mut PaletteCache,
     animation_manager: &AnimationManager
 ) -> Result<Background, DataError> {
     let background_type: BackgroundType = textsection.get_attribute_or_default("type");
 
     match background_type {
-        BackgroundType::Static => build_static_background(configuration, textsection, sprite_file),
+        BackgroundType::Static => build_static_background(
+            configuration,
+            textsection,
+            &mut sprite_file.write().expect("Could not lock sprite file"),
+            palette_cache
+        ),
         BackgroundType::Parallax => build_parallax_background(textsection, sprite_file),
-        BackgroundType::Animated => build_animated_background(textsection, sprite_file, animation_manager),
+        BackgroundType::Animated => build_animated_background(configuration, textsection, sprite_file, animation_manager),
         BackgroundType::None => Ok(Background::None),
     }
 }
@@ -32,28 +41,44 @@ pub fn build_background(
 fn build_static_background(
     configuration: &Configuration,
     textsection: &TextSection,
-    sprite_file: &mut SpriteFile
+    sprite_file: &mut SpriteFile,
+    palette_cache: &mut PaletteCache
 ) -> Result<Background, DataError> {
+    let mut expected_keys = BaseBackground::expected_keys("");
+    expected_keys.extend(vec!["type".to_string(), "spriteno".to_string()]);
+    warn_unknown_keys(textsection, &expected_keys);
+
     Ok(Background::Static(StaticBackground::build(
         configuration,
         textsection,
-        sprite_file
+        sprite_file,
+        palette_cache
     )?))
 }
 
 fn build_parallax_background(
     textsection: &TextSection,
-    sprite_file: &SpriteFile
+    sprite_file: &Arc<RwLock<SpriteFile>>
 ) -> Result<Background, DataError> {
     Ok(Background::None)
 }
 
 fn build_animated_background(
+    configuration: &Configuration,
     textsection: &TextSection,
-    sprite_file: &SpriteFile,
+    sprite_file: &Arc<RwLock<SpriteFile>>,
     animation_manager: &AnimationManager
 ) -> Result<Background, DataError> {
-    Ok(Background::None)
+    let mut expected_keys = BaseBackground::expected_keys("");
+    expected_keys.extend(vec!["type".to_string(), "actionno".to_string()]);
+    warn_unknown_keys(textsection, &expected_keys);
+
+    Ok(Background::Animated(AnimatedBackground::build(
+        configuration,
+        textsection,
+        sprite_file,
+        animation_manager
+    )?))
 }
 
 impl Background {
@@ -62,6 +87,9 @@ impl Background {
             Background::Static(static_background) => {
                 static_background.render(commands, &configuration, z_index)
             },
+            Background::Animated(animated_background) => {
+                animated_background.render(commands, configuration, z_index)
+            },
             _ => {
                 commands.spawn().insert(Empty).id()
             }
@@ -73,6 +101,9 @@ impl Background {
             Background::Static(static_background) => {
                 static_background.base_background.layer
             },
+            Background::Animated(animated_background) => {
+                animated_background.base_background.layer
+            },
             _ => {
                 BackgroundLayer::Back
             }
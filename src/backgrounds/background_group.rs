@@ -1,7 +1,9 @@
+use std::sync::{Arc, RwLock};
+
 use bevy_ecs::prelude::*;
 use bevy_transform::hierarchy::ChildBuilder;
 
-use crate::{animations::animation_manager::AnimationManager, backgrounds::background::build_background, core::{configuration::Configuration, error::DataError, regex::{RegEx, RegExFlags}}, drawing::sprite_file::SpriteFile, io::text_file::TextFile};
+use crate::{animations::animation_manager::AnimationManager, backgrounds::background::build_background, core::{configuration::Configuration, error::DataError, regex::{RegEx, RegExFlags}}, drawing::sprite_file::SpriteFile, io::text_file::TextFile, systems::visual_server::palette::PaletteCache};
 
 use super::background::Background;
 
@@ -15,12 +17,13 @@ impl BackgroundGroup {
         prefix: &str,
         configuration: &Configuration,
         textfile: &TextFile,
-        sprite_file: &mut SpriteFile,
+        sprite_file: &Arc<RwLock<SpriteFile>>,
         animation_manager: &AnimationManager,
     ) -> Result<Self, DataError> {
         let pattern = format!("^{}BG (.*)$", prefix);
         let regex = RegEx::new(&pattern, RegExFlags::IgnoreCase);
         let mut backgrounds = Vec::new();
+        let mut palette_cache = PaletteCache::new(configuration.sprite_shader.clone());
 
         for textsection in textfile.sections.iter() {
             if regex.is_match(&textsection.title) {
@@ -28,6 +31,7 @@ impl BackgroundGroup {
                     configuration,
                     textsection,
                     sprite_file,
+                    &mut palette_cache,
                     animation_manager
                 )?);
             }
@@ -1,20 +1,31 @@
 use bevy_ecs::prelude::Res;
 use gdnative::core_types::{Point2, Rect2, Vector2, Size2};
 
-use crate::{core::{blending::Blending, configuration::Configuration, enumerations::BackgroundLayer, error::DataError, regex::{RegEx, RegExFlags}}, io::text_section::TextSection, systems::visual_server::canvas_item::ClipRect};
+use crate::{core::{blending::Blending, configuration::Configuration, enumerations::BackgroundLayer, error::DataError, regex::{RegEx, RegExFlags}}, io::{from_text_section::FromTextSection, text_section::TextSection}, systems::visual_server::canvas_item::ClipRect};
 
-#[derive(Clone)]
+// Keys MUGEN backgrounds may use that aren't modelled yet, listed so they aren't reported as unknown
+#[derive(Clone, FromTextSection)]
+#[text_section(keys = "window, windowdelta, alpha, localcoord, sin.x, sin.y, positionlink, scalestart, scaledelta, zoomdelta")]
 pub struct BaseBackground {
+    #[text_section(default = "0")]
     pub id: i32,
+    #[text_section(skip)]
     pub name: String,
+    #[text_section(key = "start")]
     pub startlocation: Vector2,
     pub delta: Vector2,
+    #[text_section(key = "tile")]
     pub tiling: Vector2,
+    #[text_section(key = "tilespacing")]
     pub tilingspacing: Vector2,
     pub velocity: Vector2,
+    #[text_section(key = "mask", default = "false")]
     pub masking: bool,
+    #[text_section(key = "layerno", default = "BackgroundLayer::Back")]
     pub layer: BackgroundLayer,
+    #[text_section(key = "trans")]
     pub blending: Blending,
+    #[text_section(skip)]
     pub drawrect: Rect2,
 }
 
@@ -25,15 +36,6 @@ impl BaseBackground {
     ) -> Result<Self, DataError> {
         Ok(BaseBackground {
             name: get_background_name(textsection),
-            id: textsection.get_attribute_or("id", 0),
-            startlocation: textsection.get_attribute_or_default("start"),
-            delta: textsection.get_attribute_or_default("delta"),
-            tiling: textsection.get_attribute_or_default("tile"),
-            tilingspacing: textsection.get_attribute_or_default("tilespacing"),
-            velocity: textsection.get_attribute_or_default("velocity"),
-            masking: textsection.get_attribute_or("masking", false),
-            layer: textsection.get_attribute_or("layerno", BackgroundLayer::Back),
-            blending: textsection.get_attribute_or_default("trans"),
             drawrect: textsection.get_attribute_or(
                 "window",
                 Rect2::new(
@@ -41,6 +43,7 @@ impl BaseBackground {
                     configuration.screen_size
                 )
             ),
+            ..BaseBackground::from_text_section(textsection, "")?
         })
     }
 
@@ -99,3 +102,19 @@ fn get_background_name(textsection: &TextSection) -> String {
     }
     "".to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expected_keys_cover_mugen_background_keys() {
+        let expected_keys = BaseBackground::expected_keys("");
+
+        for key in ["id", "layerno", "start", "delta", "trans", "mask", "tile", "tilespacing", "window", "windowdelta", "velocity", "sin.x", "sin.y", "positionlink"].iter() {
+            assert!(expected_keys.iter().any(|expected_key| expected_key == key), "missing {}", key);
+        }
+
+        assert!(!expected_keys.iter().any(|expected_key| expected_key == "masking"));
+    }
+}
@@ -2,4 +2,5 @@ pub mod background;
 pub mod background_type;
 pub mod background_group;
 pub mod static_background;
+pub mod animated_background;
 pub mod base_background;
@@ -3,7 +3,7 @@ use bevy_transform::hierarchy::ChildBuilder;
 use std::{sync::Arc};
 use gdnative::{api::{visual_server::{TextureFlags, PrimitiveType}, SurfaceTool}, core_types::{Point2, Vector2, Vector3, Transform2D}};
 
-use crate::{core::{configuration::Configuration, error::DataError, sprite_id::SpriteId, enumerations::BackgroundLayer, constants::{BG_LAYER_BACK_Z_INDEX_MAX, BG_LAYER_FRONT_Z_INDEX_MAX}}, drawing::{sprite_file::SpriteFile}, io::text_section::TextSection, systems::{visual_server::{sprite::Sprite, texture::Texture, mesh_2d::{Mesh2dBundle, Mesh2d}, canvas_item::{ClipRect, ZIndex, BackBufferCopy}, material::Material}}};
+use crate::{core::{configuration::Configuration, error::DataError, sprite_id::SpriteId, enumerations::BackgroundLayer, constants::{BG_LAYER_BACK_Z_INDEX_MAX, BG_LAYER_FRONT_Z_INDEX_MAX}}, drawing::{sprite_file::SpriteFile}, io::text_section::TextSection, systems::{visual_server::{sprite::Sprite, texture::Texture, mesh_2d::{Mesh2dBundle, Mesh2d}, canvas_item::{ClipRect, ZIndex, BackBufferCopy}, material::Material, palette::{PaletteCache, PaletteHandle}}}};
 
 use super::base_background::BaseBackground;
 
@@ -12,6 +12,7 @@ pub struct StaticBackground {
     pub base_background: BaseBackground,
     pub spriteid: SpriteId,
     pub texture: Arc<Texture>,
+    pub palette: Option<PaletteHandle>,
     pub sprite: Sprite,
 }
 
@@ -19,11 +20,20 @@ impl StaticBackground {
     pub fn build(
         configuration: &Configuration,
         textsection: &TextSection,
-        sprite_file: &mut SpriteFile
+        sprite_file: &mut SpriteFile,
+        palette_cache: &mut PaletteCache
     ) -> Result<Self, DataError> {
         let spriteid = textsection.get_attribute_or("spriteno", SpriteId::invalid());
         let sff_data = sprite_file.get_sprite(&spriteid)?;
-        let texture = sff_data.create_texture(None, TextureFlags(0))?;
+
+        // Indexed sprites keep their indices on the GPU, so the palette can change without new textures
+        let (texture, palette) = if sff_data.image.is_indexed() {
+            let palette = palette_cache.get_or_create(&sff_data.select_palette(None));
+            (sff_data.create_monochromatic_texture(TextureFlags(0)), Some(palette))
+        } else {
+            (sff_data.create_texture(None, TextureFlags(0))?, None)
+        };
+
         let sprite = Sprite {
             offset: Point2::new(sff_data.x as f32, sff_data.y as f32),
             size: texture.size,
@@ -34,6 +44,7 @@ impl StaticBackground {
             base_background: BaseBackground::build(configuration, textsection)?,
             spriteid,
             texture,
+            palette,
             sprite,
         })
     }
@@ -93,6 +104,7 @@ impl StaticBackground {
                 ..Default::default()
             },
             material: Some(material),
+            palette: self.palette.clone(),
             z_index: z_index.into(),
             ..Default::default()
         })
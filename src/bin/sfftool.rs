@@ -0,0 +1,307 @@
+use std::collections::{BTreeMap, BTreeSet, btree_map::Entry};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+
+use game::core::{error::DataError, sprite_id::SpriteId};
+use game::drawing::sff::{data::FileReader, image::Palette, png_image::encode_png_rgba, sff_common::{SffData, SffIndex, SpriteNode}, sff_parser};
+use game::drawing::sprite_file::SpriteFile;
+use game::io::{file_system, native_file_system::NativeFileSystem, virtual_file_system::VirtualFileSystem};
+
+const USAGE: &str = "Usage:
+    sfftool info <file.sff>
+    sfftool list <file.sff>
+    sfftool palettes <file.sff>
+    sfftool export <file.sff> <output directory> [--group <group>]... [--sprite <group>,<image>]... [--palette <index> | --act <file>]
+    sfftool diff <a.sff> <b.sff>
+
+export writes every sprite when no group or sprite is given. The chosen palette replaces the
+palette of sprites drawn with the shared palette, like the game does with character palettes.
+diff exits with status 1 when the files are different.";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // Only the native file system, so the tool runs without Godot
+    let virtual_file_system = VirtualFileSystem::new();
+    virtual_file_system.mount("", Arc::new(NativeFileSystem::new("")));
+    file_system::set_file_system(virtual_file_system);
+
+    let result = match args.first().map(|command| command.as_str()) {
+        Some("info") if args.len() == 2 => info(&args[1]).map(|_| true),
+        Some("list") if args.len() == 2 => list(&args[1]).map(|_| true),
+        Some("palettes") if args.len() == 2 => palettes(&args[1]).map(|_| true),
+        Some("export") if args.len() >= 3 => export(&args[1], &args[2], &args[3..]).map(|_| true),
+        Some("diff") if args.len() == 3 => diff(&args[1], &args[2]),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    match result {
+        Ok(true) => {},
+        Ok(false) => process::exit(1),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn version(index: &SffIndex) -> String {
+    let metadata = &index.metadata;
+
+    format!("{}.{}.{}.{}", metadata.verhi, metadata.verlo1, metadata.verlo2, metadata.verlo3)
+}
+
+fn format_name(index: &SffIndex, sprite: &SpriteNode) -> &'static str {
+    if index.metadata.verhi != 2 {
+        return "pcx";
+    }
+
+    match sprite.fmt {
+        0 => "raw",
+        2 => "rle8",
+        3 => "rle5",
+        4 => "lz5",
+        10 => "png8",
+        11 => "png24",
+        12 => "png32",
+        _ => "unknown",
+    }
+}
+
+fn info(path: &str) -> Result<(), DataError> {
+    let index = sff_parser::read_index(path)?;
+    let groups: BTreeSet<i16> = index.sprites.iter().map(|sprite| sprite.groupno).collect();
+    let linked = index.sprites.iter().filter(|sprite| sprite.linked.is_some()).count();
+
+    println!("file: {}", path);
+    println!("version: {}", version(&index));
+    println!("sprites: {}", index.sprites.len());
+    println!("linked sprites: {}", linked);
+    println!("groups: {}", groups.len());
+    println!("palettes: {}", index.palettes.len());
+
+    Ok(())
+}
+
+fn list(path: &str) -> Result<(), DataError> {
+    let index = sff_parser::read_index(path)?;
+    let mut reader = FileReader::open(path)?;
+
+    println!("{:>6} {:>6} {:>6} {:>6} {:>6} {:>6} {:>7} {:>8} {:>12}", "group", "image", "x", "y", "w", "h", "format", "palette", "linked");
+
+    for (position, sprite) in index.sprites.iter().enumerate() {
+        let source = sprite.linked.unwrap_or(position);
+
+        // SFFv1 only knows the size after decoding
+        let (w, h) = if index.sprites[source].w == 0 && index.sprites[source].len > 0 {
+            let sff_data = sff_parser::decode_sprite(&mut reader, path, &index, source)?;
+            (sff_data.image.w, sff_data.image.h)
+        } else {
+            (index.sprites[source].w as usize, index.sprites[source].h as usize)
+        };
+
+        let palette = match sprite.palindex {
+            Some(palindex) => palindex.to_string(),
+            None if index.sprites[source].is_shared => "shared".to_string(),
+            None => "own".to_string(),
+        };
+
+        let linked = match sprite.linked {
+            Some(linked) => format!("{},{}", index.sprites[linked].groupno, index.sprites[linked].imageno),
+            None => "-".to_string(),
+        };
+
+        println!(
+            "{:>6} {:>6} {:>6} {:>6} {:>6} {:>6} {:>7} {:>8} {:>12}",
+            sprite.groupno, sprite.imageno, sprite.x, sprite.y, w, h,
+            format_name(&index, &index.sprites[source]), palette, linked
+        );
+    }
+
+    Ok(())
+}
+
+fn palettes(path: &str) -> Result<(), DataError> {
+    let index = sff_parser::read_index(path)?;
+
+    println!("{:>6} {:>6} {:>6} {:>7}", "index", "group", "item", "colors");
+
+    for (position, palette) in index.palettes.iter().enumerate() {
+        let (group, item) = match index.palette_ids.get(position) {
+            Some((group, item)) => (group.to_string(), item.to_string()),
+            None => ("-".to_string(), "-".to_string()),
+        };
+
+        println!("{:>6} {:>6} {:>6} {:>7}", position, group, item, palette.colors.len());
+    }
+
+    Ok(())
+}
+
+fn parse_number<T: std::str::FromStr>(value: Option<&String>, option: &str) -> Result<T, DataError> {
+    value
+        .and_then(|value| value.trim().parse::<T>().ok())
+        .ok_or_else(|| DataError::new(format!("Invalid value for {}", option)))
+}
+
+fn parse_sprite_id(value: Option<&String>) -> Result<SpriteId, DataError> {
+    let pieces: Vec<String> = value
+        .map(|value| value.split(',').map(|piece| piece.to_string()).collect())
+        .unwrap_or_default();
+
+    if pieces.len() != 2 {
+        return Err(DataError::new("Invalid value for --sprite, expected <group>,<image>".to_string()));
+    }
+
+    Ok(SpriteId::new(
+        parse_number(pieces.first(), "--sprite")?,
+        parse_number(pieces.get(1), "--sprite")?
+    ))
+}
+
+fn export(path: &str, output: &str, options: &[String]) -> Result<(), DataError> {
+    let index = sff_parser::read_index(path)?;
+    let mut sprite_file = SpriteFile::load(path)?;
+    let mut groups: Vec<i16> = Vec::new();
+    let mut sprite_ids: Vec<SpriteId> = Vec::new();
+    let mut palette: Option<Arc<Palette>> = None;
+    let mut options = options.iter();
+
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--group" => groups.push(parse_number(options.next(), "--group")?),
+            "--sprite" => sprite_ids.push(parse_sprite_id(options.next())?),
+            "--palette" => {
+                let position: usize = parse_number(options.next(), "--palette")?;
+                let selected = index.palettes.get(position)
+                    .ok_or_else(|| DataError::new(format!("Palette not found: {}", position)))?;
+                palette = Some(selected.clone());
+            },
+            "--act" => {
+                let act_path = options.next().ok_or_else(|| DataError::new("Missing value for --act".to_string()))?;
+                palette = Some(sff_parser::read_palette(act_path)?);
+            },
+            _ => return Err(DataError::new(format!("Unknown option: {}", option))),
+        }
+    }
+
+    let mut selected: Vec<SpriteId> = Vec::new();
+
+    for sprite in index.sprites.iter() {
+        let sprite_id = SpriteId::new(sprite.groupno, sprite.imageno);
+        let everything = groups.is_empty() && sprite_ids.is_empty();
+
+        if (everything || groups.contains(&sprite.groupno) || sprite_ids.contains(&sprite_id)) && !selected.contains(&sprite_id) {
+            selected.push(sprite_id);
+        }
+    }
+
+    for sprite_id in sprite_ids.iter() {
+        if !selected.contains(sprite_id) {
+            return Err(DataError::new(format!("Image not found: {}", sprite_id)).with_path(path));
+        }
+    }
+
+    fs::create_dir_all(output)
+        .map_err(|error| DataError::new(format!("Error creating directory: {}", error)).with_path(output))?;
+
+    for sprite_id in selected.iter() {
+        let sff_data = sprite_file.get_sprite(sprite_id)?;
+        let filename = Path::new(output).join(format!("{}-{}.png", sprite_id.group, sprite_id.image));
+        let pixels = sff_data.image.to_rgba(&sff_data.select_palette(palette.clone()));
+
+        fs::write(&filename, encode_png_rgba(sff_data.image.w, sff_data.image.h, &pixels)?)
+            .map_err(|error| DataError::new(format!("Error writing file: {}", error)).with_path(&filename.to_string_lossy()))?;
+
+        println!("{}", filename.to_string_lossy());
+    }
+
+    Ok(())
+}
+
+fn load_sprites(path: &str) -> Result<(SffIndex, BTreeMap<SpriteId, SffData>), DataError> {
+    let index = sff_parser::read_index(path)?;
+    let mut sprite_file = SpriteFile::load(path)?;
+    let mut sprites = BTreeMap::new();
+
+    for sprite in index.sprites.iter() {
+        let sprite_id = SpriteId::new(sprite.groupno, sprite.imageno);
+
+        if let Entry::Vacant(entry) = sprites.entry(sprite_id) {
+            entry.insert(sprite_file.get_sprite(&sprite_id)?);
+        }
+    }
+
+    Ok((index, sprites))
+}
+
+fn diff(path_a: &str, path_b: &str) -> Result<bool, DataError> {
+    let (index_a, sprites_a) = load_sprites(path_a)?;
+    let (index_b, sprites_b) = load_sprites(path_b)?;
+    let mut differences: Vec<String> = Vec::new();
+
+    if version(&index_a) != version(&index_b) {
+        differences.push(format!("version: {} != {}", version(&index_a), version(&index_b)));
+    }
+
+    if index_a.palettes.len() != index_b.palettes.len() {
+        differences.push(format!("palettes: {} != {}", index_a.palettes.len(), index_b.palettes.len()));
+    }
+
+    for (position, (palette_a, palette_b)) in index_a.palettes.iter().zip(index_b.palettes.iter()).enumerate() {
+        if !palette_a.equal(palette_b) {
+            differences.push(format!("palette {}: colors differ", position));
+        }
+    }
+
+    for sprite_id in sprites_a.keys().filter(|sprite_id| !sprites_b.contains_key(sprite_id)) {
+        differences.push(format!("sprite {}: only in {}", sprite_id, path_a));
+    }
+
+    for sprite_id in sprites_b.keys().filter(|sprite_id| !sprites_a.contains_key(sprite_id)) {
+        differences.push(format!("sprite {}: only in {}", sprite_id, path_b));
+    }
+
+    for (sprite_id, sprite_a) in sprites_a.iter() {
+        let sprite_b = match sprites_b.get(sprite_id) {
+            Some(sprite_b) => sprite_b,
+            None => continue,
+        };
+
+        if (sprite_a.x, sprite_a.y) != (sprite_b.x, sprite_b.y) {
+            differences.push(format!("sprite {}: axis {},{} != {},{}", sprite_id, sprite_a.x, sprite_a.y, sprite_b.x, sprite_b.y));
+        }
+
+        if (sprite_a.image.w, sprite_a.image.h) != (sprite_b.image.w, sprite_b.image.h) {
+            differences.push(format!(
+                "sprite {}: size {}x{} != {}x{}",
+                sprite_id, sprite_a.image.w, sprite_a.image.h, sprite_b.image.w, sprite_b.image.h
+            ));
+            continue;
+        }
+
+        // Compares what is drawn, so sprites encoded differently are still equal
+        let pixels_a = sprite_a.image.to_rgba(&sprite_a.select_palette(None));
+        let pixels_b = sprite_b.image.to_rgba(&sprite_b.select_palette(None));
+
+        if pixels_a != pixels_b {
+            differences.push(format!("sprite {}: pixels differ", sprite_id));
+        }
+    }
+
+    for difference in differences.iter() {
+        println!("{}", difference);
+    }
+
+    if differences.is_empty() {
+        println!("files are equal");
+    }
+
+    Ok(differences.is_empty())
+}
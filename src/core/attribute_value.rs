@@ -1,10 +1,19 @@
+use std::{ops::RangeInclusive, str::FromStr};
+
 use gdnative::{core_types::{Point2, Rect2, Size2, Vector2}};
 
 use super::{enumerations::BackgroundLayer, error::DataError};
 
-#[derive(Default, Clone, PartialEq)]
+#[derive(Default, Clone)]
 pub struct AttributeValue {
     value: String,
+    line: usize,
+}
+
+impl PartialEq for AttributeValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
 }
 
 impl AttributeValue {
@@ -12,10 +21,25 @@ impl AttributeValue {
         value: &str,
     ) -> Self {
         AttributeValue {
-            value: String::from(value)
+            value: String::from(value),
+            line: 0,
+        }
+    }
+
+    pub fn with_line(
+        value: &str,
+        line: usize,
+    ) -> Self {
+        AttributeValue {
+            value: String::from(value),
+            line,
         }
     }
 
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
     pub fn compare(&self, value: &str, index_a: usize, index_b: usize, length: usize) -> bool {
         let value_a = self.to_string().to_lowercase();
         let value_b = value.to_lowercase();
@@ -45,6 +69,51 @@ impl AttributeValue {
         }
         result
     }
+
+    pub fn parse_tuple<T: FromStr>(&self, max_length: usize) -> Result<Tuple<T>, DataError> {
+        let pieces = self.split_values();
+        let error = DataError::invalid_attribute(format!("Invalid tuple: {}", self.to_string()));
+
+        if pieces.iter().all(|piece| piece.is_empty()) {
+            return Err(error);
+        }
+
+        let mut values = Vec::new();
+
+        for piece in pieces.iter().take(max_length) {
+            if piece.is_empty() {
+                values.push(None);
+                continue;
+            }
+
+            values.push(Some(piece.parse::<T>().map_err(|_| error.clone())?));
+        }
+
+        Ok(Tuple { values })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuple<T> {
+    pub values: Vec<Option<T>>,
+}
+
+impl<T: Copy> Tuple<T> {
+    pub fn get(&self, index: usize) -> Option<T> {
+        self.values.get(index).copied().flatten()
+    }
+
+    pub fn get_or(&self, index: usize, default: T) -> T {
+        self.get(index).unwrap_or(default)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
 }
 
 pub trait ParseAttributeValue: Sized {
@@ -69,7 +138,7 @@ impl ParseAttributeValue for i32 {
 
         match text.parse::<i32>() {
             Ok(value) => Ok(value),
-            Err(err) => Err(DataError::new(format!("Invalid integer: {}", text)))
+            Err(err) => Err(DataError::invalid_attribute(format!("Invalid integer: {}", text)))
         }
     }
 }
@@ -80,7 +149,7 @@ impl ParseAttributeValue for usize {
 
         match text.parse::<usize>() {
             Ok(value) => Ok(value),
-            Err(err) => Err(DataError::new(format!("Invalid index: {}", text)))
+            Err(err) => Err(DataError::invalid_attribute(format!("Invalid index: {}", text)))
         }
     }
 }
@@ -91,7 +160,7 @@ impl ParseAttributeValue for f32 {
 
         match text.parse::<f32>() {
             Ok(value) => Ok(value),
-            Err(err) => Err(DataError::new(format!("Invalid float: {}", text)))
+            Err(err) => Err(DataError::invalid_attribute(format!("Invalid float: {}", text)))
         }
     }
 }
@@ -108,23 +177,33 @@ impl ParseAttributeValue for bool {
             return Ok(false);
         }
 
-        return Err(DataError::new(format!("Invalid bool: {}", text)));
+        return Err(DataError::invalid_attribute(format!("Invalid bool: {}", text)));
     }
 }
 
-impl ParseAttributeValue for Vector2 {
-    fn parse_attribute_value(value: AttributeValue) -> Result<Vector2, DataError> {
-        let pieces  = value.split_values();
-        let error = DataError::new(format!("Invalid vector: {}", value.to_string()));
+impl<T: FromStr> ParseAttributeValue for Tuple<T> {
+    fn parse_attribute_value(value: AttributeValue) -> Result<Tuple<T>, DataError> {
+        value.parse_tuple(usize::MAX)
+    }
+}
 
-        if pieces.len() == 2 {
-            let x = pieces[0].parse::<i32>().map_err(|_| error.clone())?;
-            let y = pieces[1].parse::<i32>().map_err(|_| error.clone())?;
+impl<T: FromStr + Copy> ParseAttributeValue for RangeInclusive<T> {
+    fn parse_attribute_value(value: AttributeValue) -> Result<RangeInclusive<T>, DataError> {
+        let tuple = value.parse_tuple::<T>(2)?;
+        let error = DataError::invalid_attribute(format!("Invalid range: {}", value.to_string()));
+        let start = tuple.get(0).ok_or_else(|| error.clone())?;
+        let end = tuple.get_or(1, start);
 
-            return Ok(Vector2::new(x as f32, y as f32));
-        }
+        Ok(start..=end)
+    }
+}
 
-        Err(error)
+impl ParseAttributeValue for Vector2 {
+    fn parse_attribute_value(value: AttributeValue) -> Result<Vector2, DataError> {
+        let tuple = value.parse_tuple::<f32>(2)
+            .map_err(|_| DataError::invalid_attribute(format!("Invalid vector: {}", value.to_string())))?;
+
+        Ok(Vector2::new(tuple.get_or(0, 0.0), tuple.get_or(1, 0.0)))
     }
 }
 
@@ -146,24 +225,19 @@ impl ParseAttributeValue for Point2 {
 
 impl ParseAttributeValue for Rect2 {
     fn parse_attribute_value(value: AttributeValue) -> Result<Rect2, DataError> {
-        let pieces  = value.split_values();
-        let error = DataError::new(format!("Invalid vector: {}", value.to_string()));
-
-        if pieces.len() > 3 {
-            let x1 = pieces[0].parse::<i32>().map_err(|_| error.clone())?;
-            let y1 = pieces[1].parse::<i32>().map_err(|_| error.clone())?;
-            let x2 = pieces[2].parse::<i32>().map_err(|_| error.clone())?;
-            let y2 = pieces[3].parse::<i32>().map_err(|_| error.clone())?;
-
-            return Ok(
-                Rect2::new(
-                    Point2::new(x1 as f32, y1 as f32),
-                    Size2::new((x2 - x1) as f32, (y2 - y1) as f32),
-                )
-            );
-        }
-
-        Err(error)
+        let tuple = value.parse_tuple::<f32>(4)
+            .map_err(|_| DataError::invalid_attribute(format!("Invalid rect: {}", value.to_string())))?;
+        let x1 = tuple.get_or(0, 0.0);
+        let y1 = tuple.get_or(1, 0.0);
+        let x2 = tuple.get_or(2, 0.0);
+        let y2 = tuple.get_or(3, 0.0);
+
+        Ok(
+            Rect2::new(
+                Point2::new(x1, y1),
+                Size2::new(x2 - x1, y2 - y1),
+            )
+        )
     }
 }
 
@@ -179,6 +253,47 @@ impl ParseAttributeValue for BackgroundLayer {
             return Ok(BackgroundLayer::Front);
         }
 
-        Err(DataError::new(format!("Invalid layer: {}", value)))
+        Err(DataError::invalid_attribute(format!("Invalid layer: {}", value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tuples() {
+        let cases: Vec<(&str, usize, Vec<Option<f32>>)> = vec![
+            ("-.5,0", usize::MAX, vec![Some(-0.5), Some(0.0)]),
+            ("1,,3", usize::MAX, vec![Some(1.0), None, Some(3.0)]),
+            ("1.5", usize::MAX, vec![Some(1.5)]),
+            (" 2 , 3 ", usize::MAX, vec![Some(2.0), Some(3.0)]),
+            ("1,2,3,4,5", 4, vec![Some(1.0), Some(2.0), Some(3.0), Some(4.0)]),
+            (",4", usize::MAX, vec![None, Some(4.0)]),
+        ];
+
+        for (text, max_length, expected) in cases {
+            let tuple = AttributeValue::new(text).parse_tuple::<f32>(max_length).unwrap();
+
+            assert_eq!(tuple.values, expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_tuples() {
+        for text in ["", " , ", "1,a", "1;2"].iter() {
+            assert!(AttributeValue::new(text).parse_tuple::<i32>(usize::MAX).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn parses_scalar_attributes_as_tuples() {
+        let tuple = Tuple::<f32>::parse_attribute_value(AttributeValue::new("1.5")).unwrap();
+
+        assert_eq!(tuple.len(), 1);
+        assert_eq!(tuple.get(0), Some(1.5));
+        assert_eq!(tuple.get_or(1, 1.0), 1.0);
+        assert_eq!(Vector2::parse_attribute_value(AttributeValue::new("-.5,0")).unwrap(), Vector2::new(-0.5, 0.0));
+        assert_eq!(RangeInclusive::<i32>::parse_attribute_value(AttributeValue::new("3")).unwrap(), 3..=3);
     }
 }
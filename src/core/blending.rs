@@ -1,8 +1,8 @@
 use std::{fmt::Display, sync::{RwLock, Arc}};
 
-use gdnative::{godot_warn, core_types::ToVariant, api::animation_node_blend_space_2d::BlendMode};
+use gdnative::{core_types::ToVariant, api::animation_node_blend_space_2d::BlendMode};
 
-use crate::{core::{error::DataError, regex::RegExFlags}, systems::visual_server::material::Material};
+use crate::{core::{diagnostics, error::DataError, regex::RegExFlags}, systems::visual_server::material::Material};
 
 use super::{attribute_value::{AttributeValue, ParseAttributeValue}, enumerations::BlendType, regex::RegEx};
 
@@ -30,6 +30,20 @@ impl Blending {
         return self.blend_type == BlendType::None;
     }
 
+    pub fn lerp(&self, target: &Blending, amount: f32) -> Blending {
+        if self.blend_type != target.blend_type {
+            return *self;
+        }
+
+        let lerp = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount).round().clamp(0.0, 255.0) as u8;
+
+        Blending::new(
+            self.blend_type,
+            lerp(self.source, target.source),
+            lerp(self.destination, target.destination)
+        )
+    }
+
     pub fn configure_material(&self, material: &Arc<RwLock<Material>>) {
         let mut material_write = material.write().expect("Could not lock material");
         material_write.set_shader_param("blend_type", (self.blend_type as i32).to_variant());
@@ -69,16 +83,17 @@ fn parse_blending(raw_text: &str) -> Result<Blending, DataError> {
 
         if let Some(source) = source_option {
             if let Some(destination) = destination_option {
+                // AIR 1.1 alpha goes up to 256
                 return Ok(Blending::new(
                     BlendType::Add,
-                    source as u8,
-                    destination as u8
+                    source.min(255) as u8,
+                    destination.min(255) as u8
                 ));
             }
         }
     }
 
-    Err(DataError::new(format!("Invalid blending format: {}", text)))
+    Err(DataError::invalid_attribute(format!("Invalid blending format: {}", text)))
 }
 
 impl From<&str> for Blending {
@@ -86,7 +101,7 @@ impl From<&str> for Blending {
         match parse_blending(raw_text) {
             Ok(blending) => blending,
             Err(error) => {
-                godot_warn!("{}", error);
+                diagnostics::warn(error);
                 Blending::default()
             }
         }
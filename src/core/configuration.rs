@@ -2,11 +2,95 @@ use std::sync::Arc;
 
 use gdnative::core_types::{Size2, Point2};
 
+use crate::core::constants::{MUGEN_CONFIG_PATH, MUGEN_USER_CONFIG_PATH};
+use crate::core::error::DataError;
+use crate::core::mugen_config::{EngineConfig, JoystickConfig, KeyBindings, OptionsConfig, RulesConfig, SoundConfig, VideoConfig};
+use crate::io::from_text_section::FromTextSection;
+use crate::io::text_file::TextFile;
+use crate::io::text_section::TextSection;
+use crate::io::file_system;
 use crate::systems::visual_server::shader::Shader;
 
 pub struct Configuration {
     pub screen_size: Size2,
     pub sprite_shader: Arc<Shader>,
+    pub options: OptionsConfig,
+    pub rules: RulesConfig,
+    pub config: EngineConfig,
+    pub video: VideoConfig,
+    pub sound: SoundConfig,
+    pub p1_keys: KeyBindings,
+    pub p2_keys: KeyBindings,
+    pub joystick: JoystickConfig,
+    document: TextFile,
+}
+
+impl Configuration {
+    pub fn load(sprite_shader: Arc<Shader>) -> Result<Configuration, DataError> {
+        // Saved settings win over the defaults shipped with the game data
+        let document = open_document(&[MUGEN_USER_CONFIG_PATH, MUGEN_CONFIG_PATH])?;
+
+        Configuration::from_text_file(document, sprite_shader)
+    }
+
+    pub fn from_text_file(document: TextFile, sprite_shader: Arc<Shader>) -> Result<Configuration, DataError> {
+        let video: VideoConfig = read_section(&document, "Video")?;
+
+        Ok(Configuration {
+            screen_size: Size2::new(video.width as f32, video.height as f32),
+            sprite_shader,
+            options: read_section(&document, "Options")?,
+            rules: read_section(&document, "Rules")?,
+            config: read_section(&document, "Config")?,
+            video,
+            sound: read_section(&document, "Sound")?,
+            p1_keys: read_section(&document, "P1 Keys")?,
+            p2_keys: read_section(&document, "P2 Keys")?,
+            joystick: read_section(&document, "Joystick")?,
+            document,
+        })
+    }
+
+    pub fn to_text_file(&self) -> TextFile {
+        let mut document = self.document.clone();
+
+        self.options.write(&mut document);
+        self.rules.write(&mut document);
+        self.config.write(&mut document);
+        self.video.write(&mut document);
+        self.sound.write(&mut document);
+        self.p1_keys.write(&mut document, "P1 Keys");
+        self.p2_keys.write(&mut document, "P2 Keys");
+        self.joystick.write(&mut document);
+
+        document
+    }
+
+    pub fn save(&self) -> Result<(), DataError> {
+        let mut document = self.to_text_file();
+
+        // res:// is read-only in exported games
+        document.filepath = MUGEN_USER_CONFIG_PATH.to_string();
+
+        file_system::save_text_file(&document)
+    }
+}
+
+fn open_document(paths: &[&str]) -> Result<TextFile, DataError> {
+    for path in paths.iter() {
+        if let Some(path) = file_system::resolve_file(path) {
+            return file_system::open_text_file(&path);
+        }
+    }
+
+    Ok(TextFile::new(paths[0].to_string(), Vec::new()))
+}
+
+fn read_section<T: FromTextSection>(document: &TextFile, title: &str) -> Result<T, DataError> {
+    match document.get_section(title) {
+        Ok(section) => T::from_text_section(&section, ""),
+        Err(_) => T::from_text_section(&TextSection::default(), ""),
+    }
 }
 
 pub trait ScaleForScreen {
@@ -42,3 +126,41 @@ impl ScaleForScreen for Point2 {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::io::memory_file_system::test_mount;
+
+    use super::*;
+
+    #[test]
+    fn user_config_is_read_before_the_defaults() {
+        let mount = test_mount("configuration_tests");
+
+        let user_path = format!("{}/user/mugen.cfg", mount);
+        let default_path = format!("{}/res/mugen.cfg", mount);
+
+        file_system::write_file(&default_path, b"[Options]\nDifficulty = 4\n").unwrap();
+
+        let document = open_document(&[&user_path, &default_path]).unwrap();
+        assert_eq!(document.filepath, default_path);
+
+        file_system::write_file(&user_path, b"[Options]\nDifficulty = 8\n").unwrap();
+
+        let document = open_document(&[&user_path, &default_path]).unwrap();
+        let difficulty: i32 = document.get_section("Options").unwrap().get_attribute_or_default("difficulty");
+        assert_eq!(difficulty, 8);
+    }
+
+    #[test]
+    fn missing_config_starts_empty_at_the_user_path() {
+        let mount = test_mount("configuration_tests");
+
+        let user_path = format!("{}/missing/user.cfg", mount);
+        let default_path = format!("{}/missing/default.cfg", mount);
+        let document = open_document(&[&user_path, &default_path]).unwrap();
+
+        assert_eq!(document.filepath, user_path);
+        assert!(document.sections.is_empty());
+    }
+}
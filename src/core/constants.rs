@@ -1,8 +1,14 @@
 pub const DATA_PATH: &str = "res://data";
 pub const MUGEN_10_SYSTEM_PATH: &str = "res://data/data/system.def";
 pub const MUGEN_11_SYSTEM_PATH: &str = "res://data/data/mugen1/system.def";
+pub const MUGEN_CONFIG_PATH: &str = "res://data/data/mugen.cfg";
+pub const MUGEN_USER_CONFIG_PATH: &str = "user://mugen.cfg";
 pub const BG_LAYER_BACK_Z_INDEX_MIN: i32 = 0;
 pub const BG_LAYER_BACK_Z_INDEX_MAX: i32 = 127;
 pub const BG_LAYER_FRONT_Z_INDEX_MIN: i32 = 128;
 pub const BG_LAYER_FRONT_Z_INDEX_MAX: i32 = 255;
 pub const TEXT_Z_INDEX: i32 = 248;
+pub const DEFAULT_SPRITE_CACHE_BUDGET: usize = 64 * 1024 * 1024;
+pub const MAX_CHARACTER_PALETTES: usize = 12;
+pub const GAME_TICK_DURATION: f64 = 1.0 / 60.0;
+pub const MAX_GAME_TICKS_PER_FRAME: usize = 4;
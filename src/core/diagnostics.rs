@@ -0,0 +1,110 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+
+use super::error::DataError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub error: DataError,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+
+        write!(f, "{} ({}): {}", severity, self.error.kind, self.error)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DiagnosticReport {
+    pub entries: Vec<Diagnostic>,
+}
+
+impl DiagnosticReport {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.entries.iter().filter(|entry| entry.severity == severity).count()
+    }
+}
+
+impl fmt::Display for DiagnosticReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in self.entries.iter() {
+            writeln!(f, "{}", entry)?;
+        }
+
+        write!(
+            f,
+            "{} warning(s), {} error(s)",
+            self.count(Severity::Warning),
+            self.count(Severity::Error)
+        )
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Diagnostics {
+    entries: Arc<Mutex<Vec<Diagnostic>>>,
+}
+
+impl Diagnostics {
+    pub fn warn(&self, error: DataError) {
+        self.push(Severity::Warning, error);
+    }
+
+    pub fn error(&self, error: DataError) {
+        self.push(Severity::Error, error);
+    }
+
+    pub fn report(&self) -> DiagnosticReport {
+        let entries = self.entries.lock().expect("Could not lock diagnostics");
+
+        DiagnosticReport { entries: entries.clone() }
+    }
+
+    pub fn take_report(&self) -> DiagnosticReport {
+        let mut entries = self.entries.lock().expect("Could not lock diagnostics");
+
+        DiagnosticReport { entries: std::mem::take(&mut *entries) }
+    }
+
+    fn push(&self, severity: Severity, error: DataError) {
+        let mut entries = self.entries.lock().expect("Could not lock diagnostics");
+
+        entries.push(Diagnostic { severity, error });
+    }
+}
+
+static DIAGNOSTICS: RwLock<Option<Diagnostics>> = RwLock::new(None);
+
+pub fn get_diagnostics() -> Diagnostics {
+    if let Some(diagnostics) = DIAGNOSTICS.read().expect("Could not lock diagnostics").as_ref() {
+        return diagnostics.clone();
+    }
+
+    let mut diagnostics = DIAGNOSTICS.write().expect("Could not lock diagnostics");
+
+    diagnostics.get_or_insert_with(Diagnostics::default).clone()
+}
+
+pub fn warn(error: DataError) {
+    get_diagnostics().warn(error);
+}
+
+pub fn error(error: DataError) {
+    get_diagnostics().error(error);
+}
@@ -246,3 +246,13 @@ impl Display for SpriteEffects {
         ))
     }
 }
+
+#[repr(u8)]
+#[derive(EnumFlags, Copy, Clone, PartialEq)]
+pub enum AnimationInterpolation {
+    None = 0b0,
+    Offset = 0b0001,
+    Blend = 0b0010,
+    Scale = 0b0100,
+    Angle = 0b1000
+}
@@ -1,18 +1,109 @@
 use std::fmt;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataErrorKind {
+    MissingFile,
+    MissingSection,
+    InvalidAttribute,
+    DecodeFailure,
+    Other,
+}
+
+impl fmt::Display for DataErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            DataErrorKind::MissingFile => "missing file",
+            DataErrorKind::MissingSection => "missing section",
+            DataErrorKind::InvalidAttribute => "invalid attribute",
+            DataErrorKind::DecodeFailure => "decode failure",
+            DataErrorKind::Other => "error",
+        };
+
+        write!(f, "{}", text)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DataError {
+    pub kind: DataErrorKind,
     pub message: String,
+    pub path: Option<String>,
+    pub section: Option<String>,
+    pub line: Option<usize>,
 }
 
 impl fmt::Display for DataError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}", path)?;
+
+            if let Some(line) = self.line {
+                write!(f, ":{}", line)?;
+            }
+
+            write!(f, ": ")?;
+        }
+
+        if let Some(section) = &self.section {
+            write!(f, "[{}] ", section)?;
+        }
+
         write!(f, "{}", self.message.to_string())
     }
 }
 
 impl DataError {
     pub fn new(message: String) -> DataError {
-        DataError { message }
+        DataError::with_kind(DataErrorKind::Other, message)
+    }
+
+    pub fn with_kind(kind: DataErrorKind, message: String) -> DataError {
+        DataError {
+            kind,
+            message,
+            path: None,
+            section: None,
+            line: None,
+        }
+    }
+
+    pub fn missing_file(path: &str, message: String) -> DataError {
+        DataError::with_kind(DataErrorKind::MissingFile, message).with_path(path)
+    }
+
+    pub fn missing_section(section: &str) -> DataError {
+        DataError::with_kind(DataErrorKind::MissingSection, format!("Missing section: {}", section))
+    }
+
+    pub fn invalid_attribute(message: String) -> DataError {
+        DataError::with_kind(DataErrorKind::InvalidAttribute, message)
+    }
+
+    pub fn decode_failure(message: String) -> DataError {
+        DataError::with_kind(DataErrorKind::DecodeFailure, message)
+    }
+
+    pub fn with_path(mut self, path: &str) -> DataError {
+        if self.path.is_none() && !path.is_empty() {
+            self.path = Some(path.to_string());
+        }
+
+        self
+    }
+
+    pub fn with_section(mut self, section: &str) -> DataError {
+        if self.section.is_none() && !section.is_empty() {
+            self.section = Some(section.to_string());
+        }
+
+        self
+    }
+
+    pub fn with_line(mut self, line: usize) -> DataError {
+        if self.line.is_none() && line > 0 {
+            self.line = Some(line);
+        }
+
+        self
     }
 }
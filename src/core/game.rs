@@ -5,7 +5,7 @@ use bevy_core::CorePlugin;
 use bevy_transform::TransformPlugin;
 use gdnative::{prelude::{NativeClass,Node2D,TRef,methods,FromVariant,Variant}};
 
-use crate::{drawing::sprite_system::SpriteSystem, systems::{debug::DebugPlugin, menu::menu_plugin::MenuPlugin, visual_server::{root_node::RootNode, time::DeltaTime, visual_server_plugin::VisualServerPlugin}, input::Input, audio_server::audio_server_plugin::AudioServerPlugin, backgrounds::background_plugin::BackgroundPlugin}, profiles::profile_loader::ProfileLoader};
+use crate::{core::diagnostics, io::{file_system, godot_file_system}, drawing::{sprite_system::SpriteSystem, palette_manager::PaletteManager}, systems::{debug::DebugPlugin, menu::menu_plugin::MenuPlugin, visual_server::{root_node::RootNode, time::DeltaTime, visual_server_plugin::VisualServerPlugin}, input::Input, audio_server::audio_server_plugin::AudioServerPlugin, backgrounds::background_plugin::BackgroundPlugin, animations::animation_plugin::AnimationPlugin, hot_reload::HotReloadPlugin, asset_loader::AssetLoaderPlugin}, profiles::profile_loader::ProfileLoader};
 
 #[derive(NativeClass)]
 #[inherit(Node2D)]
@@ -19,6 +19,8 @@ impl Game {
         let root_node = RootNode::new(&owner);
         let input = Input::default();
 
+        godot_file_system::mount_native_roots(&file_system::get_file_system());
+
         Game {
             app: std::mem::take(
                 &mut App::build()
@@ -26,12 +28,17 @@ impl Game {
                 .insert_resource(input)
                 .insert_resource(DeltaTime::default())
                 .insert_resource(SpriteSystem::new())
+                .insert_resource(PaletteManager::default())
+                .insert_resource(diagnostics::get_diagnostics())
                 .add_plugin(CorePlugin::default())
                 .add_plugin(TransformPlugin::default())
                 .add_plugin(VisualServerPlugin::default())
                 .add_plugin(AudioServerPlugin::default())
                 // .add_plugin(DebugPlugin::default())
                 .add_plugin(BackgroundPlugin::default())
+                .add_plugin(AnimationPlugin::default())
+                .add_plugin(HotReloadPlugin::default())
+                .add_plugin(AssetLoaderPlugin::default())
                 .add_plugin(MenuPlugin::default())
                 .app
             )
@@ -1,5 +1,6 @@
 pub mod game;
 pub mod error;
+pub mod diagnostics;
 pub mod sprite_id;
 pub mod attribute_value;
 pub mod enumerations;
@@ -8,4 +9,5 @@ pub mod regex;
 pub mod constants;
 pub mod sound_id;
 pub mod configuration;
+pub mod mugen_config;
 pub mod helpers;
@@ -0,0 +1,255 @@
+use crate::io::{from_text_section::FromTextSection, text_file::TextFile};
+
+fn format_bool(value: bool) -> String {
+    if value { "1".to_string() } else { "0".to_string() }
+}
+
+#[derive(Clone, FromTextSection)]
+pub struct OptionsConfig {
+    #[text_section(default = "4")]
+    pub difficulty: i32,
+    #[text_section(default = "100")]
+    pub life: i32,
+    #[text_section(default = "99")]
+    pub time: i32,
+    #[text_section(key = "gamespeed")]
+    pub game_speed: i32,
+    #[text_section(key = "team.1vs2life", default = "100")]
+    pub team_1vs2_life: i32,
+    #[text_section(key = "team.loseonko")]
+    pub team_lose_on_ko: bool,
+    pub motif: String,
+}
+
+impl OptionsConfig {
+    pub fn write(&self, document: &mut TextFile) {
+        document.set_attribute("Options", "Difficulty", &self.difficulty.to_string());
+        document.set_attribute("Options", "Life", &self.life.to_string());
+        document.set_attribute("Options", "Time", &self.time.to_string());
+        document.set_attribute("Options", "GameSpeed", &self.game_speed.to_string());
+        document.set_attribute("Options", "Team.1VS2Life", &self.team_1vs2_life.to_string());
+        document.set_attribute("Options", "Team.LoseOnKO", &format_bool(self.team_lose_on_ko));
+
+        if !self.motif.is_empty() {
+            document.set_attribute("Options", "Motif", &self.motif);
+        }
+    }
+}
+
+#[derive(Clone, FromTextSection)]
+pub struct RulesConfig {
+    #[text_section(key = "gametype", default = "\"VS\".to_string()")]
+    pub game_type: String,
+    #[text_section(key = "default.attack.lifetopowermul", default = "0.7")]
+    pub attack_life_to_power_mul: f32,
+    #[text_section(key = "default.gethit.lifetopowermul", default = "0.6")]
+    pub get_hit_life_to_power_mul: f32,
+    #[text_section(key = "super.targetdefencemul", default = "1.5")]
+    pub super_target_defence_mul: f32,
+}
+
+impl RulesConfig {
+    pub fn write(&self, document: &mut TextFile) {
+        document.set_attribute("Rules", "GameType", &self.game_type);
+        document.set_attribute("Rules", "Default.Attack.LifeToPowerMul", &self.attack_life_to_power_mul.to_string());
+        document.set_attribute("Rules", "Default.GetHit.LifeToPowerMul", &self.get_hit_life_to_power_mul.to_string());
+        document.set_attribute("Rules", "Super.TargetDefenceMul", &self.super_target_defence_mul.to_string());
+    }
+}
+
+#[derive(Clone, FromTextSection)]
+pub struct EngineConfig {
+    #[text_section(key = "gamespeed", default = "60")]
+    pub game_speed: i32,
+    #[text_section(key = "gamewidth", default = "640")]
+    pub game_width: i32,
+    #[text_section(key = "gameheight", default = "480")]
+    pub game_height: i32,
+    #[text_section(default = "\"en\".to_string()")]
+    pub language: String,
+    #[text_section(key = "drawshadows", default = "true")]
+    pub draw_shadows: bool,
+}
+
+impl EngineConfig {
+    pub fn write(&self, document: &mut TextFile) {
+        document.set_attribute("Config", "GameSpeed", &self.game_speed.to_string());
+        document.set_attribute("Config", "GameWidth", &self.game_width.to_string());
+        document.set_attribute("Config", "GameHeight", &self.game_height.to_string());
+        document.set_attribute("Config", "Language", &self.language);
+        document.set_attribute("Config", "DrawShadows", &format_bool(self.draw_shadows));
+    }
+}
+
+#[derive(Clone, FromTextSection)]
+pub struct VideoConfig {
+    #[text_section(default = "1280")]
+    pub width: i32,
+    #[text_section(default = "720")]
+    pub height: i32,
+    #[text_section(key = "vretrace", default = "true")]
+    pub vertical_retrace: bool,
+    #[text_section(key = "fullscreen")]
+    pub fullscreen: bool,
+}
+
+impl VideoConfig {
+    pub fn write(&self, document: &mut TextFile) {
+        document.set_attribute("Video", "Width", &self.width.to_string());
+        document.set_attribute("Video", "Height", &self.height.to_string());
+        document.set_attribute("Video", "VRetrace", &format_bool(self.vertical_retrace));
+        document.set_attribute("Video", "FullScreen", &format_bool(self.fullscreen));
+    }
+}
+
+#[derive(Clone, FromTextSection)]
+pub struct SoundConfig {
+    #[text_section(key = "sound", default = "true")]
+    pub enabled: bool,
+    #[text_section(key = "mastervolume", default = "100")]
+    pub master_volume: i32,
+    #[text_section(key = "wavvolume", default = "100")]
+    pub wav_volume: i32,
+    #[text_section(key = "bgmvolume", default = "100")]
+    pub bgm_volume: i32,
+}
+
+impl SoundConfig {
+    pub fn write(&self, document: &mut TextFile) {
+        document.set_attribute("Sound", "Sound", &format_bool(self.enabled));
+        document.set_attribute("Sound", "MasterVolume", &self.master_volume.to_string());
+        document.set_attribute("Sound", "WavVolume", &self.wav_volume.to_string());
+        document.set_attribute("Sound", "BGMVolume", &self.bgm_volume.to_string());
+    }
+}
+
+#[derive(Clone, FromTextSection)]
+pub struct KeyBindings {
+    #[text_section(default = "-1")]
+    pub jump: i32,
+    #[text_section(default = "-1")]
+    pub crouch: i32,
+    #[text_section(default = "-1")]
+    pub left: i32,
+    #[text_section(default = "-1")]
+    pub right: i32,
+    #[text_section(default = "-1")]
+    pub a: i32,
+    #[text_section(default = "-1")]
+    pub b: i32,
+    #[text_section(default = "-1")]
+    pub c: i32,
+    #[text_section(default = "-1")]
+    pub x: i32,
+    #[text_section(default = "-1")]
+    pub y: i32,
+    #[text_section(default = "-1")]
+    pub z: i32,
+    #[text_section(default = "-1")]
+    pub start: i32,
+}
+
+impl KeyBindings {
+    pub fn write(&self, document: &mut TextFile, section_title: &str) {
+        document.set_attribute(section_title, "Jump", &self.jump.to_string());
+        document.set_attribute(section_title, "Crouch", &self.crouch.to_string());
+        document.set_attribute(section_title, "Left", &self.left.to_string());
+        document.set_attribute(section_title, "Right", &self.right.to_string());
+        document.set_attribute(section_title, "A", &self.a.to_string());
+        document.set_attribute(section_title, "B", &self.b.to_string());
+        document.set_attribute(section_title, "C", &self.c.to_string());
+        document.set_attribute(section_title, "X", &self.x.to_string());
+        document.set_attribute(section_title, "Y", &self.y.to_string());
+        document.set_attribute(section_title, "Z", &self.z.to_string());
+        document.set_attribute(section_title, "Start", &self.start.to_string());
+    }
+}
+
+#[derive(Clone, FromTextSection)]
+pub struct JoystickConfig {
+    #[text_section(key = "p1.use", default = "true")]
+    pub p1_use: bool,
+    #[text_section(key = "p2.use", default = "true")]
+    pub p2_use: bool,
+}
+
+impl JoystickConfig {
+    pub fn write(&self, document: &mut TextFile) {
+        document.set_attribute("Joystick", "P1.Use", &format_bool(self.p1_use));
+        document.set_attribute("Joystick", "P2.Use", &format_bool(self.p2_use));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use gdnative::core_types::Rid;
+
+    use crate::{core::configuration::Configuration, systems::visual_server::shader::Shader};
+
+    use super::*;
+
+    const CONFIG: &str = "; Game options
+[Options]
+Difficulty = 6   ; 1-8
+Life = 100
+Team.1VS2Life = 150
+Team.LoseOnKO = 1
+Unknown.Option = kept
+
+[P1 Keys]
+Jump = 273
+A = 97
+Start = 13
+";
+
+    fn configuration(text: &str) -> Configuration {
+        let document = TextFile::from_string("mugen.cfg".to_string(), text.to_string());
+
+        // An invalid rid is never freed, so no visual server is needed
+        Configuration::from_text_file(document, Arc::new(Shader { rid: Rid::new() })).unwrap()
+    }
+
+    #[test]
+    fn reads_typed_values_and_defaults() {
+        let configuration = configuration(CONFIG);
+
+        assert_eq!(configuration.options.difficulty, 6);
+        assert_eq!(configuration.options.team_1vs2_life, 150);
+        assert!(configuration.options.team_lose_on_ko);
+        assert_eq!(configuration.options.time, 99);
+        assert_eq!((configuration.p1_keys.jump, configuration.p1_keys.a, configuration.p1_keys.start), (273, 97, 13));
+        assert_eq!(configuration.p1_keys.b, -1);
+        assert_eq!(configuration.p2_keys.jump, -1);
+        assert_eq!((configuration.video.width, configuration.video.height), (1280, 720));
+        assert_eq!(configuration.rules.game_type, "VS");
+        assert_eq!(configuration.config.language, "en");
+        assert!(configuration.sound.enabled);
+        assert!(configuration.joystick.p1_use);
+    }
+
+    #[test]
+    fn writing_keeps_comments_and_unknown_keys() {
+        let mut loaded = configuration(CONFIG);
+
+        loaded.options.difficulty = 8;
+        loaded.p1_keys.a = 122;
+        loaded.video.fullscreen = true;
+
+        let text = loaded.to_text_file().write();
+
+        assert!(text.starts_with("; Game options\n[Options]\nDifficulty = 8   ; 1-8\n"));
+        assert!(text.contains("Unknown.Option = kept"));
+        assert!(text.contains("A = 122"));
+
+        let reloaded = configuration(&text);
+
+        assert_eq!(reloaded.options.difficulty, 8);
+        assert_eq!(reloaded.options.team_1vs2_life, 150);
+        assert_eq!(reloaded.p1_keys.a, 122);
+        assert_eq!(reloaded.p1_keys.jump, 273);
+        assert!(reloaded.video.fullscreen);
+        assert_eq!(reloaded.video.width, 1280);
+    }
+}
@@ -31,7 +31,7 @@ impl Display for SoundId {
 impl ParseAttributeValue for SoundId {
     fn parse_attribute_value(value: AttributeValue) -> Result<SoundId, DataError> {
         let pieces  = value.split_values();
-        let error = DataError::new(format!("Invalid sound id format: {}", value.to_string()));
+        let error = DataError::invalid_attribute(format!("Invalid sound id format: {}", value.to_string()));
 
         if pieces.len() == 2 {
             let x = pieces[0].parse::<i16>().map_err(|_| error.clone())?;
@@ -10,6 +10,7 @@ pub struct SpriteId {
 
 impl SpriteId {
     pub const SMALL_PORTRAIT: SpriteId = SpriteId { group: 9000, image: 0 };
+    pub const BIG_PORTRAIT: SpriteId = SpriteId { group: 9000, image: 1 };
 
     pub fn new(group: i16, image: i16) -> Self {
         SpriteId { group: group, image: image }
@@ -27,7 +28,7 @@ impl From<&SpriteId> for String {
 impl ParseAttributeValue for SpriteId {
     fn parse_attribute_value(value: AttributeValue) -> Result<SpriteId, DataError> {
         let pieces  = value.split_values();
-        let error = DataError::new(format!("Invalid sprite id format: {}", value.to_string()));
+        let error = DataError::invalid_attribute(format!("Invalid sprite id format: {}", value.to_string()));
 
         if pieces.len() == 2 {
             let x = pieces[0].parse::<i16>().map_err(|_| error.clone())?;
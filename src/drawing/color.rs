@@ -1,6 +1,6 @@
 use gdnative::core_types::Color as GodotColor;
 
-use crate::core::error::DataError;
+use crate::core::{attribute_value::{AttributeValue, ParseAttributeValue}, error::DataError};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
 pub struct Color {
@@ -10,18 +10,22 @@ pub struct Color {
 }
 
 impl Color {
-    pub fn parse(values: &[String]) -> Result<Self, DataError> {
-        let error = DataError::new(format!("Invalid color: {:?}", values));
+    pub fn new(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b }
+    }
 
-        if values.len() != 3 {
-            return Err(error);
-        }
+    pub fn parse(values: &[String]) -> Result<Self, DataError> {
+        Color::parse_attribute_value(AttributeValue::new(&values.join(",")))
+    }
+}
 
-        let r = values[0].parse::<u8>().map_err(|_| error.clone())?;
-        let g = values[1].parse::<u8>().map_err(|_| error.clone())?;
-        let b = values[2].parse::<u8>().map_err(|_| error.clone())?;
+impl ParseAttributeValue for Color {
+    fn parse_attribute_value(value: AttributeValue) -> Result<Color, DataError> {
+        let tuple = value.parse_tuple::<f32>(3)
+            .map_err(|_| DataError::invalid_attribute(format!("Invalid color: {}", value.to_string())))?;
+        let component = |index: usize| tuple.get_or(index, 0.0).max(0.0).min(255.0) as u8;
 
-        Ok(Color { r, g, b })
+        Ok(Color::new(component(0), component(1), component(2)))
     }
 }
 
@@ -1,10 +1,10 @@
 use std::{collections::HashMap, sync::Arc};
 
-use gdnative::{api::{file::File, visual_server::TextureFlags}, core_types::{Point2, Vector2, Size2, Rect2}};
+use gdnative::core_types::{Point2, Vector2, Size2, Rect2};
 
-use crate::{core::{error::DataError, attribute_value::{ParseAttributeValue, AttributeValue}}, systems::visual_server::texture::Texture, io::text_file::TextFile};
+use crate::{core::{error::DataError, attribute_value::{ParseAttributeValue, AttributeValue}}, io::{text_encoding, text_file::TextFile}};
 
-use super::sff::{data::{DataReader, BufferReader, FileReader}, pcx::read_pcx};
+use super::sff::{data::{DataReader, BufferReader, FileReader}, image::RawImage, pcx::read_pcx};
 
 #[allow(dead_code)]
 pub struct FileHeader {
@@ -21,19 +21,19 @@ pub struct FileHeader {
 }
 
 impl FileHeader {
-    pub fn read(reader: &mut dyn DataReader) -> FileHeader {
-        FileHeader {
-            signature: reader.get_text(12),
-            verlo3: reader.get_u8(),
-            verlo2: reader.get_u8(),
-            verlo1: reader.get_u8(),
-            verhi: reader.get_u8(),
-            pcx_offset: reader.get_u32(),
-            pcx_size: reader.get_u32(),
-            text_offset: reader.get_u32(),
-            text_size: reader.get_u32(),
-            unused: reader.get_buffer(40),
-        }
+    pub fn read(reader: &mut dyn DataReader) -> Result<FileHeader, DataError> {
+        Ok(FileHeader {
+            signature: reader.get_text(12)?,
+            verlo3: reader.get_u8()?,
+            verlo2: reader.get_u8()?,
+            verlo1: reader.get_u8()?,
+            verhi: reader.get_u8()?,
+            pcx_offset: reader.get_u32()?,
+            pcx_size: reader.get_u32()?,
+            text_offset: reader.get_u32()?,
+            text_size: reader.get_u32()?,
+            unused: reader.get_buffer(40)?,
+        })
     }
 }
 
@@ -70,52 +70,40 @@ pub struct FntFile {
     pub spacing: Vector2,
     pub font_type: FntType,
     pub char_map: HashMap<char, CharData>,
-    pub textures: Vec<Arc<Texture>>,
+    pub image: Arc<RawImage>,
 }
 
 pub fn read_fnt_file(path: &str) -> Result<FntFile, DataError> {
-    let file = File::new();
-    let open_result = file.open(path.to_string(), File::READ);
-
-    if let Err(detail) = open_result {
-        return Result::Err(DataError::new(format!(
-            "Error opening fnt file: {}",
-            detail
-        )));
-    }
-
-    let mut reader = FileReader::new(&file);
-    let head = FileHeader::read(&mut reader);
+    let mut reader = FileReader::open(path)?;
+    let head = FileHeader::read(&mut reader).map_err(|error| error.with_path(path))?;
 
     if head.signature != "ElecbyteFnt" {
-        file.close();
-        return Result::Err(DataError::new(format!(
+        return Result::Err(DataError::decode_failure(format!(
             "Fnt invalid signature: {}",
             head.signature
-        )));
+        )).with_path(path));
     }
 
-    file.seek(head.text_offset as i64);
-    let text = reader.get_text(head.text_size as usize);
+    reader.seek(head.text_offset as usize);
+    let text_buffer = reader.get_buffer(head.text_size as usize).map_err(|error| error.with_path(path))?;
+    let text_length = text_buffer.iter().position(|code| *code == 0).unwrap_or(text_buffer.len());
+    let text = text_encoding::decode_text_file(path, &text_buffer[..text_length]);
 
-    file.seek(head.pcx_offset as i64);
-    let pcx_arr = reader.get_buffer(head.pcx_size as usize);
+    reader.seek(head.pcx_offset as usize);
+    let pcx_arr = reader.get_buffer(head.pcx_size as usize).map_err(|error| error.with_path(path))?;
     let mut pcx_arr_reader = BufferReader::new(&pcx_arr);
     let image_result = read_pcx(&mut pcx_arr_reader);
 
     match image_result {
         Ok(image) => {
-            let image = image.borrow().create_image();
-
             parse_fnt_file(
                 path.to_string(),
                 text,
-                Texture::allocate(image, TextureFlags(0))
+                Arc::new(image.borrow().clone())
             )
         }
         Err(message) => {
-            file.close();
-            Result::Err(DataError::new(message.to_string()))
+            Result::Err(DataError::decode_failure(message.to_string()).with_path(path))
         }
     }
 }
@@ -123,7 +111,7 @@ pub fn read_fnt_file(path: &str) -> Result<FntFile, DataError> {
 fn parse_fnt_file(
     path: String,
     text: String,
-    texture: Arc<Texture>
+    image: Arc<RawImage>
 ) -> Result<FntFile, DataError> {
     let text_file = TextFile::from_string(path, text);
     let def_section = text_file.get_section("def")?;
@@ -134,19 +122,17 @@ fn parse_fnt_file(
     let spacing: Vector2 = def_section.get_attribute_or_default("spacing");
     let font_type: FntType = def_section.get_attribute_or_default("type");
     let mut char_map: HashMap<char, CharData> = HashMap::new();
-    let textures = vec![texture];
 
     for (iterator, line) in map_section.lines.iter().enumerate() {
         let pieces = line.split_with_separator(' ', false);
-        let character = parse_character(pieces[0].to_string())?;
+        let invalid_line = || DataError::decode_failure(format!("Invalid char line: {}", line.to_string()));
+        let character = parse_character(pieces.first().ok_or_else(invalid_line)?.to_string())?;
         let mut char_start_x = iterator as f32 * size.width;
         let mut char_width = size.width;
 
         if font_type == FntType::Variable {
-            char_start_x = pieces[1].parse()
-                .map_err(|_| DataError::new(format!("Invalid char line: {}", line.to_string())))?;
-            char_width = pieces[2].parse()
-                .map_err(|_| DataError::new(format!("Invalid char line: {}", line.to_string())))?;
+            char_start_x = pieces.get(1).ok_or_else(invalid_line)?.parse().map_err(|_| invalid_line())?;
+            char_width = pieces.get(2).ok_or_else(invalid_line)?.parse().map_err(|_| invalid_line())?;
         }
 
         // TODO: Review char_width usage
@@ -166,7 +152,7 @@ fn parse_fnt_file(
         spacing,
         font_type,
         char_map,
-        textures,
+        image,
     })
 }
 
@@ -176,13 +162,73 @@ fn parse_character(text: String) -> Result<char, DataError> {
             text.to_lowercase().trim_start_matches("0x"),
             16
         ).map_err(
-            |_| DataError::new(format!("Invalid char format: {}", text))
+            |_| DataError::decode_failure(format!("Invalid char format: {}", text))
         )?;
 
         return char::from_u32(num)
-            .ok_or(DataError::new(format!("Invalid char code: {}", text)))
+            .ok_or(DataError::decode_failure(format!("Invalid char code: {}", text)))
     }
 
     text.chars().next()
-        .ok_or(DataError::new(format!("Invalid char: {}", text)))
+        .ok_or(DataError::decode_failure(format!("Invalid char: {}", text)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::drawing::sff::image::{Palette, RawColor};
+    use crate::drawing::sff::pcx::encode_pcx_8;
+    use crate::io::file_system;
+    use crate::io::memory_file_system::test_mount;
+
+    use super::*;
+
+    const HEADER_SIZE: usize = 64;
+
+    fn fnt(text: &str) -> Vec<u8> {
+        let palette = Palette::from_colors((0..256).map(|i| RawColor::new(i as u8, 0, 0, 255)).collect());
+        let image = RawImage::indexed(8, 2, (0..16).collect(), Arc::new(palette.clone()));
+        let pcx = encode_pcx_8(&image, Some(&palette));
+        let mut data = b"ElecbyteFnt\0".to_vec();
+
+        data.extend_from_slice(&[0, 0, 0, 1]);
+        data.extend_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        data.extend_from_slice(&(pcx.len() as u32).to_le_bytes());
+        data.extend_from_slice(&((HEADER_SIZE + pcx.len()) as u32).to_le_bytes());
+        data.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        data.resize(HEADER_SIZE, 0);
+        data.extend_from_slice(&pcx);
+        data.extend_from_slice(text.as_bytes());
+        data
+    }
+
+    fn read(name: &str, data: &[u8]) -> Result<FntFile, DataError> {
+        let path = format!("{}/{}", test_mount("fnt_parser_tests"), name);
+        file_system::write_file(&path, data).unwrap();
+        read_fnt_file(&path)
+    }
+
+    #[test]
+    fn reads_fixed_fonts() {
+        let font = read("fixed.fnt", &fnt("[Def]\nsize = 4,2\ntype = fixed\n[Map]\nA\n0x42\n")).unwrap();
+
+        assert_eq!(font.char_map.len(), 2);
+        assert_eq!(font.char_map[&'B'].rect.origin.x, 4.0);
+        assert_eq!((font.image.w, font.image.h), (8, 2));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let data = fnt("[Def]\nsize = 4,2\n[Map]\nA\n");
+
+        assert!(read("truncated_text.fnt", &data[..data.len() - 4]).is_err());
+        assert!(read("truncated_pcx.fnt", &data[..HEADER_SIZE + 100]).is_err());
+        assert!(read("truncated_header.fnt", &data[..HEADER_SIZE - 8]).is_err());
+    }
+
+    #[test]
+    fn rejects_short_variable_lines() {
+        let data = fnt("[Def]\nsize = 4,2\ntype = variable\n[Map]\nA 0\n");
+
+        assert!(read("short_line.fnt", &data).is_err());
+    }
 }
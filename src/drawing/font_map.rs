@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use bevy_ecs::prelude::{Entity, Commands, Mut};
 use gdnative::core_types::{Vector2, Transform2D, Color as GodotColor};
 
-use crate::{systems::visual_server::{text::{text_plugin::TextBundle, common::{Text, TextStyle, TextAlignment, HorizontalAlign}}, canvas_item::ClipRect}, core::constants::TEXT_Z_INDEX};
+use crate::{systems::visual_server::{text::{text_plugin::TextBundle, common::{Text, TextStyle, TextAlignment, HorizontalAlign}}, canvas_item::ClipRect, palette::PaletteHandle}, core::constants::TEXT_Z_INDEX};
 
 use super::{mugen_font::MugenFont, print_data::PrintData};
 
@@ -29,7 +29,7 @@ impl FontMap {
         if font_option.is_none() {
             return None;
         }
-        let font = font_option.unwrap().get_color_bank(print_data.colorindex as usize);
+        let font = font_option.unwrap();
         let color = match print_data.color {
             Some(color) => GodotColor::from(color),
             None => GodotColor::rgba(1.0, 1.0, 1.0, 1.0),
@@ -38,7 +38,7 @@ impl FontMap {
             text: Text::new(
                 text,
                 TextStyle {
-                    font,
+                    font: font.get_font(),
                     font_size: 1,
                     color,
                     ..Default::default()
@@ -51,6 +51,7 @@ impl FontMap {
             z_index: TEXT_Z_INDEX.into(),
             clip_rect,
             transform: Transform2D::translation(location.x, location.y),
+            palette: font.get_color_bank(print_data.colorindex as usize),
             ..Default::default()
         }).id();
 
@@ -60,15 +61,17 @@ impl FontMap {
     pub fn update_font(
         &self,
         print_data: PrintData,
-        mut text: Mut<Text>
+        mut text: Mut<Text>,
+        mut palette: Mut<Option<PaletteHandle>>
     ) {
         let font_option = self.map.get(&(print_data.index as usize));
         if font_option.is_none() {
             return;
         }
-        let font = font_option.unwrap().get_color_bank(print_data.colorindex as usize);
+        let font = font_option.unwrap();
 
-        text.style.font = font;
+        text.style.font = font.get_font();
+        *palette = font.get_color_bank(print_data.colorindex as usize);
         text.alignment = TextAlignment {
             horizontal: print_data.justification.into(),
             ..Default::default()
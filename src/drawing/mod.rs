@@ -1,7 +1,11 @@
 pub mod font_map;
 pub mod sprite_system;
 pub mod sff;
+pub mod sprite_cache;
 pub mod sprite_file;
+pub mod palette_manager;
+pub mod palfx;
+pub mod texture_atlas;
 pub mod print_data;
 pub mod color;
 pub mod fnt_parser;
@@ -1,30 +1,47 @@
+use std::sync::Arc;
+
 use gdnative::{core_types::{Rect2, Size2, Vector2, Point2}, api::visual_server::TextureFlags, godot_warn};
 
-use crate::{core::error::DataError, io::file_system, systems::visual_server::{text::{font::Font, common::{FontSpacing, GlyphSpacing}, bitmap_font::BitmapFont, font_loader::load_dynamic_font}, texture::Texture}};
+use crate::{core::error::DataError, io::file_system, systems::visual_server::{text::{font::Font, common::{FontSpacing, GlyphSpacing}, bitmap_font::BitmapFont, font_loader::load_dynamic_font}, palette::PaletteHandle, shader::Shader}};
 
-use super::{fnt_parser::read_fnt_file, sprite_system::SpriteSystem};
+use super::{fnt_parser::read_fnt_file, sprite_system::SpriteSystem, texture_atlas::TextureAtlasBuilder};
 
+// Bitmap fonts keep their glyphs as palette indices, color banks are palettes over the same glyphs
 #[derive(Clone)]
 pub struct MugenFont {
-    font_banks: Vec<Font>,
+    font: Font,
+    color_banks: Vec<PaletteHandle>,
     pub size: i32,
 }
 
 impl MugenFont {
-    pub fn get_color_bank(&self, color_bank: usize) -> Font {
-        if color_bank >= self.font_banks.len() {
+    pub fn get_font(&self) -> Font {
+        self.font.clone()
+    }
+
+    pub fn get_color_bank(&self, color_bank: usize) -> Option<PaletteHandle> {
+        if self.color_banks.is_empty() {
+            return None;
+        }
+
+        if color_bank >= self.color_banks.len() {
             gdnative::godot_warn!("Color bank not found: {}", color_bank);
-            return self.font_banks[0].clone()
+            return Some(self.color_banks[0].clone())
         }
 
-        self.font_banks[color_bank].clone()
+        Some(self.color_banks[color_bank].clone())
     }
 
-    pub fn load_font_v1(path: &str) -> Result<MugenFont, DataError> {
+    pub fn load_font_v1(path: &str, shader: Arc<Shader>) -> Result<MugenFont, DataError> {
         let fnt_file = read_fnt_file(path)?;
+        let color_banks = if fnt_file.image.is_indexed() {
+            vec![PaletteHandle::new(shader, fnt_file.image.color_table.clone())]
+        } else {
+            Vec::new()
+        };
 
         let mut bitmap_font = BitmapFont::new(
-            fnt_file.textures,
+            vec![fnt_file.image.create_monochromatic_texture(TextureFlags(0))],
             FontSpacing {
                 line_gap: fnt_file.size.height + fnt_file.spacing.y,
                 ..Default::default()
@@ -56,14 +73,15 @@ impl MugenFont {
         );
 
         return Ok(MugenFont {
-            font_banks: vec![Font::BitmapFont {
+            font: Font::BitmapFont {
                 font: bitmap_font,
-            }],
+            },
+            color_banks,
             size: 0,
         })
     }
 
-    pub fn load_font_v2(path: &str, sprite_system: &SpriteSystem) -> Result<MugenFont, DataError> {
+    pub fn load_font_v2(path: &str, sprite_system: &SpriteSystem, shader: Arc<Shader>) -> Result<MugenFont, DataError> {
         let text_file = file_system::open_text_file(path)?;
         let def_section = text_file.get_section("def")?;
         let filename: String = def_section.get_attribute_or_fail("file")?;
@@ -73,7 +91,7 @@ impl MugenFont {
         let font_path = file_system::get_path_by_refferrer(&filename, path);
 
         if font_path.to_lowercase().ends_with(".sff") {
-            let base_bitmap_font = BitmapFont::new(
+            let mut bitmap_font = BitmapFont::new(
                 vec![],
                 FontSpacing {
                     line_gap: size.height + spacing.y,
@@ -83,46 +101,69 @@ impl MugenFont {
             let mut sprite_file = sprite_system.get_sprite_file(&font_path)?;
             let palettes = sprite_system.load_palettes(&font_path)?;
             let images = sprite_file.get_group(0)?;
-            let mut font_banks: Vec<Font> = Vec::new();
-
-            for palette in palettes.iter() {
-                let mut bitmap_font = base_bitmap_font.clone();
-                let mut texture_id: usize = 0;
-                for sff_item in images.iter() {
-                    let character = char::from_u32(sff_item.imageno as u32)
-                        .ok_or(DataError::new(format!("Invalid char code: {}", sff_item.imageno)))?;
-                    let image = &sff_item.image;
-                    bitmap_font.add_texture(Texture::allocate(image.create_image_with_palette(&palette), TextureFlags::FLAGS_DEFAULT));
-                    bitmap_font.add_character(
-                        character,
-                        texture_id,
-                        Rect2::new(
-                            Point2::new(0.0, 0.0),
-                            Size2::new(image.w as f32, image.h as f32)
-                        ),
-                        Point2::new(offset.x, offset.y - size.height),
-                        GlyphSpacing {
-                            h_advance: image.w as f32 + spacing.x,
-                            ..Default::default()
-                        }
-                    );
-                    texture_id += 1;
+            let mut atlas_builder = TextureAtlasBuilder::new();
+            let mut characters: Vec<(char, usize)> = Vec::new();
+
+            // Color banks only apply to indexed glyphs, true color glyphs are drawn as they are
+            let indexed = images.iter().all(|sff_item| sff_item.image.is_indexed());
+
+            for sff_item in images.iter() {
+                let character = char::from_u32(sff_item.imageno as u32)
+                    .ok_or(DataError::new(format!("Invalid char code: {}", sff_item.imageno)))?;
+                let image = &sff_item.image;
+
+                if indexed {
+                    atlas_builder.add_image(character, image);
+                } else {
+                    atlas_builder.add_baked_image(character, image, &image.color_table);
                 }
+
+                characters.push((character, image.w));
+            }
+
+            // Filtering and mipmaps would bleed neighbour glyphs into each other
+            let atlas = atlas_builder.build(TextureFlags(0));
+
+            for texture in atlas.textures.iter() {
+                bitmap_font.add_texture(texture.clone());
+            }
+
+            for (character, width) in characters {
+                let region = atlas.get_region(&character).expect("Glyph not packed");
+
                 bitmap_font.add_character(
-                    ' ',
-                    0,
-                    Rect2::default(),
-                    Point2::new(0.0, 0.0),
+                    character,
+                    region.page,
+                    region.rect,
+                    Point2::new(offset.x, offset.y - size.height),
                     GlyphSpacing {
-                        h_advance: size.width + spacing.x,
+                        h_advance: width as f32 + spacing.x,
                         ..Default::default()
                     }
                 );
-                font_banks.push(Font::BitmapFont { font: bitmap_font });
             }
+            bitmap_font.add_character(
+                ' ',
+                0,
+                Rect2::default(),
+                Point2::new(0.0, 0.0),
+                GlyphSpacing {
+                    h_advance: size.width + spacing.x,
+                    ..Default::default()
+                }
+            );
+
+            let color_banks = if indexed {
+                palettes.iter()
+                    .map(|palette| PaletteHandle::new(shader.clone(), palette.clone()))
+                    .collect()
+            } else {
+                Vec::new()
+            };
 
             return Ok(MugenFont {
-                font_banks,
+                font: Font::BitmapFont { font: bitmap_font },
+                color_banks,
                 size: 0,
             })
         }
@@ -134,7 +175,8 @@ impl MugenFont {
         match font_result {
             Ok(font) => {
                 return Ok(MugenFont {
-                    font_banks: vec![font],
+                    font,
+                    color_banks: Vec::new(),
                     size: size.height as i32,
                 })
             },
@@ -144,7 +186,8 @@ impl MugenFont {
                 godot_warn!("True type font not found: {}, using fallback", file_system::get_name(&font_path));
 
                 Ok(MugenFont {
-                    font_banks: vec![font],
+                    font,
+                    color_banks: Vec::new(),
                     size: size.height as i32,
                 })
             }
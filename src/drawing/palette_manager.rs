@@ -0,0 +1,286 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use gdnative::api::visual_server::TextureFlags;
+
+use crate::core::{constants::MAX_CHARACTER_PALETTES, diagnostics, error::DataError};
+use crate::profiles::player_profile::PlayerProfile;
+use crate::systems::visual_server::{palette::PaletteHandle, shader::Shader, texture::Texture};
+
+use super::sff::{image::Palette, sff_common::SffData, sff_parser};
+
+// Palettes a character can be drawn with, numbered like pal1..pal12 in its definition file
+pub struct CharacterPalettes {
+    pub definition_path: String,
+    palettes: BTreeMap<usize, Arc<Palette>>,
+    order: Vec<usize>,
+    remapped: Vec<i16>,
+}
+
+impl CharacterPalettes {
+    pub fn load(profile: &PlayerProfile) -> Result<Self, DataError> {
+        let sprite_file = profile.sprite_file.read().expect("Could not lock sprite file");
+        let sff_palettes = sprite_file.palettes();
+        let mut palettes: BTreeMap<usize, Arc<Palette>> = BTreeMap::new();
+        let mut remapped: Vec<i16> = Vec::new();
+
+        if sprite_file.has_palettes() {
+            // SFFv2 keeps the character palettes in group 1, palette 1,1 being pal1
+            for number in 1..=MAX_CHARACTER_PALETTES {
+                if let Some(position) = sprite_file.find_palette(1, number as i16) {
+                    palettes.insert(number, sff_palettes[position].clone());
+                }
+            }
+
+            // Sprites drawn with 1,1 (or a palette linked to it) follow the selected palette
+            let main_palette = sprite_file.find_palette(1, 1).unwrap_or(0);
+
+            if let Some(main) = sff_palettes.get(main_palette) {
+                remapped = sff_palettes.iter()
+                    .enumerate()
+                    .filter(|(_, palette)| Arc::ptr_eq(*palette, main))
+                    .map(|(position, _)| position as i16)
+                    .collect();
+            }
+        } else {
+            // SFFv1 swaps the shared palette, sprites with their own palette keep it
+            if let Some(main) = sff_palettes.first() {
+                palettes.insert(1, main.clone());
+            }
+
+            remapped.push(0);
+        }
+
+        for (number, path) in profile.palette_files.iter() {
+            if *number == 0 || *number > MAX_CHARACTER_PALETTES || path.is_empty() {
+                continue;
+            }
+
+            match sff_parser::read_palette(path) {
+                Ok(palette) => {
+                    palettes.insert(*number, palette);
+                },
+                Err(error) => {
+                    diagnostics::warn(error.with_path(path));
+                }
+            }
+        }
+
+        let mut order: Vec<usize> = Vec::new();
+
+        for number in profile.palette_order.iter() {
+            if palettes.contains_key(number) && !order.contains(number) {
+                order.push(*number);
+            }
+        }
+
+        Ok(CharacterPalettes {
+            definition_path: profile.definition_path.clone(),
+            palettes,
+            order,
+            remapped,
+        })
+    }
+
+    pub fn len(&self) -> usize { self.palettes.len() }
+
+    pub fn is_empty(&self) -> bool { self.palettes.is_empty() }
+
+    pub fn has_palette(&self, number: usize) -> bool { self.palettes.contains_key(&number) }
+
+    pub fn numbers(&self) -> Vec<usize> { self.palettes.keys().copied().collect() }
+
+    pub fn get(&self, number: usize) -> Option<Arc<Palette>> {
+        self.palettes.get(&number).cloned()
+    }
+
+    pub fn default_palette(&self) -> usize {
+        self.order.first()
+            .or_else(|| self.palettes.keys().next())
+            .copied()
+            .unwrap_or(1)
+    }
+
+    // Palette to use instead of the requested one, skipping the ones already taken.
+    // The order comes from pal.defaults first, then every other palette by number.
+    pub fn alternate(&self, number: usize, taken: &[usize]) -> Option<usize> {
+        let mut candidates = self.order.clone();
+        candidates.extend(self.palettes.keys().filter(|key| !self.order.contains(*key)));
+
+        let start = candidates.iter().position(|candidate| *candidate == number).map_or(0, |position| position + 1);
+
+        candidates[start..].iter()
+            .chain(candidates[..start].iter())
+            .find(|candidate| !taken.contains(*candidate))
+            .copied()
+    }
+
+    pub fn is_remapped(&self, sff_data: &SffData) -> bool {
+        self.remapped.contains(&sff_data.palindex)
+    }
+
+    pub fn select_palette(&self, sff_data: &SffData, number: usize) -> Arc<Palette> {
+        match self.get(number) {
+            Some(palette) if self.is_remapped(sff_data) && !palette.is_empty() => palette,
+            _ => sff_data.image.color_table.clone(),
+        }
+    }
+
+    pub fn create_texture(&self, sff_data: &SffData, number: usize, flags: TextureFlags) -> Arc<Texture> {
+        let palette = self.select_palette(sff_data, number);
+
+        Texture::allocate(sff_data.image.create_image_with_palette(&palette), flags)
+    }
+
+    pub fn create_handle(&self, shader: Arc<Shader>, sff_data: &SffData, number: usize) -> PaletteHandle {
+        PaletteHandle::new(shader, self.select_palette(sff_data, number))
+    }
+
+    pub fn apply(&self, handle: &PaletteHandle, sff_data: &SffData, number: usize) {
+        handle.set_palette(self.select_palette(sff_data, number));
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct PaletteSelection {
+    pub definition_path: String,
+    pub palette: usize,
+}
+
+// Palettes chosen by each player, so two players using the same character look different
+#[derive(Default)]
+pub struct PaletteManager {
+    characters: HashMap<String, Arc<CharacterPalettes>>,
+    selections: BTreeMap<usize, PaletteSelection>,
+}
+
+impl PaletteManager {
+    pub fn load(&mut self, profile: &PlayerProfile) -> Result<Arc<CharacterPalettes>, DataError> {
+        if let Some(palettes) = self.characters.get(&profile.definition_path) {
+            return Ok(palettes.clone());
+        }
+
+        let palettes = Arc::new(CharacterPalettes::load(profile)?);
+        self.characters.insert(profile.definition_path.clone(), palettes.clone());
+
+        Ok(palettes)
+    }
+
+    // Returns the palette number actually used by the player
+    pub fn select(&mut self, player: usize, profile: &PlayerProfile, palette: usize) -> Result<usize, DataError> {
+        let palettes = self.load(profile)?;
+
+        Ok(self.select_palette(player, &palettes, palette))
+    }
+
+    fn select_palette(&mut self, player: usize, palettes: &CharacterPalettes, palette: usize) -> usize {
+        let requested = if palettes.has_palette(palette) { palette } else { palettes.default_palette() };
+
+        let taken: Vec<usize> = self.selections.iter()
+            .filter(|(other, selection)| **other != player && selection.definition_path == palettes.definition_path)
+            .map(|(_, selection)| selection.palette)
+            .collect();
+
+        let number = if taken.contains(&requested) {
+            palettes.alternate(requested, &taken).unwrap_or(requested)
+        } else {
+            requested
+        };
+
+        self.selections.insert(player, PaletteSelection {
+            definition_path: palettes.definition_path.clone(),
+            palette: number,
+        });
+
+        number
+    }
+
+    pub fn get_selection(&self, player: usize) -> Option<&PaletteSelection> {
+        self.selections.get(&player)
+    }
+
+    pub fn get_palettes(&self, player: usize) -> Option<Arc<CharacterPalettes>> {
+        self.selections.get(&player)
+            .and_then(|selection| self.characters.get(&selection.definition_path))
+            .cloned()
+    }
+
+    pub fn deselect(&mut self, player: usize) {
+        self.selections.remove(&player);
+    }
+
+    pub fn clear(&mut self) {
+        self.selections.clear();
+        self.characters.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palettes(definition_path: &str, numbers: &[usize], order: &[usize]) -> CharacterPalettes {
+        CharacterPalettes {
+            definition_path: definition_path.to_string(),
+            palettes: numbers.iter().map(|number| (*number, Arc::new(Palette::new(256)))).collect(),
+            order: order.to_vec(),
+            remapped: vec![0],
+        }
+    }
+
+    #[test]
+    fn alternate_follows_pal_defaults_then_numbers() {
+        let palettes = palettes("kfm.def", &[1, 2, 3, 4, 5], &[3, 1]);
+
+        assert_eq!(palettes.alternate(3, &[3]), Some(1));
+        assert_eq!(palettes.alternate(1, &[1]), Some(2));
+        assert_eq!(palettes.alternate(1, &[1, 2]), Some(4));
+        assert_eq!(palettes.alternate(5, &[5]), Some(3));
+        assert_eq!(palettes.alternate(6, &[3]), Some(1));
+        assert_eq!(palettes.alternate(1, &[1, 2, 3, 4, 5]), None);
+    }
+
+    #[test]
+    fn default_palette_prefers_pal_defaults() {
+        assert_eq!(palettes("kfm.def", &[1, 2, 3], &[2]).default_palette(), 2);
+        assert_eq!(palettes("kfm.def", &[2, 3], &[]).default_palette(), 2);
+        assert_eq!(palettes("kfm.def", &[], &[]).default_palette(), 1);
+    }
+
+    #[test]
+    fn select_falls_back_to_the_default_palette() {
+        let mut manager = PaletteManager::default();
+        let kfm = palettes("kfm.def", &[1, 2, 3], &[2, 1]);
+
+        assert_eq!(manager.select_palette(0, &kfm, 3), 3);
+        assert_eq!(manager.select_palette(0, &kfm, 9), 2);
+        assert_eq!(manager.get_selection(0), Some(&PaletteSelection { definition_path: "kfm.def".to_string(), palette: 2 }));
+    }
+
+    #[test]
+    fn select_picks_an_alternate_for_the_same_character() {
+        let mut manager = PaletteManager::default();
+        let kfm = palettes("kfm.def", &[1, 2, 3], &[1, 2]);
+        let suave = palettes("suave.def", &[1, 2], &[1]);
+
+        assert_eq!(manager.select_palette(0, &kfm, 1), 1);
+        assert_eq!(manager.select_palette(1, &kfm, 1), 2);
+        assert_eq!(manager.select_palette(2, &suave, 1), 1);
+
+        // Selecting again only avoids the other players
+        assert_eq!(manager.select_palette(0, &kfm, 1), 1);
+
+        manager.deselect(1);
+        assert_eq!(manager.select_palette(0, &kfm, 2), 2);
+        assert_eq!(manager.select_palette(1, &kfm, 2), 3);
+    }
+
+    #[test]
+    fn select_keeps_the_palette_when_every_palette_is_taken() {
+        let mut manager = PaletteManager::default();
+        let kfm = palettes("kfm.def", &[1], &[1]);
+
+        assert_eq!(manager.select_palette(0, &kfm, 1), 1);
+        assert_eq!(manager.select_palette(1, &kfm, 1), 1);
+    }
+}
@@ -0,0 +1,365 @@
+use std::f64::consts::PI;
+use std::sync::{Arc, RwLock};
+
+use gdnative::core_types::{ToVariant, Vector3};
+
+use crate::systems::visual_server::material::Material;
+
+use super::sff::image::{Palette, RawColor};
+
+// MUGEN PalFX. add and sinadd are in color units, mul and color are scaled by 256.
+// Each component becomes (color + add) * mul / 256, after invertall and the color level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PalFx {
+    pub time: i32,
+    pub add: [i32; 3],
+    pub mul: [i32; 3],
+    pub sinadd: [i32; 3],
+    pub sinadd_period: i32,
+    pub invertall: bool,
+    pub color: i32,
+}
+
+impl Default for PalFx {
+    fn default() -> Self {
+        PalFx {
+            time: -1,
+            add: [0, 0, 0],
+            mul: [256, 256, 256],
+            sinadd: [0, 0, 0],
+            sinadd_period: 0,
+            invertall: false,
+            color: 256,
+        }
+    }
+}
+
+impl PalFx {
+    // A negative time lasts until the effect is replaced
+    pub fn is_active(&self, tick: i32) -> bool {
+        self.time < 0 || tick < self.time
+    }
+
+    pub fn state(&self, tick: i32) -> Option<PalFxState> {
+        if !self.is_active(tick) {
+            return None;
+        }
+
+        let mut add = self.add;
+
+        if self.sinadd_period != 0 {
+            let wave = (2.0 * PI * tick as f64 / self.sinadd_period as f64).sin();
+
+            for (add, amplitude) in add.iter_mut().zip(self.sinadd.iter()) {
+                *add += (*amplitude as f64 * wave).round() as i32;
+            }
+        }
+
+        Some(PalFxState {
+            add,
+            mul: self.mul,
+            color: self.color,
+            invertall: self.invertall,
+        })
+    }
+}
+
+// Moves from one effect to another over duration ticks, then holds the second one until it ends.
+// Used for fades like a flash that dies out or a background fading in from black.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PalFxRamp {
+    pub from: PalFx,
+    pub to: PalFx,
+    pub duration: i32,
+}
+
+impl PalFxRamp {
+    pub fn state(&self, tick: i32) -> Option<PalFxState> {
+        let to = self.to.state(tick)?;
+
+        if self.duration <= 0 || tick >= self.duration {
+            return Some(to);
+        }
+
+        let from = self.from.state(tick).unwrap_or_default();
+
+        Some(from.lerp(&to, tick.max(0), self.duration))
+    }
+}
+
+// The effect at a given tick, what is applied to colors and sent to the shader
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PalFxState {
+    pub add: [i32; 3],
+    pub mul: [i32; 3],
+    pub color: i32,
+    pub invertall: bool,
+}
+
+impl Default for PalFxState {
+    fn default() -> Self {
+        PalFxState {
+            add: [0, 0, 0],
+            mul: [256, 256, 256],
+            color: 256,
+            invertall: false,
+        }
+    }
+}
+
+// Uniforms of sprite.glsl, in the 0-1 range the shader works with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PalFxUniforms {
+    pub add: [f32; 3],
+    pub mul: [f32; 3],
+    pub color: f32,
+    pub invertall: bool,
+}
+
+fn lerp(from: i32, to: i32, tick: i32, duration: i32) -> i32 {
+    from + (to - from) * tick / duration
+}
+
+// Rounds like the shader output does, negative values are clamped anyway
+fn round_div(value: i64, divisor: i64) -> i64 {
+    if value <= 0 {
+        return 0;
+    }
+
+    (value + divisor / 2) / divisor
+}
+
+impl PalFxState {
+    pub fn is_identity(&self) -> bool {
+        *self == PalFxState::default()
+    }
+
+    pub fn lerp(&self, other: &PalFxState, tick: i32, duration: i32) -> PalFxState {
+        let mut add = [0; 3];
+        let mut mul = [0; 3];
+
+        for i in 0..3 {
+            add[i] = lerp(self.add[i], other.add[i], tick, duration);
+            mul[i] = lerp(self.mul[i], other.mul[i], tick, duration);
+        }
+
+        PalFxState {
+            add,
+            mul,
+            color: lerp(self.color, other.color, tick, duration),
+            invertall: if tick * 2 < duration { self.invertall } else { other.invertall },
+        }
+    }
+
+    pub fn apply_color(&self, color: RawColor) -> RawColor {
+        let mut rgb = [color.r as i64, color.g as i64, color.b as i64];
+
+        if self.invertall {
+            for component in rgb.iter_mut() {
+                *component = 255 - *component;
+            }
+        }
+
+        // Scaled by 3 * 256 so the gray level and the color level don't truncate
+        let gray = rgb[0] + rgb[1] + rgb[2];
+        let mut result = [0u8; 3];
+
+        for i in 0..3 {
+            let level = gray * 256 + (rgb[i] * 3 - gray) * self.color as i64;
+            let value = (level + self.add[i] as i64 * 768) * self.mul[i] as i64;
+
+            result[i] = round_div(value, 768 * 256).min(255) as u8;
+        }
+
+        RawColor::new(result[0], result[1], result[2], color.a)
+    }
+
+    pub fn apply(&self, palette: &Palette) -> Palette {
+        Palette::from_colors(palette.colors.iter().map(|color| self.apply_color(*color)).collect())
+    }
+
+    pub fn uniforms(&self) -> PalFxUniforms {
+        PalFxUniforms {
+            add: [self.add[0] as f32 / 255.0, self.add[1] as f32 / 255.0, self.add[2] as f32 / 255.0],
+            mul: [self.mul[0] as f32 / 256.0, self.mul[1] as f32 / 256.0, self.mul[2] as f32 / 256.0],
+            color: self.color as f32 / 256.0,
+            invertall: self.invertall,
+        }
+    }
+
+    pub fn configure_material(&self, material: &Arc<RwLock<Material>>) {
+        let uniforms = self.uniforms();
+        let mut material_write = material.write().expect("Could not lock material");
+
+        material_write.set_shader_param("palfx_add", Vector3::new(uniforms.add[0], uniforms.add[1], uniforms.add[2]).to_variant());
+        material_write.set_shader_param("palfx_mul", Vector3::new(uniforms.mul[0], uniforms.mul[1], uniforms.mul[2]).to_variant());
+        material_write.set_shader_param("palfx_color", uniforms.color.to_variant());
+        material_write.set_shader_param("palfx_invertall", (uniforms.invertall as i32).to_variant());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(color: RawColor) -> (u8, u8, u8, u8) {
+        (color.r, color.g, color.b, color.a)
+    }
+
+    fn sample() -> RawColor {
+        RawColor::new(100, 150, 200, 255)
+    }
+
+    fn state(add: [i32; 3], mul: [i32; 3], color: i32, invertall: bool) -> PalFxState {
+        PalFxState { add, mul, color, invertall }
+    }
+
+    // Same steps as the palfx block of sprite.glsl
+    fn shader(uniforms: &PalFxUniforms, color: RawColor) -> [u8; 3] {
+        let mut rgb = [color.r as f32 / 255.0, color.g as f32 / 255.0, color.b as f32 / 255.0];
+
+        if uniforms.invertall {
+            for component in rgb.iter_mut() {
+                *component = 1.0 - *component;
+            }
+        }
+
+        let gray = (rgb[0] + rgb[1] + rgb[2]) / 3.0;
+        let mut result = [0u8; 3];
+
+        for i in 0..3 {
+            let level = gray + (rgb[i] - gray) * uniforms.color;
+            let value = ((level + uniforms.add[i]) * uniforms.mul[i]).clamp(0.0, 1.0);
+
+            result[i] = (value * 255.0).round() as u8;
+        }
+
+        result
+    }
+
+    #[test]
+    fn identity_keeps_colors() {
+        let identity = PalFxState::default();
+
+        assert!(identity.is_identity());
+        assert_eq!(rgb(identity.apply_color(sample())), (100, 150, 200, 255));
+        assert_eq!(rgb(identity.apply_color(RawColor::new(0, 255, 1, 0))), (0, 255, 1, 0));
+    }
+
+    #[test]
+    fn add_is_clamped() {
+        let effect = state([30, -160, 100], [256, 256, 256], 256, false);
+
+        assert_eq!(rgb(effect.apply_color(sample())), (130, 0, 255, 255));
+    }
+
+    #[test]
+    fn mul_applies_after_add() {
+        assert_eq!(rgb(state([0, 0, 0], [128, 128, 128], 256, false).apply_color(sample())), (50, 75, 100, 255));
+        assert_eq!(rgb(state([56, 56, 56], [128, 128, 128], 256, false).apply_color(sample())), (78, 103, 128, 255));
+        assert_eq!(rgb(state([0, 0, 0], [512, 256, 0], 256, false).apply_color(sample())), (200, 150, 0, 255));
+    }
+
+    #[test]
+    fn color_level_desaturates() {
+        assert_eq!(rgb(state([0, 0, 0], [256, 256, 256], 0, false).apply_color(sample())), (150, 150, 150, 255));
+        assert_eq!(rgb(state([0, 0, 0], [256, 256, 256], 128, false).apply_color(sample())), (125, 150, 175, 255));
+    }
+
+    #[test]
+    fn invertall_applies_before_add() {
+        assert_eq!(rgb(state([0, 0, 0], [256, 256, 256], 256, true).apply_color(sample())), (155, 105, 55, 255));
+        assert_eq!(rgb(state([10, 10, 10], [256, 256, 256], 256, true).apply_color(sample())), (165, 115, 65, 255));
+    }
+
+    #[test]
+    fn alpha_is_kept() {
+        let effect = state([255, 255, 255], [256, 256, 256], 256, false);
+
+        assert_eq!(rgb(effect.apply_color(RawColor::new(0, 0, 0, 0))), (255, 255, 255, 0));
+    }
+
+    #[test]
+    fn sinadd_follows_the_period() {
+        let effect = PalFx {
+            sinadd: [100, 50, -100],
+            sinadd_period: 20,
+            ..PalFx::default()
+        };
+
+        assert_eq!(effect.state(0).unwrap().add, [0, 0, 0]);
+        assert_eq!(effect.state(5).unwrap().add, [100, 50, -100]);
+        assert_eq!(effect.state(10).unwrap().add, [0, 0, 0]);
+        assert_eq!(effect.state(15).unwrap().add, [-100, -50, 100]);
+    }
+
+    #[test]
+    fn time_limits_the_effect() {
+        let effect = PalFx {
+            time: 10,
+            add: [40, 40, 40],
+            ..PalFx::default()
+        };
+
+        assert_eq!(effect.state(9).unwrap().add, [40, 40, 40]);
+        assert!(effect.state(10).is_none());
+        assert!(PalFx::default().state(100_000).is_some());
+    }
+
+    #[test]
+    fn ramp_interpolates_then_holds() {
+        let ramp = PalFxRamp {
+            from: PalFx {
+                mul: [0, 0, 0],
+                ..PalFx::default()
+            },
+            to: PalFx {
+                time: 20,
+                ..PalFx::default()
+            },
+            duration: 10,
+        };
+
+        assert_eq!(ramp.state(0).unwrap().mul, [0, 0, 0]);
+        assert_eq!(ramp.state(5).unwrap().mul, [128, 128, 128]);
+        assert_eq!(ramp.state(10).unwrap().mul, [256, 256, 256]);
+        assert_eq!(rgb(ramp.state(5).unwrap().apply_color(sample())), (50, 75, 100, 255));
+        assert!(ramp.state(20).is_none());
+    }
+
+    #[test]
+    fn palette_applies_every_color() {
+        let palette = Palette::from_colors(vec![RawColor::new(0, 0, 0, 0), sample()]);
+        let result = state([0, 0, 0], [256, 256, 256], 256, true).apply(&palette);
+
+        assert_eq!(result.colors.len(), 2);
+        assert_eq!(rgb(result.colors[0]), (255, 255, 255, 0));
+        assert_eq!(rgb(result.colors[1]), (155, 105, 55, 255));
+    }
+
+    #[test]
+    fn shader_uniforms_match_cpu() {
+        let effects = [
+            PalFxState::default(),
+            state([30, -160, 100], [256, 256, 256], 256, false),
+            state([56, 56, 56], [128, 200, 300], 256, false),
+            state([0, 0, 0], [256, 256, 256], 0, false),
+            state([-20, 15, 0], [256, 128, 256], 96, true),
+            state([255, 0, -255], [64, 512, 256], 300, true),
+        ];
+
+        for effect in effects.iter() {
+            let uniforms = effect.uniforms();
+
+            for value in (0..=255).step_by(5) {
+                let color = RawColor::new(value as u8, (255 - value) as u8, ((value * 7) % 256) as u8, 255);
+                let cpu = effect.apply_color(color);
+                let gpu = shader(&uniforms, color);
+
+                for (cpu, gpu) in [cpu.r, cpu.g, cpu.b].iter().zip(gpu.iter()) {
+                    assert!((*cpu as i32 - *gpu as i32).abs() <= 1, "{:?} {} != {}", effect, cpu, gpu);
+                }
+            }
+        }
+    }
+}
@@ -1,22 +1,50 @@
 use byteorder::{LittleEndian, ReadBytesExt};
-use gdnative::api::file::File;
 use std::io::Cursor;
 use std::io::Read;
+use std::io::{Seek, SeekFrom};
 
+use crate::core::error::DataError;
+use crate::io::file_system::{self, FileStream};
+
+fn unexpected_end() -> DataError {
+    DataError::decode_failure("Unexpected end of data".to_string())
+}
+
+// Compressed sprites start with their decoded size, a shorter result means the data was cut off
+pub fn check_decoded_size(dest: Vec<u8>, expected: u32) -> Result<Vec<u8>, DataError> {
+    if dest.len() < expected as usize {
+        return Err(DataError::decode_failure(format!(
+            "Decoded {} bytes, expected {}",
+            dest.len(),
+            expected
+        )));
+    }
+
+    Ok(dest)
+}
+
+// Reads fail past the end of the data, so truncated files become errors instead of zeros
 pub trait DataReader {
-    fn get_bool(&mut self) -> bool;
-    fn get_u8(&mut self) -> u8;
-    fn get_i8(&mut self) -> i8;
-    fn get_u16(&mut self) -> u16;
-    fn get_i16(&mut self) -> i16;
-    fn get_u32(&mut self) -> u32;
-    fn get_i32(&mut self) -> i32;
-    fn get_buffer(&mut self, size: usize) -> Vec<u8>;
+    fn get_u8(&mut self) -> Result<u8, DataError>;
+    fn get_i8(&mut self) -> Result<i8, DataError>;
+    fn get_u16(&mut self) -> Result<u16, DataError>;
+    fn get_i16(&mut self) -> Result<i16, DataError>;
+    fn get_u32(&mut self) -> Result<u32, DataError>;
+    fn get_i32(&mut self) -> Result<i32, DataError>;
+    fn get_buffer(&mut self, size: usize) -> Result<Vec<u8>, DataError>;
+    fn seek(&mut self, pos: usize);
     fn eof(&mut self) -> bool;
     fn pos(&mut self) -> usize;
     fn size(&mut self) -> usize;
-    fn get_text(&mut self, size: usize) -> String {
-        let buffer = self.get_buffer(size);
+    fn get_bool(&mut self) -> Result<bool, DataError> {
+        Ok(self.get_u8()? != 0)
+    }
+    fn remaining(&mut self) -> usize {
+        let pos = self.pos();
+        self.size().saturating_sub(pos)
+    }
+    fn get_text(&mut self, size: usize) -> Result<String, DataError> {
+        let buffer = self.get_buffer(size)?;
         let mut text = String::from("");
         let mut i = 0;
         while i < buffer.len() {
@@ -29,74 +57,78 @@ pub trait DataReader {
             text.push(character);
             i += 1;
         }
-        text
+        Ok(text)
     }
 }
 
-pub struct FileReader<'a> {
-    file: &'a File,
-    pos: usize,
+pub struct FileReader {
+    stream: Box<dyn FileStream>,
+    size: usize,
 }
 
-impl<'a> FileReader<'a> {
-    pub fn new(file: &'a File) -> FileReader {
-        FileReader { file, pos: 0 }
+impl FileReader {
+    pub fn new(mut stream: Box<dyn FileStream>) -> FileReader {
+        let size = stream.seek(SeekFrom::End(0)).unwrap_or(0) as usize;
+        stream.seek(SeekFrom::Start(0)).ok();
+
+        FileReader { stream, size }
+    }
+
+    pub fn open(path: &str) -> Result<FileReader, DataError> {
+        Ok(FileReader::new(file_system::open_file(path)?))
     }
 }
 
-impl<'a> DataReader for FileReader<'a> {
-    fn get_bool(&mut self) -> bool {
-        self.pos += 1;
-        self.file.get_8() != 0
+impl DataReader for FileReader {
+    fn get_u8(&mut self) -> Result<u8, DataError> {
+        self.stream.read_u8().map_err(|_| unexpected_end())
     }
 
-    fn get_u8(&mut self) -> u8 {
-        self.pos += 1;
-        self.file.get_8() as u8
+    fn get_i8(&mut self) -> Result<i8, DataError> {
+        self.stream.read_i8().map_err(|_| unexpected_end())
     }
 
-    fn get_i8(&mut self) -> i8 {
-        self.pos += 1;
-        self.file.get_8() as i8
+    fn get_u16(&mut self) -> Result<u16, DataError> {
+        self.stream.read_u16::<LittleEndian>().map_err(|_| unexpected_end())
     }
 
-    fn get_u16(&mut self) -> u16 {
-        self.pos += 2;
-        self.file.get_16() as u16
+    fn get_i16(&mut self) -> Result<i16, DataError> {
+        self.stream.read_i16::<LittleEndian>().map_err(|_| unexpected_end())
     }
 
-    fn get_i16(&mut self) -> i16 {
-        self.pos += 2;
-        self.file.get_16() as i16
+    fn get_u32(&mut self) -> Result<u32, DataError> {
+        self.stream.read_u32::<LittleEndian>().map_err(|_| unexpected_end())
     }
 
-    fn get_u32(&mut self) -> u32 {
-        self.pos += 4;
-        self.file.get_32() as u32
+    fn get_i32(&mut self) -> Result<i32, DataError> {
+        self.stream.read_i32::<LittleEndian>().map_err(|_| unexpected_end())
     }
 
-    fn get_i32(&mut self) -> i32 {
-        self.pos += 4;
-        self.file.get_32() as i32
+    fn get_buffer(&mut self, size: usize) -> Result<Vec<u8>, DataError> {
+        // Checked before allocating, a corrupt size must not reserve gigabytes
+        if size > self.remaining() {
+            return Err(unexpected_end());
+        }
+
+        let mut buf = vec![0u8; size];
+        self.stream.read_exact(&mut buf).map_err(|_| unexpected_end())?;
+        Ok(buf)
     }
 
-    fn get_buffer(&mut self, size: usize) -> Vec<u8> {
-        self.pos += size;
-        let buffer = self.file.get_buffer(size as i64);
-        let reader = buffer.read();
-        reader.to_vec()
+    fn seek(&mut self, pos: usize) {
+        self.stream.seek(SeekFrom::Start(pos as u64)).ok();
     }
 
     fn eof(&mut self) -> bool {
-        self.file.eof_reached()
+        self.pos() >= self.size
     }
 
     fn pos(&mut self) -> usize {
-        self.pos
+        self.stream.stream_position().unwrap_or(0) as usize
     }
 
     fn size(&mut self) -> usize {
-        self.file.get_len() as usize
+        self.size
     }
 }
 
@@ -116,53 +148,42 @@ impl<'a> BufferReader<'a> {
 }
 
 impl<'a> DataReader for BufferReader<'a> {
-    fn get_bool(&mut self) -> bool {
-        let result: Result<u8, u8> = self.cursor.read_u8().or(Ok(0));
-
-        result.unwrap() != 0
+    fn get_u8(&mut self) -> Result<u8, DataError> {
+        self.cursor.read_u8().map_err(|_| unexpected_end())
     }
 
-    fn get_u8(&mut self) -> u8 {
-        let result: Result<u8, u8> = self.cursor.read_u8().or(Ok(0));
-
-        result.unwrap()
+    fn get_i8(&mut self) -> Result<i8, DataError> {
+        self.cursor.read_i8().map_err(|_| unexpected_end())
     }
 
-    fn get_i8(&mut self) -> i8 {
-        let result: Result<i8, i8> = self.cursor.read_i8().or(Ok(0));
-
-        result.unwrap()
+    fn get_u16(&mut self) -> Result<u16, DataError> {
+        self.cursor.read_u16::<LittleEndian>().map_err(|_| unexpected_end())
     }
 
-    fn get_u16(&mut self) -> u16 {
-        let result: Result<u16, u16> = self.cursor.read_u16::<LittleEndian>().or(Ok(0));
-
-        result.unwrap()
+    fn get_i16(&mut self) -> Result<i16, DataError> {
+        self.cursor.read_i16::<LittleEndian>().map_err(|_| unexpected_end())
     }
 
-    fn get_i16(&mut self) -> i16 {
-        let result: Result<i16, i16> = self.cursor.read_i16::<LittleEndian>().or(Ok(0));
-
-        result.unwrap()
+    fn get_u32(&mut self) -> Result<u32, DataError> {
+        self.cursor.read_u32::<LittleEndian>().map_err(|_| unexpected_end())
     }
 
-    fn get_u32(&mut self) -> u32 {
-        let result: Result<u32, u32> = self.cursor.read_u32::<LittleEndian>().or(Ok(0));
-
-        result.unwrap()
+    fn get_i32(&mut self) -> Result<i32, DataError> {
+        self.cursor.read_i32::<LittleEndian>().map_err(|_| unexpected_end())
     }
 
-    fn get_i32(&mut self) -> i32 {
-        let result: Result<i32, i32> = self.cursor.read_i32::<LittleEndian>().or(Ok(0));
+    fn get_buffer(&mut self, size: usize) -> Result<Vec<u8>, DataError> {
+        if size > self.remaining() {
+            return Err(unexpected_end());
+        }
 
-        result.unwrap()
+        let mut buf = vec![0u8; size];
+        self.cursor.read_exact(&mut buf).map_err(|_| unexpected_end())?;
+        Ok(buf)
     }
 
-    #[allow(unused_must_use)]
-    fn get_buffer(&mut self, size: usize) -> Vec<u8> {
-        let mut buf = vec![0u8; size];
-        self.cursor.read_exact(&mut buf);
-        buf
+    fn seek(&mut self, pos: usize) {
+        self.cursor.set_position(pos as u64);
     }
 
     fn eof(&mut self) -> bool {
@@ -186,11 +207,11 @@ pub trait BufferAccess {
     fn right(&self, new_size: usize) -> Vec<u8> {
         let actual_size = self.len();
 
-        if new_size >= actual_size {
-            return self.subarray(0, actual_size - 1);
+        if actual_size == 0 || new_size == 0 {
+            return Vec::new();
         }
 
-        let start_index = actual_size - new_size;
+        let start_index = actual_size.saturating_sub(new_size);
 
         self.subarray(start_index, actual_size - 1)
     }
@@ -204,10 +225,35 @@ impl BufferAccess for Vec<u8> {
     fn subarray(&self, start: usize, end: usize) -> Vec<u8> {
         let mysize = self.len();
 
-        if start > end || end > mysize - 1 || start > mysize - 1 || mysize == 0 {
+        if mysize == 0 {
+            return Vec::new();
+        }
+
+        if start > end || end > mysize - 1 {
             return self[..].to_vec();
         }
 
         self[start..end + 1].to_vec()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn right_of_empty_buffer_is_empty() {
+        assert!(Vec::<u8>::new().right(768).is_empty());
+        assert_eq!(vec![1, 2, 3].right(2), vec![2, 3]);
+    }
+
+    #[test]
+    fn buffer_reader_fails_past_the_end() {
+        let data = vec![1, 2, 3];
+        let mut reader = BufferReader::new(&data);
+
+        assert_eq!(reader.get_u16().unwrap(), 0x0201);
+        assert!(reader.get_u16().is_err());
+        assert!(reader.get_buffer(4).is_err());
+    }
+}
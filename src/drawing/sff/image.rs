@@ -34,6 +34,8 @@ impl RawColor {
     }
 }
 
+pub const PALETTE_SIZE: usize = 256;
+
 #[derive(Clone)]
 pub struct Palette {
     pub colors: Vec<RawColor>,
@@ -67,36 +69,62 @@ impl Palette {
         true
     }
 
-    pub fn create_texture(&self) -> Arc<Texture> {
-        let width = self.colors.len();
-        let mut my_byte_array: Vec<u8> = Vec::with_capacity(width * 4);
+    // Palette textures always have PALETTE_SIZE entries, so they can be updated in place
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let mut my_byte_array: Vec<u8> = vec![0u8; PALETTE_SIZE * 4];
 
-        for color in self.colors.iter() {
-            my_byte_array.push(color.r);
-            my_byte_array.push(color.g);
-            my_byte_array.push(color.b);
-            my_byte_array.push(color.a);
+        for (index, color) in self.colors.iter().take(PALETTE_SIZE).enumerate() {
+            my_byte_array[index * 4] = color.r;
+            my_byte_array[index * 4 + 1] = color.g;
+            my_byte_array[index * 4 + 2] = color.b;
+            my_byte_array[index * 4 + 3] = color.a;
         }
 
-        let dest = ByteArray::from_slice(my_byte_array.as_slice());
+        my_byte_array
+    }
+
+    pub fn create_image(&self) -> Ref<Image, Unique> {
+        let dest = ByteArray::from_slice(self.to_rgba().as_slice());
         let image = Image::new();
 
         image.create_from_data(
-            width as i64,
+            PALETTE_SIZE as i64,
             1 as i64,
             false,
             Image::FORMAT_RGBA8,
             dest,
         );
 
+        image
+    }
+
+    pub fn create_texture(&self) -> Arc<Texture> {
+        let image = self.create_image();
+
         Texture::allocate(image, TextureFlags(0))
     }
 }
 
+#[derive(Copy, Clone, PartialEq)]
+pub enum PixelFormat {
+    Indexed,
+    Rgba,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Indexed => 1,
+            PixelFormat::Rgba => 4,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct RawImage {
     pub w: usize,
     pub h: usize,
+    pub format: PixelFormat,
     pub pixels: Arc<Vec<u8>>,
     pub color_table: Arc<Palette>,
 }
@@ -109,22 +137,58 @@ impl RawImage {
         RawImage {
             w: 0,
             h: 0,
+            format: PixelFormat::Indexed,
             pixels,
             color_table,
         }
     }
 
-    pub fn create_image_with_palette(&self, palette: &Palette) -> Ref<Image, Unique> {
+    pub fn indexed(w: usize, h: usize, pixels: Vec<u8>, color_table: Arc<Palette>) -> RawImage {
+        RawImage {
+            w,
+            h,
+            format: PixelFormat::Indexed,
+            pixels: Arc::new(pixels),
+            color_table,
+        }
+    }
+
+    pub fn rgba(w: usize, h: usize, pixels: Vec<u8>) -> RawImage {
+        RawImage {
+            w,
+            h,
+            format: PixelFormat::Rgba,
+            pixels: Arc::new(pixels),
+            color_table: Arc::new(Palette::new(0)),
+        }
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.format == PixelFormat::Indexed
+    }
+
+    pub fn to_rgba(&self, palette: &Palette) -> Vec<u8> {
+        if !self.is_indexed() {
+            return self.pixels.to_vec();
+        }
+
         let mut my_byte_array: Vec<u8> = Vec::with_capacity(self.w * self.h * 4);
+        let transparent = RawColor::new(0, 0, 0, 0);
 
         for &pixel in self.pixels.iter() {
-            let color = &palette.colors[pixel as usize];
+            let color = palette.colors.get(pixel as usize).unwrap_or(&transparent);
             my_byte_array.push(color.r);
             my_byte_array.push(color.g);
             my_byte_array.push(color.b);
             my_byte_array.push(color.a);
         }
 
+        my_byte_array
+    }
+
+    pub fn create_image_with_palette(&self, palette: &Palette) -> Ref<Image, Unique> {
+        let my_byte_array = self.to_rgba(palette);
+
         let dest = ByteArray::from_slice(my_byte_array.as_slice());
 
         let image = Image::new();
@@ -143,6 +207,10 @@ impl RawImage {
     }
 
     pub fn create_monochromatic_texture(&self, flags: TextureFlags) -> Arc<Texture> {
+        if !self.is_indexed() {
+            return Texture::allocate(self.create_image(), flags);
+        }
+
         let dest = ByteArray::from_slice(self.pixels.as_slice());
 
         let image = Image::new();
@@ -159,27 +227,6 @@ impl RawImage {
     }
 
     pub fn create_palette_texture(&self) -> Arc<Texture> {
-        let width = self.color_table.colors.len();
-        let mut my_byte_array: Vec<u8> = Vec::with_capacity(width * 4);
-
-        for color in self.color_table.colors.iter() {
-            my_byte_array.push(color.r);
-            my_byte_array.push(color.g);
-            my_byte_array.push(color.b);
-            my_byte_array.push(color.a);
-        }
-
-        let dest = ByteArray::from_slice(my_byte_array.as_slice());
-        let image = Image::new();
-
-        image.create_from_data(
-            width as i64,
-            1 as i64,
-            false,
-            Image::FORMAT_RGBA8,
-            dest,
-        );
-
-        Texture::allocate(image, TextureFlags(0))
+        self.color_table.create_texture()
     }
 }
@@ -1,13 +1,17 @@
-use super::data::{BufferAccess, DataReader};
+use std::cmp;
+
+use crate::core::error::DataError;
+
+use super::data::{check_decoded_size, BufferAccess, DataReader};
 
 struct ControlPacket {
     flags: [u8; 8],
 }
 
 impl ControlPacket {
-    fn read(reader: &mut dyn DataReader) -> ControlPacket {
+    fn read(reader: &mut dyn DataReader) -> Result<ControlPacket, DataError> {
         let mut flags: [u8; 8] = [0; 8];
-        let byte = reader.get_u8();
+        let byte = reader.get_u8()?;
 
         flags[7] = (byte & 0x80) / 0x80;
         flags[6] = (byte & 0x40) / 0x40;
@@ -18,7 +22,7 @@ impl ControlPacket {
         flags[1] = (byte & 0x02) / 0x02;
         flags[0] = byte & 0x01;
 
-        ControlPacket { flags }
+        Ok(ControlPacket { flags })
     }
 }
 
@@ -35,18 +39,19 @@ impl Lz5RlePacket {
         }
     }
 
-    fn read(reader: &mut dyn DataReader, packet: &mut Lz5RlePacket) {
+    fn read(reader: &mut dyn DataReader, packet: &mut Lz5RlePacket) -> Result<(), DataError> {
         {
-            let byte1: u8 = reader.get_u8();
+            let byte1: u8 = reader.get_u8()?;
             let byte2: u8;
             packet.num_times = ((byte1 & 0xe0) >> 5) as i64;
             if packet.num_times == 0 {
-                byte2 = reader.get_u8();
+                byte2 = reader.get_u8()?;
                 packet.num_times = byte2 as i64;
                 packet.num_times += 8;
             }
             packet.color = byte1 & 0x1f;
         }
+        Ok(())
     }
 }
 
@@ -72,14 +77,14 @@ impl Lz5LzPacket {
         self.recycled_bits_filled = 0;
     }
 
-    fn read(reader: &mut dyn DataReader, pack: &mut Lz5LzPacket) {
-        let byte1: u8 = reader.get_u8();
+    fn read(reader: &mut dyn DataReader, pack: &mut Lz5LzPacket) -> Result<(), DataError> {
+        let byte1: u8 = reader.get_u8()?;
         let byte2: u8;
         let byte3: u8;
         pack.len = (byte1 & 0x3f) as i32;
         if pack.len == 0 {
-            byte2 = reader.get_u8();
-            byte3 = reader.get_u8();
+            byte2 = reader.get_u8()?;
+            byte3 = reader.get_u8()?;
             pack.offset = (byte1 & 0xc0) as i32;
             pack.offset *= 4;
             pack.offset += byte2 as i32;
@@ -101,7 +106,7 @@ impl Lz5LzPacket {
             pack.recycled += tmp_recyc;
             pack.recycled_bits_filled += 2;
             if pack.recycled_bits_filled < 8 {
-                byte2 = reader.get_u8();
+                byte2 = reader.get_u8()?;
                 pack.offset = byte2 as i32;
             }
             if pack.recycled_bits_filled == 8 {
@@ -110,31 +115,39 @@ impl Lz5LzPacket {
             }
             pack.offset += 1;
         }
+        Ok(())
     }
 }
 
-pub fn decode_lz5(reader: &mut dyn DataReader) -> Vec<u8> {
+pub fn decode_lz5(reader: &mut dyn DataReader) -> Result<Vec<u8>, DataError> {
     let mut dest: Vec<u8> = Vec::new();
     let mut rle = Lz5RlePacket::new();
     let mut lz = Lz5LzPacket::new();
 
-    reader.get_u32(); // skip first 4 bytes (result size)
+    let size = reader.get_u32()?;
     while !reader.eof() {
-        let ctrl = ControlPacket::read(reader);
+        let ctrl = ControlPacket::read(reader)?;
         for a in 0..8 {
             if reader.eof() {
                 break;
             }
             if ctrl.flags[a] == 0 {
                 //rle packet
-                Lz5RlePacket::read(reader, &mut rle);
+                Lz5RlePacket::read(reader, &mut rle)?;
                 for _ in 0..rle.num_times {
                     dest.push(rle.color);
                 }
             }
             if ctrl.flags[a] == 1 {
                 //lz packet
-                Lz5LzPacket::read(reader, &mut lz);
+                Lz5LzPacket::read(reader, &mut lz)?;
+                if lz.offset as usize > dest.len() {
+                    return Err(DataError::decode_failure(format!(
+                        "LZ5 offset {} is outside of the {} decoded bytes",
+                        lz.offset,
+                        dest.len()
+                    )));
+                }
                 let mut tmp_arr: Vec<u8> = dest.right(lz.offset as usize).to_vec();
                 tmp_arr.truncate(lz.len as usize);
                 while tmp_arr.len() < lz.len as usize {
@@ -145,5 +158,157 @@ pub fn decode_lz5(reader: &mut dyn DataReader) -> Vec<u8> {
             }
         }
     }
-    dest
+    check_decoded_size(dest, size)
+}
+
+const LZ5_WINDOW: usize = 1024;
+const LZ5_SHORT_WINDOW: usize = 256;
+const LZ5_MAX_CANDIDATES: usize = 128;
+
+fn find_lz5_match(pixels: &[u8], position: usize, chains: &[Vec<usize>]) -> (usize, usize) {
+    let max_len = cmp::min(pixels.len() - position, 258);
+    let mut best = (0, 0);
+
+    if max_len < 2 {
+        return best;
+    }
+
+    let key = get_chain_key(pixels, position);
+
+    for &candidate in chains[key].iter().rev().take(LZ5_MAX_CANDIDATES) {
+        let distance = position - candidate;
+
+        if distance > LZ5_WINDOW {
+            break;
+        }
+
+        let mut len = 0;
+
+        while len < max_len && pixels[candidate + len] == pixels[position + len] {
+            len += 1;
+        }
+
+        if len > best.0 {
+            best = (len, distance);
+
+            if len == max_len {
+                break;
+            }
+        }
+    }
+
+    best
+}
+
+fn get_chain_key(pixels: &[u8], position: usize) -> usize {
+    ((pixels[position] as usize) << 5) | pixels[position + 1] as usize
+}
+
+pub fn encode_lz5(pixels: &[u8]) -> Result<Vec<u8>, DataError> {
+    if pixels.iter().any(|pixel| *pixel > 0x1f) {
+        return Err(DataError::new("LZ5 only supports 5-bit images".to_string()));
+    }
+
+    let mut dest: Vec<u8> = Vec::with_capacity(pixels.len() / 2 + 4);
+    let mut chains: Vec<Vec<usize>> = vec![Vec::new(); 1024];
+    let mut recycled_offsets: Vec<usize> = Vec::new();
+    let mut control_offset = 0;
+    let mut flag_count = 8;
+    let mut position = 0;
+
+    dest.extend_from_slice(&(pixels.len() as u32).to_le_bytes());
+
+    while position < pixels.len() {
+        if flag_count == 8 {
+            control_offset = dest.len();
+            dest.push(0);
+            flag_count = 0;
+        }
+
+        let color = pixels[position];
+        let mut run_len = 1;
+
+        while position + run_len < pixels.len() && pixels[position + run_len] == color && run_len < 263 {
+            run_len += 1;
+        }
+
+        let (match_len, distance) = find_lz5_match(pixels, position, &chains);
+        let use_short = match_len >= 2 && distance <= LZ5_SHORT_WINDOW && match_len <= 64;
+        let use_long = match_len >= 3;
+        let packet_len;
+
+        if match_len > run_len && (use_short || use_long) {
+            dest[control_offset] |= 1 << flag_count;
+
+            if use_short {
+                let offset = (distance - 1) as u8;
+                packet_len = match_len;
+
+                if recycled_offsets.len() < 3 {
+                    recycled_offsets.push(dest.len());
+                    dest.push((packet_len - 1) as u8);
+                    dest.push(offset);
+                } else {
+                    // The fourth short packet stores its offset in the top bits of the previous three
+                    dest[recycled_offsets[0]] |= offset & 0xc0;
+                    dest[recycled_offsets[1]] |= (offset & 0x30) << 2;
+                    dest[recycled_offsets[2]] |= (offset & 0x0c) << 4;
+                    dest.push((packet_len - 1) as u8 | ((offset & 0x03) << 6));
+                    recycled_offsets.clear();
+                }
+            } else {
+                let offset = distance - 1;
+                packet_len = cmp::min(match_len, 258);
+
+                dest.push(((offset >> 8) << 6) as u8);
+                dest.push((offset & 0xff) as u8);
+                dest.push((packet_len - 3) as u8);
+            }
+        } else {
+            packet_len = run_len;
+
+            if run_len < 8 {
+                dest.push(((run_len as u8) << 5) | color);
+            } else {
+                dest.push(color);
+                dest.push((run_len - 8) as u8);
+            }
+        }
+
+        for index in position..(position + packet_len) {
+            if index + 1 < pixels.len() {
+                chains[get_chain_key(pixels, index)].push(index);
+            }
+        }
+
+        position += packet_len;
+        flag_count += 1;
+    }
+
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::drawing::sff::data::BufferReader;
+
+    use super::*;
+
+    #[test]
+    fn lz5_rejects_truncated_data() {
+        let mut pixels: Vec<u8> = (0..300).map(|i| ((i * 7) % 13) as u8).collect();
+        pixels.extend(vec![3; 40]);
+        let encoded = encode_lz5(&pixels).unwrap();
+
+        for len in 0..encoded.len() {
+            assert!(decode_lz5(&mut BufferReader::new(&encoded[..len].to_vec())).is_err());
+        }
+    }
+
+    #[test]
+    fn lz5_rejects_offsets_before_the_start() {
+        let data = vec![0, 0, 0, 0, 0x01, 0x00, 0x00, 0x00];
+
+        assert!(decode_lz5(&mut BufferReader::new(&data)).is_err());
+    }
 }
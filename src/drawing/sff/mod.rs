@@ -2,8 +2,10 @@ pub mod data;
 pub mod image;
 pub mod lz5;
 pub mod pcx;
+pub mod png_image;
 pub mod rle5;
 pub mod sff_common;
 pub mod sff_parser;
+pub mod sff_writer;
 pub mod sffv1;
 pub mod sffv2;
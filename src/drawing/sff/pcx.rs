@@ -1,4 +1,3 @@
-use gdnative::prelude::*;
 use std::cell::RefCell;
 use std::cmp;
 use std::rc::Rc;
@@ -8,7 +7,7 @@ use std::sync::Arc;
 use crate::core::error::DataError;
 
 use super::data::{DataReader};
-use super::image::{Palette, RawColor, RawImage};
+use super::image::{Palette, PixelFormat, RawColor, RawImage};
 
 #[allow(dead_code)]
 pub struct PcxHeader {
@@ -31,18 +30,18 @@ pub struct PcxHeader {
     v_screen_size: u16,
 }
 
-fn read_palette(reader: &mut dyn DataReader) -> Arc<Palette> {
+fn read_palette(reader: &mut dyn DataReader) -> Result<Arc<Palette>, DataError> {
     let mut palette = Palette::new(16);
 
     for i in 0..16 {
-        let r = reader.get_u8();
-        let g = reader.get_u8();
-        let b = reader.get_u8();
+        let r = reader.get_u8()?;
+        let g = reader.get_u8()?;
+        let b = reader.get_u8()?;
 
         palette.colors[i] = RawColor::new(r, g, b, if i == 0 { 0 } else { 255 });
     }
 
-    Arc::new(palette)
+    Ok(Arc::new(palette))
 }
 
 impl PcxHeader {
@@ -58,30 +57,30 @@ impl PcxHeader {
         self.encoding == 1
     }
 
-    pub fn from(reader: &mut dyn DataReader) -> PcxHeader {
-        let manufacturer: u8 = reader.get_u8();
-        let version: u8 = reader.get_u8();
-        let encoding: u8 = reader.get_u8();
-        let bpp: u8 = reader.get_u8();
-        let x_min: u16 = reader.get_u16();
-        let y_min: u16 = reader.get_u16();
-        let x_max: u16 = reader.get_u16();
-        let y_max: u16 = reader.get_u16();
-        let h_dpi: u16 = reader.get_u16();
-        let y_dpi: u16 = reader.get_u16();
-        let color_map: Arc<Palette> = read_palette(reader);
-        let reserved: u8 = reader.get_u8();
-        let n_planes: u8 = reader.get_u8();
-        let bytes_per_line: u16 = reader.get_u16();
-        let palette_info: u16 = reader.get_u16();
-        let h_screen_size: u16 = reader.get_u16();
-        let v_screen_size: u16 = reader.get_u16();
+    pub fn from(reader: &mut dyn DataReader) -> Result<PcxHeader, DataError> {
+        let manufacturer: u8 = reader.get_u8()?;
+        let version: u8 = reader.get_u8()?;
+        let encoding: u8 = reader.get_u8()?;
+        let bpp: u8 = reader.get_u8()?;
+        let x_min: u16 = reader.get_u16()?;
+        let y_min: u16 = reader.get_u16()?;
+        let x_max: u16 = reader.get_u16()?;
+        let y_max: u16 = reader.get_u16()?;
+        let h_dpi: u16 = reader.get_u16()?;
+        let y_dpi: u16 = reader.get_u16()?;
+        let color_map: Arc<Palette> = read_palette(reader)?;
+        let reserved: u8 = reader.get_u8()?;
+        let n_planes: u8 = reader.get_u8()?;
+        let bytes_per_line: u16 = reader.get_u16()?;
+        let palette_info: u16 = reader.get_u16()?;
+        let h_screen_size: u16 = reader.get_u16()?;
+        let v_screen_size: u16 = reader.get_u16()?;
 
         while reader.pos() < 128 {
-            reader.get_u8();
+            reader.get_u8()?;
         }
 
-        PcxHeader {
+        Ok(PcxHeader {
             manufacturer,
             version,
             encoding,
@@ -99,11 +98,41 @@ impl PcxHeader {
             palette_info,
             h_screen_size,
             v_screen_size,
+        })
+    }
+
+    // Rejects sizes the remaining data can't hold before the pixels are allocated.
+    // A compressed byte pair expands to at most 63 bytes.
+    fn validate(&self, remaining: usize) -> Result<(), DataError> {
+        if self.width() <= 0 || self.height() <= 0 || self.bytes_per_line == 0 {
+            return Err(DataError::decode_failure(format!(
+                "Invalid pcx size: {}x{}, {} bytes per line",
+                self.width(), self.height(), self.bytes_per_line
+            )));
+        }
+
+        if self.width() as usize * self.bpp as usize > self.bytes_per_line as usize * 8 {
+            return Err(DataError::decode_failure(format!(
+                "Pcx lines of {} bytes can't hold {} pixels",
+                self.bytes_per_line, self.width()
+            )));
+        }
+
+        let line_size = self.bytes_per_line as usize * cmp::max(self.n_planes, 1) as usize;
+        let min_line_size = if self.is_compressed() { (line_size + 62) / 63 } else { line_size };
+
+        if min_line_size * self.height() as usize > remaining {
+            return Err(DataError::decode_failure(format!(
+                "Pcx data too small for {}x{} pixels",
+                self.width(), self.height()
+            )));
         }
+
+        Ok(())
     }
 }
 
-fn read_line(reader: &mut dyn DataReader, buf: &mut Vec<u8>, header: &PcxHeader) {
+fn read_line(reader: &mut dyn DataReader, buf: &mut Vec<u8>, header: &PcxHeader) -> Result<(), DataError> {
     let size = buf.len();
     let mut i: usize = 0;
     let mut byte: u8;
@@ -112,10 +141,10 @@ fn read_line(reader: &mut dyn DataReader, buf: &mut Vec<u8>, header: &PcxHeader)
     if header.is_compressed() {
         while i < size {
             count = 1;
-            byte = reader.get_u8();
+            byte = reader.get_u8()?;
             if byte > 0xc0 {
                 count = byte - 0xc0;
-                byte = reader.get_u8();
+                byte = reader.get_u8()?;
             }
             loop {
                 // TODO: Review performance
@@ -130,25 +159,23 @@ fn read_line(reader: &mut dyn DataReader, buf: &mut Vec<u8>, header: &PcxHeader)
         }
     } else {
         while i < size {
-            byte = reader.get_u8();
+            byte = reader.get_u8()?;
             buf[i] = byte;
             i += 1;
         }
     }
+
+    Ok(())
 }
 
-pub fn read_image_1(reader: &mut dyn DataReader, header: &PcxHeader) -> Rc<RefCell<RawImage>> {
+pub fn read_image_1(reader: &mut dyn DataReader, header: &PcxHeader) -> Result<Rc<RefCell<RawImage>>, DataError> {
     let mut buf: Vec<u8> = Vec::new();
     buf.resize(header.bytes_per_line as usize, 0);
     let width = header.width();
-    let mut pixels: Vec<u8> = vec![0u8; (header.width() * header.height()) as usize];
+    let mut pixels: Vec<u8> = vec![0u8; header.width() as usize * header.height() as usize];
 
     for y in 0..header.height() {
-        if reader.eof() {
-            return Rc::new(RefCell::new(RawImage::empty()));
-        }
-
-        read_line(reader, &mut buf, header);
+        read_line(reader, &mut buf, header)?;
 
         let line_offset: usize = (width * y) as usize;
         let bpl = cmp::min((width + 7) / 8, header.bytes_per_line as i32);
@@ -160,35 +187,34 @@ pub fn read_image_1(reader: &mut dyn DataReader, header: &PcxHeader) -> Rc<RefCe
     palette.colors[0] = RawColor::new(0, 0, 0, 255);
     palette.colors[1] = RawColor::new(255, 255, 255, 255);
 
-    Rc::new(RefCell::new(RawImage {
+    Ok(Rc::new(RefCell::new(RawImage {
         w: header.width() as usize,
         h: header.height() as usize,
+        format: PixelFormat::Indexed,
         pixels: Arc::new(pixels),
         color_table: Arc::new(palette),
-    }))
+    })))
 }
 
-pub fn read_image_4(reader: &mut dyn DataReader, header: &PcxHeader) -> Rc<RefCell<RawImage>> {
+pub fn read_image_4(reader: &mut dyn DataReader, header: &PcxHeader) -> Result<Rc<RefCell<RawImage>>, DataError> {
     let mut buf: Vec<u8> = Vec::new();
-    buf.resize((header.bytes_per_line * 4) as usize, 0);
+    buf.resize(header.bytes_per_line as usize * 4, 0);
     let mut pixbuf: Vec<u8> = Vec::new();
-    buf.resize(header.width() as usize, 0);
+    pixbuf.resize(header.width() as usize, 0);
 
-    let mut pixels: Vec<u8> = vec![0u8; (header.width() * header.height()) as usize];
+    let mut pixels: Vec<u8> = vec![0u8; header.width() as usize * header.height() as usize];
     let width = header.width();
+    // Lines shorter than the image leave the remaining pixels blank
+    let plane_width = cmp::min(header.width() as usize, header.bytes_per_line as usize * 8);
 
     for y in 0..header.height() {
-        if reader.eof() {
-            return Rc::new(RefCell::new(RawImage::empty()));
-        }
-
         pixbuf.fill(0);
 
-        read_line(reader, &mut buf, header);
+        read_line(reader, &mut buf, header)?;
 
         for i in 0..4 {
             let offset: usize = i * header.bytes_per_line as usize;
-            for x in 0..(header.width() as usize) {
+            for x in 0..plane_width {
                 if (buf[offset + (x / 8)] & (128 >> (x % 8))) != 0 {
                     pixbuf[x] += 1 << i;
                 }
@@ -201,88 +227,105 @@ pub fn read_image_4(reader: &mut dyn DataReader, header: &PcxHeader) -> Rc<RefCe
             .clone_from_slice(&pixbuf[..(header.width() as usize)]);
     }
 
-    Rc::new(RefCell::new(RawImage {
+    Ok(Rc::new(RefCell::new(RawImage {
         w: header.width() as usize,
         h: header.height() as usize,
+        format: PixelFormat::Indexed,
         pixels: Arc::new(pixels),
         color_table: Arc::clone(&header.color_map),
-    }))
+    })))
 }
 
-pub fn read_image_8(reader: &mut dyn DataReader, header: &PcxHeader) -> Rc<RefCell<RawImage>> {
+pub fn read_image_8(reader: &mut dyn DataReader, header: &PcxHeader) -> Result<Rc<RefCell<RawImage>>, DataError> {
     let mut buf: Vec<u8> = Vec::new();
     buf.resize(header.bytes_per_line as usize, 0);
-    let mut pixels: Vec<u8> = vec![0u8; (header.width() * header.height()) as usize];
+    let mut pixels: Vec<u8> = vec![0u8; header.width() as usize * header.height() as usize];
     let width = header.width();
 
     for y in 0..header.height() {
-        if reader.eof() {
-            return Rc::new(RefCell::new(RawImage::empty()));
-        }
-
-        read_line(reader, &mut buf, header);
+        read_line(reader, &mut buf, header)?;
 
         let line_offset: usize = (width * y) as usize;
         let bpl: usize = cmp::min(header.bytes_per_line as usize, width as usize);
         pixels[line_offset..(bpl + line_offset)].clone_from_slice(&buf[..bpl]);
     }
 
-    let flag: u8 = reader.get_u8();
+    let flag: u8 = if reader.eof() { 0 } else { reader.get_u8()? };
     let mut colors: Vec<RawColor> = Vec::new();
 
+    // Without a palette the image keeps an empty color table and callers fall back to a shared one
     if flag == 12 && (header.version == 5 || header.version == 2) {
         for i in 0..256 {
             let color = RawColor::new(
-                reader.get_u8(),
-                reader.get_u8(),
-                reader.get_u8(),
+                reader.get_u8()?,
+                reader.get_u8()?,
+                reader.get_u8()?,
                 if i == 0 { 0 } else { 255 },
             );
             colors.push(color);
         }
-    } else {
-        godot_print!("error: unsupported pcx, palette not set");
     }
 
-    Rc::new(RefCell::new(RawImage {
+    Ok(Rc::new(RefCell::new(RawImage {
         w: header.width() as usize,
         h: header.height() as usize,
+        format: PixelFormat::Indexed,
         pixels: Arc::new(pixels),
         color_table: Arc::new(Palette::from_colors(colors)),
-    }))
+    })))
 }
 
-pub fn read_image_24(_: &mut dyn DataReader, _: &PcxHeader) -> Rc<RefCell<RawImage>> {
-    godot_print!("error: unsupported 24bit pcx"); // TODO: Add 24bit support
+pub fn read_image_24(reader: &mut dyn DataReader, header: &PcxHeader) -> Result<Rc<RefCell<RawImage>>, DataError> {
+    let bytes_per_line = header.bytes_per_line as usize;
+    let mut buf: Vec<u8> = vec![0u8; bytes_per_line * 3];
+    let width = cmp::min(header.width() as usize, bytes_per_line);
+    let mut pixels: Vec<u8> = vec![0u8; header.width() as usize * header.height() as usize * 4];
+
+    for y in 0..header.height() {
+        // Each scanline stores the red, green and blue planes one after another
+        read_line(reader, &mut buf, header)?;
+
+        let line_offset: usize = y as usize * header.width() as usize * 4;
 
-    Rc::new(RefCell::new(RawImage::empty()))
+        for x in 0..width {
+            let offset = line_offset + x * 4;
+            pixels[offset] = buf[x];
+            pixels[offset + 1] = buf[bytes_per_line + x];
+            pixels[offset + 2] = buf[bytes_per_line * 2 + x];
+            pixels[offset + 3] = 255;
+        }
+    }
+
+    Ok(Rc::new(RefCell::new(RawImage::rgba(
+        header.width() as usize,
+        header.height() as usize,
+        pixels,
+    ))))
 }
 
 pub fn read_pcx(reader: &mut dyn DataReader) -> Result<Rc<RefCell<RawImage>>, DataError> {
     if reader.size() < 128 {
-        return Result::Err(DataError {
-            message: "Pcx data too small".to_string(),
-        });
+        return Result::Err(DataError::decode_failure("Pcx data too small".to_string()));
     }
 
-    let header = PcxHeader::from(reader);
+    let header = PcxHeader::from(reader)?;
 
     if header.manufacturer != 10 || reader.eof() {
-        return Result::Err(DataError {
-            message: format!("error: invalid pcx header: {}", header.manufacturer),
-        });
+        return Result::Err(DataError::decode_failure(format!("error: invalid pcx header: {}", header.manufacturer)));
     }
 
+    header.validate(reader.remaining())?;
+
     let img: Rc<RefCell<RawImage>>;
 
     if header.bpp == 1 && header.n_planes == 1 {
-        img = read_image_1(reader, &header);
+        img = read_image_1(reader, &header)?;
     } else if header.bpp == 1 && header.n_planes == 4 {
-        img = read_image_4(reader, &header);
+        img = read_image_4(reader, &header)?;
     } else if header.bpp == 8 && header.n_planes == 1 {
-        img = read_image_8(reader, &header);
+        img = read_image_8(reader, &header)?;
     } else if header.bpp == 8 && header.n_planes == 3 {
-        img = read_image_24(reader, &header);
+        img = read_image_24(reader, &header)?;
     } else {
         img = Rc::new(RefCell::new(RawImage::empty()));
     }
@@ -291,7 +334,153 @@ pub fn read_pcx(reader: &mut dyn DataReader) -> Result<Rc<RefCell<RawImage>>, Da
         return Result::Ok(img);
     }
 
-    Result::Err(DataError {
-        message: "Failed decoding PCX pixels".to_string(),
-    })
+    Result::Err(DataError::decode_failure("Failed decoding PCX pixels".to_string()))
+}
+
+fn write_pcx_header(dest: &mut Vec<u8>, width: usize, height: usize, n_planes: u8, bytes_per_line: usize) {
+    dest.extend_from_slice(&[10, 5, 1, 8]);
+    dest.extend_from_slice(&0u16.to_le_bytes());
+    dest.extend_from_slice(&0u16.to_le_bytes());
+    dest.extend_from_slice(&((width as u16).wrapping_sub(1)).to_le_bytes());
+    dest.extend_from_slice(&((height as u16).wrapping_sub(1)).to_le_bytes());
+    dest.extend_from_slice(&72u16.to_le_bytes());
+    dest.extend_from_slice(&72u16.to_le_bytes());
+    dest.extend_from_slice(&[0u8; 48]);
+    dest.push(0);
+    dest.push(n_planes);
+    dest.extend_from_slice(&(bytes_per_line as u16).to_le_bytes());
+    dest.extend_from_slice(&1u16.to_le_bytes());
+    dest.extend_from_slice(&0u16.to_le_bytes());
+    dest.extend_from_slice(&0u16.to_le_bytes());
+    dest.resize(128, 0);
+}
+
+fn write_line(dest: &mut Vec<u8>, line: &[u8]) {
+    let mut i: usize = 0;
+
+    while i < line.len() {
+        let byte = line[i];
+        let mut count: usize = 1;
+
+        while i + count < line.len() && line[i + count] == byte && count < 0x3f {
+            count += 1;
+        }
+
+        if count > 1 || byte >= 0xc0 {
+            dest.push(0xc0 | count as u8);
+        }
+
+        dest.push(byte);
+        i += count;
+    }
+}
+
+pub fn encode_pcx_8(image: &RawImage, palette: Option<&Palette>) -> Vec<u8> {
+    let bytes_per_line = (image.w + 1) & !1;
+    let mut dest: Vec<u8> = Vec::with_capacity(128 + image.w * image.h + 769);
+    let mut line: Vec<u8> = vec![0u8; bytes_per_line];
+
+    write_pcx_header(&mut dest, image.w, image.h, 1, bytes_per_line);
+
+    for y in 0..image.h {
+        line[..image.w].copy_from_slice(&image.pixels[(y * image.w)..((y + 1) * image.w)]);
+        write_line(&mut dest, &line);
+    }
+
+    if let Some(palette) = palette {
+        dest.push(12);
+
+        for i in 0..256 {
+            let color = palette.colors.get(i).copied().unwrap_or_else(RawColor::empty);
+            dest.extend_from_slice(&[color.r, color.g, color.b]);
+        }
+    }
+
+    dest
+}
+
+pub fn encode_pcx_24(image: &RawImage) -> Vec<u8> {
+    let bytes_per_line = (image.w + 1) & !1;
+    let mut dest: Vec<u8> = Vec::with_capacity(128 + image.w * image.h * 3);
+    let mut line: Vec<u8> = vec![0u8; bytes_per_line * 3];
+
+    write_pcx_header(&mut dest, image.w, image.h, 3, bytes_per_line);
+
+    for y in 0..image.h {
+        for x in 0..image.w {
+            let offset = (y * image.w + x) * 4;
+            line[x] = image.pixels[offset];
+            line[bytes_per_line + x] = image.pixels[offset + 1];
+            line[bytes_per_line * 2 + x] = image.pixels[offset + 2];
+        }
+
+        write_line(&mut dest, &line[..bytes_per_line]);
+        write_line(&mut dest, &line[bytes_per_line..(bytes_per_line * 2)]);
+        write_line(&mut dest, &line[(bytes_per_line * 2)..]);
+    }
+
+    dest
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::drawing::sff::data::BufferReader;
+
+    use super::*;
+
+    fn palette() -> Arc<Palette> {
+        Arc::new(Palette::from_colors((0..256).map(|i| RawColor::new(i as u8, 10, (255 - i) as u8, 255)).collect()))
+    }
+
+    #[test]
+    fn pcx_round_trip() {
+        let data = encode_pcx_8(&RawImage::indexed(3, 2, vec![1, 2, 3, 0xc4, 0xc4, 0], palette()), None);
+        let image = read_pcx(&mut BufferReader::new(&data)).unwrap();
+
+        assert_eq!((image.borrow().w, image.borrow().h), (3, 2));
+        assert_eq!(*image.borrow().pixels, vec![1, 2, 3, 0xc4, 0xc4, 0]);
+    }
+
+    #[test]
+    fn pcx_24_round_trip() {
+        for (w, h) in [(3, 2), (4, 3), (1, 1)].iter() {
+            let pixels: Vec<u8> = (0..w * h)
+                .flat_map(|i| vec![(i * 50) as u8, 0xc7, (255 - i * 3) as u8, 255])
+                .collect();
+            let data = encode_pcx_24(&RawImage::rgba(*w, *h, pixels.clone()));
+            let image = read_pcx(&mut BufferReader::new(&data)).unwrap();
+            let image = image.borrow();
+
+            // Scanlines are padded to an even number of bytes per plane
+            assert_eq!(u16::from_le_bytes([data[66], data[67]]) as usize, (w + 1) & !1);
+            assert_eq!((image.w, image.h), (*w, *h));
+            assert!(image.format == PixelFormat::Rgba);
+            assert_eq!(*image.pixels, pixels);
+        }
+    }
+
+    #[test]
+    fn pcx_24_ignores_alpha() {
+        let data = encode_pcx_24(&RawImage::rgba(1, 1, vec![1, 2, 3, 0]));
+        let image = read_pcx(&mut BufferReader::new(&data)).unwrap();
+
+        assert_eq!(*image.borrow().pixels, vec![1, 2, 3, 255]);
+    }
+
+    #[test]
+    fn pcx_rejects_sizes_larger_than_the_data() {
+        let mut data = encode_pcx_8(&RawImage::indexed(2, 2, vec![1, 2, 3, 4], palette()), None);
+
+        data[8..10].copy_from_slice(&0xffffu16.to_le_bytes());
+        data[10..12].copy_from_slice(&0xffffu16.to_le_bytes());
+
+        assert!(read_pcx(&mut BufferReader::new(&data)).is_err());
+    }
+
+    #[test]
+    fn pcx_rejects_truncated_headers() {
+        let data = encode_pcx_8(&RawImage::indexed(2, 2, vec![1, 2, 3, 4], palette()), None);
+
+        assert!(read_pcx(&mut BufferReader::new(&data[..100].to_vec())).is_err());
+    }
 }
@@ -0,0 +1,235 @@
+use png::{BitDepth, ColorType, Decoder, Encoder, Transformations};
+
+use crate::core::error::DataError;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+pub struct PngImage {
+    pub w: usize,
+    pub h: usize,
+    pub pixels: Vec<u8>,
+}
+
+fn get_png_data(data: &[u8]) -> &[u8] {
+    // SFFv2 stores the uncompressed size in the first 4 bytes
+    if !data.starts_with(&PNG_SIGNATURE) && data.len() > 4 {
+        return &data[4..];
+    }
+
+    data
+}
+
+pub fn decode_png_indexed(data: &[u8]) -> Result<PngImage, DataError> {
+    let decoder = Decoder::new(get_png_data(data));
+    let mut reader = decoder.read_info()
+        .map_err(|error| DataError::decode_failure(format!("Invalid png: {}", error)))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)
+        .map_err(|error| DataError::decode_failure(format!("Invalid png: {}", error)))?;
+
+    if info.color_type != ColorType::Indexed && info.color_type != ColorType::Grayscale {
+        return Err(DataError::decode_failure(format!("Invalid png8 color type: {:?}", info.color_type)));
+    }
+
+    let bits = match info.bit_depth {
+        BitDepth::One => 1,
+        BitDepth::Two => 2,
+        BitDepth::Four => 4,
+        BitDepth::Eight => 8,
+        BitDepth::Sixteen => {
+            return Err(DataError::decode_failure("Invalid png8 bit depth: 16".to_string()));
+        }
+    };
+
+    let w = info.width as usize;
+    let h = info.height as usize;
+    let mut pixels = Vec::with_capacity(w * h);
+
+    for y in 0..h {
+        let line = &buffer[y * info.line_size..(y + 1) * info.line_size];
+
+        for x in 0..w {
+            let bit_offset = x * bits;
+            let byte = line[bit_offset / 8];
+            let shift = 8 - bits - (bit_offset % 8);
+
+            pixels.push((byte >> shift) & ((1u16 << bits) - 1) as u8);
+        }
+    }
+
+    Ok(PngImage { w, h, pixels })
+}
+
+pub fn decode_png_rgba(data: &[u8]) -> Result<PngImage, DataError> {
+    let mut decoder = Decoder::new(get_png_data(data));
+    decoder.set_transformations(Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()
+        .map_err(|error| DataError::decode_failure(format!("Invalid png: {}", error)))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)
+        .map_err(|error| DataError::decode_failure(format!("Invalid png: {}", error)))?;
+
+    let w = info.width as usize;
+    let h = info.height as usize;
+    let mut pixels = Vec::with_capacity(w * h * 4);
+
+    for y in 0..h {
+        let line = &buffer[y * info.line_size..(y + 1) * info.line_size];
+
+        match info.color_type {
+            ColorType::Rgba => pixels.extend_from_slice(&line[..w * 4]),
+            ColorType::Rgb => {
+                for pixel in line[..w * 3].chunks(3) {
+                    pixels.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 255]);
+                }
+            }
+            ColorType::GrayscaleAlpha => {
+                for pixel in line[..w * 2].chunks(2) {
+                    pixels.extend_from_slice(&[pixel[0], pixel[0], pixel[0], pixel[1]]);
+                }
+            }
+            ColorType::Grayscale => {
+                for pixel in line[..w].iter() {
+                    pixels.extend_from_slice(&[*pixel, *pixel, *pixel, 255]);
+                }
+            }
+            ColorType::Indexed => {
+                return Err(DataError::decode_failure("Invalid png: palette was not expanded".to_string()));
+            }
+        }
+    }
+
+    Ok(PngImage { w, h, pixels })
+}
+
+pub fn encode_png_rgba(w: usize, h: usize, pixels: &[u8]) -> Result<Vec<u8>, DataError> {
+    let mut dest: Vec<u8> = Vec::new();
+
+    {
+        let mut encoder = Encoder::new(&mut dest, w as u32, h as u32);
+        encoder.set_color(ColorType::Rgba);
+        encoder.set_depth(BitDepth::Eight);
+
+        let mut writer = encoder.write_header()
+            .map_err(|error| DataError::new(format!("Error encoding png: {}", error)))?;
+
+        writer.write_image_data(pixels)
+            .map_err(|error| DataError::new(format!("Error encoding png: {}", error)))?;
+    }
+
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(w: usize, h: usize, color: ColorType, depth: BitDepth, palette: Option<Vec<u8>>, data: &[u8]) -> Vec<u8> {
+        let mut dest: Vec<u8> = Vec::new();
+
+        {
+            let mut encoder = Encoder::new(&mut dest, w as u32, h as u32);
+            encoder.set_color(color);
+            encoder.set_depth(depth);
+
+            if let Some(palette) = palette {
+                encoder.set_palette(palette);
+            }
+
+            encoder.write_header().unwrap().write_image_data(data).unwrap();
+        }
+
+        dest
+    }
+
+    // Packs pixels into rows of the given bit depth, most significant bits first
+    fn pack(pixels: &[u8], w: usize, bits: usize) -> Vec<u8> {
+        let line_size = (w * bits - 1) / 8 + 1;
+        let mut data = Vec::new();
+
+        for row in pixels.chunks(w) {
+            let mut line = vec![0u8; line_size];
+
+            for (x, pixel) in row.iter().enumerate() {
+                let bit_offset = x * bits;
+                line[bit_offset / 8] |= pixel << (8 - bits - bit_offset % 8);
+            }
+
+            data.extend_from_slice(&line);
+        }
+
+        data
+    }
+
+    fn bit_depth(bits: usize) -> BitDepth {
+        match bits {
+            1 => BitDepth::One,
+            2 => BitDepth::Two,
+            4 => BitDepth::Four,
+            _ => BitDepth::Eight,
+        }
+    }
+
+    #[test]
+    fn decodes_indexed_pixels_of_every_bit_depth() {
+        let (w, h) = (5, 3);
+
+        for bits in [1, 2, 4, 8].iter() {
+            let colors = 1usize << bits;
+            let pixels: Vec<u8> = (0..w * h).map(|i| ((i * 7 + i / w) % colors) as u8).collect();
+            let palette: Vec<u8> = (0..colors * 3).map(|i| i as u8).collect();
+            let indexed = encode(w, h, ColorType::Indexed, bit_depth(*bits), Some(palette), &pack(&pixels, w, *bits));
+            let grayscale = encode(w, h, ColorType::Grayscale, bit_depth(*bits), None, &pack(&pixels, w, *bits));
+
+            for (color, png) in [("indexed", indexed), ("grayscale", grayscale)].iter() {
+                let image = decode_png_indexed(png).unwrap();
+
+                assert_eq!((image.w, image.h), (w, h));
+                assert_eq!(image.pixels, pixels, "{} bits, {}", bits, color);
+            }
+        }
+    }
+
+    #[test]
+    fn indexed_decoding_rejects_colors_and_16_bit_depth() {
+        let rgb = encode(1, 1, ColorType::Rgb, BitDepth::Eight, None, &[1, 2, 3]);
+        let gray16 = encode(1, 1, ColorType::Grayscale, BitDepth::Sixteen, None, &[1, 2]);
+
+        assert!(decode_png_indexed(&rgb).is_err());
+        assert!(decode_png_indexed(&gray16).is_err());
+        assert!(decode_png_indexed(&[0, 0, 0, 0, 1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn expands_rgb_and_grayscale_to_rgba() {
+        let rgb = encode(2, 1, ColorType::Rgb, BitDepth::Eight, None, &[1, 2, 3, 4, 5, 6]);
+        let gray = encode(2, 1, ColorType::Grayscale, BitDepth::Eight, None, &[7, 8]);
+        let gray_alpha = encode(2, 1, ColorType::GrayscaleAlpha, BitDepth::Eight, None, &[7, 100, 8, 200]);
+
+        assert_eq!(decode_png_rgba(&rgb).unwrap().pixels, vec![1, 2, 3, 255, 4, 5, 6, 255]);
+        assert_eq!(decode_png_rgba(&gray).unwrap().pixels, vec![7, 7, 7, 255, 8, 8, 8, 255]);
+        assert_eq!(decode_png_rgba(&gray_alpha).unwrap().pixels, vec![7, 7, 7, 100, 8, 8, 8, 200]);
+    }
+
+    #[test]
+    fn expands_palettes_and_16_bit_colors_to_rgba() {
+        let indexed = encode(3, 1, ColorType::Indexed, BitDepth::Two, Some(vec![0, 0, 0, 10, 20, 30, 40, 50, 60]), &pack(&[1, 2, 0], 3, 2));
+        let rgb16 = encode(1, 1, ColorType::Rgb, BitDepth::Sixteen, None, &[1, 0, 2, 0, 3, 0]);
+
+        assert_eq!(decode_png_rgba(&indexed).unwrap().pixels, vec![10, 20, 30, 255, 40, 50, 60, 255, 0, 0, 0, 255]);
+        assert_eq!(decode_png_rgba(&rgb16).unwrap().pixels, vec![1, 2, 3, 255]);
+    }
+
+    #[test]
+    fn rgba_round_trip_with_the_sff_size_prefix() {
+        let pixels: Vec<u8> = (0..3 * 2 * 4).map(|i| (i * 11) as u8).collect();
+        let mut data = ((3 * 2 * 4) as u32).to_le_bytes().to_vec();
+
+        data.extend(encode_png_rgba(3, 2, &pixels).unwrap());
+
+        let image = decode_png_rgba(&data).unwrap();
+
+        assert_eq!((image.w, image.h), (3, 2));
+        assert_eq!(image.pixels, pixels);
+    }
+}
@@ -1,4 +1,6 @@
-use super::data::DataReader;
+use crate::core::error::DataError;
+
+use super::data::{check_decoded_size, DataReader};
 
 struct Rle5Packet {
     run_len: u8,
@@ -7,31 +9,32 @@ struct Rle5Packet {
 }
 
 impl Rle5Packet {
-    fn read(reader: &mut dyn DataReader) -> Rle5Packet {
+    fn read(reader: &mut dyn DataReader) -> Result<Rle5Packet, DataError> {
         let mut rle5 = Rle5Packet {
             run_len: 0,
             color_bit: 0,
             data_len: 0,
         };
-        rle5.run_len = reader.get_u8();
+        rle5.run_len = reader.get_u8()?;
         {
-            let byte_process = reader.get_u8();
+            let byte_process = reader.get_u8()?;
             rle5.color_bit = (byte_process & 0x80) / 0x80;
             rle5.data_len = byte_process & 0x7f;
         }
-        rle5
+        Ok(rle5)
     }
 }
 
-pub fn decode_rle5(reader: &mut dyn DataReader) -> Vec<u8> {
+pub fn decode_rle5(reader: &mut dyn DataReader) -> Result<Vec<u8>, DataError> {
     let mut dest: Vec<u8> = Vec::new();
+    let size;
     {
         let mut color: u8 = 0;
-        reader.get_u32(); // skip first 4 bytes (result size)
+        size = reader.get_u32()?;
         while !reader.eof() {
-            let rle5 = Rle5Packet::read(reader);
+            let rle5 = Rle5Packet::read(reader)?;
             if rle5.color_bit == 1 {
-                color = reader.get_u8();
+                color = reader.get_u8()?;
             }
             if rle5.color_bit == 0 {
                 color = 0;
@@ -40,7 +43,7 @@ pub fn decode_rle5(reader: &mut dyn DataReader) -> Vec<u8> {
                 dest.push(color);
             }
             for _ in 0..rle5.data_len {
-                let one_byte = reader.get_u8();
+                let one_byte = reader.get_u8()?;
                 color = one_byte & 0x1f;
                 let run_len: u8 = one_byte >> 5;
                 for _ in 0..run_len {
@@ -49,20 +52,20 @@ pub fn decode_rle5(reader: &mut dyn DataReader) -> Vec<u8> {
             }
         }
     }
-    dest
+    check_decoded_size(dest, size)
 }
 
-pub fn decode_rle8(reader: &mut dyn DataReader) -> Vec<u8> {
+pub fn decode_rle8(reader: &mut dyn DataReader) -> Result<Vec<u8>, DataError> {
     let mut dest: Vec<u8> = Vec::new();
     let mut ch: u8;
     let mut color: u8;
 
-    reader.get_u32(); // skip first 4 bytes
+    let size = reader.get_u32()?;
 
     while !reader.eof() {
-        ch = reader.get_u8();
+        ch = reader.get_u8()?;
         if (ch & 0xc0) == 0x40 {
-            color = reader.get_u8();
+            color = reader.get_u8()?;
             for _ in 0..(ch & 0x3f) {
                 dest.push(color);
             }
@@ -72,5 +75,112 @@ pub fn decode_rle8(reader: &mut dyn DataReader) -> Vec<u8> {
         }
     }
 
+    check_decoded_size(dest, size)
+}
+
+fn get_runs(pixels: &[u8], max_length: usize) -> Vec<(u8, usize)> {
+    let mut runs: Vec<(u8, usize)> = Vec::new();
+
+    for &pixel in pixels.iter() {
+        match runs.last_mut() {
+            Some((color, length)) if *color == pixel && *length < max_length => *length += 1,
+            _ => runs.push((pixel, 1)),
+        }
+    }
+
+    runs
+}
+
+pub fn encode_rle5(pixels: &[u8]) -> Vec<u8> {
+    let mut dest: Vec<u8> = Vec::with_capacity(pixels.len() / 2 + 4);
+    let runs = get_runs(pixels, 255);
+    let mut index = 0;
+
+    dest.extend_from_slice(&(pixels.len() as u32).to_le_bytes());
+
+    while index < runs.len() {
+        let (color, run_len) = runs[index];
+        let packet_offset = dest.len();
+        let mut data_len: u8 = 0;
+
+        dest.push(run_len as u8);
+        dest.push(0);
+
+        if color != 0 {
+            dest[packet_offset + 1] = 0x80;
+            dest.push(color);
+        }
+
+        index += 1;
+
+        // Short runs of 5-bit colors are packed as (run << 5 | color) data bytes
+        while index < runs.len() && data_len < 0x7f {
+            let (color, run_len) = runs[index];
+
+            if color > 0x1f || run_len > 7 {
+                break;
+            }
+
+            dest.push(((run_len as u8) << 5) | color);
+            data_len += 1;
+            index += 1;
+        }
+
+        dest[packet_offset + 1] |= data_len;
+    }
+
+    dest
+}
+
+pub fn encode_rle8(pixels: &[u8]) -> Vec<u8> {
+    let mut dest: Vec<u8> = Vec::with_capacity(pixels.len() + 4);
+
+    dest.extend_from_slice(&(pixels.len() as u32).to_le_bytes());
+
+    for (color, run_len) in get_runs(pixels, 0x3f) {
+        if run_len == 1 && (color & 0xc0) != 0x40 {
+            dest.push(color);
+        } else {
+            dest.push(0x40 | run_len as u8);
+            dest.push(color);
+        }
+    }
+
     dest
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::drawing::sff::data::BufferReader;
+
+    use super::*;
+
+    fn pixels() -> Vec<u8> {
+        let mut pixels = Vec::new();
+
+        for (color, len) in [(0, 9), (5, 1), (31, 3), (0x41, 2), (2, 70), (0, 1), (17, 4)].iter() {
+            pixels.extend(vec![*color as u8; *len]);
+        }
+
+        pixels
+    }
+
+    #[test]
+    fn rle8_rejects_truncated_data() {
+        let encoded = encode_rle8(&pixels());
+
+        for len in 0..encoded.len() {
+            assert!(decode_rle8(&mut BufferReader::new(&encoded[..len].to_vec())).is_err());
+        }
+    }
+
+    #[test]
+    fn rle5_rejects_truncated_data() {
+        let pixels: Vec<u8> = pixels().into_iter().map(|pixel| pixel & 0x1f).collect();
+        let encoded = encode_rle5(&pixels);
+
+        for len in 0..encoded.len() {
+            assert!(decode_rle5(&mut BufferReader::new(&encoded[..len].to_vec())).is_err());
+        }
+    }
+}
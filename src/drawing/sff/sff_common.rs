@@ -63,25 +63,80 @@ pub struct SffMetadata {
     pub verhi: u8,
 }
 
-impl SffData {
-    pub fn create_texture(
-        &self,
-        preferred_palette: Option<Arc<Palette>>,
-        flags: TextureFlags
-    ) -> Result<Arc<Texture>, DataError> {
-        let raw_image = self.image.clone();
+#[derive(Clone)]
+pub struct SpriteNode {
+    pub groupno: i16,
+    pub imageno: i16,
+    pub x: i16,
+    pub y: i16,
+    pub w: u16,
+    pub h: u16,
+    pub fmt: u8,
+    pub offset: usize,
+    pub len: usize,
+    pub palindex: Option<i16>, // Known only after decoding for SFFv1
+    pub linked: Option<usize>,
+    pub is_shared: bool,
+    pub palette_source: Option<usize>,
+}
+
+#[derive(Clone)]
+pub struct SffIndex {
+    pub metadata: SffMetadata,
+    pub sprites: Vec<SpriteNode>,
+    pub palettes: Vec<Arc<Palette>>,
+    pub palette_ids: Vec<(i16, i16)>, // Group and item number of each palette, SFFv2 only
+}
+
+impl SffIndex {
+    // Follows link chains so every linked sprite points to the sprite holding the pixels
+    pub fn resolve_links(&mut self) -> Result<(), DataError> {
+        for index in 0..self.sprites.len() {
+            let mut target = index;
+            let mut steps = 0;
+
+            while let Some(linked) = self.sprites[target].linked {
+                if linked >= self.sprites.len() || steps > self.sprites.len() {
+                    let sprite = &self.sprites[index];
 
-        let palette = match preferred_palette {
+                    return Err(DataError::decode_failure(format!(
+                        "invalid linked image: {},{}",
+                        sprite.groupno, sprite.imageno
+                    )));
+                }
+
+                target = linked;
+                steps += 1;
+            }
+
+            self.sprites[index].linked = if target == index { None } else { Some(target) };
+        }
+
+        Ok(())
+    }
+}
+
+impl SffData {
+    pub fn select_palette(&self, preferred_palette: Option<Arc<Palette>>) -> Arc<Palette> {
+        match preferred_palette {
             Some(palette) => {
                 if palette.is_empty() || self.palindex != 0 {
                     self.image.color_table.clone()
                 } else {
-                    palette.clone()
+                    palette
                 }
             }
             None => self.image.color_table.clone()
-        };
+        }
+    }
 
+    pub fn create_texture(
+        &self,
+        preferred_palette: Option<Arc<Palette>>,
+        flags: TextureFlags
+    ) -> Result<Arc<Texture>, DataError> {
+        let raw_image = self.image.clone();
+        let palette = self.select_palette(preferred_palette);
         let image = raw_image.create_image_with_palette(&palette);
 
         Ok(Texture::allocate(
@@ -2,35 +2,139 @@ use std::sync::Arc;
 
 use crate::core::error::DataError;
 
+use super::data::{DataReader, FileReader};
 use super::image::Palette;
-use super::sff_common::{SffData, SffMetadata};
+use super::sff_common::{SffData, SffIndex, SffMetadata};
 use super::sffv1;
 use super::sffv2;
 
-pub fn read_metadata(path: &str) -> Result<SffMetadata, DataError> {
-    let result_v2 = sffv2::read_metadata(&path);
+// Only the signature and version are read here, so a corrupt file reports the error of its own version
+fn read_version(path: &str) -> Result<u8, DataError> {
+    let mut reader = FileReader::open(path)?;
+    let signature = reader.get_text(12)?;
+    let version = reader.get_buffer(4)?;
 
-    if result_v2.is_err() {
-        return sffv1::read_metadata(&path);
+    if signature != "ElecbyteSpr" {
+        return Err(DataError::decode_failure(format!("invalid signature: {}", signature)));
     }
 
-    result_v2
+    match version[3] {
+        1 | 2 => Ok(version[3]),
+        _ => Err(DataError::decode_failure(format!(
+            "invalid version: {}.{}.{}.{}",
+            version[3], version[2], version[1], version[0]
+        ))),
+    }
+}
+
+pub fn read_metadata(path: &str) -> Result<SffMetadata, DataError> {
+    let result = match read_version(path).map_err(|error| error.with_path(path))? {
+        2 => sffv2::read_metadata(path),
+        _ => sffv1::read_metadata(path),
+    };
+
+    result.map_err(|error| error.with_path(path))
 }
 
 pub fn read_palette(path: &str) -> Result<Arc<Palette>, DataError> {
-    sffv1::read_palette(&path)
+    sffv1::read_palette(&path).map_err(|error| error.with_path(path))
 }
 
 pub fn read_palettes(path: &str) -> Result<Vec<Arc<Palette>>, DataError> {
-    sffv2::read_palettes(&path)
+    sffv2::read_palettes(&path).map_err(|error| error.with_path(path))
 }
 
 pub fn read_images(path: &str, groups: &[i16]) -> Result<Vec<SffData>, DataError> {
-    let result_v2 = sffv2::read_images(&path, &groups);
+    let result = match read_version(path).map_err(|error| error.with_path(path))? {
+        2 => sffv2::read_images(path, groups),
+        _ => sffv1::read_images(path, groups),
+    };
+
+    result.map_err(|error| error.with_path(path))
+}
+
+pub fn read_index(path: &str) -> Result<SffIndex, DataError> {
+    let result = match read_version(path).map_err(|error| error.with_path(path))? {
+        2 => sffv2::read_index(path),
+        _ => sffv1::read_index(path),
+    };
+
+    result.map_err(|error| error.with_path(path))
+}
+
+// The reader is opened once by the caller, decoding sprites on demand would otherwise reopen the file each time
+pub fn decode_sprite(reader: &mut FileReader, path: &str, index: &SffIndex, sprite_index: usize) -> Result<SffData, DataError> {
+    let result = match index.metadata.verhi {
+        2 => sffv2::decode_sprite(reader, path, index, sprite_index),
+        _ => sffv1::decode_sprite(reader, path, index, sprite_index),
+    };
+
+    result.map_err(|error| error.with_path(path))
+}
 
-    if result_v2.is_err() {
-        return sffv1::read_images(&path, &groups);
+#[cfg(test)]
+mod tests {
+    use crate::drawing::sff::image::{RawColor, RawImage};
+    use crate::drawing::sff::sff_writer::{encode_sff_v1, encode_sff_v2, SpriteCompression};
+    use crate::io::{file_system, memory_file_system::test_mount};
+
+    use super::*;
+
+    fn write(name: &str, data: &[u8]) -> String {
+        let path = format!("{}/{}", test_mount("sff_parser_tests"), name);
+        file_system::write_file(&path, data).unwrap();
+        path
+    }
+
+    fn sprites() -> (Vec<SffData>, Vec<Arc<Palette>>) {
+        let palette = Arc::new(Palette::from_colors((0..256).map(|i| RawColor::new(i as u8, 0, 0, 255)).collect()));
+        let sprites = vec![SffData {
+            image: Arc::new(RawImage::indexed(4, 3, (0..12).collect(), palette.clone())),
+            groupno: 0,
+            imageno: 0,
+            x: 0,
+            y: 0,
+            palindex: 0,
+            linked: 0,
+        }];
+
+        (sprites, vec![palette])
+    }
+
+    #[test]
+    fn reads_both_versions() {
+        let (sprites, palettes) = sprites();
+        let v1_path = write("v1.sff", &encode_sff_v1(&sprites, &palettes).unwrap());
+        let v2_path = write("v2.sff", &encode_sff_v2(&sprites, &palettes, SpriteCompression::Rle8).unwrap());
+
+        assert_eq!(read_index(&v1_path).unwrap().metadata.verhi, 1);
+        assert_eq!(read_index(&v2_path).unwrap().metadata.verhi, 2);
+        assert_eq!(read_images(&v1_path, &[]).unwrap().len(), 1);
+        assert_eq!(read_images(&v2_path, &[]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn corrupt_v2_files_report_the_v2_error() {
+        let (sprites, palettes) = sprites();
+        let data = encode_sff_v2(&sprites, &palettes, SpriteCompression::Rle8).unwrap();
+        let path = write("truncated_v2.sff", &data[..data.len() - 10]);
+        let expected = sffv2::read_index(&path).err().unwrap();
+        let error = read_index(&path).err().unwrap();
+
+        assert_eq!(error.message, expected.message);
+        assert!(!error.message.contains("invalid version"));
+        assert!(!read_images(&path, &[]).err().unwrap().message.contains("invalid version"));
     }
 
-    result_v2
+    #[test]
+    fn rejects_unknown_signatures_and_versions() {
+        let (sprites, palettes) = sprites();
+        let mut data = encode_sff_v2(&sprites, &palettes, SpriteCompression::Rle8).unwrap();
+
+        data[15] = 3;
+        assert!(read_index(&write("v3.sff", &data)).err().unwrap().message.contains("invalid version"));
+
+        data[0] = b'X';
+        assert!(read_index(&write("signature.sff", &data)).err().unwrap().message.contains("invalid signature"));
+    }
 }
@@ -0,0 +1,543 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use crate::core::error::DataError;
+use crate::io::file_system;
+
+use super::image::{Palette, PixelFormat, RawImage};
+use super::lz5::encode_lz5;
+use super::pcx::{encode_pcx_24, encode_pcx_8};
+use super::png_image::encode_png_rgba;
+use super::rle5::{encode_rle5, encode_rle8};
+use super::sff_common::SffData;
+
+const SIGNATURE: &[u8; 12] = b"ElecbyteSpr\0";
+const HEADER_SIZE: usize = 512;
+const V1_SUBHEADER_SIZE: usize = 32;
+const V2_SPRITE_NODE_SIZE: usize = 28;
+const V2_PALETTE_NODE_SIZE: usize = 16;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SffVersion {
+    V1,
+    V2,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SpriteCompression {
+    Raw,
+    Rle8,
+    Rle5,
+    Lz5,
+}
+
+pub fn write_sff(
+    path: &str,
+    sprites: &[SffData],
+    palettes: &[Arc<Palette>],
+    version: SffVersion,
+    compression: SpriteCompression,
+) -> Result<(), DataError> {
+    let data = match version {
+        SffVersion::V1 => encode_sff_v1(sprites, palettes)?,
+        SffVersion::V2 => encode_sff_v2(sprites, palettes, compression)?,
+    };
+
+    file_system::write_file(path, &data)
+}
+
+struct PaletteTable {
+    palettes: Vec<Arc<Palette>>,
+}
+
+impl PaletteTable {
+    fn new(palettes: &[Arc<Palette>]) -> Self {
+        PaletteTable {
+            palettes: palettes.to_vec(),
+        }
+    }
+
+    fn resolve(&mut self, sprite: &SffData) -> usize {
+        let palindex = sprite.palindex as usize;
+
+        if palindex < self.palettes.len() {
+            return palindex;
+        }
+
+        let color_table = &sprite.image.color_table;
+
+        if let Some(index) = self.palettes.iter().position(|palette| palette.equal(color_table)) {
+            return index;
+        }
+
+        self.palettes.push(color_table.clone());
+
+        self.palettes.len() - 1
+    }
+
+    fn find_duplicate(&self, index: usize) -> Option<usize> {
+        self.palettes[..index]
+            .iter()
+            .position(|palette| palette.equal(&self.palettes[index]))
+    }
+}
+
+struct DuplicateFinder {
+    images: HashMap<u64, Vec<(usize, usize)>>,
+}
+
+impl DuplicateFinder {
+    fn new() -> Self {
+        DuplicateFinder {
+            images: HashMap::new(),
+        }
+    }
+
+    // Returns the first sprite with the same pixels and palette, registering the sprite otherwise
+    fn find_or_insert(&mut self, sprites: &[SffData], index: usize, palindex: usize) -> Option<usize> {
+        let image = &sprites[index].image;
+        let mut hasher = DefaultHasher::new();
+        image.w.hash(&mut hasher);
+        image.h.hash(&mut hasher);
+        image.pixels.hash(&mut hasher);
+        let candidates = self.images.entry(hasher.finish()).or_default();
+
+        for (candidate, candidate_palindex) in candidates.iter() {
+            let other = &sprites[*candidate].image;
+
+            if *candidate_palindex == palindex
+                && other.w == image.w
+                && other.h == image.h
+                && other.format == image.format
+                && other.pixels == image.pixels
+            {
+                return Some(*candidate);
+            }
+        }
+
+        candidates.push((index, palindex));
+
+        None
+    }
+}
+
+fn validate_image(sprite: &SffData) -> Result<(), DataError> {
+    let image = &sprite.image;
+
+    if image.w == 0 || image.h == 0 || image.pixels.len() != image.w * image.h * image.format.bytes_per_pixel() {
+        return Err(DataError::new(format!(
+            "Invalid sprite image. GroupNo={}. ImageNo={}",
+            sprite.groupno, sprite.imageno
+        )));
+    }
+
+    Ok(())
+}
+
+fn write_header(dest: &mut Vec<u8>, version: [u8; 4]) {
+    dest.extend_from_slice(SIGNATURE);
+    dest.extend_from_slice(&version);
+}
+
+fn count_groups(sprites: &[SffData]) -> usize {
+    let mut groups: Vec<i16> = sprites.iter().map(|sprite| sprite.groupno).collect();
+    groups.sort_unstable();
+    groups.dedup();
+    groups.len()
+}
+
+pub fn encode_sff_v1(sprites: &[SffData], palettes: &[Arc<Palette>]) -> Result<Vec<u8>, DataError> {
+    let mut palette_table = PaletteTable::new(palettes);
+    let mut duplicates = DuplicateFinder::new();
+    let mut last_palette: Option<Arc<Palette>> = None;
+    let mut dest: Vec<u8> = Vec::new();
+
+    write_header(&mut dest, [0, 1, 0, 1]);
+    dest.extend_from_slice(&(count_groups(sprites) as u32).to_le_bytes());
+    dest.extend_from_slice(&(sprites.len() as u32).to_le_bytes());
+    dest.extend_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+    dest.extend_from_slice(&(V1_SUBHEADER_SIZE as u32).to_le_bytes());
+    dest.push(0); // individual palettes
+    dest.resize(HEADER_SIZE, 0);
+
+    for (index, sprite) in sprites.iter().enumerate() {
+        validate_image(sprite)?;
+
+        let palindex = palette_table.resolve(sprite);
+        let linked = duplicates.find_or_insert(sprites, index, palindex);
+        let mut is_shared = false;
+
+        let data = match (linked, sprite.image.format) {
+            (Some(_), _) => Vec::new(),
+            (None, PixelFormat::Rgba) => {
+                // True color sprites carry no palette, so they reuse the previous one
+                is_shared = true;
+                encode_pcx_24(&sprite.image)
+            }
+            (None, PixelFormat::Indexed) => {
+                let palette = palette_table.palettes[palindex].clone();
+
+                is_shared = last_palette.as_ref().map_or(false, |last| last.equal(&palette));
+
+                if is_shared {
+                    encode_pcx_8(&sprite.image, None)
+                } else {
+                    last_palette = Some(palette.clone());
+                    encode_pcx_8(&sprite.image, Some(&palette))
+                }
+            }
+        };
+
+        let offset_next_sprite = dest.len() + V1_SUBHEADER_SIZE + data.len();
+
+        dest.extend_from_slice(&(offset_next_sprite as u32).to_le_bytes());
+        dest.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        dest.extend_from_slice(&sprite.x.to_le_bytes());
+        dest.extend_from_slice(&sprite.y.to_le_bytes());
+        dest.extend_from_slice(&sprite.groupno.to_le_bytes());
+        dest.extend_from_slice(&sprite.imageno.to_le_bytes());
+        dest.extend_from_slice(&(linked.unwrap_or(0) as i16).to_le_bytes());
+        dest.push(is_shared as u8);
+        dest.extend_from_slice(&[0u8; 13]);
+        dest.extend_from_slice(&data);
+    }
+
+    Ok(dest)
+}
+
+fn encode_png32(image: &RawImage) -> Result<Vec<u8>, DataError> {
+    let mut dest: Vec<u8> = Vec::new();
+
+    dest.extend_from_slice(&((image.w * image.h * 4) as u32).to_le_bytes());
+    dest.extend_from_slice(&encode_png_rgba(image.w, image.h, &image.pixels)?);
+
+    Ok(dest)
+}
+
+fn encode_sprite_v2(image: &RawImage, compression: SpriteCompression) -> Result<(u8, u8, Vec<u8>), DataError> {
+    if !image.is_indexed() {
+        return Ok((12, 32, encode_png32(image)?));
+    }
+
+    let is_5_bit = image.pixels.iter().all(|pixel| *pixel <= 0x1f);
+    let colordepth = if is_5_bit { 5 } else { 8 };
+
+    Ok(match compression {
+        SpriteCompression::Raw => (0, 8, image.pixels.to_vec()),
+        SpriteCompression::Rle8 => (2, 8, encode_rle8(&image.pixels)),
+        SpriteCompression::Rle5 => (3, colordepth, encode_rle5(&image.pixels)),
+        SpriteCompression::Lz5 if is_5_bit => (4, 5, encode_lz5(&image.pixels)?),
+        SpriteCompression::Lz5 => (2, 8, encode_rle8(&image.pixels)),
+    })
+}
+
+pub fn encode_sff_v2(
+    sprites: &[SffData],
+    palettes: &[Arc<Palette>],
+    compression: SpriteCompression,
+) -> Result<Vec<u8>, DataError> {
+    let mut palette_table = PaletteTable::new(palettes);
+    let mut duplicates = DuplicateFinder::new();
+    let mut sprite_nodes: Vec<u8> = Vec::with_capacity(sprites.len() * V2_SPRITE_NODE_SIZE);
+    let mut palette_nodes: Vec<u8> = Vec::new();
+    let mut ldata: Vec<u8> = Vec::new();
+
+    for (index, sprite) in sprites.iter().enumerate() {
+        validate_image(sprite)?;
+
+        let palindex = palette_table.resolve(sprite);
+        let linked = duplicates.find_or_insert(sprites, index, palindex);
+        let (fmt, colordepth, data) = match linked {
+            Some(_) => (0, 0, Vec::new()),
+            None => encode_sprite_v2(&sprite.image, compression)?,
+        };
+
+        sprite_nodes.extend_from_slice(&sprite.groupno.to_le_bytes());
+        sprite_nodes.extend_from_slice(&sprite.imageno.to_le_bytes());
+        sprite_nodes.extend_from_slice(&(sprite.image.w as i16).to_le_bytes());
+        sprite_nodes.extend_from_slice(&(sprite.image.h as i16).to_le_bytes());
+        sprite_nodes.extend_from_slice(&sprite.x.to_le_bytes());
+        sprite_nodes.extend_from_slice(&sprite.y.to_le_bytes());
+        sprite_nodes.extend_from_slice(&(linked.unwrap_or(0) as i16).to_le_bytes());
+        sprite_nodes.push(fmt);
+        sprite_nodes.push(colordepth);
+        sprite_nodes.extend_from_slice(&(if data.is_empty() { 0 } else { ldata.len() as u32 }).to_le_bytes());
+        sprite_nodes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        sprite_nodes.extend_from_slice(&(palindex as i16).to_le_bytes());
+        sprite_nodes.extend_from_slice(&0i16.to_le_bytes());
+
+        ldata.extend_from_slice(&data);
+    }
+
+    for (index, palette) in palette_table.palettes.iter().enumerate() {
+        let duplicate = palette_table.find_duplicate(index);
+        let numcols = palette.colors.len();
+
+        palette_nodes.extend_from_slice(&1i16.to_le_bytes());
+        palette_nodes.extend_from_slice(&(index as i16 + 1).to_le_bytes());
+        palette_nodes.extend_from_slice(&(numcols as i16).to_le_bytes());
+        palette_nodes.extend_from_slice(&(duplicate.unwrap_or(0) as i16).to_le_bytes());
+
+        match duplicate {
+            Some(_) => {
+                palette_nodes.extend_from_slice(&0u32.to_le_bytes());
+                palette_nodes.extend_from_slice(&0u32.to_le_bytes());
+            }
+            None => {
+                palette_nodes.extend_from_slice(&(ldata.len() as u32).to_le_bytes());
+                palette_nodes.extend_from_slice(&((numcols * 4) as u32).to_le_bytes());
+
+                for color in palette.colors.iter() {
+                    ldata.extend_from_slice(&[color.r, color.g, color.b, 0]);
+                }
+            }
+        }
+    }
+
+    let first_sprnode_offset = HEADER_SIZE;
+    let first_palnode_offset = first_sprnode_offset + sprite_nodes.len();
+    let ldata_offset = first_palnode_offset + palette_nodes.len();
+    let tdata_offset = ldata_offset + ldata.len();
+    let mut dest: Vec<u8> = Vec::with_capacity(tdata_offset);
+
+    write_header(&mut dest, [0, 1, 0, 2]);
+    dest.extend_from_slice(&[0u8; 8]);
+    dest.extend_from_slice(&[0, 1, 0, 2]);
+    dest.extend_from_slice(&[0u8; 8]);
+    dest.extend_from_slice(&(first_sprnode_offset as u32).to_le_bytes());
+    dest.extend_from_slice(&(sprites.len() as u32).to_le_bytes());
+    dest.extend_from_slice(&(first_palnode_offset as u32).to_le_bytes());
+    dest.extend_from_slice(&(palette_table.palettes.len() as u32).to_le_bytes());
+    dest.extend_from_slice(&(ldata_offset as u32).to_le_bytes());
+    dest.extend_from_slice(&(ldata.len() as u32).to_le_bytes());
+    dest.extend_from_slice(&(tdata_offset as u32).to_le_bytes());
+    dest.extend_from_slice(&0u32.to_le_bytes());
+    dest.resize(HEADER_SIZE, 0);
+    dest.extend_from_slice(&sprite_nodes);
+    dest.extend_from_slice(&palette_nodes);
+    dest.extend_from_slice(&ldata);
+
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::drawing::sff::data::BufferReader;
+    use crate::drawing::sff::image::{Palette, RawColor, RawImage};
+    use crate::drawing::sff::lz5::{decode_lz5, encode_lz5};
+    use crate::drawing::sff::rle5::{decode_rle5, decode_rle8, encode_rle5, encode_rle8};
+    use crate::drawing::sff::sff_common::SffData;
+    use crate::drawing::sff::{sffv1, sffv2};
+    use crate::io::file_system;
+    use crate::io::memory_file_system::test_mount;
+
+    use super::*;
+
+    fn pseudo_random(len: usize, seed: u32, modulo: u32) -> Vec<u8> {
+        let mut state = seed;
+
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state % modulo) as u8
+            })
+            .collect()
+    }
+
+    fn samples() -> Vec<Vec<u8>> {
+        let mut runs: Vec<u8> = Vec::new();
+
+        for (index, len) in [1, 2, 7, 8, 9, 63, 64, 65, 300, 1000].iter().enumerate() {
+            runs.extend(vec![(index * 3) as u8; *len]);
+        }
+
+        let mut repeated = pseudo_random(200, 7, 32);
+        repeated.extend(repeated.to_vec());
+        repeated.extend(pseudo_random(50, 11, 32));
+        repeated.extend(repeated[100..400].to_vec());
+
+        vec![
+            vec![0],
+            (0..=255).collect(),
+            runs,
+            repeated,
+            pseudo_random(5000, 3, 32),
+            pseudo_random(5000, 5, 4),
+            pseudo_random(5000, 9, 256),
+        ]
+    }
+
+    fn palette(seed: u8) -> Arc<Palette> {
+        Arc::new(Palette::from_colors((0..256)
+            .map(|i| RawColor::new(i as u8, seed, (255 - i) as u8, if i == 0 { 0 } else { 255 }))
+            .collect()))
+    }
+
+    fn sprite(groupno: i16, imageno: i16, image: RawImage, palindex: i16) -> SffData {
+        SffData {
+            image: Arc::new(image),
+            groupno,
+            imageno,
+            x: groupno * 3 - 7,
+            y: imageno * 5 + 2,
+            palindex,
+            linked: 0,
+        }
+    }
+
+    fn sort(mut sprites: Vec<SffData>) -> Vec<SffData> {
+        sprites.sort_by_key(|sprite| (sprite.groupno, sprite.imageno));
+        sprites
+    }
+
+    fn assert_same_sprites(expected: &[SffData], actual: &[SffData]) {
+        assert_eq!(expected.len(), actual.len());
+
+        for (expected, actual) in expected.iter().zip(actual.iter()) {
+            assert_eq!((expected.groupno, expected.imageno), (actual.groupno, actual.imageno));
+            assert_eq!((expected.x, expected.y), (actual.x, actual.y));
+            assert_eq!((expected.image.w, expected.image.h), (actual.image.w, actual.image.h));
+            assert!(expected.image.format == actual.image.format);
+            assert_eq!(expected.image.pixels, actual.image.pixels);
+
+            if expected.image.is_indexed() {
+                assert!(expected.image.color_table.equal(&actual.image.color_table));
+            }
+        }
+    }
+
+    fn indexed_sprites(palettes: &[Arc<Palette>]) -> Vec<SffData> {
+        vec![
+            sprite(0, 1, RawImage::indexed(32, 32, pseudo_random(32 * 32, 2, 4), palettes[1].clone()), 1),
+            sprite(0, 0, RawImage::indexed(17, 9, pseudo_random(17 * 9, 1, 256), palettes[0].clone()), 0),
+            sprite(5, 0, RawImage::indexed(17, 9, pseudo_random(17 * 9, 1, 256), palettes[0].clone()), 0),
+            sprite(9000, 2, RawImage::indexed(1, 1, vec![200], palettes[0].clone()), 0),
+            sprite(9000, 3, RawImage::indexed(40, 3, vec![0xc1; 120], palettes[1].clone()), 1),
+        ]
+    }
+
+    #[test]
+    fn rle8_round_trip() {
+        for pixels in samples() {
+            assert_eq!(decode_rle8(&mut BufferReader::new(&encode_rle8(&pixels))).unwrap(), pixels);
+        }
+    }
+
+    #[test]
+    fn rle5_round_trip() {
+        for pixels in samples() {
+            assert_eq!(decode_rle5(&mut BufferReader::new(&encode_rle5(&pixels))).unwrap(), pixels);
+        }
+    }
+
+    #[test]
+    fn lz5_round_trip() {
+        for pixels in samples().into_iter().filter(|pixels| pixels.iter().all(|pixel| *pixel <= 0x1f)) {
+            let encoded = encode_lz5(&pixels).unwrap();
+
+            assert_eq!(decode_lz5(&mut BufferReader::new(&encoded)).unwrap(), pixels);
+        }
+    }
+
+    #[test]
+    fn lz5_rejects_8_bit_pixels() {
+        assert!(encode_lz5(&[0, 32]).is_err());
+    }
+
+    #[test]
+    fn sffv1_round_trip() {
+        let mount = test_mount("sff_writer_tests");
+
+        let palettes = vec![palette(10), palette(20)];
+        let sprites = indexed_sprites(&palettes);
+        let path = format!("{}/v1.sff", mount);
+
+        write_sff(&path, &sprites, &palettes, SffVersion::V1, SpriteCompression::Raw).unwrap();
+
+        let actual = sort(sffv1::read_images(&path, &[]).unwrap());
+
+        assert_same_sprites(&sort(sprites), &actual);
+    }
+
+    #[test]
+    fn sffv1_links_duplicates() {
+        let palettes = vec![palette(10), palette(20)];
+        let sprites = indexed_sprites(&palettes);
+        let unique = encode_sff_v1(&sprites[..2], &palettes).unwrap();
+        let linked = encode_sff_v1(&sprites[..3], &palettes).unwrap();
+
+        // The duplicate only adds a subheader
+        assert_eq!(linked.len() - unique.len(), V1_SUBHEADER_SIZE);
+    }
+
+    #[test]
+    fn sffv2_round_trip() {
+        let mount = test_mount("sff_writer_tests");
+
+        let palettes = vec![palette(10), palette(20)];
+        let mut sprites = indexed_sprites(&palettes);
+        sprites.push(sprite(7, 1, RawImage::rgba(3, 2, pseudo_random(3 * 2 * 4, 4, 256)), 0));
+
+        for compression in [SpriteCompression::Raw, SpriteCompression::Rle8, SpriteCompression::Rle5, SpriteCompression::Lz5].iter() {
+            let path = format!("{}/v2_{:?}.sff", mount, compression);
+
+            write_sff(&path, &sprites, &palettes, SffVersion::V2, *compression).unwrap();
+
+            let actual = sort(sffv2::read_images(&path, &[]).unwrap());
+
+            assert_same_sprites(&sort(sprites.to_vec()), &actual);
+        }
+    }
+
+    #[test]
+    fn sffv2_deduplicates_palettes() {
+        let mount = test_mount("sff_writer_tests");
+
+        let palettes = vec![palette(10), palette(20), palette(20)];
+        let mut sprites = indexed_sprites(&palettes);
+        sprites.push(sprite(1, 0, RawImage::indexed(2, 2, vec![1, 2, 3, 4], palette(30)), -1));
+
+        let path = format!("{}/v2_palettes.sff", mount);
+
+        write_sff(&path, &sprites, &palettes, SffVersion::V2, SpriteCompression::Lz5).unwrap();
+
+        let actual = sffv2::read_palettes(&path).unwrap();
+
+        assert_eq!(actual.len(), 4);
+        assert!(actual[2].equal(&palettes[1]));
+        assert!(actual[3].equal(&palette(30)));
+
+        let data = file_system::read_file(&path).unwrap();
+        let palnode_offset = u32::from_le_bytes([data[44], data[45], data[46], data[47]]) as usize;
+        let linked_palette = &data[palnode_offset + V2_PALETTE_NODE_SIZE * 2..palnode_offset + V2_PALETTE_NODE_SIZE * 3];
+
+        assert_eq!(&linked_palette[6..8], &1i16.to_le_bytes());
+        assert_eq!(&linked_palette[12..16], &0u32.to_le_bytes());
+    }
+
+    #[test]
+    fn sffv2_links_duplicates() {
+        let palettes = vec![palette(10), palette(20)];
+        let sprites = indexed_sprites(&palettes);
+        let data = encode_sff_v2(&sprites, &palettes, SpriteCompression::Rle8).unwrap();
+        let node = &data[HEADER_SIZE + V2_SPRITE_NODE_SIZE * 2..HEADER_SIZE + V2_SPRITE_NODE_SIZE * 3];
+
+        // The third sprite repeats the second one
+        assert_eq!(&node[12..14], &1i16.to_le_bytes());
+        assert_eq!(&node[20..24], &0u32.to_le_bytes());
+    }
+
+    #[test]
+    fn rejects_empty_images() {
+        let sprites = vec![sprite(0, 0, RawImage::empty(), 0)];
+
+        assert!(encode_sff_v1(&sprites, &[]).is_err());
+        assert!(encode_sff_v2(&sprites, &[], SpriteCompression::Rle8).is_err());
+    }
+}
@@ -1,17 +1,16 @@
-use gdnative::Ref;
-use gdnative::api::file::File;
-use gdnative::prelude::Unique;
 use std::cell::RefCell;
+use std::cmp;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::Arc;
 
 use crate::core::error::DataError;
+use crate::io::file_system;
 
 use super::data::{BufferAccess, BufferReader, DataReader, FileReader};
 use super::image::{Palette, RawColor, RawImage};
 use super::pcx::read_pcx;
-use super::sff_common::{MutableSffData, SffData, SffPal, SffMetadata};
+use super::sff_common::{MutableSffData, SffData, SffIndex, SffPal, SffMetadata, SpriteNode};
 
 #[allow(dead_code)]
 struct FileHeader {
@@ -43,25 +42,25 @@ struct SpriteHeader {
 }
 
 struct FileHandler {
-    file: Rc<Ref<File, Unique>>,
+    reader: FileReader,
     head: FileHeader
 }
 
-fn read_file_header(reader: &mut dyn DataReader) -> FileHeader {
-    let signature: String = reader.get_text(12);
-    let verhi: u8 = reader.get_u8();
-    let verlo1: u8 = reader.get_u8();
-    let verlo2: u8 = reader.get_u8();
-    let verlo3: u8 = reader.get_u8();
-    let num_groups: u32 = reader.get_u32();
-    let num_images: u32 = reader.get_u32();
-    let first_offset: u32 = reader.get_u32();
-    let subheader_size: u32 = reader.get_u32();
-    let is_shared: bool = reader.get_bool();
-    let reserved: Vec<u8> = reader.get_buffer(3);
-    let comments: Vec<u8> = reader.get_buffer(476);
-
-    FileHeader {
+fn read_file_header(reader: &mut dyn DataReader) -> Result<FileHeader, DataError> {
+    let signature: String = reader.get_text(12)?;
+    let verlo3: u8 = reader.get_u8()?;
+    let verlo2: u8 = reader.get_u8()?;
+    let verlo1: u8 = reader.get_u8()?;
+    let verhi: u8 = reader.get_u8()?;
+    let num_groups: u32 = reader.get_u32()?;
+    let num_images: u32 = reader.get_u32()?;
+    let first_offset: u32 = reader.get_u32()?;
+    let subheader_size: u32 = reader.get_u32()?;
+    let is_shared: bool = reader.get_bool()?;
+    let reserved: Vec<u8> = reader.get_buffer(3)?;
+    let comments: Vec<u8> = reader.get_buffer(476)?;
+
+    Ok(FileHeader {
         signature,
         verhi,
         verlo1,
@@ -74,21 +73,21 @@ fn read_file_header(reader: &mut dyn DataReader) -> FileHeader {
         is_shared,
         reserved,
         comments,
-    }
+    })
 }
 
-fn read_sprite_header(reader: &mut dyn DataReader) -> SpriteHeader {
-    let offset_next_sprite: u32 = reader.get_u32();
-    let subfile_len: u32 = reader.get_u32();
-    let x: i16 = reader.get_i16();
-    let y: i16 = reader.get_i16();
-    let groupno: i16 = reader.get_i16();
-    let imageno: i16 = reader.get_i16();
-    let linked: i16 = reader.get_i16();
-    let is_shared: bool = reader.get_bool();
-    let blank: Vec<u8> = reader.get_buffer(13);
-
-    SpriteHeader {
+fn read_sprite_header(reader: &mut dyn DataReader) -> Result<SpriteHeader, DataError> {
+    let offset_next_sprite: u32 = reader.get_u32()?;
+    let subfile_len: u32 = reader.get_u32()?;
+    let x: i16 = reader.get_i16()?;
+    let y: i16 = reader.get_i16()?;
+    let groupno: i16 = reader.get_i16()?;
+    let imageno: i16 = reader.get_i16()?;
+    let linked: i16 = reader.get_i16()?;
+    let is_shared: bool = reader.get_bool()?;
+    let blank: Vec<u8> = reader.get_buffer(13)?;
+
+    Ok(SpriteHeader {
         offset_next_sprite,
         subfile_len,
         x,
@@ -98,52 +97,40 @@ fn read_sprite_header(reader: &mut dyn DataReader) -> SpriteHeader {
         linked,
         is_shared,
         blank,
-    }
+    })
 }
 
-fn matrix_to_pal(reader: &mut dyn DataReader) -> Arc<Palette> {
+fn matrix_to_pal(reader: &mut dyn DataReader) -> Result<Arc<Palette>, DataError> {
     let mut colors: Vec<RawColor> = Vec::new();
     for a in 0..256 {
-        let r = reader.get_u8();
-        let g = reader.get_u8();
-        let b = reader.get_u8();
+        let r = reader.get_u8()?;
+        let g = reader.get_u8()?;
+        let b = reader.get_u8()?;
         colors.push(RawColor::new(r, g, b, if a == 0 { 0 } else { 255 }));
     }
-    Arc::new(Palette::from_colors(colors))
+    Ok(Arc::new(Palette::from_colors(colors)))
 }
 
 fn open(filename: &str) -> Result<FileHandler, DataError> {
-    let file = Rc::new(File::new());
-    let result = file.open(filename, File::READ);
-
-    if let Err(detail) = result {
-        return Result::Err(DataError::new(format!(
-            "error opening file: {}",
-            detail
-        )));
-    }
-
-    let mut reader = FileReader::new(&file);
-    let head = read_file_header(&mut reader);
+    let mut reader = FileReader::open(filename)?;
+    let head = read_file_header(&mut reader).map_err(|error| error.with_path(filename))?;
 
     if head.signature != "ElecbyteSpr" {
-        file.close();
-        return Result::Err(DataError::new(format!(
+        return Result::Err(DataError::decode_failure(format!(
             "invalid signature: {}",
             head.signature
         )));
     }
 
-    if head.verhi != 0 && head.verlo1 != 1 && head.verlo2 != 0 && head.verlo3 != 1 {
-        file.close();
-        return Result::Err(DataError::new(format!(
+    if head.verhi != 1 {
+        return Result::Err(DataError::decode_failure(format!(
             "invalid version: {}.{}.{}.{}",
             head.verhi, head.verlo1, head.verlo2, head.verlo3
         )));
     }
 
     Result::Ok(FileHandler {
-        file,
+        reader,
         head
     })
 }
@@ -156,11 +143,8 @@ pub fn read_metadata(filename: &str) -> Result<SffMetadata, DataError> {
     }
 
     let handler = open_result.expect("Invalid open result");
-    let file = handler.file;
     let head = handler.head;
 
-    file.close();
-
     Result::Ok(SffMetadata {
         verlo3: head.verlo3,
         verlo2: head.verlo2,
@@ -169,6 +153,152 @@ pub fn read_metadata(filename: &str) -> Result<SffMetadata, DataError> {
     })
 }
 
+fn read_palette_bytes(reader: &mut FileReader, sprite: &SpriteNode) -> Result<Vec<u8>, DataError> {
+    let size = cmp::min(sprite.len, 768);
+
+    reader.seek(sprite.offset + sprite.len - size);
+    reader.get_buffer(size)
+}
+
+// Sprites are a linked list, a link that doesn't move forward would read the same sprites forever
+fn next_sprite_offset(spr: &SpriteHeader, actual_offset: u32) -> Option<u32> {
+    if spr.offset_next_sprite > actual_offset {
+        Some(spr.offset_next_sprite)
+    } else {
+        None
+    }
+}
+
+pub fn read_index(filename: &str) -> Result<SffIndex, DataError> {
+    let handler = open(filename)?;
+    let mut reader = handler.reader;
+    let head = handler.head;
+    let mut sprites: Vec<SpriteNode> = Vec::with_capacity(cmp::min(head.num_images as usize, reader.remaining() / 32));
+    let mut actual_offset = head.first_offset;
+    let mut palette_source: Option<usize> = None;
+
+    for counter in 0..head.num_images as usize {
+        if reader.eof() {
+            break;
+        }
+
+        reader.seek(actual_offset as usize);
+
+        let spr = read_sprite_header(&mut reader).map_err(|error| error.with_path(filename))?;
+        let array_size = spr.offset_next_sprite.saturating_sub(actual_offset.saturating_add(32)) as usize;
+
+        // Shared sprites use the palette of the last sprite with its own palette
+        if array_size > 0 && !spr.is_shared {
+            palette_source = Some(counter);
+        }
+
+        sprites.push(SpriteNode {
+            groupno: spr.groupno,
+            imageno: spr.imageno,
+            x: spr.x,
+            y: spr.y,
+            w: 0,
+            h: 0,
+            fmt: 0,
+            offset: actual_offset as usize + 32,
+            len: array_size,
+            palindex: None,
+            linked: if array_size == 0 { Some(spr.linked as usize) } else { None },
+            is_shared: spr.is_shared,
+            palette_source,
+        });
+
+        actual_offset = match next_sprite_offset(&spr, actual_offset) {
+            Some(offset) => offset,
+            None => break,
+        };
+    }
+
+    let individual: Vec<usize> = (0..sprites.len())
+        .filter(|index| sprites[*index].linked.is_none() && !sprites[*index].is_shared)
+        .collect();
+
+    let mut primary_source = individual.first().copied();
+
+    if head.is_shared {
+        let force_source = individual.iter()
+            .find(|index| sprites[**index].groupno == 0)
+            .or_else(|| individual.iter().find(|index| sprites[**index].groupno == 9000 && sprites[**index].imageno == 0))
+            .or_else(|| individual.first())
+            .copied();
+
+        for sprite in sprites.iter_mut().filter(|sprite| sprite.is_shared) {
+            sprite.palette_source = force_source;
+        }
+
+        primary_source = force_source;
+    }
+
+    let mut palettes: Vec<Arc<Palette>> = Vec::new();
+
+    if let Some(source) = primary_source {
+        let tmp_arr = read_palette_bytes(&mut reader, &sprites[source]).map_err(|error| error.with_path(filename))?;
+        palettes.push(matrix_to_pal(&mut BufferReader::new(&tmp_arr)).map_err(|error| error.with_path(filename))?);
+    }
+
+    let mut index = SffIndex {
+        metadata: SffMetadata {
+            verlo3: head.verlo3,
+            verlo2: head.verlo2,
+            verlo1: head.verlo1,
+            verhi: head.verhi,
+        },
+        sprites,
+        palettes,
+        palette_ids: Vec::new(),
+    };
+
+    index.resolve_links().map_err(|error| error.with_path(filename))?;
+
+    Ok(index)
+}
+
+pub fn decode_sprite(reader: &mut FileReader, filename: &str, index: &SffIndex, sprite_index: usize) -> Result<SffData, DataError> {
+    let sprite = &index.sprites[sprite_index];
+
+    reader.seek(sprite.offset);
+
+    let mut tmp_arr = reader.get_buffer(sprite.len).map_err(|error| error.with_path(filename))?;
+
+    if sprite.is_shared {
+        if let Some(source) = sprite.palette_source {
+            tmp_arr.push(12);
+            tmp_arr.extend(read_palette_bytes(reader, &index.sprites[source]).map_err(|error| error.with_path(filename))?);
+        }
+    } else {
+        let pallete_ref = tmp_arr.right(768);
+        tmp_arr.extend(pallete_ref);
+    }
+
+    let image = read_pcx(&mut BufferReader::new(&tmp_arr)).map_err(|error| DataError::decode_failure(format!(
+        "pcx: {}. buffer size: {}",
+        error,
+        tmp_arr.len()
+    )))?;
+    let image = image.borrow().clone();
+
+    // Palette 0 is the one replaced by the selected character palette
+    let palindex = match index.palettes.first() {
+        Some(palette) if image.color_table.equal(palette) => 0,
+        _ => sprite.palette_source.map_or(0, |source| cmp::min(source + 1, i16::MAX as usize) as i16),
+    };
+
+    Ok(SffData {
+        image: Arc::new(image),
+        groupno: sprite.groupno,
+        imageno: sprite.imageno,
+        x: sprite.x,
+        y: sprite.y,
+        palindex,
+        linked: -1,
+    })
+}
+
 pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataError> {
     let open_result = open(filename);
 
@@ -177,24 +307,23 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
     }
 
     let handler = open_result.expect("Invalid open result");
-    let file = handler.file;
+    let mut reader = handler.reader;
     let head = handler.head;
     let mut actual_offset = head.first_offset;
     let mut counter: i32 = -1;
-    let mut reader = FileReader::new(&file);
     let mut requested_indexes: Vec<i32> = Vec::new();
 
-    while !file.eof_reached() {
+    while !reader.eof() {
         counter += 1;
 
         if counter >= head.num_images as i32 {
             break;
         }
 
-        file.seek(actual_offset as i64);
+        reader.seek(actual_offset as usize);
 
-        let spr = read_sprite_header(&mut reader);
-        let array_size = spr.offset_next_sprite - actual_offset - 32;
+        let spr = read_sprite_header(&mut reader).map_err(|error| error.with_path(filename))?;
+        let array_size = spr.offset_next_sprite.saturating_sub(actual_offset.saturating_add(32));
 
         if groups.contains(&spr.groupno) {
             requested_indexes.push(counter);
@@ -204,7 +333,10 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
             }
         }
 
-        actual_offset = spr.offset_next_sprite;
+        actual_offset = match next_sprite_offset(&spr, actual_offset) {
+            Some(offset) => offset,
+            None => break,
+        };
     }
 
     let mut pallete_ref: Vec<u8> = Vec::new();
@@ -214,23 +346,29 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
     let mut sffdata: HashMap<i32, MutableSffData> = HashMap::new();
     let mut paldata: Vec<SffPal> = Vec::new();
 
-    file.seek(head.first_offset as i64);
+    reader.seek(head.first_offset as usize);
     counter = -1;
     actual_offset = head.first_offset;
 
-    while !file.eof_reached() {
+    while !reader.eof() {
         counter += 1;
 
         if counter >= head.num_images as i32 {
             break;
         }
 
+        reader.seek(actual_offset as usize);
+
         if !groups.is_empty() && !requested_indexes.contains(&counter) {
+            let spr = read_sprite_header(&mut reader).map_err(|error| error.with_path(filename))?;
+
+            actual_offset = match next_sprite_offset(&spr, actual_offset) {
+                Some(offset) => offset,
+                None => break,
+            };
             continue;
         }
 
-        file.seek(actual_offset as i64);
-
         let mut sffitem = MutableSffData {
             image: Rc::new(RefCell::new(RawImage::empty())),
             groupno: 0,
@@ -241,18 +379,19 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
             linked: 0,
         };
 
-        let spr = read_sprite_header(&mut reader);
-        let array_size = spr.offset_next_sprite - actual_offset - 32;
+        let spr = read_sprite_header(&mut reader).map_err(|error| error.with_path(filename))?;
+        let array_size = spr.offset_next_sprite.saturating_sub(actual_offset.saturating_add(32));
 
         if array_size > 0 {
-            let mut tmp_arr = reader.get_buffer(array_size as usize);
+            let mut tmp_arr = reader.get_buffer(array_size as usize).map_err(|error| error.with_path(filename))?;
 
             if head.is_shared && spr.is_shared {
                 shared_image.push(counter as usize);
                 tmp_arr.append(&mut vec![0u8; 768]);
             }
 
-            if !head.is_shared && spr.is_shared {
+            if !head.is_shared && spr.is_shared && !pallete_ref.is_empty() {
+                tmp_arr.push(12);
                 tmp_arr.extend(pallete_ref.to_vec());
             }
 
@@ -264,7 +403,7 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
                 tmp_arr.extend(pallete_ref.to_vec());
                 let mut palete_ref_reader = BufferReader::new(&pallete_ref);
                 let sffpal = SffPal {
-                    pal: matrix_to_pal(&mut palete_ref_reader),
+                    pal: matrix_to_pal(&mut palete_ref_reader).map_err(|error| error.with_path(filename))?,
                     groupno: paldata.len() as i32 + 1,
                     itemno: 1,
                     is_used: true,
@@ -292,7 +431,7 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
                 if let Ok(image) = result {
                     sffitem.image = image;
                 } else if let Err(error) = result {
-                    return Result::Err(DataError::new(format!(
+                    return Result::Err(DataError::decode_failure(format!(
                         "pcx: {}. buffer size: {}",
                         error,
                         tmp_arr.len()
@@ -311,7 +450,7 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
                     }
                 },
                 None => {
-                    return Result::Err(DataError::new(format!(
+                    return Result::Err(DataError::decode_failure(format!(
                         "invalid linked image: {},{}",
                         spr.groupno,
                         spr.imageno
@@ -331,10 +470,15 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
         }
 
         sffdata.insert(counter, sffitem);
-        actual_offset = spr.offset_next_sprite;
+
+        actual_offset = match next_sprite_offset(&spr, actual_offset) {
+            Some(offset) => offset,
+            None => break,
+        };
     }
 
-    if head.is_shared {
+    // Shared palettes come from sprites with their own palette, a file without any has nothing to share
+    if head.is_shared && !ind_image.is_empty() && !paldata.is_empty() {
         let mut force_pal = Arc::new(Palette::new(0));
         let mut have0 = false;
         for other in ind_image.iter() {
@@ -347,7 +491,7 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
                     }
                 },
                 None => {
-                    return Result::Err(DataError::new(format!(
+                    return Result::Err(DataError::decode_failure(format!(
                         "invalid shared image: other = {}",
                         other
                     )));
@@ -366,7 +510,7 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
                         }
                     },
                     None => {
-                        return Result::Err(DataError::new(format!(
+                        return Result::Err(DataError::decode_failure(format!(
                             "invalid shared image: other = {}",
                             other
                         )));
@@ -379,7 +523,7 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
                         force_pal = Arc::clone(&linked.image.borrow().color_table);
                     },
                     None => {
-                        return Result::Err(DataError::new("invalid shared image: k = 0".to_string()));
+                        return Result::Err(DataError::decode_failure("invalid shared image: k = 0".to_string()));
                     }
                 }
             }
@@ -426,7 +570,7 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
                     shared.palindex = 0;
                 },
                 None => {
-                    return Result::Err(DataError::new(format!(
+                    return Result::Err(DataError::decode_failure(format!(
                         "invalid shared image: other = {}",
                         other
                     )));
@@ -435,8 +579,6 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
         }
     }
 
-    file.close();
-
     let mut result: Vec<SffData> = Vec::new();
 
     for value in sffdata.values() {
@@ -448,31 +590,23 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
 
 pub fn load_pal_format_pal(filename: &str) -> Result<Arc<Palette>, DataError> {
     let mut pal: Palette = Palette::new(0);
-    let file = File::new();
-    let result = file.open(filename, File::READ);
-
-    if let Err(details) = result {
-        file.close();
-        return Result::Err(DataError {
-            message: format!("Error opening palette {}", details),
-        });
-    }
-
-    if file.get_line().to_uppercase().to_string() != "JASC-PAL" {
-        file.close();
-        return Result::Err(DataError {
-            message: "Invalid pallete header".to_string(),
-        });
+    let text = file_system::open_file_as_string(filename).map_err(|error| DataError {
+        message: format!("Error opening palette {}", error.message),
+        ..error
+    })?;
+    let mut lines = text.lines();
+
+    if lines.next().unwrap_or("").trim().to_uppercase() != "JASC-PAL" {
+        return Result::Err(DataError::decode_failure("Invalid pallete header".to_string()).with_path(filename));
     }
 
-    file.get_line(); //0100
-    file.get_line(); //256 (color palette)
+    lines.next(); //0100
+    lines.next(); //256 (color palette)
     let mut counter = -1;
 
-    while !file.eof_reached() {
+    for line in lines {
         counter += 1;
-        let line = file.get_line().to_string();
-        let strcolor: Vec<&str> = line.split(' ').collect::<Vec<&str>>();
+        let strcolor: Vec<&str> = line.trim().split(' ').collect::<Vec<&str>>();
         if strcolor.len() < 3 {
             continue;
         }
@@ -488,35 +622,26 @@ pub fn load_pal_format_pal(filename: &str) -> Result<Arc<Palette>, DataError> {
     }
 
     if pal.colors.is_empty() {
-        return Result::Err(DataError {
-            message: "invalid palette file, no colors".to_string(),
-        });
+        return Result::Err(DataError::decode_failure("invalid palette file, no colors".to_string()).with_path(filename));
     }
 
-    file.close();
-
     Result::Ok(Arc::new(pal))
 }
 
 pub fn load_pal_format_act(filename: &str) -> Result<Arc<Palette>, DataError> {
     let mut pal: Palette = Palette::new(0);
-    let file = File::new();
-    let result = file.open(filename, File::READ);
-
-    if let Err(details) = result {
-        file.close();
-        return Result::Err(DataError {
-            message: format!("Error opening palette {}", details),
-        });
-    }
-
-    let mut reader = FileReader::new(&file);
+    let mut reader = FileReader::open(filename).map_err(|error| DataError {
+        message: format!("Error opening palette {}", error.message),
+        ..error
+    })?;
     let mut reversed: Vec<RawColor> = Vec::new();
+    let palette_bytes = reader.get_buffer(768).map_err(|error| error.with_path(filename))?;
+    let mut palette_reader = BufferReader::new(&palette_bytes);
 
     for a in 0..256 {
-        let r = reader.get_u8();
-        let g = reader.get_u8();
-        let b = reader.get_u8();
+        let r = palette_reader.get_u8()?;
+        let g = palette_reader.get_u8()?;
+        let b = palette_reader.get_u8()?;
         reversed.push(RawColor::new(r, g, b, if a == 255 { 0 } else { 255 }));
     }
 
@@ -527,8 +652,6 @@ pub fn load_pal_format_act(filename: &str) -> Result<Arc<Palette>, DataError> {
         i -= 1;
     }
 
-    file.close();
-
     Result::Ok(Arc::new(pal))
 }
 
@@ -1,14 +1,13 @@
 use crate::core::error::DataError;
 
 use super::data::{BufferReader, DataReader, FileReader};
-use super::image::{Palette, RawColor, RawImage};
+use super::image::{Palette, PixelFormat, RawColor, RawImage};
 use super::lz5::decode_lz5;
+use super::png_image::{decode_png_indexed, decode_png_rgba};
 use super::rle5::{decode_rle5, decode_rle8};
-use super::sff_common::{SffData, SffPal, SffMetadata, MutableSffData};
-use gdnative::Ref;
-use gdnative::api::file::File;
-use gdnative::prelude::Unique;
+use super::sff_common::{SffData, SffIndex, SffPal, SffMetadata, SpriteNode, MutableSffData};
 use std::cell::RefCell;
+use std::cmp;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::Arc;
@@ -42,36 +41,37 @@ struct FileHeader {
 }
 
 impl FileHeader {
-    fn read(reader: &mut dyn DataReader) -> FileHeader {
-        FileHeader {
-            signature: reader.get_text(12),
-            verlo3: reader.get_u8(),
-            verlo2: reader.get_u8(),
-            verlo1: reader.get_u8(),
-            verhi: reader.get_u8(),
-            reserved1: reader.get_buffer(4),
-            reserved2: reader.get_buffer(4),
-            compatverlo3: reader.get_u8(),
-            compatverlo2: reader.get_u8(),
-            compatverlo1: reader.get_u8(),
-            compatverhi: reader.get_u8(),
-            reserved3: reader.get_buffer(4),
-            reserved4: reader.get_buffer(4),
-            first_sprnode_offset: reader.get_u32(),
-            total_frames: reader.get_u32(),
-            first_palnode_offset: reader.get_u32(),
-            total_palettes: reader.get_u32(),
-            ldata_offset: reader.get_u32(),
-            ldata_length: reader.get_u32(),
-            tdata_offset: reader.get_u32(),
-            tdata_length: reader.get_u32(),
-            reserved5: reader.get_buffer(4),
-            reserved6: reader.get_buffer(4),
-            unused: reader.get_buffer(436),
-        }
+    fn read(reader: &mut dyn DataReader) -> Result<FileHeader, DataError> {
+        Ok(FileHeader {
+            signature: reader.get_text(12)?,
+            verlo3: reader.get_u8()?,
+            verlo2: reader.get_u8()?,
+            verlo1: reader.get_u8()?,
+            verhi: reader.get_u8()?,
+            reserved1: reader.get_buffer(4)?,
+            reserved2: reader.get_buffer(4)?,
+            compatverlo3: reader.get_u8()?,
+            compatverlo2: reader.get_u8()?,
+            compatverlo1: reader.get_u8()?,
+            compatverhi: reader.get_u8()?,
+            reserved3: reader.get_buffer(4)?,
+            reserved4: reader.get_buffer(4)?,
+            first_sprnode_offset: reader.get_u32()?,
+            total_frames: reader.get_u32()?,
+            first_palnode_offset: reader.get_u32()?,
+            total_palettes: reader.get_u32()?,
+            ldata_offset: reader.get_u32()?,
+            ldata_length: reader.get_u32()?,
+            tdata_offset: reader.get_u32()?,
+            tdata_length: reader.get_u32()?,
+            reserved5: reader.get_buffer(4)?,
+            reserved6: reader.get_buffer(4)?,
+            unused: reader.get_buffer(436)?,
+        })
     }
 }
 
+#[allow(dead_code)]
 struct SpriteHeader {
     groupno: i16,
     imageno: i16,
@@ -89,22 +89,22 @@ struct SpriteHeader {
 }
 
 impl SpriteHeader {
-    fn read(reader: &mut dyn DataReader) -> SpriteHeader {
-        SpriteHeader {
-            groupno: reader.get_i16(),
-            imageno: reader.get_i16(),
-            w: reader.get_i16(),
-            h: reader.get_i16(),
-            x: reader.get_i16(),
-            y: reader.get_i16(),
-            linked: reader.get_i16(),
-            fmt: reader.get_u8(),
-            colordepth: reader.get_u8(),
-            offset: reader.get_u32(),
-            len: reader.get_u32(),
-            palindex: reader.get_i16(),
-            flags: reader.get_i16(),
-        }
+    fn read(reader: &mut dyn DataReader) -> Result<SpriteHeader, DataError> {
+        Ok(SpriteHeader {
+            groupno: reader.get_i16()?,
+            imageno: reader.get_i16()?,
+            w: reader.get_i16()?,
+            h: reader.get_i16()?,
+            x: reader.get_i16()?,
+            y: reader.get_i16()?,
+            linked: reader.get_i16()?,
+            fmt: reader.get_u8()?,
+            colordepth: reader.get_u8()?,
+            offset: reader.get_u32()?,
+            len: reader.get_u32()?,
+            palindex: reader.get_i16()?,
+            flags: reader.get_i16()?,
+        })
     }
 }
 
@@ -118,66 +118,81 @@ struct PaletteHeader {
 }
 
 impl PaletteHeader {
-    fn read(reader: &mut dyn DataReader) -> PaletteHeader {
-        PaletteHeader {
-            groupno: reader.get_i16(),
-            itemno: reader.get_i16(),
-            numcols: reader.get_i16(),
-            linked: reader.get_i16(),
-            offset: reader.get_u32(),
-            len: reader.get_u32(),
-        }
+    fn read(reader: &mut dyn DataReader) -> Result<PaletteHeader, DataError> {
+        Ok(PaletteHeader {
+            groupno: reader.get_i16()?,
+            itemno: reader.get_i16()?,
+            numcols: reader.get_i16()?,
+            linked: reader.get_i16()?,
+            offset: reader.get_u32()?,
+            len: reader.get_u32()?,
+        })
     }
 }
 struct FileHandler {
-    file: Rc<Ref<File, Unique>>,
+    reader: FileReader,
     head: FileHeader
 }
 
-fn matrix_to_pal(reader: &mut dyn DataReader, size: usize) -> Arc<Palette> {
+fn matrix_to_pal(reader: &mut dyn DataReader, size: usize) -> Result<Arc<Palette>, DataError> {
     let mut colors: Vec<RawColor> = Vec::new();
     for i in 0..size {
-        let r = reader.get_u8();
-        let g = reader.get_u8();
-        let b = reader.get_u8();
-        reader.get_u8();
+        let r = reader.get_u8()?;
+        let g = reader.get_u8()?;
+        let b = reader.get_u8()?;
+        reader.get_u8()?;
         colors.push(RawColor::new(r, g, b, if i == 0 { 0 } else { 255 }));
     }
-    Arc::new(Palette::from_colors(colors))
+    Ok(Arc::new(Palette::from_colors(colors)))
 }
 
-fn open(filename: &str) -> Result<FileHandler, DataError> {
-    let file = Rc::new(File::new());
-    let result = file.open(filename, File::READ);
-
-    if let Err(detail) = result {
-        return Result::Err(DataError::new(format!(
-            "error opening file: {}",
-            detail
+fn read_palette_data(
+    reader: &mut FileReader,
+    head: &FileHeader,
+    palette: &PaletteHeader,
+    previous: &[Arc<Palette>],
+) -> Result<Arc<Palette>, DataError> {
+    if palette.len == 0 {
+        return previous.get(palette.linked as usize).cloned().ok_or_else(|| DataError::decode_failure(format!(
+            "invalid linked palette: {},{}",
+            palette.groupno, palette.itemno
         )));
     }
 
-    let mut reader = FileReader::new(&file);
-    let head = FileHeader::read(&mut reader);
+    reader.seek(head.ldata_offset as usize + palette.offset as usize);
+
+    let numcols = cmp::max(palette.numcols, 0) as usize;
+    let tmp_arr = reader.get_buffer(numcols * 4)?;
+    let mut tmp_arr_reader = BufferReader::new(&tmp_arr);
+
+    matrix_to_pal(&mut tmp_arr_reader, numcols)
+}
+
+// Corrupt counts must not reserve more entries than the file can hold
+fn capacity(reader: &mut FileReader, count: u32, entry_size: usize) -> usize {
+    cmp::min(count as usize, reader.remaining() / entry_size)
+}
+
+fn open(filename: &str) -> Result<FileHandler, DataError> {
+    let mut reader = FileReader::open(filename)?;
+    let head = FileHeader::read(&mut reader).map_err(|error| error.with_path(filename))?;
 
     if head.signature != "ElecbyteSpr" {
-        file.close();
-        return Result::Err(DataError::new(format!(
+        return Result::Err(DataError::decode_failure(format!(
             "invalid signature: {}",
             head.signature
         )));
     }
 
     if head.verhi != 2 {
-        file.close();
-        return Result::Err(DataError::new(format!(
+        return Result::Err(DataError::decode_failure(format!(
             "invalid version: {}.{}.{}.{}",
             head.verhi, head.verlo1, head.verlo2, head.verlo3
         )));
     }
 
     Result::Ok(FileHandler {
-        file,
+        reader,
         head
     })
 }
@@ -190,11 +205,8 @@ pub fn read_metadata(filename: &str) -> Result<SffMetadata, DataError> {
     }
 
     let handler = open_result.expect("Invalid open result");
-    let file = handler.file;
     let head = handler.head;
 
-    file.close();
-
     Result::Ok(SffMetadata {
         verlo3: head.verlo3,
         verlo2: head.verlo2,
@@ -211,32 +223,19 @@ pub fn read_palettes(filename: &str) -> Result<Vec<Arc<Palette>>, DataError> {
     }
 
     let handler = open_result.expect("Invalid open result");
-    let file = handler.file;
+    let mut reader = handler.reader;
     let head = handler.head;
     let mut result: Vec<Arc<Palette>> = Vec::new();
     let mut palnode: Vec<PaletteHeader> = Vec::new();
-    let mut reader = FileReader::new(&file);
 
-    file.seek(head.first_palnode_offset as i64);
+    reader.seek(head.first_palnode_offset as usize);
 
     for _ in 0..head.total_palettes {
-        palnode.push(PaletteHeader::read(&mut reader));
+        palnode.push(PaletteHeader::read(&mut reader).map_err(|error| error.with_path(filename))?);
     }
 
     for palette in palnode.iter() {
-        let pal: Arc<Palette> = match palette.len {
-            0 => Arc::clone(&result[palette.linked as usize]),
-            len if len > 0 => {
-                let mut offset: usize = head.ldata_offset as usize;
-                offset += palette.offset as usize;
-                file.seek(offset as i64);
-
-                let tmp_arr = reader.get_buffer((palette.numcols * 4) as usize);
-                let mut tmp_arr_reader = BufferReader::new(&tmp_arr);
-                matrix_to_pal(&mut tmp_arr_reader, palette.numcols as usize)
-            }
-            _ => Arc::new(Palette::new(0)),
-        };
+        let pal = read_palette_data(&mut reader, &head, palette, &result).map_err(|error| error.with_path(filename))?;
 
         result.push(pal);
     }
@@ -244,6 +243,144 @@ pub fn read_palettes(filename: &str) -> Result<Vec<Arc<Palette>>, DataError> {
     Result::Ok(result)
 }
 
+#[allow(clippy::too_many_arguments)]
+fn decode_image(
+    filename: &str,
+    groupno: i16,
+    imageno: i16,
+    fmt: u8,
+    w: usize,
+    h: usize,
+    mut tmp_arr: Vec<u8>,
+    palette: &Arc<Palette>,
+) -> Result<RawImage, DataError> {
+    let mut tmp_reader = BufferReader::new(&tmp_arr);
+    let mut format = PixelFormat::Indexed;
+
+    match fmt {
+        2 => tmp_arr = decode_rle8(&mut tmp_reader).map_err(|error| error.with_path(filename))?,
+        3 => tmp_arr = decode_rle5(&mut tmp_reader).map_err(|error| error.with_path(filename))?,
+        4 => tmp_arr = decode_lz5(&mut tmp_reader).map_err(|error| error.with_path(filename))?,
+        10 => tmp_arr = decode_png_indexed(&tmp_arr)
+            .map_err(|error| error.with_path(filename))?
+            .pixels,
+        11 | 12 => {
+            tmp_arr = decode_png_rgba(&tmp_arr)
+                .map_err(|error| error.with_path(filename))?
+                .pixels;
+            format = PixelFormat::Rgba;
+        }
+        _ => (),
+    };
+
+    let expected_size = w * h * format.bytes_per_pixel();
+    let actual_size = tmp_arr.len();
+
+    if expected_size != actual_size {
+        return Err(DataError::decode_failure(format!(
+            "Image decoding failed. GroupNo={}. ImageNo={}",
+            groupno, imageno
+        )).with_path(filename));
+    }
+
+    Ok(match format {
+        PixelFormat::Indexed => RawImage::indexed(w, h, tmp_arr, Arc::clone(palette)),
+        PixelFormat::Rgba => RawImage::rgba(w, h, tmp_arr),
+    })
+}
+
+pub fn read_index(filename: &str) -> Result<SffIndex, DataError> {
+    let palettes = read_palettes(filename)?;
+    let handler = open(filename)?;
+    let mut reader = handler.reader;
+    let head = handler.head;
+    let mut sprites: Vec<SpriteNode> = Vec::with_capacity(capacity(&mut reader, head.total_frames, 28));
+    let mut palette_ids: Vec<(i16, i16)> = Vec::with_capacity(capacity(&mut reader, head.total_palettes, 16));
+
+    reader.seek(head.first_palnode_offset as usize);
+
+    for _ in 0..head.total_palettes {
+        let palette = PaletteHeader::read(&mut reader).map_err(|error| error.with_path(filename))?;
+        palette_ids.push((palette.groupno, palette.itemno));
+    }
+
+    reader.seek(head.first_sprnode_offset as usize);
+
+    for _ in 0..head.total_frames {
+        let spr = SpriteHeader::read(&mut reader).map_err(|error| error.with_path(filename))?;
+        let data_offset = if spr.flags == 0 { head.ldata_offset } else { head.tdata_offset };
+
+        sprites.push(SpriteNode {
+            groupno: spr.groupno,
+            imageno: spr.imageno,
+            x: spr.x,
+            y: spr.y,
+            w: spr.w as u16,
+            h: spr.h as u16,
+            fmt: spr.fmt,
+            offset: data_offset as usize + spr.offset as usize,
+            len: spr.len as usize,
+            palindex: Some(spr.palindex),
+            linked: if spr.len == 0 { Some(spr.linked as usize) } else { None },
+            is_shared: false,
+            palette_source: None,
+        });
+    }
+
+    let mut index = SffIndex {
+        metadata: SffMetadata {
+            verlo3: head.verlo3,
+            verlo2: head.verlo2,
+            verlo1: head.verlo1,
+            verhi: head.verhi,
+        },
+        sprites,
+        palettes,
+        palette_ids,
+    };
+
+    index.resolve_links().map_err(|error| error.with_path(filename))?;
+
+    Ok(index)
+}
+
+pub fn decode_sprite(reader: &mut FileReader, filename: &str, index: &SffIndex, sprite_index: usize) -> Result<SffData, DataError> {
+    let sprite = &index.sprites[sprite_index];
+    let palindex = sprite.palindex.unwrap_or(0);
+    let palette = match index.palettes.get(palindex as usize) {
+        Some(palette) => palette.clone(),
+        None => {
+            return Err(DataError::decode_failure(format!(
+                "invalid palette {} for image: {},{}",
+                palindex, sprite.groupno, sprite.imageno
+            )).with_path(filename));
+        }
+    };
+
+    reader.seek(sprite.offset);
+
+    let image = decode_image(
+        filename,
+        sprite.groupno,
+        sprite.imageno,
+        sprite.fmt,
+        sprite.w as usize,
+        sprite.h as usize,
+        reader.get_buffer(sprite.len).map_err(|error| error.with_path(filename))?,
+        &palette,
+    )?;
+
+    Ok(SffData {
+        image: Arc::new(image),
+        groupno: sprite.groupno,
+        imageno: sprite.imageno,
+        x: sprite.x,
+        y: sprite.y,
+        palindex,
+        linked: -1,
+    })
+}
+
 pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataError> {
     let open_result = open(filename);
 
@@ -252,9 +389,8 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
     }
 
     let handler = open_result.expect("Invalid open result");
-    let file = handler.file;
+    let mut reader = handler.reader;
     let head = handler.head;
-    let mut reader = FileReader::new(&file);
 
     let mut sffdata: HashMap<i32, MutableSffData> = HashMap::new();
     let mut paldata: Vec<SffPal> = Vec::new();
@@ -262,16 +398,16 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
     let mut palnode: Vec<PaletteHeader> = Vec::new();
     let mut requested_indexes: Vec<i32> = Vec::new();
 
-    file.seek(head.first_palnode_offset as i64);
+    reader.seek(head.first_palnode_offset as usize);
 
     for _ in 0..head.total_palettes {
-        palnode.push(PaletteHeader::read(&mut reader));
+        palnode.push(PaletteHeader::read(&mut reader).map_err(|error| error.with_path(filename))?);
     }
 
-    file.seek(head.first_sprnode_offset as i64);
+    reader.seek(head.first_sprnode_offset as usize);
 
     for counter in 0..head.total_frames {
-        let spr = SpriteHeader::read(&mut reader);
+        let spr = SpriteHeader::read(&mut reader).map_err(|error| error.with_path(filename))?;
 
         if groups.contains(&spr.groupno) {
             requested_indexes.push(counter as i32);
@@ -284,19 +420,8 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
     }
 
     for palette in palnode.iter() {
-        let pal: Arc<Palette> = match palette.len {
-            0 => Arc::clone(&paldata[palette.linked as usize].pal),
-            len if len > 0 => {
-                let mut offset: usize = head.ldata_offset as usize;
-                offset += palette.offset as usize;
-                file.seek(offset as i64);
-
-                let tmp_arr = reader.get_buffer((palette.numcols * 4) as usize);
-                let mut tmp_arr_reader = BufferReader::new(&tmp_arr);
-                matrix_to_pal(&mut tmp_arr_reader, palette.numcols as usize)
-            }
-            _ => Arc::new(Palette::new(0)),
-        };
+        let previous: Vec<Arc<Palette>> = paldata.iter().map(|item| item.pal.clone()).collect();
+        let pal = read_palette_data(&mut reader, &head, palette, &previous).map_err(|error| error.with_path(filename))?;
 
         paldata.push(SffPal {
             pal,
@@ -319,7 +444,15 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
         if sprite.len == 0 {
             //linked image
             linked = -1;
-            image = Rc::clone(&sffdata[&(sprite.linked as i32)].image);
+            image = match sffdata.get(&(sprite.linked as i32)) {
+                Some(linked) => Rc::clone(&linked.image),
+                None => {
+                    return Err(DataError::decode_failure(format!(
+                        "invalid linked image: {},{}",
+                        sprite.groupno, sprite.imageno
+                    )).with_path(filename));
+                }
+            };
         } else {
             //"normal" image
             let mut offset: usize = 0;
@@ -330,37 +463,29 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
                 offset = head.tdata_offset as usize;
             }
             offset += sprite.offset as usize;
-            file.seek(offset as i64);
-
-            let mut tmp_arr = reader.get_buffer(sprite.len as usize);
-            let mut tmp_reader = BufferReader::new(&tmp_arr);
-
-            match sprite.fmt {
-                2 => tmp_arr = decode_rle8(&mut tmp_reader),
-                3 => tmp_arr = decode_rle5(&mut tmp_reader),
-                4 => tmp_arr = decode_lz5(&mut tmp_reader),
-                _ => (),
+            reader.seek(offset);
+
+            let tmp_arr = reader.get_buffer(sprite.len as usize).map_err(|error| error.with_path(filename))?;
+            let palette = match paldata.get(sprite.palindex as usize) {
+                Some(palette) => &palette.pal,
+                None => {
+                    return Err(DataError::decode_failure(format!(
+                        "invalid palette {} for image: {},{}",
+                        sprite.palindex, sprite.groupno, sprite.imageno
+                    )).with_path(filename));
+                }
             };
 
-            let expected_size = (sprite.w as usize * sprite.h as usize) as usize;
-            let actual_size = tmp_arr.len();
-
-            if expected_size != actual_size {
-                return Err(DataError::new(format!(
-                    "Image decoding failed. GroupNo={}. ImageNo={}",
-                    sprite.groupno, sprite.imageno
-                )));
-            }
-
-            //adding image
-            if sprite.colordepth == 5 || sprite.colordepth == 8 {
-                image = Rc::new(RefCell::new(RawImage {
-                    w: sprite.w as usize,
-                    h: sprite.h as usize,
-                    pixels: Arc::new(tmp_arr),
-                    color_table: Arc::clone(&paldata[sprite.palindex as usize].pal),
-                }));
-            }
+            image = Rc::new(RefCell::new(decode_image(
+                filename,
+                sprite.groupno,
+                sprite.imageno,
+                sprite.fmt,
+                sprite.w as usize,
+                sprite.h as usize,
+                tmp_arr,
+                palette,
+            )?));
 
             linked = -1;
         }
@@ -377,15 +502,14 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
     }
 
     for (a, item) in sffdata.iter_mut() {
-        let b = item.palindex as usize;
-        if !paldata[b].is_used {
-            paldata[b].is_used = true;
-            paldata[b].usedby = *a;
+        if let Some(palette) = paldata.get_mut(item.palindex as usize) {
+            if !palette.is_used {
+                palette.is_used = true;
+                palette.usedby = *a;
+            }
         }
     }
 
-    file.close();
-
     let mut result: Vec<SffData> = Vec::new();
 
     for value in sffdata.values() {
@@ -394,3 +518,71 @@ pub fn read_images(filename: &str, groups: &[i16]) -> Result<Vec<SffData>, DataE
 
     Result::Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::drawing::sff::sff_writer::{encode_sff_v2, SpriteCompression};
+    use crate::io::file_system;
+    use crate::io::memory_file_system::test_mount;
+
+    use super::*;
+
+    const FIRST_SPRITE_NODE: usize = 512;
+
+    fn write(name: &str, data: &[u8]) -> String {
+        let path = format!("{}/{}", test_mount("sffv2_tests"), name);
+        file_system::write_file(&path, data).unwrap();
+        path
+    }
+
+    fn encoded() -> Vec<u8> {
+        let palette = Arc::new(Palette::from_colors((0..256).map(|i| RawColor::new(i as u8, 0, 0, 255)).collect()));
+        let sprites = vec![SffData {
+            image: Arc::new(RawImage::indexed(4, 3, (0..12).collect(), palette.clone())),
+            groupno: 0,
+            imageno: 0,
+            x: 0,
+            y: 0,
+            palindex: 0,
+            linked: 0,
+        }];
+
+        encode_sff_v2(&sprites, &[palette], SpriteCompression::Lz5).unwrap()
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let data = encoded();
+        let path = write("truncated.sff", &data[..data.len() - 10]);
+
+        assert!(read_images(&path, &[]).is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range_palettes() {
+        let mut data = encoded();
+
+        // palindex of the first sprite node
+        data[FIRST_SPRITE_NODE + 24..FIRST_SPRITE_NODE + 26].copy_from_slice(&99i16.to_le_bytes());
+
+        let path = write("bad_palette.sff", &data);
+
+        assert!(read_images(&path, &[]).is_err());
+    }
+
+    #[test]
+    fn decode_sprite_rejects_out_of_range_palettes() {
+        for palindex in [99i16, -1].iter() {
+            let mut data = encoded();
+
+            data[FIRST_SPRITE_NODE + 24..FIRST_SPRITE_NODE + 26].copy_from_slice(&palindex.to_le_bytes());
+
+            let path = write("bad_palette_index.sff", &data);
+            let index = read_index(&path).unwrap();
+            let mut reader = FileReader::open(&path).unwrap();
+            let error = decode_sprite(&mut reader, &path, &index, 0).err().unwrap();
+
+            assert!(error.message.contains("invalid palette"), "{}", error.message);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::sff::sff_common::SffData;
+
+struct CacheEntry {
+    sff_data: SffData,
+    size: usize,
+    last_used: u64,
+}
+
+// Least recently used cache of decoded sprites, limited by the size of their pixel data
+pub struct SpriteCache {
+    budget: usize,
+    size: usize,
+    clock: u64,
+    entries: HashMap<usize, CacheEntry>,
+    usage: BTreeMap<u64, usize>,
+}
+
+impl SpriteCache {
+    pub fn new(budget: usize) -> Self {
+        SpriteCache {
+            budget,
+            size: 0,
+            clock: 0,
+            entries: HashMap::new(),
+            usage: BTreeMap::new(),
+        }
+    }
+
+    pub fn budget(&self) -> usize { self.budget }
+
+    pub fn size(&self) -> usize { self.size }
+
+    pub fn len(&self) -> usize { self.entries.len() }
+
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict(0);
+    }
+
+    pub fn get(&mut self, key: usize) -> Option<SffData> {
+        self.clock += 1;
+
+        let clock = self.clock;
+        let entry = self.entries.get_mut(&key)?;
+
+        self.usage.remove(&entry.last_used);
+        self.usage.insert(clock, key);
+        entry.last_used = clock;
+
+        Some(entry.sff_data.clone())
+    }
+
+    pub fn insert(&mut self, key: usize, sff_data: SffData) {
+        self.remove(key);
+
+        let size = sff_data.image.pixels.len();
+
+        if size > self.budget {
+            return;
+        }
+
+        self.evict(size);
+        self.clock += 1;
+        self.size += size;
+        self.usage.insert(self.clock, key);
+        self.entries.insert(key, CacheEntry {
+            sff_data,
+            size,
+            last_used: self.clock,
+        });
+    }
+
+    pub fn remove(&mut self, key: usize) {
+        if let Some(entry) = self.entries.remove(&key) {
+            self.usage.remove(&entry.last_used);
+            self.size -= entry.size;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.usage.clear();
+        self.size = 0;
+    }
+
+    fn evict(&mut self, reserved: usize) {
+        while self.size + reserved > self.budget {
+            let oldest = match self.usage.keys().next() {
+                Some(last_used) => *last_used,
+                None => break,
+            };
+
+            if let Some(key) = self.usage.remove(&oldest) {
+                if let Some(entry) = self.entries.remove(&key) {
+                    self.size -= entry.size;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::drawing::sff::image::{Palette, RawImage};
+
+    use super::*;
+
+    // Indexed images take one byte per pixel
+    fn sprite(size: usize) -> SffData {
+        SffData {
+            image: Arc::new(RawImage::indexed(size, 1, vec![0; size], Arc::new(Palette::new(0)))),
+            groupno: 0,
+            imageno: 0,
+            x: 0,
+            y: 0,
+            palindex: 0,
+            linked: -1,
+        }
+    }
+
+    fn keys(cache: &SpriteCache) -> Vec<usize> {
+        let mut keys: Vec<usize> = cache.entries.keys().copied().collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_sprites_first() {
+        let mut cache = SpriteCache::new(100);
+
+        cache.insert(1, sprite(40));
+        cache.insert(2, sprite(40));
+        cache.insert(3, sprite(40));
+
+        assert_eq!(keys(&cache), vec![2, 3]);
+        assert_eq!(cache.size(), 80);
+        assert!(cache.get(1).is_none());
+    }
+
+    #[test]
+    fn get_refreshes_the_last_use() {
+        let mut cache = SpriteCache::new(100);
+
+        cache.insert(1, sprite(40));
+        cache.insert(2, sprite(40));
+
+        assert_eq!(cache.get(1).unwrap().image.pixels.len(), 40);
+
+        cache.insert(3, sprite(40));
+
+        assert_eq!(keys(&cache), vec![1, 3]);
+        assert_eq!(cache.usage.len(), cache.len());
+    }
+
+    #[test]
+    fn insert_replaces_an_existing_key() {
+        let mut cache = SpriteCache::new(100);
+
+        cache.insert(1, sprite(40));
+        cache.insert(1, sprite(30));
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.size(), 30);
+        assert_eq!(cache.usage.len(), 1);
+        assert_eq!(cache.get(1).unwrap().image.pixels.len(), 30);
+
+        // Replacing a key doesn't evict other entries for its old size
+        cache.insert(2, sprite(60));
+        cache.insert(1, sprite(40));
+
+        assert_eq!(keys(&cache), vec![1, 2]);
+        assert_eq!(cache.size(), 100);
+    }
+
+    #[test]
+    fn sprites_bigger_than_the_budget_are_not_cached() {
+        let mut cache = SpriteCache::new(100);
+
+        cache.insert(1, sprite(40));
+        cache.insert(2, sprite(101));
+
+        assert_eq!(keys(&cache), vec![1]);
+        assert_eq!(cache.size(), 40);
+
+        cache.insert(1, sprite(101));
+
+        assert!(cache.is_empty());
+        assert_eq!(cache.size(), 0);
+    }
+
+    #[test]
+    fn shrinking_the_budget_evicts_the_oldest_sprites() {
+        let mut cache = SpriteCache::new(100);
+
+        cache.insert(1, sprite(30));
+        cache.insert(2, sprite(30));
+        cache.insert(3, sprite(30));
+        cache.get(1);
+        cache.set_budget(60);
+
+        assert_eq!(cache.budget(), 60);
+        assert_eq!(keys(&cache), vec![1, 3]);
+        assert_eq!(cache.size(), 60);
+
+        cache.set_budget(0);
+
+        assert!(cache.is_empty());
+        assert!(cache.usage.is_empty());
+        assert_eq!(cache.size(), 0);
+    }
+}
@@ -1,69 +1,146 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::{Arc, Mutex}};
 
-use crate::core::{error::DataError, sprite_id::SpriteId};
+use crate::core::{constants::DEFAULT_SPRITE_CACHE_BUDGET, error::DataError, sprite_id::SpriteId};
 
-use super::sff::{sff_common::{SffData, SffMetadata}, sff_parser};
+use super::sff::{data::FileReader, image::Palette, sff_common::{SffData, SffIndex, SffMetadata}, sff_parser};
+use super::sprite_cache::SpriteCache;
 
 pub struct SpriteFile {
     pub path: String,
     pub metadata: SffMetadata,
-    cache: HashMap<SpriteId, SffData>,
+    index: SffIndex,
+    sprite_ids: HashMap<SpriteId, usize>,
+    cache: SpriteCache,
+    // Only used through &mut self, the mutex keeps SpriteFile Sync
+    reader: Mutex<FileReader>,
 }
 
 impl SpriteFile {
     pub fn load(path: &str) -> Result<Self, DataError> {
-        let metadata = sff_parser::read_metadata(&path)?;
+        SpriteFile::load_with_budget(path, DEFAULT_SPRITE_CACHE_BUDGET)
+    }
+
+    pub fn load_with_budget(path: &str, cache_budget: usize) -> Result<Self, DataError> {
+        let index = sff_parser::read_index(path)?;
+        let reader = FileReader::open(path)?;
+        let mut sprite_ids: HashMap<SpriteId, usize> = HashMap::new();
+
+        for (position, sprite) in index.sprites.iter().enumerate() {
+            sprite_ids.entry(SpriteId::new(sprite.groupno, sprite.imageno)).or_insert(position);
+        }
 
         Ok(SpriteFile {
             path: path.to_string(),
-            cache: HashMap::new(),
-            metadata,
+            metadata: index.metadata.clone(),
+            index,
+            sprite_ids,
+            cache: SpriteCache::new(cache_budget),
+            reader: Mutex::new(reader),
         })
     }
 
     pub fn has_palettes(&self) -> bool { self.metadata.verhi == 2 }
 
-    pub fn get_sprite(&mut self, sprite_id: &SpriteId) -> Result<SffData, DataError> {
-        if !self.cache.contains_key(sprite_id) {
-            self.load_sprite(sprite_id)?;
-        }
+    pub fn has_sprite(&self, sprite_id: &SpriteId) -> bool { self.sprite_ids.contains_key(sprite_id) }
 
-        let sff_data = self.cache.get(sprite_id)
-            .ok_or_else(|| DataError::new(format!("Image not found: {}", sprite_id)))?;
+    pub fn palettes(&self) -> &[Arc<Palette>] { &self.index.palettes }
 
-        Ok(sff_data.clone())
+    pub fn find_palette(&self, groupno: i16, itemno: i16) -> Option<usize> {
+        self.index.palette_ids.iter().position(|id| *id == (groupno, itemno))
     }
 
-    pub fn get_group(&mut self, group_id: i16) -> Result<Vec<SffData>, DataError> {
-        let images = sff_parser::read_images(&self.path, &[group_id])?;
+    pub fn cache_size(&self) -> usize { self.cache.size() }
 
-        for image in images.iter() {
-            let new_key = SpriteId::new(image.groupno, image.imageno);
-            self.cache.insert(new_key, image.clone());
-        }
+    pub fn set_cache_budget(&mut self, cache_budget: usize) {
+        self.cache.set_budget(cache_budget);
+    }
+
+    pub fn get_sprite(&mut self, sprite_id: &SpriteId) -> Result<SffData, DataError> {
+        let position = *self.sprite_ids.get(sprite_id)
+            .ok_or_else(|| DataError::new(format!("Image not found: {}", sprite_id)).with_path(&self.path))?;
+
+        self.get_sprite_at(position)
+    }
+
+    pub fn get_group(&mut self, group_id: i16) -> Result<Vec<SffData>, DataError> {
+        let positions: Vec<usize> = self.index.sprites.iter()
+            .enumerate()
+            .filter(|(_, sprite)| sprite.groupno == group_id)
+            .map(|(position, _)| position)
+            .collect();
 
-        Ok(images)
+        positions.into_iter().map(|position| self.get_sprite_at(position)).collect()
     }
 
     pub fn load_sprite(&mut self, sprite_id: &SpriteId) -> Result<(), DataError> {
-        let images = sff_parser::read_images(&self.path, &[sprite_id.group])?;
+        self.get_sprite(sprite_id).map(|_| ())
+    }
 
-        for image in images.iter() {
-            let new_key = SpriteId::new(image.groupno, image.imageno);
-            self.cache.insert(new_key, image.clone());
+    pub fn load_all_sprites(&mut self) -> Result<(), DataError> {
+        for position in 0..self.index.sprites.len() {
+            self.get_sprite_at(position)?;
         }
 
         Ok(())
     }
 
-    pub fn load_all_sprites(&mut self) -> Result<(), DataError> {
-        let images = sff_parser::read_images(&self.path, &[])?;
+    fn get_sprite_at(&mut self, position: usize) -> Result<SffData, DataError> {
+        let sprite = &self.index.sprites[position];
+        let source = sprite.linked.unwrap_or(position);
+
+        let sff_data = match self.cache.get(source) {
+            Some(sff_data) => sff_data,
+            None => {
+                let reader = self.reader.get_mut().expect("Could not lock sprite file reader");
+                let sff_data = sff_parser::decode_sprite(reader, &self.path, &self.index, source)?;
+                self.cache.insert(source, sff_data.clone());
+                sff_data
+            }
+        };
+
+        Ok(SffData {
+            image: sff_data.image,
+            groupno: sprite.groupno,
+            imageno: sprite.imageno,
+            x: sprite.x,
+            y: sprite.y,
+            palindex: sprite.palindex.unwrap_or(sff_data.palindex),
+            linked: -1,
+        })
+    }
+}
 
-        for image in images.iter() {
-            let new_key = SpriteId::new(image.groupno, image.imageno);
-            self.cache.insert(new_key, image.clone());
-        }
+#[cfg(test)]
+mod tests {
+    use crate::drawing::sff::{image::{RawColor, RawImage}, sff_writer::{encode_sff_v2, SpriteCompression}};
+    use crate::io::{file_system, memory_file_system::MemoryFileSystem};
 
-        Ok(())
+    use super::*;
+
+    #[test]
+    fn cache_misses_use_the_open_reader() {
+        let memory_file_system = Arc::new(MemoryFileSystem::new());
+        let palette = Arc::new(Palette::from_colors((0..256).map(|i| RawColor::new(i as u8, 0, 0, 255)).collect()));
+        let sprites: Vec<SffData> = (0..2).map(|imageno| SffData {
+            image: Arc::new(RawImage::indexed(4, 3, (0..12).map(|i| i + imageno as u8).collect(), palette.clone())),
+            groupno: 0,
+            imageno,
+            x: 0,
+            y: 0,
+            palindex: 0,
+            linked: 0,
+        }).collect();
+
+        memory_file_system.insert("kfm.sff", encode_sff_v2(&sprites, &[palette], SpriteCompression::Lz5).unwrap());
+        file_system::get_file_system().mount("mem://sprite_file_tests", memory_file_system.clone());
+
+        let mut sprite_file = SpriteFile::load("mem://sprite_file_tests/kfm.sff").unwrap();
+
+        memory_file_system.remove("kfm.sff");
+
+        let sff_data = sprite_file.get_sprite(&SpriteId::new(0, 1)).unwrap();
+
+        assert_eq!(*sff_data.image.pixels, (1..13).collect::<Vec<u8>>());
+        assert!(sprite_file.cache_size() > 0);
     }
 }
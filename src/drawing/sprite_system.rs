@@ -1,28 +1,34 @@
 use std::sync::Arc;
 
-use crate::core::error::DataError;
+use crate::core::{constants::DEFAULT_SPRITE_CACHE_BUDGET, error::DataError};
+use crate::systems::visual_server::shader::Shader;
 use super::mugen_font::MugenFont;
 use super::{sff::{image::Palette, sff_parser}, sprite_file::SpriteFile};
 
-pub struct SpriteSystem {}
+#[derive(Clone)]
+pub struct SpriteSystem {
+    pub sprite_cache_budget: usize,
+}
 
 impl SpriteSystem {
     pub fn new() -> Self {
-        SpriteSystem { }
+        SpriteSystem {
+            sprite_cache_budget: DEFAULT_SPRITE_CACHE_BUDGET,
+        }
     }
 
-    pub fn load_font(&self, path: &str) -> Result<MugenFont, DataError> {
+    pub fn load_font(&self, path: &str, shader: Arc<Shader>) -> Result<MugenFont, DataError> {
         if path.to_lowercase().ends_with(".fnt") {
-            return MugenFont::load_font_v1(path);
+            return MugenFont::load_font_v1(path, shader);
         } else if path.to_lowercase().ends_with(".def") {
-            return MugenFont::load_font_v2(path, self);
+            return MugenFont::load_font_v2(path, self, shader);
         }
 
         Err(DataError::new(format!("Font file not supported: {}", path)))
     }
 
     pub fn get_sprite_file(&self, path: &str) -> Result<SpriteFile, DataError> {
-        SpriteFile::load(path)
+        SpriteFile::load_with_budget(path, self.sprite_cache_budget)
     }
 
     pub fn load_palettes(&self, path: &str) -> Result<Vec<Arc<Palette>>, DataError> {
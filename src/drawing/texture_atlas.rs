@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use gdnative::{api::{Image, visual_server::TextureFlags}, core_types::{ByteArray, Point2, Rect2, Size2}};
+
+use crate::systems::visual_server::texture::Texture;
+
+use super::sff::{image::{Palette, PixelFormat, RawImage}, sff_common::SffData};
+
+pub const DEFAULT_ATLAS_SIZE: usize = 2048;
+const PADDING: usize = 1;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PackedRect {
+    pub page: usize,
+    pub x: usize,
+    pub y: usize,
+    pub w: usize,
+    pub h: usize,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct PackedLayout {
+    pub pages: Vec<(usize, usize)>,
+    pub rects: Vec<PackedRect>,
+}
+
+struct Shelf {
+    y: usize,
+    height: usize,
+    x: usize,
+}
+
+struct Page {
+    width: usize,
+    height: usize,
+    shelves: Vec<Shelf>,
+    oversized: bool,
+}
+
+impl Page {
+    fn place(&mut self, w: usize, h: usize, max_width: usize, max_size: usize) -> Option<(usize, usize)> {
+        if self.oversized {
+            return None;
+        }
+
+        for shelf in self.shelves.iter_mut() {
+            if h <= shelf.height && shelf.x + w <= max_width {
+                let position = (shelf.x, shelf.y);
+                shelf.x += w + PADDING;
+                self.width = self.width.max(shelf.x - PADDING);
+                return Some(position);
+            }
+        }
+
+        let y = self.shelves.last().map_or(0, |shelf| shelf.y + shelf.height + PADDING);
+
+        if y + h > max_size {
+            return None;
+        }
+
+        self.shelves.push(Shelf { y, height: h, x: w + PADDING });
+        self.width = self.width.max(w);
+        self.height = y + h;
+
+        Some((0, y))
+    }
+}
+
+// Shelf packing, tallest images first. Images bigger than a page get a page of their own.
+pub fn pack_rects(sizes: &[(usize, usize)], max_size: usize) -> PackedLayout {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    let mut pages: Vec<Page> = Vec::new();
+    let mut rects: Vec<PackedRect> = vec![PackedRect { page: 0, x: 0, y: 0, w: 0, h: 0 }; sizes.len()];
+
+    order.sort_by(|a, b| sizes[*b].1.cmp(&sizes[*a].1).then(sizes[*b].0.cmp(&sizes[*a].0)));
+
+    // Aim for square pages instead of a single long shelf
+    let fitting = sizes.iter().filter(|(w, h)| *w <= max_size && *h <= max_size);
+    let area: usize = fitting.clone().map(|(w, h)| (w + PADDING) * (h + PADDING)).sum();
+    let widest = fitting.map(|(w, _)| *w).max().unwrap_or(0);
+    let max_width = ((area as f64).sqrt().ceil() as usize).max(widest).min(max_size);
+
+    for index in order {
+        let (w, h) = sizes[index];
+
+        if w > max_size || h > max_size {
+            rects[index] = PackedRect { page: pages.len(), x: 0, y: 0, w, h };
+            pages.push(Page { width: w, height: h, shelves: Vec::new(), oversized: true });
+            continue;
+        }
+
+        let placed = pages.iter_mut()
+            .enumerate()
+            .find_map(|(page_index, page)| page.place(w, h, max_width, max_size).map(|(x, y)| (page_index, x, y)));
+
+        let (page, x, y) = match placed {
+            Some(placed) => placed,
+            None => {
+                let mut page = Page { width: 0, height: 0, shelves: Vec::new(), oversized: false };
+                let (x, y) = page.place(w, h, max_width, max_size).expect("Image must fit an empty page");
+                pages.push(page);
+                (pages.len() - 1, x, y)
+            }
+        };
+
+        rects[index] = PackedRect { page, x, y, w, h };
+    }
+
+    PackedLayout {
+        pages: pages.iter().map(|page| (page.width.max(1), page.height.max(1))).collect(),
+        rects,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct AtlasRegion {
+    pub page: usize,
+    pub rect: Rect2,
+}
+
+pub struct TextureAtlas<K: Hash + Eq> {
+    pub textures: Vec<Arc<Texture>>,
+    regions: HashMap<K, AtlasRegion>,
+}
+
+impl<K: Hash + Eq> TextureAtlas<K> {
+    pub fn get_region(&self, key: &K) -> Option<AtlasRegion> {
+        self.regions.get(key).copied()
+    }
+
+    pub fn get(&self, key: &K) -> Option<(Arc<Texture>, Rect2)> {
+        self.regions.get(key)
+            .map(|region| (self.textures[region.page].clone(), region.rect))
+    }
+}
+
+struct AtlasImage {
+    w: usize,
+    h: usize,
+    format: PixelFormat,
+    pixels: Arc<Vec<u8>>,
+}
+
+pub struct TextureAtlasBuilder<K: Hash + Eq> {
+    max_size: usize,
+    keys: Vec<K>,
+    images: Vec<AtlasImage>,
+}
+
+impl<K: Hash + Eq> Default for TextureAtlasBuilder<K> {
+    fn default() -> Self {
+        TextureAtlasBuilder::new()
+    }
+}
+
+impl<K: Hash + Eq> TextureAtlasBuilder<K> {
+    pub fn new() -> Self {
+        TextureAtlasBuilder::with_max_size(DEFAULT_ATLAS_SIZE)
+    }
+
+    pub fn with_max_size(max_size: usize) -> Self {
+        TextureAtlasBuilder {
+            max_size,
+            keys: Vec::new(),
+            images: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool { self.images.is_empty() }
+
+    // Indexed images are packed as indices and need a palette handle to be drawn
+    pub fn add_image(&mut self, key: K, image: &RawImage) {
+        self.keys.push(key);
+        self.images.push(AtlasImage {
+            w: image.w,
+            h: image.h,
+            format: image.format,
+            pixels: image.pixels.clone(),
+        });
+    }
+
+    pub fn add_baked_image(&mut self, key: K, image: &RawImage, palette: &Palette) {
+        self.keys.push(key);
+        self.images.push(AtlasImage {
+            w: image.w,
+            h: image.h,
+            format: PixelFormat::Rgba,
+            pixels: Arc::new(image.to_rgba(palette)),
+        });
+    }
+
+    pub fn add_sprite(&mut self, key: K, sff_data: &SffData) {
+        self.add_image(key, &sff_data.image);
+    }
+
+    pub fn build(self, flags: TextureFlags) -> TextureAtlas<K> {
+        let (pages, image_regions) = pack_pages(&self.images, self.max_size);
+        let textures = pages.iter()
+            .map(|page| {
+                let image_format = match page.format {
+                    PixelFormat::Indexed => Image::FORMAT_R8,
+                    PixelFormat::Rgba => Image::FORMAT_RGBA8,
+                };
+                let image = Image::new();
+                image.create_from_data(page.w as i64, page.h as i64, false, image_format, ByteArray::from_slice(&page.pixels));
+                Texture::allocate(image, flags)
+            })
+            .collect();
+
+        TextureAtlas {
+            textures,
+            regions: self.keys.into_iter().zip(image_regions).collect(),
+        }
+    }
+}
+
+struct AtlasPage {
+    format: PixelFormat,
+    w: usize,
+    h: usize,
+    pixels: Vec<u8>,
+}
+
+// Copies the images into their pages, regions are returned in the order of the images
+fn pack_pages(images: &[AtlasImage], max_size: usize) -> (Vec<AtlasPage>, Vec<AtlasRegion>) {
+    let mut pages: Vec<AtlasPage> = Vec::new();
+    let mut regions: Vec<Option<AtlasRegion>> = vec![None; images.len()];
+
+    // Indices and colors can't share a page, so each format gets its own pages
+    for format in [PixelFormat::Indexed, PixelFormat::Rgba].iter() {
+        let members: Vec<usize> = (0..images.len())
+            .filter(|index| images[*index].format == *format)
+            .collect();
+
+        if members.is_empty() {
+            continue;
+        }
+
+        let sizes: Vec<(usize, usize)> = members.iter().map(|index| (images[*index].w, images[*index].h)).collect();
+        let layout = pack_rects(&sizes, max_size);
+        let bytes_per_pixel = format.bytes_per_pixel();
+        let first_page = pages.len();
+
+        pages.extend(layout.pages.iter().map(|(w, h)| AtlasPage {
+            format: *format,
+            w: *w,
+            h: *h,
+            pixels: vec![0u8; w * h * bytes_per_pixel],
+        }));
+
+        for (index, rect) in members.iter().zip(layout.rects.iter()) {
+            let image = &images[*index];
+            let page = &mut pages[first_page + rect.page];
+            let row_length = image.w * bytes_per_pixel;
+
+            for y in 0..image.h {
+                let source = y * row_length;
+                let dest = ((rect.y + y) * page.w + rect.x) * bytes_per_pixel;
+                page.pixels[dest..dest + row_length].copy_from_slice(&image.pixels[source..source + row_length]);
+            }
+
+            regions[*index] = Some(AtlasRegion {
+                page: first_page + rect.page,
+                rect: Rect2::new(
+                    Point2::new(rect.x as f32, rect.y as f32),
+                    Size2::new(rect.w as f32, rect.h as f32)
+                ),
+            });
+        }
+    }
+
+    (pages, regions.into_iter().map(|region| region.expect("Atlas image not packed")).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pseudo_random_sizes(count: usize, seed: usize, max: usize) -> Vec<(usize, usize)> {
+        (0..count).map(|index| ((index * 37 + seed) % max + 1, (index * 53 + seed * 7) % max + 1)).collect()
+    }
+
+    fn overlap(a: &PackedRect, b: &PackedRect) -> bool {
+        a.page == b.page
+            && a.x < b.x + b.w + PADDING && b.x < a.x + a.w + PADDING
+            && a.y < b.y + b.h + PADDING && b.y < a.y + a.h + PADDING
+    }
+
+    fn assert_valid(sizes: &[(usize, usize)], layout: &PackedLayout) {
+        assert_eq!(layout.rects.len(), sizes.len());
+
+        for (rect, (w, h)) in layout.rects.iter().zip(sizes.iter()) {
+            let (page_width, page_height) = layout.pages[rect.page];
+
+            assert_eq!((rect.w, rect.h), (*w, *h));
+            assert!(rect.x + rect.w <= page_width && rect.y + rect.h <= page_height, "{:?} outside {:?}", rect, (page_width, page_height));
+        }
+
+        let rects: Vec<&PackedRect> = layout.rects.iter().filter(|rect| rect.w > 0 && rect.h > 0).collect();
+
+        for (index, a) in rects.iter().enumerate() {
+            for b in rects[index + 1..].iter() {
+                assert!(!overlap(a, b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn packed_rects_stay_inside_their_page_without_overlapping() {
+        let cases = vec![
+            (pseudo_random_sizes(200, 3, 40), 128),
+            (pseudo_random_sizes(50, 11, 100), 256),
+            (pseudo_random_sizes(30, 5, 64), 64),
+            (vec![(1, 1); 100], 16),
+            (vec![(64, 1), (1, 64), (32, 32)], 64),
+        ];
+
+        for (sizes, max_size) in cases {
+            let layout = pack_rects(&sizes, max_size);
+
+            assert_valid(&sizes, &layout);
+
+            for (w, h) in layout.pages.iter() {
+                assert!(*w <= max_size && *h <= max_size);
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_images_get_their_own_page() {
+        let sizes = vec![(10, 10), (100, 5), (10, 10), (5, 70)];
+        let layout = pack_rects(&sizes, 64);
+
+        assert_valid(&sizes, &layout);
+
+        for oversized in [1, 3].iter() {
+            let rect = layout.rects[*oversized];
+
+            assert_eq!((rect.x, rect.y), (0, 0));
+            assert_eq!(layout.pages[rect.page], sizes[*oversized]);
+            assert_eq!(layout.rects.iter().filter(|other| other.page == rect.page).count(), 1);
+        }
+
+        assert_eq!(layout.rects[0].page, layout.rects[2].page);
+    }
+
+    #[test]
+    fn full_pages_continue_on_a_new_page() {
+        let sizes = vec![(31, 31); 5];
+        let layout = pack_rects(&sizes, 64);
+
+        assert_valid(&sizes, &layout);
+        assert_eq!(layout.pages.len(), 2);
+        assert_eq!(layout.rects.iter().filter(|rect| rect.page == 0).count(), 4);
+    }
+
+    #[test]
+    fn zero_sized_images_are_packed() {
+        let sizes = vec![(0, 0), (5, 0), (0, 5), (3, 3)];
+        let layout = pack_rects(&sizes, 64);
+
+        assert_valid(&sizes, &layout);
+        assert_eq!(layout.pages.len(), 1);
+        assert_eq!(pack_rects(&[(0, 0)], 64).pages, vec![(1, 1)]);
+        assert_eq!(pack_rects(&[], 64), PackedLayout { pages: Vec::new(), rects: Vec::new() });
+    }
+
+    #[test]
+    fn indexed_and_rgba_images_use_separate_pages() {
+        let image = |w: usize, h: usize, format: PixelFormat, seed: u8| AtlasImage {
+            w,
+            h,
+            format,
+            pixels: Arc::new((0..w * h * format.bytes_per_pixel()).map(|i| (i as u8).wrapping_add(seed)).collect()),
+        };
+        let images = vec![
+            image(4, 3, PixelFormat::Indexed, 0),
+            image(2, 2, PixelFormat::Rgba, 10),
+            image(5, 1, PixelFormat::Indexed, 20),
+            image(3, 2, PixelFormat::Rgba, 30),
+        ];
+        let (pages, regions) = pack_pages(&images, 64);
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(regions.len(), images.len());
+
+        for (image, region) in images.iter().zip(regions.iter()) {
+            let page = &pages[region.page];
+            let bytes_per_pixel = image.format.bytes_per_pixel();
+            let (x, y) = (region.rect.origin.x as usize, region.rect.origin.y as usize);
+
+            assert!(page.format == image.format);
+            assert_eq!(page.pixels.len(), page.w * page.h * bytes_per_pixel);
+            assert_eq!((region.rect.size.width as usize, region.rect.size.height as usize), (image.w, image.h));
+
+            for row in 0..image.h {
+                let dest = ((y + row) * page.w + x) * bytes_per_pixel;
+                let source = row * image.w * bytes_per_pixel;
+                let length = image.w * bytes_per_pixel;
+
+                assert_eq!(&page.pixels[dest..dest + length], &image.pixels[source..source + length]);
+            }
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::{collections::HashMap, sync::{Arc, RwLock}};
+
+use bevy_ecs::prelude::*;
+use gdnative::{api::visual_server::TextureFlags, core_types::{Point2, Size2}};
+
+use crate::{animations::animation_manager::AnimationManager, core::{blending::Blending, constants::{GAME_TICK_DURATION, MAX_GAME_TICKS_PER_FRAME}, diagnostics, enumerations::SpriteEffects, error::DataError, sprite_id::SpriteId}, drawing::sprite_file::SpriteFile, systems::visual_server::{material::Material, palette::{PaletteCache, PaletteHandle}, shader::Shader, sprite::{Sprite, SpriteBundle}, texture::Texture}};
+
+#[derive(Clone)]
+struct AnimationFrame {
+    texture: Arc<Texture>,
+    palette: Option<PaletteHandle>,
+    offset: Point2,
+}
+
+pub struct AnimatedSprite {
+    pub animation_manager: AnimationManager,
+    pub sprite_file: Arc<RwLock<SpriteFile>>,
+    frames: HashMap<SpriteId, AnimationFrame>,
+    palette_cache: PaletteCache,
+    blending: Option<Blending>,
+    elapsed: f64,
+}
+
+impl AnimatedSprite {
+    pub fn new(
+        mut animation_manager: AnimationManager,
+        sprite_file: Arc<RwLock<SpriteFile>>,
+        shader: Arc<Shader>,
+        animationnumber: i32
+    ) -> Result<Self, DataError> {
+        animation_manager.set_local_animation(animationnumber, 0)?;
+
+        Ok(AnimatedSprite {
+            animation_manager,
+            sprite_file,
+            frames: HashMap::new(),
+            palette_cache: PaletteCache::new(shader),
+            blending: None,
+            elapsed: 0.0,
+        })
+    }
+
+    pub fn advance(&mut self, delta: f64) -> Result<(), DataError> {
+        advance_animation(&mut self.animation_manager, &mut self.elapsed, delta)
+    }
+
+    pub fn update(
+        &mut self,
+        mut sprite: Mut<Sprite>,
+        mut texture: Mut<Arc<Texture>>,
+        mut palette: Mut<Option<PaletteHandle>>,
+        material: &Option<Arc<RwLock<Material>>>,
+        delta: f64
+    ) -> Result<(), DataError> {
+        self.advance(delta)?;
+
+        let element = self.animation_manager.currentelement
+            .clone()
+            .ok_or_else(|| DataError::new("Animation manager element is null".to_string()))?;
+
+        let interpolated = self.animation_manager
+            .get_interpolated_element()
+            .ok_or_else(|| DataError::new("Animation manager animation is null".to_string()))?;
+
+        let frame = self.get_frame(element.sprite_id);
+        let flip_h = element.flip.contains(SpriteEffects::FlipHorizontally);
+        let flip_v = element.flip.contains(SpriteEffects::FlipVertically);
+        let scale = interpolated.scale;
+
+        // Flipped sprites are drawn backwards from the offset, so mirroring the axis is enough
+        let mut offset = Point2::new(frame.offset.x * scale.x, frame.offset.y * scale.y);
+
+        if flip_h {
+            offset.x = -offset.x;
+        }
+
+        if flip_v {
+            offset.y = -offset.y;
+        }
+
+        let next_sprite = Sprite {
+            size: Size2::new(frame.texture.size.width * scale.x, frame.texture.size.height * scale.y),
+            rect: None,
+            offset: offset + interpolated.offset,
+            flip_h,
+            flip_v,
+        };
+
+        // Only touch changed components, the visual server redraws everything that changed
+        if !Arc::ptr_eq(&*texture, &frame.texture) {
+            *texture = frame.texture;
+        }
+
+        let same_palette = match (&*palette, &frame.palette) {
+            (Some(current), Some(next)) => current.ptr_eq(next),
+            (None, None) => true,
+            _ => false,
+        };
+
+        if !same_palette {
+            *palette = frame.palette;
+        }
+
+        if *sprite != next_sprite {
+            *sprite = next_sprite;
+        }
+
+        if let Some(material) = material {
+            if self.blending != Some(interpolated.blending) {
+                interpolated.blending.configure_material(material);
+                self.blending = Some(interpolated.blending);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_frame(&mut self, sprite_id: SpriteId) -> AnimationFrame {
+        if let Some(frame) = self.frames.get(&sprite_id) {
+            return frame.clone();
+        }
+
+        let palette_cache = &mut self.palette_cache;
+        let frame_result = self.sprite_file
+            .write()
+            .expect("Could not lock sprite file")
+            .get_sprite(&sprite_id)
+            .map(|sff_data| {
+                let palette = if sff_data.image.is_indexed() {
+                    Some(palette_cache.get_or_create(&sff_data.select_palette(None)))
+                } else {
+                    None
+                };
+
+                AnimationFrame {
+                    texture: sff_data.create_monochromatic_texture(TextureFlags(0)),
+                    palette,
+                    offset: sff_data.offset(),
+                }
+            });
+
+        // Missing sprites draw nothing, as in MUGEN, and are only reported once
+        let frame = frame_result.unwrap_or_else(|error| {
+            diagnostics::warn(error);
+
+            AnimationFrame {
+                texture: Arc::new(Texture::invalid()),
+                palette: None,
+                offset: Point2::default(),
+            }
+        });
+
+        self.frames.insert(sprite_id, frame.clone());
+
+        frame
+    }
+}
+
+// Runs the game ticks that fit in the frame time, dropping the backlog after a long stall
+fn advance_animation(animation_manager: &mut AnimationManager, elapsed: &mut f64, delta: f64) -> Result<(), DataError> {
+    *elapsed += delta;
+
+    let mut ticks = 0;
+
+    while *elapsed >= GAME_TICK_DURATION {
+        if ticks == MAX_GAME_TICKS_PER_FRAME {
+            *elapsed = 0.0;
+            break;
+        }
+
+        animation_manager.update()?;
+        *elapsed -= GAME_TICK_DURATION;
+        ticks += 1;
+    }
+
+    Ok(())
+}
+
+#[derive(Bundle)]
+pub struct AnimatedSpriteBundle {
+    pub animated_sprite: AnimatedSprite,
+    #[bundle]
+    pub sprite_bundle: SpriteBundle,
+}
+
+impl AnimatedSpriteBundle {
+    pub fn new(animated_sprite: AnimatedSprite, shader: Arc<Shader>) -> Self {
+        AnimatedSpriteBundle {
+            animated_sprite,
+            sprite_bundle: SpriteBundle {
+                material: Some(Material::allocate(shader)),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{animations::animation_loader::AnimationLoader, io::text_file::TextFile};
+
+    use super::*;
+
+    const AIR: &str = "\
+[Begin Action 0]
+0,0, 0,0, 3
+0,1, 0,0, 4
+0,2, 0,0, 5
+
+[Begin Action 1]
+1,0, 0,0, 2
+Loopstart
+1,1, 0,0, 3
+1,2, 0,0, 4
+
+[Begin Action 2]
+2,0, 0,0, 2
+2,1, 0,0, -1
+2,2, 0,0, 5
+";
+
+    fn manager(action: i32) -> AnimationManager {
+        let text_file = TextFile::from_string("test.air".to_string(), AIR.to_string());
+        let mut manager = AnimationManager::new("test.air", AnimationLoader::new().read_animations(&text_file));
+
+        manager.set_local_animation(action, 0).unwrap();
+        manager
+    }
+
+    fn sprite_id(manager: &AnimationManager) -> SpriteId {
+        manager.currentelement.as_ref().unwrap().sprite_id
+    }
+
+    fn run_frames(manager: &mut AnimationManager, elapsed: &mut f64, frames: usize) {
+        for _ in 0..frames {
+            advance_animation(manager, elapsed, GAME_TICK_DURATION).unwrap();
+        }
+    }
+
+    #[test]
+    fn ticks_once_per_game_frame() {
+        let mut manager = manager(0);
+        let mut elapsed = 0.0;
+
+        run_frames(&mut manager, &mut elapsed, 2);
+        assert!(sprite_id(&manager) == SpriteId::new(0, 0));
+
+        run_frames(&mut manager, &mut elapsed, 1);
+        assert!(sprite_id(&manager) == SpriteId::new(0, 1));
+
+        // Without loopstart the whole action repeats
+        run_frames(&mut manager, &mut elapsed, 9);
+        assert!(sprite_id(&manager) == SpriteId::new(0, 0));
+    }
+
+    #[test]
+    fn short_frames_add_up_to_a_tick() {
+        let mut manager = manager(0);
+        let mut elapsed = 0.0;
+
+        for _ in 0..5 {
+            advance_animation(&mut manager, &mut elapsed, GAME_TICK_DURATION / 2.0).unwrap();
+        }
+
+        assert_eq!(manager.animationtime, 2);
+    }
+
+    #[test]
+    fn loops_back_to_loopstart() {
+        let mut manager = manager(1);
+        let mut elapsed = 0.0;
+
+        run_frames(&mut manager, &mut elapsed, 8);
+        assert!(sprite_id(&manager) == SpriteId::new(1, 2));
+
+        run_frames(&mut manager, &mut elapsed, 1);
+        assert!(sprite_id(&manager) == SpriteId::new(1, 1));
+
+        run_frames(&mut manager, &mut elapsed, 7);
+        assert!(sprite_id(&manager) == SpriteId::new(1, 1));
+    }
+
+    #[test]
+    fn holds_infinite_frames() {
+        let mut manager = manager(2);
+        let mut elapsed = 0.0;
+
+        run_frames(&mut manager, &mut elapsed, 200);
+
+        assert!(sprite_id(&manager) == SpriteId::new(2, 1));
+    }
+
+    #[test]
+    fn drops_the_backlog_after_a_stall() {
+        let mut manager = manager(0);
+        let mut elapsed = 0.0;
+
+        advance_animation(&mut manager, &mut elapsed, 1.0).unwrap();
+
+        assert_eq!(manager.animationtime, MAX_GAME_TICKS_PER_FRAME as i32);
+        assert_eq!(elapsed, 0.0);
+        assert!(sprite_id(&manager) == SpriteId::new(0, 1));
+    }
+}
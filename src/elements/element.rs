@@ -1,8 +1,11 @@
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
-use gdnative::{core_types::{Point2, Vector2}};
+use bevy_ecs::prelude::*;
+use gdnative::{api::visual_server::TextureFlags, core_types::{Point2, Size2, Transform2D, Vector2}};
 
-use crate::{core::{sprite_id::SpriteId, sound_id::SoundId, enumerations::{SpriteEffects, ElementType}, error::DataError}, io::text_section::TextSection, drawing::{print_data::PrintData, sprite_file::SpriteFile, sff::sff_common::SffData}};
+use crate::{animations::animation_manager::AnimationManager, core::{sprite_id::SpriteId, sound_id::SoundId, enumerations::{SpriteEffects, ElementType}, error::DataError}, io::{from_text_section::FromTextSection, text_section::TextSection}, drawing::{print_data::PrintData, sprite_file::SpriteFile, sff::sff_common::SffData}, systems::visual_server::{palette::PaletteCache, shader::Shader, sprite::{Sprite, SpriteBundle}}};
+
+use super::animated_image::{AnimatedSprite, AnimatedSpriteBundle};
 
 #[derive(Clone)]
 pub struct Element {
@@ -19,56 +22,158 @@ pub struct Element {
     pub layerno: i32,
     pub scale: Vector2,
     pub element_type: ElementType,
-    pub sff: Arc<SffData>,
+    // Only static elements have a sprite, animated ones read theirs from the sprite file
+    pub sff: Option<Arc<SffData>>,
+    animation_manager: Option<AnimationManager>,
+    sprite_file: Arc<RwLock<SpriteFile>>,
+}
+
+#[derive(Clone, FromTextSection)]
+pub struct ElementDefinition {
+    #[text_section(key = "anim", default = "-1")]
+    pub animationnumber: i32,
+    #[text_section(key = "spr", default = "SpriteId::invalid()")]
+    pub spriteid: SpriteId,
+    #[text_section(key = "font")]
+    pub fontdata: PrintData,
+    pub text: String,
+    #[text_section(key = "snd", default = "SoundId::invalid()")]
+    pub soundid: SoundId,
+    pub sndtime: i32,
+    pub offset: Point2,
+    pub displaytime: i32,
+    pub facing: i32,
+    pub vfacing: i32,
+    pub layerno: i32,
+    #[text_section(default = "Vector2::new(1.0, 1.0)")]
+    pub scale: Vector2,
 }
 
 impl Element {
     pub fn build(
         textsection: &TextSection,
         prefix: &str,
-        sprite_file: &mut SpriteFile
+        sprite_file: &Arc<RwLock<SpriteFile>>,
+        animation_manager: &AnimationManager
     ) -> Result<Element, DataError> {
+        let definition = ElementDefinition::from_text_section(textsection, prefix)?;
         let mut flip = SpriteEffects::None;
 
-        if textsection.get_attribute_or_default::<i32>(&format!("{}.facing", prefix)) > 0 {
+        if definition.facing > 0 {
             flip |= SpriteEffects::FlipHorizontally;
         }
 
-        if textsection.get_attribute_or_default::<i32>(&format!("{}.vfacing", prefix)) > 0 {
+        if definition.vfacing > 0 {
             flip |= SpriteEffects::FlipVertically;
         }
 
-        let animationnumber = textsection.get_attribute_or(&format!("{}.anim", prefix), -1);
-        let spriteid = textsection.get_attribute_or(&format!("{}.spr", prefix), SpriteId::invalid());
-        let fontdata = textsection.get_attribute_or_default(&format!("{}.font", prefix));
-        let text = textsection.get_attribute_or_default(&format!("{}.text", prefix));
         let mut element_type = ElementType::None;
+        let mut element_animation_manager = None;
+        let mut sff = None;
+
+        if definition.animationnumber >= 0 {
+            if !animation_manager.has_animation(definition.animationnumber) {
+                return Err(DataError::invalid_attribute(format!("Action not found: {}", definition.animationnumber))
+                    .with_path(&textsection.filepath)
+                    .with_section(&textsection.title));
+            }
 
-        if animationnumber > 0 {
             element_type = ElementType::Animation;
-        } else if spriteid != SpriteId::invalid() {
+            element_animation_manager = Some(animation_manager.clone());
+        } else if definition.spriteid != SpriteId::invalid() {
             element_type = ElementType::Static;
-        } else if fontdata != PrintData::default() {
+            sff = Some(Arc::new(sprite_file
+                .write()
+                .expect("Could not lock sprite file")
+                .get_sprite(&definition.spriteid)?));
+        } else if definition.fontdata != PrintData::default() {
             element_type = ElementType::Text;
         }
 
-        let sff = Arc::new(sprite_file.get_sprite(&spriteid)?);
-
         Ok(Element {
             flip,
             element_type,
-            animationnumber,
-            spriteid,
-            fontdata,
-            text,
+            animationnumber: definition.animationnumber,
+            spriteid: definition.spriteid,
+            fontdata: definition.fontdata,
+            text: definition.text,
             prefix: prefix.to_string(),
-            soundid: textsection.get_attribute_or(&format!("{}.snd", prefix), SoundId::invalid()),
-            sndtime: textsection.get_attribute_or_default(&format!("{}.sndtime", prefix)),
-            offset: textsection.get_attribute_or_default(&format!("{}.offset", prefix)),
-            displaytime: textsection.get_attribute_or_default(&format!("{}.displaytime", prefix)),
-            layerno: textsection.get_attribute_or_default(&format!("{}.layerno", prefix)),
-            scale: textsection.get_attribute_or(&format!("{}.scale", prefix), Vector2::new(1.0, 1.0)),
+            soundid: definition.soundid,
+            sndtime: definition.sndtime,
+            offset: definition.offset,
+            displaytime: definition.displaytime,
+            layerno: definition.layerno,
+            scale: definition.scale,
             sff,
+            animation_manager: element_animation_manager,
+            sprite_file: sprite_file.clone(),
         })
     }
+
+    // Spawns the sprite or animation of the element at its offset, text elements are drawn by their screens
+    pub fn spawn(
+        &self,
+        commands: &mut Commands,
+        palette_cache: &mut PaletteCache,
+        shader: Arc<Shader>
+    ) -> Result<Option<Entity>, DataError> {
+        let transform = Transform2D::translation(self.offset.x, self.offset.y);
+
+        if let Some(animation_manager) = &self.animation_manager {
+            let animated_sprite = AnimatedSprite::new(
+                animation_manager.clone(),
+                self.sprite_file.clone(),
+                shader.clone(),
+                self.animationnumber
+            )?;
+            let mut bundle = AnimatedSpriteBundle::new(animated_sprite, shader);
+
+            bundle.sprite_bundle.transform = transform;
+
+            return Ok(Some(commands.spawn_bundle(bundle).id()));
+        }
+
+        let sff = match &self.sff {
+            Some(sff) => sff,
+            None => return Ok(None),
+        };
+
+        let (texture, palette) = if sff.image.is_indexed() {
+            (sff.create_monochromatic_texture(TextureFlags(0)), Some(palette_cache.get_or_create(&sff.select_palette(None))))
+        } else {
+            (sff.create_texture(None, TextureFlags(0))?, None)
+        };
+
+        let flip_h = self.flip.contains(SpriteEffects::FlipHorizontally);
+        let flip_v = self.flip.contains(SpriteEffects::FlipVertically);
+        let mut offset = Point2::new(sff.offset().x * self.scale.x, sff.offset().y * self.scale.y);
+
+        if flip_h {
+            offset.x = -offset.x;
+        }
+
+        if flip_v {
+            offset.y = -offset.y;
+        }
+
+        let sprite = Sprite {
+            size: Size2::new(texture.size.width * self.scale.x, texture.size.height * self.scale.y),
+            offset,
+            flip_h,
+            flip_v,
+            ..Default::default()
+        };
+
+        Ok(Some(commands.spawn_bundle(SpriteBundle {
+            texture,
+            sprite,
+            transform,
+            palette,
+            ..Default::default()
+        }).id()))
+    }
+
+    pub fn expected_keys(prefix: &str) -> Vec<String> {
+        ElementDefinition::expected_keys(prefix)
+    }
 }
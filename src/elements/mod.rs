@@ -1 +1,2 @@
 pub mod element;
+pub mod animated_image;
@@ -1,90 +1,219 @@
 use crate::core::error::DataError;
 use crate::io::text_file::TextFile;
 use std::ffi::OsStr;
+use std::io::{Read, Seek};
 use std::path::{PathBuf, Path};
-use gdnative::prelude::*;
-use gdnative::api::file::File;
+use std::sync::{Arc, RwLock};
+
+use super::path_resolver;
+use super::text_encoding;
+use super::virtual_file_system::VirtualFileSystem;
+use super::zip_file_system::ZipFileSystem;
+
+pub trait FileStream: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> FileStream for T {}
+
+pub trait FileSystem: Send + Sync {
+    fn exists(&self, path: &str) -> bool;
+
+    fn open(&self, path: &str) -> Result<Box<dyn FileStream>, DataError>;
+
+    fn read_dir(&self, path: &str) -> Result<Vec<String>, DataError>;
+
+    fn read(&self, path: &str) -> Result<Vec<u8>, DataError> {
+        let mut stream = self.open(path)?;
+        let mut buffer = Vec::new();
+
+        stream.read_to_end(&mut buffer)
+            .map_err(|error| DataError::new(format!("Error reading file: {}, {}", path, error)))?;
+
+        Ok(buffer)
+    }
+
+    fn write(&self, path: &str, _data: &[u8]) -> Result<(), DataError> {
+        Err(DataError::new(format!("Error writing file: {}, file system is read-only", path)))
+    }
+
+    fn modified_time(&self, _path: &str) -> Option<u64> {
+        None
+    }
+
+    // Whether the asset loader threads can read from it
+    fn is_thread_safe(&self) -> bool {
+        true
+    }
+}
+
+static FILE_SYSTEM: RwLock<Option<Arc<VirtualFileSystem>>> = RwLock::new(None);
+
+pub fn get_file_system() -> Arc<VirtualFileSystem> {
+    if let Some(file_system) = FILE_SYSTEM.read().expect("Could not lock file system").as_ref() {
+        return file_system.clone();
+    }
+
+    let mut file_system = FILE_SYSTEM.write().expect("Could not lock file system");
+
+    file_system
+        .get_or_insert_with(|| Arc::new(VirtualFileSystem::default()))
+        .clone()
+}
+
+pub fn set_file_system(file_system: VirtualFileSystem) {
+    *FILE_SYSTEM.write().expect("Could not lock file system") = Some(Arc::new(file_system));
+}
+
+pub fn mount_archive(filepath: &str) -> Result<String, DataError> {
+    let file_system = get_file_system();
+    let normalized_path = path_resolver::normalize_path(filepath);
+
+    if file_system.is_mounted(&normalized_path) {
+        return Ok(normalized_path);
+    }
+
+    let path = resolve_file(&normalized_path)
+        .ok_or_else(|| DataError::missing_file(filepath, "Archive not found".to_string()))?;
+
+    if !file_system.is_mounted(&path) {
+        let archive = ZipFileSystem::new(file_system.read(&path)?)
+            .map_err(|error| error.with_path(&path))?;
+
+        file_system.mount(&path, Arc::new(archive));
+    }
+
+    Ok(path)
+}
 
 pub fn does_file_exist(filepath: &str) -> bool {
-    let file = File::new();
-    return file.file_exists(filepath);
+    resolve_file(filepath).is_some()
+}
+
+pub fn resolve_file(filepath: &str) -> Option<String> {
+    path_resolver::resolve_case_insensitive(get_file_system().as_ref(), filepath)
 }
 
 pub fn get_path_by_refferrer(name: &str, referrer: &str) -> String {
-    let mut directory = get_directory(referrer);
-    let mut path = combine_paths(&directory, name);
-
-    for _ in 0..2 {
-        if !does_file_exist(&path) {
-            directory = get_directory(&directory);
-            path = combine_paths(&directory, name);
-            continue;
-        }
+    find_path_by_refferrer(name, referrer)
+        .unwrap_or_else(|_| combine_paths(&get_directory(referrer), name))
+}
 
-        break;
+pub fn find_path_by_refferrer(name: &str, referrer: &str) -> Result<String, DataError> {
+    let candidates = path_resolver::get_search_paths(name, referrer);
+
+    for candidate in candidates.iter() {
+        if let Some(path) = resolve_file(candidate) {
+            return Ok(path);
+        }
     }
 
-    path
+    Err(DataError::missing_file(referrer, format!(
+        "File not found: {}, tried: {}",
+        name,
+        candidates.join(", ")
+    )))
 }
 
 pub fn combine_paths(lhs: &str, rhs: &str) -> String {
-    return format!("{}/{}", lhs.trim_end_matches('/'), rhs.trim_start_matches('/'))
+    path_resolver::normalize_path(&format!("{}/{}", lhs, rhs))
 }
 
 pub fn get_directory(filepath: &str) -> String {
-    let mut path_buf = PathBuf::from(filepath);
+    let mut path_buf = PathBuf::from(path_resolver::normalize_path(filepath));
     path_buf.pop();
     path_buf.to_str().unwrap().to_string()
 }
 
 pub fn get_name(filepath: &str) -> String {
-    let path_buff = Path::new(filepath);
+    let normalized_path = path_resolver::normalize_path(filepath);
+    let path_buff = Path::new(&normalized_path);
     let default = OsStr::new("");
     let result = path_buff.file_name().unwrap_or(&default);
     result.to_str().unwrap_or("").to_string()
 }
 
-pub fn open_file(filepath: &str) -> Result<Ref<File, Unique>, DataError> {
-    let file = File::new();
+pub fn open_file(filepath: &str) -> Result<Box<dyn FileStream>, DataError> {
+    let path = resolve_file(filepath).unwrap_or_else(|| filepath.to_string());
 
-    if let Err(detail) = file.open(filepath, File::READ) {
-        return Result::Err(DataError::new(format!(
-            "Error opening file: {}, {}",
-            filepath,
-            detail
-        )));
-    }
+    get_file_system().open(&path)
+}
 
-    Result::Ok(file)
+pub fn read_file(filepath: &str) -> Result<Vec<u8>, DataError> {
+    let path = resolve_file(filepath).unwrap_or_else(|| filepath.to_string());
+
+    get_file_system().read(&path)
 }
 
-pub fn open_file_as_string(filepath: &str) -> Result<String, DataError> {
-    let file = File::new();
+pub fn write_file(filepath: &str, data: &[u8]) -> Result<(), DataError> {
+    let path = resolve_file(filepath).unwrap_or_else(|| path_resolver::normalize_path(filepath));
 
-    if let Err(detail) = file.open(filepath, File::READ) {
-        return Result::Err(DataError::new(format!(
-            "Error opening file: {}",
-            detail
-        )));
-    }
+    get_file_system().write(&path, data)
+}
 
-    Ok(file.get_as_text().to_string())
+pub fn save_text_file(text_file: &TextFile) -> Result<(), DataError> {
+    let data = text_encoding::encode_text(&text_file.write(), text_file.encoding, text_file.bom);
+
+    write_file(&text_file.filepath, &data)
 }
 
-pub fn open_text_file(filepath: &str) -> Result<TextFile, DataError> {
-    let result = open_file(filepath);
+pub fn open_file_as_string(filepath: &str) -> Result<String, DataError> {
+    let buffer = read_file(filepath)?;
 
-    match result {
-        Ok(file) => Result::Ok(build_text_file(filepath, file)),
-        Err(error) => Result::Err(error)
-    }
+    Ok(text_encoding::decode_text_file(filepath, &buffer))
 }
 
-pub fn build_text_file(filepath: &str, file: Ref<File, Unique>) -> TextFile {
-    let text = file.get_as_text().to_string();
+pub fn open_text_file(filepath: &str) -> Result<TextFile, DataError> {
+    let buffer = read_file(filepath)?;
+
+    Ok(build_text_file(filepath, &buffer))
+}
 
-    TextFile::from_string(
+pub fn build_text_file(filepath: &str, buffer: &[u8]) -> TextFile {
+    let decoded = text_encoding::decode_text_file_with_encoding(filepath, buffer);
+    let mut text_file = TextFile::from_string(
         filepath.to_string(),
-        text
-    )
+        decoded.text
+    );
+
+    text_file.encoding = decoded.encoding;
+    text_file.bom = decoded.bom;
+    text_file
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::SHIFT_JIS;
+
+    use crate::io::memory_file_system::test_mount;
+
+    use super::*;
+
+    #[test]
+    fn saved_text_files_keep_their_encoding() {
+        let mount = test_mount("file_system_tests");
+
+        let path = format!("{}/kfm.def", mount);
+        let (encoded, _, _) = SHIFT_JIS.encode("[Info]\nname = \"カンフー男\"\n");
+        write_file(&path, &encoded).unwrap();
+
+        let mut text_file = open_text_file(&path).unwrap();
+        text_file.set_attribute("Info", "author", "Elecbyte");
+        save_text_file(&text_file).unwrap();
+
+        let (expected, _, _) = SHIFT_JIS.encode("[Info]\nname = \"カンフー男\"\nauthor = Elecbyte\n");
+
+        assert_eq!(read_file(&path).unwrap(), expected.to_vec());
+    }
+
+    #[test]
+    fn saved_text_files_keep_their_bom() {
+        let mount = test_mount("file_system_tests");
+
+        let path = format!("{}/system.def", mount);
+        write_file(&path, b"\xEF\xBB\xBF[Files]\nspr = system.sff\n").unwrap();
+
+        let text_file = open_text_file(&path).unwrap();
+        save_text_file(&text_file).unwrap();
+
+        assert_eq!(read_file(&path).unwrap(), b"\xEF\xBB\xBF[Files]\nspr = system.sff\n".to_vec());
+    }
 }
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use super::file_system::{self, FileSystem};
+
+#[derive(Default)]
+pub struct FileWatcher {
+    files: HashMap<String, Option<u64>>,
+}
+
+impl FileWatcher {
+    pub fn watch(&mut self, filepath: &str) {
+        let path = file_system::resolve_file(filepath).unwrap_or_else(|| filepath.to_string());
+        let modified_time = file_system::get_file_system().modified_time(&path);
+
+        self.files.insert(path, modified_time);
+    }
+
+    pub fn clear(&mut self) {
+        self.files.clear();
+    }
+
+    pub fn poll(&mut self) -> Vec<String> {
+        let file_system = file_system::get_file_system();
+        let mut changed = Vec::new();
+
+        for (path, modified_time) in self.files.iter_mut() {
+            let current_time = file_system.modified_time(path);
+
+            if current_time != *modified_time {
+                *modified_time = current_time;
+                changed.push(path.clone());
+            }
+        }
+
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::memory_file_system::test_mount;
+
+    use super::*;
+
+    #[test]
+    fn reports_rewritten_files_once() {
+        let mount = test_mount("file_watcher_tests");
+        let changed_path = format!("{}/changed.def", mount);
+        let unchanged_path = format!("{}/unchanged.def", mount);
+        let mut watcher = FileWatcher::default();
+
+        file_system::write_file(&changed_path, b"[Info]").unwrap();
+        file_system::write_file(&unchanged_path, b"[Info]").unwrap();
+        watcher.watch(&changed_path);
+        watcher.watch(&unchanged_path);
+
+        assert!(watcher.poll().is_empty());
+
+        file_system::write_file(&changed_path, b"[Info]\nname = changed").unwrap();
+
+        assert_eq!(watcher.poll(), vec![changed_path]);
+        assert!(watcher.poll().is_empty());
+    }
+
+    #[test]
+    fn clear_stops_reporting() {
+        let mount = test_mount("file_watcher_tests");
+        let path = format!("{}/cleared.def", mount);
+        let mut watcher = FileWatcher::default();
+
+        file_system::write_file(&path, b"[Info]").unwrap();
+        watcher.watch(&path);
+        watcher.clear();
+        file_system::write_file(&path, b"[Info]\nname = changed").unwrap();
+
+        assert!(watcher.poll().is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::core::{diagnostics, error::DataError};
+
+use super::text_section::TextSection;
+
+pub use game_derive::FromTextSection;
+
+pub trait FromTextSection: Sized {
+    fn from_text_section(section: &TextSection, prefix: &str) -> Result<Self, DataError>;
+
+    fn expected_keys(prefix: &str) -> Vec<String>;
+}
+
+pub fn join_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        return key.to_string();
+    }
+
+    if key.is_empty() {
+        return prefix.to_string();
+    }
+
+    format!("{}.{}", prefix, key)
+}
+
+pub fn warn_unknown_keys(section: &TextSection, expected_keys: &[String]) {
+    for (key, value) in section.parsedlines.iter() {
+        let is_expected = expected_keys
+            .iter()
+            .any(|expected_key| expected_key.to_lowercase() == key.to_lowercase());
+
+        if !is_expected {
+            diagnostics::warn(section.locate_error(
+                DataError::invalid_attribute(format!("Unknown attribute: {}", key)),
+                value.line()
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::text_file::TextFile;
+
+    use super::*;
+
+    #[derive(Debug, Default, FromTextSection, PartialEq)]
+    struct Cursor {
+        #[text_section(key = "spr")]
+        sprite: i32,
+        #[text_section(default = "1.5")]
+        scale: f32,
+        font: Option<i32>,
+    }
+
+    #[derive(Debug, FromTextSection, PartialEq)]
+    #[text_section(keys = "legacy, player.legacy")]
+    struct Player {
+        #[text_section(required)]
+        name: String,
+        #[text_section(key = "anim", default = "-1")]
+        animation: i32,
+        #[text_section(prefix = "player.cursor")]
+        cursor: Cursor,
+        lives: i32,
+        #[text_section(skip, default = "7")]
+        computed: i32,
+        #[text_section(skip)]
+        cache: Vec<i32>,
+    }
+
+    const TEXT: &str = "
+[Player]
+name = Kung Fu Man
+player.cursor.spr = 3
+lives = 2
+computed = 99
+cache = 1
+
+[Anonymous]
+anim = 5
+player.cursor.scale = 2
+player.cursor.font = 4
+";
+
+    fn section(title: &str) -> TextSection {
+        TextFile::from_string("test.def".to_string(), TEXT.to_string()).get_section(title).unwrap()
+    }
+
+    #[test]
+    fn reads_keys_prefixes_and_defaults() {
+        let player = Player::from_text_section(&section("Player"), "").unwrap();
+
+        assert_eq!(player, Player {
+            name: "Kung Fu Man".to_string(),
+            animation: -1,
+            cursor: Cursor { sprite: 3, scale: 1.5, font: None },
+            lives: 2,
+            computed: 7,
+            cache: Vec::new(),
+        });
+    }
+
+    #[test]
+    fn reads_optional_fields_and_nested_prefixes() {
+        let cursor = Cursor::from_text_section(&section("Anonymous"), "player.cursor").unwrap();
+
+        assert_eq!(cursor, Cursor { sprite: 0, scale: 2.0, font: Some(4) });
+    }
+
+    #[test]
+    fn missing_required_keys_fail_with_the_section_line() {
+        let section = section("Anonymous");
+        let error = Player::from_text_section(&section, "").unwrap_err();
+
+        assert!(error.message.contains("Missing attribute: name"));
+        assert_eq!(error.path.as_deref(), Some("test.def"));
+        assert_eq!(error.section.as_deref(), Some("Anonymous"));
+        assert_eq!(error.line, Some(9));
+    }
+
+    #[test]
+    fn expected_keys_cover_fields_prefixes_and_extra_keys() {
+        assert_eq!(Player::expected_keys("select"), vec![
+            "select.name",
+            "select.anim",
+            "select.player.cursor.spr",
+            "select.player.cursor.scale",
+            "select.player.cursor.font",
+            "select.lives",
+            "select.legacy",
+            "select.player.legacy",
+        ]);
+    }
+}
@@ -0,0 +1,234 @@
+use std::cmp;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use std::sync::Arc;
+
+use gdnative::Ref;
+use gdnative::api::directory::Directory;
+use gdnative::api::file::File;
+use gdnative::api::{OS, ProjectSettings};
+use gdnative::prelude::{ByteArray, Unique};
+
+use crate::core::error::DataError;
+
+use super::file_system::{FileStream, FileSystem};
+use super::native_file_system::NativeFileSystem;
+use super::virtual_file_system::VirtualFileSystem;
+
+pub struct GodotFileSystem {
+    root: String,
+}
+
+impl GodotFileSystem {
+    pub fn new(root: &str) -> Self {
+        GodotFileSystem {
+            root: root.to_string(),
+        }
+    }
+
+    fn get_full_path(&self, path: &str) -> String {
+        if path.is_empty() {
+            return self.root.clone();
+        }
+
+        if self.root.ends_with('/') {
+            return format!("{}{}", self.root, path);
+        }
+
+        format!("{}/{}", self.root, path)
+    }
+}
+
+// Replaces the Godot mounts with native ones where they are plain directories, so the asset
+// loader threads never use Godot objects. Exported games keep res:// inside the pack.
+pub fn mount_native_roots(file_system: &VirtualFileSystem) {
+    let os = OS::godot_singleton();
+
+    file_system.mount("user://", Arc::new(NativeFileSystem::new(&os.get_user_data_dir().to_string())));
+
+    if os.has_feature("editor") {
+        let root = ProjectSettings::godot_singleton().globalize_path("res://").to_string();
+
+        file_system.mount("res://", Arc::new(NativeFileSystem::new(&root)));
+    }
+}
+
+impl FileSystem for GodotFileSystem {
+    fn exists(&self, path: &str) -> bool {
+        let file = File::new();
+        file.file_exists(self.get_full_path(path))
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn FileStream>, DataError> {
+        let full_path = self.get_full_path(path);
+        let file = File::new();
+
+        if let Err(detail) = file.open(full_path.as_str(), File::READ) {
+            return Err(DataError::missing_file(
+                &full_path,
+                format!("Error opening file: {}", detail)
+            ));
+        }
+
+        Ok(Box::new(GodotFileStream::new(file)))
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<(), DataError> {
+        let full_path = self.get_full_path(path);
+        let file = File::new();
+
+        if let Err(detail) = file.open(full_path.as_str(), File::WRITE) {
+            return Err(DataError::new(format!(
+                "Error writing file: {}, {}",
+                full_path,
+                detail
+            )));
+        }
+
+        file.store_buffer(data.iter().copied().collect::<ByteArray>());
+        file.close();
+
+        Ok(())
+    }
+
+    fn modified_time(&self, path: &str) -> Option<u64> {
+        let full_path = self.get_full_path(path);
+        let file = File::new();
+
+        if !file.file_exists(full_path.as_str()) {
+            return None;
+        }
+
+        Some(file.get_modified_time(full_path.as_str()) as u64)
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<String>, DataError> {
+        let full_path = self.get_full_path(path);
+        let directory = Directory::new();
+
+        if let Err(detail) = directory.open(full_path.as_str()) {
+            return Err(DataError::new(format!(
+                "Error reading directory: {}, {}",
+                full_path,
+                detail
+            )));
+        }
+
+        if let Err(detail) = directory.list_dir_begin(true, false) {
+            return Err(DataError::new(format!(
+                "Error reading directory: {}, {}",
+                full_path,
+                detail
+            )));
+        }
+
+        let mut entries = Vec::new();
+
+        loop {
+            let name = directory.get_next().to_string();
+
+            if name.is_empty() {
+                break;
+            }
+
+            entries.push(name);
+        }
+
+        directory.list_dir_end();
+
+        Ok(entries)
+    }
+
+    fn is_thread_safe(&self) -> bool {
+        false
+    }
+}
+
+// Every call into File crosses the FFI boundary, so reads are served from a chunk of the file
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+pub struct GodotFileStream {
+    file: Ref<File, Unique>,
+    len: u64,
+    position: u64,
+    file_position: u64,
+    buffer: Vec<u8>,
+    buffer_start: u64,
+}
+
+impl GodotFileStream {
+    fn new(file: Ref<File, Unique>) -> Self {
+        let len = cmp::max(file.get_len(), 0) as u64;
+
+        GodotFileStream {
+            file,
+            len,
+            position: 0,
+            file_position: 0,
+            buffer: Vec::new(),
+            buffer_start: 0,
+        }
+    }
+
+    fn fill_buffer(&mut self, size: u64) {
+        if self.file_position != self.position {
+            self.file.seek(self.position as i64);
+        }
+
+        let data = self.file.get_buffer(size as i64);
+
+        self.buffer.clear();
+        self.buffer.extend_from_slice(&data.read());
+        self.buffer_start = self.position;
+        self.file_position = self.position + self.buffer.len() as u64;
+    }
+}
+
+impl Read for GodotFileStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.position);
+
+        if remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let buffer_end = self.buffer_start + self.buffer.len() as u64;
+
+        if self.position < self.buffer_start || self.position >= buffer_end {
+            self.fill_buffer(cmp::min(cmp::max(buf.len(), STREAM_BUFFER_SIZE) as u64, remaining));
+        }
+
+        let offset = (self.position - self.buffer_start) as usize;
+        let size = cmp::min(buf.len(), self.buffer.len() - offset);
+
+        buf[..size].copy_from_slice(&self.buffer[offset..offset + size]);
+        self.position += size as u64;
+
+        Ok(size)
+    }
+}
+
+impl Seek for GodotFileStream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+            SeekFrom::End(offset) => self.len as i64 + offset,
+        };
+
+        if position < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position"));
+        }
+
+        // The file itself is only moved on the next buffer fill
+        self.position = position as u64;
+
+        Ok(self.position)
+    }
+}
+
+impl Drop for GodotFileStream {
+    fn drop(&mut self) {
+        self.file.close();
+    }
+}
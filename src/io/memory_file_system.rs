@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::core::error::DataError;
+
+use super::file_system::{FileStream, FileSystem};
+
+#[derive(Clone)]
+struct SharedBuffer(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for SharedBuffer {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
+struct MemoryFile {
+    data: SharedBuffer,
+    // Stands in for the modified time, every write gets a new revision
+    revision: u64,
+}
+
+#[derive(Default)]
+pub struct MemoryFileSystem {
+    files: RwLock<HashMap<String, MemoryFile>>,
+    revision: AtomicU64,
+}
+
+impl MemoryFileSystem {
+    pub fn new() -> Self {
+        MemoryFileSystem::default()
+    }
+
+    pub fn insert(&self, path: &str, data: Vec<u8>) {
+        let revision = self.revision.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut files = self.files.write().expect("Could not lock memory files");
+
+        files.insert(normalize_path(path), MemoryFile { data: SharedBuffer(Arc::new(data)), revision });
+    }
+
+    pub fn insert_text(&self, path: &str, text: &str) {
+        self.insert(path, text.as_bytes().to_vec());
+    }
+
+    pub fn remove(&self, path: &str) {
+        let mut files = self.files.write().expect("Could not lock memory files");
+        files.remove(&normalize_path(path));
+    }
+}
+
+// Mounts a memory file system at mem://<name> in the global file system once and returns the prefix,
+// tests of the same module share it so each one writes under its own file names
+#[cfg(test)]
+pub fn test_mount(name: &str) -> String {
+    static MOUNT_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    let _guard = MOUNT_LOCK.lock().expect("Could not lock test mounts");
+    let prefix = format!("mem://{}", name);
+    let file_system = super::file_system::get_file_system();
+
+    if !file_system.is_mounted(&prefix) {
+        file_system.mount(&prefix, Arc::new(MemoryFileSystem::new()));
+    }
+
+    prefix
+}
+
+fn normalize_path(path: &str) -> String {
+    path.trim_start_matches('/').to_string()
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn exists(&self, path: &str) -> bool {
+        let files = self.files.read().expect("Could not lock memory files");
+        files.contains_key(&normalize_path(path))
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn FileStream>, DataError> {
+        let files = self.files.read().expect("Could not lock memory files");
+        let file = files.get(&normalize_path(path))
+            .ok_or_else(|| DataError::missing_file(path, "File not found".to_string()))?;
+
+        Ok(Box::new(Cursor::new(file.data.clone())))
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>, DataError> {
+        let files = self.files.read().expect("Could not lock memory files");
+        let file = files.get(&normalize_path(path))
+            .ok_or_else(|| DataError::missing_file(path, "File not found".to_string()))?;
+
+        Ok(file.data.0.to_vec())
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<(), DataError> {
+        self.insert(path, data.to_vec());
+
+        Ok(())
+    }
+
+    fn modified_time(&self, path: &str) -> Option<u64> {
+        let files = self.files.read().expect("Could not lock memory files");
+
+        files.get(&normalize_path(path)).map(|file| file.revision)
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<String>, DataError> {
+        let files = self.files.read().expect("Could not lock memory files");
+        let directory = normalize_path(path);
+        let prefix = if directory.is_empty() { String::new() } else { format!("{}/", directory.trim_end_matches('/')) };
+        let mut entries: Vec<String> = Vec::new();
+
+        for key in files.keys() {
+            if let Some(rest) = key.strip_prefix(&prefix) {
+                let name = rest.split('/').next().unwrap_or("").to_string();
+
+                if !name.is_empty() && !entries.contains(&name) {
+                    entries.push(name);
+                }
+            }
+        }
+
+        if entries.is_empty() {
+            return Err(DataError::new(format!("Error reading directory: {}, directory not found", path)));
+        }
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek, SeekFrom};
+
+    use super::*;
+
+    #[test]
+    fn reads_written_files() {
+        let file_system = MemoryFileSystem::new();
+
+        file_system.write("/data/system.def", b"[Info]").unwrap();
+
+        assert!(file_system.exists("data/system.def"));
+        assert_eq!(file_system.read("data/system.def").unwrap(), b"[Info]");
+
+        file_system.remove("data/system.def");
+        assert!(!file_system.exists("data/system.def"));
+        assert!(file_system.read("data/system.def").is_err());
+    }
+
+    #[test]
+    fn opened_streams_can_seek() {
+        let file_system = MemoryFileSystem::new();
+        let mut buffer = [0; 3];
+
+        file_system.insert("file.bin", vec![1, 2, 3, 4, 5]);
+
+        let mut stream = file_system.open("file.bin").unwrap();
+        stream.seek(SeekFrom::End(-3)).unwrap();
+        stream.read_exact(&mut buffer).unwrap();
+
+        assert_eq!(buffer, [3, 4, 5]);
+        assert!(file_system.open("missing.bin").is_err());
+    }
+
+    #[test]
+    fn lists_direct_children_once() {
+        let file_system = MemoryFileSystem::new();
+
+        file_system.insert_text("chars/kfm/kfm.def", "");
+        file_system.insert_text("chars/kfm/kfm.air", "");
+        file_system.insert_text("chars/suave.def", "");
+        file_system.insert_text("data/system.def", "");
+
+        let mut entries = file_system.read_dir("chars").unwrap();
+        entries.sort();
+        assert_eq!(entries, vec!["kfm", "suave.def"]);
+
+        let mut entries = file_system.read_dir("").unwrap();
+        entries.sort();
+        assert_eq!(entries, vec!["chars", "data"]);
+
+        assert!(file_system.read_dir("stages").is_err());
+    }
+}
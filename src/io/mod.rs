@@ -1,3 +1,12 @@
 pub mod text_section;
 pub mod text_file;
+pub mod from_text_section;
 pub mod file_system;
+pub mod file_watcher;
+pub mod path_resolver;
+pub mod text_encoding;
+pub mod virtual_file_system;
+pub mod godot_file_system;
+pub mod native_file_system;
+pub mod memory_file_system;
+pub mod zip_file_system;
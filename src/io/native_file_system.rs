@@ -0,0 +1,124 @@
+use std::fs;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use crate::core::error::DataError;
+
+use super::file_system::{FileStream, FileSystem};
+
+pub struct NativeFileSystem {
+    root: PathBuf,
+}
+
+impl NativeFileSystem {
+    pub fn new(root: &str) -> Self {
+        NativeFileSystem {
+            root: PathBuf::from(root),
+        }
+    }
+
+    fn get_full_path(&self, path: &str) -> PathBuf {
+        let full_path = self.root.join(path);
+
+        if full_path.as_os_str().is_empty() {
+            return PathBuf::from(".");
+        }
+
+        full_path
+    }
+}
+
+impl FileSystem for NativeFileSystem {
+    fn exists(&self, path: &str) -> bool {
+        self.get_full_path(path).is_file()
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn FileStream>, DataError> {
+        let full_path = self.get_full_path(path);
+        let file = fs::File::open(&full_path)
+            .map_err(|error| DataError::missing_file(path, format!("Error opening file: {}", error)))?;
+
+        Ok(Box::new(BufReader::new(file)))
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<(), DataError> {
+        let full_path = self.get_full_path(path);
+
+        fs::write(&full_path, data)
+            .map_err(|error| DataError::new(format!("Error writing file: {}, {}", full_path.display(), error)))
+    }
+
+    fn modified_time(&self, path: &str) -> Option<u64> {
+        let modified = fs::metadata(self.get_full_path(path)).ok()?.modified().ok()?;
+
+        modified.duration_since(UNIX_EPOCH).ok().map(|duration| duration.as_secs())
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<String>, DataError> {
+        let full_path = self.get_full_path(path);
+        let entries = fs::read_dir(&full_path)
+            .map_err(|error| DataError::new(format!("Error reading directory: {}, {}", full_path.display(), error)))?;
+
+        Ok(entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str().map(|name| name.to_string()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::process;
+
+    use super::*;
+
+    fn create_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("native_file_system_{}_{}", name, process::id()));
+
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("data")).unwrap();
+
+        root
+    }
+
+    #[test]
+    fn reads_and_writes_below_the_root() {
+        let root = create_root("read_write");
+        let file_system = NativeFileSystem::new(root.to_str().unwrap());
+        let mut text = String::new();
+
+        file_system.write("data/system.def", b"[Info]").unwrap();
+
+        assert!(file_system.exists("data/system.def"));
+        assert!(!file_system.exists("data"));
+        assert!(file_system.modified_time("data/system.def").is_some());
+        assert!(file_system.modified_time("data/missing.def").is_none());
+
+        file_system.open("data/system.def").unwrap().read_to_string(&mut text).unwrap();
+        assert_eq!(text, "[Info]");
+
+        let error = file_system.open("data/missing.def").err().unwrap();
+        assert_eq!(error.path.as_deref(), Some("data/missing.def"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn lists_directories() {
+        let root = create_root("read_dir");
+        let file_system = NativeFileSystem::new(root.to_str().unwrap());
+
+        file_system.write("data/fight.def", b"").unwrap();
+        file_system.write("data/system.def", b"").unwrap();
+
+        let mut entries = file_system.read_dir("data").unwrap();
+        entries.sort();
+
+        assert_eq!(entries, vec!["fight.def", "system.def"]);
+        assert!(file_system.read_dir("missing").is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+use crate::core::constants::DATA_PATH;
+
+use super::file_system::FileSystem;
+
+pub fn normalize_path(path: &str) -> String {
+    let path = path.trim().replace('\\', "/");
+    let (root, rest) = split_root(&path);
+    let mut components: Vec<&str> = Vec::new();
+
+    for component in rest.split('/') {
+        match component {
+            "" | "." => continue,
+            ".." => {
+                if components.last().map_or(false, |last| *last != "..") {
+                    components.pop();
+                } else if root.is_empty() {
+                    components.push(component);
+                }
+            }
+            _ => components.push(component),
+        }
+    }
+
+    format!("{}{}", root, components.join("/"))
+}
+
+pub fn resolve_case_insensitive(file_system: &dyn FileSystem, path: &str) -> Option<String> {
+    let path = normalize_path(path);
+
+    if file_system.exists(&path) {
+        return Some(path);
+    }
+
+    let (root, rest) = split_root(&path);
+    let mut current = root.to_string();
+
+    if rest.is_empty() {
+        return None;
+    }
+
+    for component in rest.split('/') {
+        let entries = file_system.read_dir(&current).ok()?;
+        let lowercase_component = component.to_lowercase();
+        let entry = entries.iter()
+            .find(|entry| entry.as_str() == component)
+            .or_else(|| entries.iter().find(|entry| entry.to_lowercase() == lowercase_component))?;
+
+        current = join_path(&current, entry);
+    }
+
+    if file_system.exists(&current) {
+        return Some(current);
+    }
+
+    None
+}
+
+pub fn get_search_paths(name: &str, referrer: &str) -> Vec<String> {
+    let name = normalize_path(name);
+
+    if !split_root(&name).0.is_empty() {
+        return vec![name];
+    }
+
+    let referrer = normalize_path(referrer);
+    let referrer_directory = match referrer.rfind('/') {
+        Some(index) if index + 1 > split_root(&referrer).0.len() => &referrer[..index],
+        _ => split_root(&referrer).0,
+    };
+
+    let candidates = vec![
+        normalize_path(&join_path(referrer_directory, &name)),
+        normalize_path(&join_path(&join_path(DATA_PATH, "data"), &name)),
+        normalize_path(&join_path(DATA_PATH, &name)),
+    ];
+
+    let mut result: Vec<String> = Vec::new();
+
+    for candidate in candidates {
+        if !result.contains(&candidate) {
+            result.push(candidate);
+        }
+    }
+
+    result
+}
+
+fn split_root(path: &str) -> (&str, &str) {
+    if let Some(index) = path.find("://") {
+        return path.split_at(index + 3);
+    }
+
+    if path.starts_with('/') {
+        return path.split_at(1);
+    }
+
+    ("", path)
+}
+
+fn join_path(lhs: &str, rhs: &str) -> String {
+    if lhs.is_empty() {
+        return rhs.to_string();
+    }
+
+    if lhs.ends_with('/') {
+        return format!("{}{}", lhs, rhs);
+    }
+
+    format!("{}/{}", lhs, rhs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::io::{memory_file_system::MemoryFileSystem, virtual_file_system::VirtualFileSystem};
+
+    use super::*;
+
+    #[test]
+    fn normalizes_paths() {
+        let cases = [
+            ("chars\\kfm\\kfm.def", "chars/kfm/kfm.def"),
+            (" chars/./kfm//kfm.def ", "chars/kfm/kfm.def"),
+            ("chars/kfm/../suave/suave.def", "chars/suave/suave.def"),
+            ("../data/system.def", "../data/system.def"),
+            ("res://data/../../system.def", "res://system.def"),
+            ("/home/../mugen/data", "/mugen/data"),
+            ("zip://kfm.zip/./kfm.def", "zip://kfm.zip/kfm.def"),
+        ];
+
+        for (path, expected) in cases.iter() {
+            assert_eq!(normalize_path(path), *expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn resolves_paths_ignoring_case() {
+        let file_system = MemoryFileSystem::new();
+
+        file_system.insert_text("chars/kfm/kfm.def", "");
+        file_system.insert_text("chars/KFM/KFM.DEF", "");
+        file_system.insert_text("data/System.def", "");
+
+        assert_eq!(resolve_case_insensitive(&file_system, "Data\\system.DEF"), Some("data/System.def".to_string()));
+        assert_eq!(resolve_case_insensitive(&file_system, "chars/KFM/KFM.DEF"), Some("chars/KFM/KFM.DEF".to_string()));
+        assert_eq!(resolve_case_insensitive(&file_system, "chars/kfm/kfm.def"), Some("chars/kfm/kfm.def".to_string()));
+        assert_eq!(resolve_case_insensitive(&file_system, "chars"), None);
+        assert_eq!(resolve_case_insensitive(&file_system, "chars/suave/suave.def"), None);
+    }
+
+    #[test]
+    fn resolves_paths_below_mount_roots() {
+        let file_system = VirtualFileSystem::new();
+        let memory_file_system = MemoryFileSystem::new();
+
+        memory_file_system.insert_text("data/System.def", "");
+        file_system.mount("mem://", Arc::new(memory_file_system));
+
+        assert_eq!(resolve_case_insensitive(&file_system, "mem://DATA/system.def"), Some("mem://data/System.def".to_string()));
+        assert_eq!(resolve_case_insensitive(&file_system, "mem://"), None);
+    }
+
+    #[test]
+    fn searches_next_to_the_referrer_then_in_the_data_directories() {
+        assert_eq!(get_search_paths("fight.def", "chars/kfm/kfm.def"), vec![
+            "chars/kfm/fight.def".to_string(),
+            format!("{}/data/fight.def", DATA_PATH),
+            format!("{}/fight.def", DATA_PATH),
+        ]);
+        assert_eq!(get_search_paths("../common.cmd", "res://data/chars/kfm.def")[0], "res://data/common.cmd");
+        assert_eq!(get_search_paths("fight.def", "kfm.def")[0], "fight.def");
+        assert_eq!(get_search_paths("res://fonts/f-6x9.fnt", "chars/kfm/kfm.def"), vec!["res://fonts/f-6x9.fnt".to_string()]);
+    }
+
+    #[test]
+    fn search_paths_are_unique() {
+        let paths = get_search_paths("fight.def", &format!("{}/data/system.def", DATA_PATH));
+
+        assert_eq!(paths, vec![
+            format!("{}/data/fight.def", DATA_PATH),
+            format!("{}/fight.def", DATA_PATH),
+        ]);
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use encoding_rs::{Encoding, SHIFT_JIS, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+use super::path_resolver;
+
+const UTF_8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+
+static ENCODING_HINTS: RwLock<Option<HashMap<String, &'static Encoding>>> = RwLock::new(None);
+
+pub fn set_encoding_hint(filepath: &str, encoding: &'static Encoding) {
+    ENCODING_HINTS.write()
+        .expect("Could not lock encoding hints")
+        .get_or_insert_with(HashMap::new)
+        .insert(get_hint_key(filepath), encoding);
+}
+
+pub fn get_encoding_hint(filepath: &str) -> Option<&'static Encoding> {
+    ENCODING_HINTS.read()
+        .expect("Could not lock encoding hints")
+        .as_ref()
+        .and_then(|hints| hints.get(&get_hint_key(filepath)).copied())
+}
+
+pub fn find_encoding(label: &str) -> Option<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+}
+
+pub struct DecodedText {
+    pub text: String,
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+}
+
+pub fn decode_text_file(filepath: &str, buffer: &[u8]) -> String {
+    decode_text(buffer, get_encoding_hint(filepath))
+}
+
+pub fn decode_text_file_with_encoding(filepath: &str, buffer: &[u8]) -> DecodedText {
+    decode_text_with_encoding(buffer, get_encoding_hint(filepath))
+}
+
+pub fn decode_text(buffer: &[u8], hint: Option<&'static Encoding>) -> String {
+    decode_text_with_encoding(buffer, hint).text
+}
+
+pub fn decode_text_with_encoding(buffer: &[u8], hint: Option<&'static Encoding>) -> DecodedText {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(buffer) {
+        return DecodedText {
+            text: encoding.decode_without_bom_handling(&buffer[bom_length..]).0.into_owned(),
+            encoding,
+            bom: true,
+        };
+    }
+
+    let encoding = hint.unwrap_or_else(|| detect_encoding(buffer));
+
+    DecodedText {
+        text: encoding.decode_without_bom_handling(buffer).0.into_owned(),
+        encoding,
+        bom: false,
+    }
+}
+
+// Characters the encoding can't represent are written as UTF-8 with a BOM, so the file still reads back as is
+pub fn encode_text(text: &str, encoding: &'static Encoding, bom: bool) -> Vec<u8> {
+    let mut buffer = Vec::new();
+
+    if encoding == UTF_16LE {
+        buffer.extend_from_slice(&[0xFF, 0xFE]);
+        text.encode_utf16().for_each(|unit| buffer.extend_from_slice(&unit.to_le_bytes()));
+        return buffer;
+    }
+
+    if encoding == UTF_16BE {
+        buffer.extend_from_slice(&[0xFE, 0xFF]);
+        text.encode_utf16().for_each(|unit| buffer.extend_from_slice(&unit.to_be_bytes()));
+        return buffer;
+    }
+
+    let (encoded, _, had_errors) = encoding.encode(text);
+
+    if had_errors {
+        buffer.extend_from_slice(UTF_8_BOM);
+        buffer.extend_from_slice(text.as_bytes());
+        return buffer;
+    }
+
+    if bom && encoding == UTF_8 {
+        buffer.extend_from_slice(UTF_8_BOM);
+    }
+
+    buffer.extend_from_slice(&encoded);
+    buffer
+}
+
+pub fn detect_encoding(buffer: &[u8]) -> &'static Encoding {
+    if std::str::from_utf8(buffer).is_ok() {
+        return UTF_8;
+    }
+
+    if let Some(text) = SHIFT_JIS.decode_without_bom_handling_and_without_replacement(buffer) {
+        if text.chars().any(is_japanese) && !text.chars().any(is_private_use) {
+            return SHIFT_JIS;
+        }
+    }
+
+    WINDOWS_1252
+}
+
+// Halfwidth katakana is left out, Windows-1252 accented letters decode to it as single bytes
+fn is_japanese(character: char) -> bool {
+    matches!(character as u32,
+        0x3040..=0x30FF // Hiragana and Katakana
+        | 0x4E00..=0x9FFF // CJK Unified Ideographs
+    )
+}
+
+fn is_private_use(character: char) -> bool {
+    matches!(character as u32, 0xE000..=0xF8FF)
+}
+
+fn get_hint_key(filepath: &str) -> String {
+    path_resolver::normalize_path(filepath).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_shift_jis_from_full_width_text() {
+        let (encoded, _, _) = SHIFT_JIS.encode("[Info]\nname = \"カンフー男\"\n");
+
+        assert_eq!(detect_encoding(&encoded), SHIFT_JIS);
+    }
+
+    #[test]
+    fn halfwidth_katakana_is_not_japanese_evidence() {
+        // "Café Señor" in Windows-1252 is also valid Shift-JIS made of halfwidth katakana
+        let (encoded, _, _) = WINDOWS_1252.encode("name = Café Señor");
+
+        assert_eq!(detect_encoding(&encoded), WINDOWS_1252);
+    }
+
+    #[test]
+    fn decoding_reports_the_encoding_and_bom() {
+        let decoded = decode_text_with_encoding(b"\xEF\xBB\xBFname = kfm", None);
+
+        assert_eq!(decoded.text, "name = kfm");
+        assert_eq!(decoded.encoding, UTF_8);
+        assert!(decoded.bom);
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        let text = "[Info]\nname = \"カンフー男\"\n";
+
+        for (encoding, bom) in [(SHIFT_JIS, false), (UTF_8, true), (UTF_8, false), (UTF_16LE, true), (UTF_16BE, true)].iter() {
+            let decoded = decode_text_with_encoding(&encode_text(text, encoding, *bom), Some(encoding));
+
+            assert_eq!(decoded.text, text);
+            assert_eq!(decoded.encoding, *encoding);
+            assert_eq!(decoded.bom, *bom);
+        }
+    }
+
+    #[test]
+    fn unmappable_text_is_written_as_utf_8() {
+        let encoded = encode_text("name = カンフー男", WINDOWS_1252, false);
+        let decoded = decode_text_with_encoding(&encoded, None);
+
+        assert_eq!(decoded.text, "name = カンフー男");
+        assert_eq!(decoded.encoding, UTF_8);
+    }
+}
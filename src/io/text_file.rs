@@ -1,8 +1,37 @@
-use crate::{core::{error::DataError, attribute_value::AttributeValue, regex::{RegEx, RegExFlags}}, io::text_section::TextSection};
+use std::fmt;
 
+use encoding_rs::{Encoding, UTF_8};
+
+use crate::{core::{error::DataError, attribute_value::AttributeValue}, io::text_section::TextSection};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TextLineKind {
+    Empty,
+    Section,
+    Attribute { value_start: usize, value_end: usize },
+    Value,
+}
+
+#[derive(Clone)]
+pub struct TextLine {
+    pub raw: String,
+    pub ending: String,
+    pub kind: TextLineKind,
+    pub comment: Option<String>,
+    section: Option<usize>,
+    entry: Option<usize>,
+    attribute: Option<usize>,
+}
+
+#[derive(Clone)]
 pub struct TextFile {
     pub filepath: String,
-    pub sections: Vec<TextSection>
+    pub preamble: TextSection,
+    pub sections: Vec<TextSection>,
+    pub document: Vec<TextLine>,
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+    line_ending: String,
 }
 
 impl TextFile {
@@ -10,9 +39,47 @@ impl TextFile {
         filepath: String,
         sections: Vec<TextSection>
     ) -> Self {
+        let mut document = Vec::new();
+
+        for (index, section) in sections.iter().enumerate() {
+            if index > 0 {
+                document.push(parse_line("", "\n", None));
+            }
+
+            document.push(parse_line(&format!("[{}]", section.title), "\n", Some(index)));
+
+            for (entry, line) in section.lines.iter().enumerate() {
+                let mut text_line = parse_line(&line.to_string(), "\n", Some(index));
+                text_line.entry = Some(entry);
+                text_line.attribute = find_attribute_index(section, entry);
+                document.push(text_line);
+            }
+        }
+
+        TextFile::with_document(filepath, TextSection::default(), sections, document, "\n".to_string())
+    }
+
+    fn with_document(
+        filepath: String,
+        mut preamble: TextSection,
+        mut sections: Vec<TextSection>,
+        document: Vec<TextLine>,
+        line_ending: String,
+    ) -> Self {
+        preamble.filepath = filepath.clone();
+
+        for section in sections.iter_mut() {
+            section.filepath = filepath.clone();
+        }
+
         TextFile {
-          filepath: filepath,
-          sections: sections
+            filepath: filepath,
+            preamble: preamble,
+            sections: sections,
+            document: document,
+            encoding: UTF_8,
+            bom: false,
+            line_ending: line_ending,
         }
     }
 
@@ -23,67 +90,327 @@ impl TextFile {
             }
         }
 
-        Err(DataError::new(format!("Missing section: {}", key)))
+        Err(DataError::missing_section(key).with_path(&self.filepath))
+    }
+
+    pub fn get_sections(&self, key: &str) -> Vec<&TextSection> {
+        self.sections
+            .iter()
+            .filter(|section| section.title.to_lowercase() == key.to_lowercase())
+            .collect()
     }
 
     pub fn from_string(path: String, text: String) -> Self {
-        let titleregex = RegEx::new(r"^\s*\[(.+?)\]\s*$", RegExFlags::IgnoreCase);
-        let parsedlineregex = RegEx::new(r"^\s*(.+?)\s*=\s*(.+?)\s*$", RegExFlags::IgnoreCase);
+        let line_ending = if text.contains("\r\n") { "\r\n" } else { "\n" };
+        let mut preamble = TextSection::default();
         let mut sections: Vec<TextSection> = Vec::new();
-        let mut sectiontitle: String = "".to_string();
-        let mut sectionlines: Vec<AttributeValue> = Vec::new();
-        let mut sectionparsedlines: Vec<(String, AttributeValue)> = Vec::new();
+        let mut document: Vec<TextLine> = Vec::new();
+
+        for (index, full_line) in text.split_inclusive('\n').enumerate() {
+            let line_number = index + 1;
+            let raw_line = full_line.trim_end_matches('\n').trim_end_matches('\r');
+            let ending = &full_line[raw_line.len()..];
+            let mut text_line = parse_line(raw_line, ending, sections.len().checked_sub(1));
 
-        for raw_line in text.lines() {
-            let mut line = raw_line.trim().to_string();
+            match text_line.kind {
+                TextLineKind::Empty => {}
+                TextLineKind::Section => {
+                    let title = get_section_title(raw_line).unwrap_or_default();
 
-            if let Some(commentindex) = line.find(';') {
-                line = line[..commentindex].to_string();
+                    text_line.section = Some(sections.len());
+                    sections.push(TextSection::new(
+                        title,
+                        line_number,
+                        Vec::new(),
+                        Vec::new()
+                    ));
+                }
+                TextLineKind::Attribute { value_start, value_end } => {
+                    let section = current_section(&mut preamble, &mut sections);
+                    let content = raw_line[..value_start].trim_end();
+                    let key = content[..content.len() - 1].trim().to_string();
+                    let value = &raw_line[value_start..value_end];
+
+                    text_line.entry = Some(section.lines.len());
+                    text_line.attribute = Some(section.parsedlines.len());
+                    section.lines.push(AttributeValue::with_line(get_content(raw_line).trim(), line_number));
+                    section.parsedlines.push((key, AttributeValue::with_line(value, line_number)));
+                }
+                TextLineKind::Value => {
+                    let section = current_section(&mut preamble, &mut sections);
+
+                    text_line.entry = Some(section.lines.len());
+                    section.lines.push(AttributeValue::with_line(get_content(raw_line).trim(), line_number));
+                }
             }
 
-            line = line.trim().to_string();
+            document.push(text_line);
+        }
+
+        TextFile::with_document(path, preamble, sections, document, line_ending.to_string())
+    }
+
+    pub fn set_attribute(&mut self, section_title: &str, key: &str, value: &str) {
+        let section_index = match self.sections.iter().position(|section| section.title.to_lowercase() == section_title.to_lowercase()) {
+            Some(section_index) => section_index,
+            None => self.add_section(section_title),
+        };
+
+        let existing_line = self.document.iter().position(|line| {
+            line.section == Some(section_index) && line.attribute.map_or(false, |attribute| {
+                self.sections[section_index].parsedlines[attribute].0.to_lowercase() == key.to_lowercase()
+            })
+        });
 
-            if line.is_empty() {
-                continue;
+        if let Some(line_index) = existing_line {
+            let text_line = &mut self.document[line_index];
+
+            if let TextLineKind::Attribute { value_start, value_end } = text_line.kind {
+                text_line.raw = format!("{}{}{}", &text_line.raw[..value_start], value, &text_line.raw[value_end..]);
+                text_line.kind = TextLineKind::Attribute { value_start, value_end: value_start + value.len() };
             }
 
-            if let Some(title_match) = titleregex.search(&line) {
-                if !sectiontitle.is_empty() {
-                    sections.push(TextSection::new(
-                        sectiontitle.clone(),
-                        sectionlines.clone(),
-                        sectionparsedlines.clone()
-                    ));
-                }
+            let section = &mut self.sections[section_index];
+            let line_number = text_line.entry.map_or(0, |entry| section.lines[entry].line());
 
-                sectiontitle = title_match.get_string(1).to_string();
-                sectionlines = Vec::new();
-                sectionparsedlines = Vec::new();
-                continue;
+            if let Some(entry) = text_line.entry {
+                section.lines[entry] = AttributeValue::with_line(get_content(&text_line.raw).trim(), line_number);
             }
 
-            if sectiontitle.is_empty() {
-                continue;
+            if let Some(attribute) = text_line.attribute {
+                section.parsedlines[attribute].1 = AttributeValue::with_line(value, line_number);
             }
 
-            sectionlines.push(AttributeValue::new(&line));
+            return;
+        }
+
+        let raw = format!("{} = {}", key, value);
+        let insert_index = self.document
+            .iter()
+            .rposition(|line| line.section == Some(section_index) && line.kind != TextLineKind::Empty)
+            .map_or(self.document.len(), |index| index + 1);
+        let mut text_line = parse_line(&raw, "", Some(section_index));
+        let section = &mut self.sections[section_index];
+
+        text_line.entry = Some(section.lines.len());
+        text_line.attribute = Some(section.parsedlines.len());
+        section.lines.push(AttributeValue::new(&raw));
+        section.parsedlines.push((key.to_string(), AttributeValue::new(value)));
+
+        self.insert_line(insert_index, text_line);
+    }
+
+    pub fn add_section(&mut self, title: &str) -> usize {
+        let section_index = self.sections.len();
+        let mut section = TextSection::new(title.to_string(), 0, Vec::new(), Vec::new());
+        section.filepath = self.filepath.clone();
+
+        if self.document.last().map_or(false, |line| line.kind != TextLineKind::Empty) {
+            self.insert_line(self.document.len(), parse_line("", "", section_index.checked_sub(1)));
+        }
+
+        self.insert_line(self.document.len(), parse_line(&format!("[{}]", title), "", Some(section_index)));
+        self.sections.push(section);
+
+        section_index
+    }
+
+    pub fn write(&self) -> String {
+        let mut text = String::new();
+
+        for line in self.document.iter() {
+            text.push_str(&line.raw);
+            text.push_str(&line.ending);
+        }
+
+        text
+    }
 
-            if let Some(line_match) = parsedlineregex.search(&line) {
-                let key = line_match.get_string(1).to_string();
-                let value = line_match.get_string(2).to_string();
+    fn insert_line(&mut self, index: usize, mut text_line: TextLine) {
+        text_line.ending = self.line_ending.clone();
 
-                sectionparsedlines.push((key, AttributeValue::new(&value)));
+        if index > 0 && self.document[index - 1].ending.is_empty() {
+            self.document[index - 1].ending = self.line_ending.clone();
+
+            if index == self.document.len() {
+                text_line.ending = String::new();
             }
         }
 
-        if !sectiontitle.is_empty() {
-            sections.push(TextSection::new(
-                sectiontitle.clone(),
-                sectionlines.clone(),
-                sectionparsedlines.clone()
-            ));
+        self.document.insert(index, text_line);
+    }
+}
+
+impl fmt::Display for TextFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.write())
+    }
+}
+
+fn current_section<'a>(preamble: &'a mut TextSection, sections: &'a mut Vec<TextSection>) -> &'a mut TextSection {
+    match sections.last_mut() {
+        Some(section) => section,
+        None => preamble,
+    }
+}
+
+fn find_attribute_index(section: &TextSection, entry: usize) -> Option<usize> {
+    let line = section.lines[entry].to_string();
+    let equals = line.find('=')?;
+    let key = line[..equals].trim().to_lowercase();
+    let previous_count = section.lines[..entry]
+        .iter()
+        .filter(|line| line.to_string().find('=').map_or(false, |index| line.to_string()[..index].trim().to_lowercase() == key))
+        .count();
+
+    section.parsedlines
+        .iter()
+        .enumerate()
+        .filter(|(_, (data_key, _))| data_key.to_lowercase() == key)
+        .nth(previous_count)
+        .map(|(index, _)| index)
+}
+
+fn parse_line(raw_line: &str, ending: &str, section: Option<usize>) -> TextLine {
+    let content = get_content(raw_line);
+    let trimmed = content.trim();
+    let comment = find_comment_start(raw_line).map(|start| raw_line[start..].to_string());
+
+    if trimmed.is_empty() {
+        return TextLine {
+            raw: raw_line.to_string(),
+            ending: ending.to_string(),
+            kind: TextLineKind::Empty,
+            comment,
+            section,
+            entry: None,
+            attribute: None,
+        };
+    }
+
+    let mut kind = TextLineKind::Value;
+
+    if get_section_title(raw_line).is_some() {
+        kind = TextLineKind::Section;
+    } else if let Some(equals) = content.find('=') {
+        let key = content[..equals].trim();
+        let value_part = &content[equals + 1..];
+        let value = value_part.trim();
+
+        if !key.is_empty() && !value.is_empty() {
+            let value_start = equals + 1 + (value_part.len() - value_part.trim_start().len());
+
+            kind = TextLineKind::Attribute {
+                value_start,
+                value_end: value_start + value.len(),
+            };
+        }
+    }
+
+    TextLine {
+        raw: raw_line.to_string(),
+        ending: ending.to_string(),
+        kind,
+        comment,
+        section,
+        entry: None,
+        attribute: None,
+    }
+}
+
+fn get_section_title(raw_line: &str) -> Option<String> {
+    let trimmed = get_content(raw_line).trim();
+
+    if trimmed.len() > 2 && trimmed.starts_with('[') && trimmed.ends_with(']') {
+        return Some(trimmed[1..trimmed.len() - 1].to_string());
+    }
+
+    None
+}
+
+fn get_content(raw_line: &str) -> &str {
+    match find_comment_start(raw_line) {
+        Some(start) => &raw_line[..start],
+        None => raw_line,
+    }
+}
+
+fn find_comment_start(raw_line: &str) -> Option<usize> {
+    let mut quoted = false;
+
+    for (index, character) in raw_line.char_indices() {
+        match character {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return Some(index),
+            _ => {}
         }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = concat!(
+        "; Configuration\r\n",
+        "\r\n",
+        "[Options]\r\n",
+        "Difficulty = 4   ; 1-8\r\n",
+        "Life       = 100\r\n",
+        "\r\n",
+        "; Fonts\r\n",
+        "[Files]\r\n",
+        "font1 = \"font;1.fnt\" ; quoted\r\n",
+        "  font2=f-6x9.fnt\r\n",
+        "\r\n",
+    );
+
+    #[test]
+    fn unchanged_files_write_back_identically() {
+        let text_file = TextFile::from_string("mugen.cfg".to_string(), CONFIG.to_string());
+
+        assert_eq!(text_file.write(), CONFIG);
+        assert_eq!(text_file.get_section("Files").unwrap().get_attribute_or_default::<String>("font1"), "font;1.fnt");
+    }
+
+    #[test]
+    fn set_attribute_only_replaces_the_value() {
+        let mut text_file = TextFile::from_string("mugen.cfg".to_string(), CONFIG.to_string());
+
+        text_file.set_attribute("options", "difficulty", "8");
+        text_file.set_attribute("Files", "font1", "\"font;2.fnt\"");
+        text_file.set_attribute("Files", "font2", "f-4x6.fnt");
+
+        let expected = CONFIG
+            .replace("Difficulty = 4   ; 1-8", "Difficulty = 8   ; 1-8")
+            .replace("\"font;1.fnt\" ; quoted", "\"font;2.fnt\" ; quoted")
+            .replace("  font2=f-6x9.fnt", "  font2=f-4x6.fnt");
+
+        assert_eq!(text_file.write(), expected);
+        assert_eq!(text_file.get_section("Options").unwrap().get_attribute_or_default::<i32>("Difficulty"), 8);
+    }
+
+    #[test]
+    fn new_attributes_go_after_the_last_line_of_their_section() {
+        let mut text_file = TextFile::from_string("mugen.cfg".to_string(), CONFIG.to_string());
+
+        text_file.set_attribute("Options", "Time", "99");
+
+        let expected = CONFIG.replace("Life       = 100\r\n", "Life       = 100\r\nTime = 99\r\n");
+
+        assert_eq!(text_file.write(), expected);
+        assert_eq!(text_file.get_section("Options").unwrap().get_attribute_or_default::<i32>("Time"), 99);
+    }
+
+    #[test]
+    fn new_sections_are_appended_with_the_file_line_ending() {
+        let mut text_file = TextFile::from_string("mugen.cfg".to_string(), "[Options]\r\nLife = 100".to_string());
+
+        text_file.set_attribute("Sound", "WavVolume", "80");
 
-        TextFile::new(path, sections)
+        assert_eq!(text_file.write(), "[Options]\r\nLife = 100\r\n\r\n[Sound]\r\nWavVolume = 80");
+        assert_eq!(text_file.get_section("Sound").unwrap().get_attribute_or_default::<i32>("WavVolume"), 80);
     }
 }
@@ -1,10 +1,10 @@
-use gdnative::godot_warn;
+use crate::core::{attribute_value::{AttributeValue, ParseAttributeValue}, diagnostics, error::DataError};
 
-use crate::core::{attribute_value::{AttributeValue, ParseAttributeValue}, error::DataError};
-
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct TextSection {
     pub title: String,
+    pub filepath: String,
+    pub line: usize,
     pub lines: Vec<AttributeValue>,
     pub parsedlines: Vec<(String, AttributeValue)>,
 }
@@ -12,16 +12,26 @@ pub struct TextSection {
 impl TextSection {
     pub fn new(
         title: String,
+        line: usize,
         lines: Vec<AttributeValue>,
         parsedlines: Vec<(String, AttributeValue)>,
     ) -> Self {
         TextSection {
             title: title,
+            filepath: String::new(),
+            line: line,
             lines: lines,
             parsedlines: parsedlines,
         }
     }
 
+    pub fn locate_error(&self, error: DataError, line: usize) -> DataError {
+        error
+            .with_path(&self.filepath)
+            .with_section(&self.title)
+            .with_line(line)
+    }
+
     pub fn has_attribute(&self, key: &String) -> bool {
         if let Some(_) = self.get_attribute::<AttributeValue>(key) {
             return true
@@ -36,7 +46,10 @@ impl TextSection {
                 let result = T::parse_attribute_value(data_value.clone());
 
                 if let Err(error) = result {
-                    godot_warn!("{}", error);
+                    diagnostics::warn(self.locate_error(
+                        DataError::invalid_attribute(format!("{}: {}", data_key, error.message)),
+                        data_value.line()
+                    ));
                 } else {
                     return result.ok();
                 }
@@ -65,7 +78,10 @@ impl TextSection {
     pub fn get_attribute_or_fail<T: Default + ParseAttributeValue>(&self, key: &str) -> Result<T, DataError> {
         match self.get_attribute::<T>(key) {
             Some(value) => Ok(value),
-            None => Err(DataError::new(format!("Missing attribute: {}", key))),
+            None => Err(self.locate_error(
+                DataError::invalid_attribute(format!("Missing attribute: {}", key)),
+                self.line
+            )),
         }
     }
 }
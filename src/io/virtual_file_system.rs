@@ -0,0 +1,211 @@
+use std::sync::{Arc, RwLock};
+
+use crate::core::error::DataError;
+
+use super::{file_system::{FileStream, FileSystem}, godot_file_system::GodotFileSystem, native_file_system::NativeFileSystem};
+
+struct Mount {
+    prefix: String,
+    file_system: Arc<dyn FileSystem>,
+}
+
+pub struct VirtualFileSystem {
+    mounts: RwLock<Vec<Mount>>,
+}
+
+impl VirtualFileSystem {
+    pub fn new() -> Self {
+        VirtualFileSystem {
+            mounts: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn mount(&self, prefix: &str, file_system: Arc<dyn FileSystem>) {
+        let mut mounts = self.mounts.write().expect("Could not lock mounts");
+
+        mounts.retain(|mount| mount.prefix != prefix);
+        mounts.push(Mount {
+            prefix: prefix.to_string(),
+            file_system,
+        });
+
+        // Longest prefixes first, so nested mounts win over their parents
+        mounts.sort_by(|a, b| b.prefix.len().cmp(&a.prefix.len()));
+    }
+
+    pub fn unmount(&self, prefix: &str) {
+        let mut mounts = self.mounts.write().expect("Could not lock mounts");
+
+        mounts.retain(|mount| mount.prefix != prefix);
+    }
+
+    pub fn is_mounted(&self, prefix: &str) -> bool {
+        let mounts = self.mounts.read().expect("Could not lock mounts");
+
+        mounts.iter().any(|mount| mount.prefix == prefix)
+    }
+
+    fn resolve(&self, path: &str) -> Option<(Arc<dyn FileSystem>, String)> {
+        let mounts = self.mounts.read().expect("Could not lock mounts");
+
+        for mount in mounts.iter() {
+            if let Some(relative_path) = strip_mount_prefix(&mount.prefix, path) {
+                return Some((mount.file_system.clone(), relative_path.to_string()));
+            }
+        }
+
+        None
+    }
+}
+
+fn strip_mount_prefix<'a>(prefix: &str, path: &'a str) -> Option<&'a str> {
+    if prefix.is_empty() {
+        return Some(path);
+    }
+
+    let rest = path.strip_prefix(prefix)?;
+
+    if prefix.ends_with('/') || rest.is_empty() || rest.starts_with('/') {
+        return Some(rest.trim_start_matches('/'));
+    }
+
+    None
+}
+
+impl Default for VirtualFileSystem {
+    fn default() -> Self {
+        let file_system = VirtualFileSystem::new();
+
+        file_system.mount("", Arc::new(NativeFileSystem::new("")));
+        file_system.mount("res://", Arc::new(GodotFileSystem::new("res://")));
+        file_system.mount("user://", Arc::new(GodotFileSystem::new("user://")));
+
+        file_system
+    }
+}
+
+impl FileSystem for VirtualFileSystem {
+    fn exists(&self, path: &str) -> bool {
+        match self.resolve(path) {
+            Some((file_system, relative_path)) => file_system.exists(&relative_path),
+            None => false,
+        }
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn FileStream>, DataError> {
+        let (file_system, relative_path) = self.resolve(path)
+            .ok_or_else(|| DataError::missing_file(path, "No file system mounted".to_string()))?;
+
+        file_system.open(&relative_path).map_err(|mut error| {
+            error.path = Some(path.to_string());
+            error
+        })
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<(), DataError> {
+        let (file_system, relative_path) = self.resolve(path)
+            .ok_or_else(|| DataError::missing_file(path, "No file system mounted".to_string()))?;
+
+        file_system.write(&relative_path, data)
+    }
+
+    fn modified_time(&self, path: &str) -> Option<u64> {
+        let (file_system, relative_path) = self.resolve(path)?;
+
+        file_system.modified_time(&relative_path)
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<String>, DataError> {
+        let (file_system, relative_path) = self.resolve(path)
+            .ok_or_else(|| DataError::new(format!("Error reading directory: {}, no file system mounted", path)))?;
+
+        file_system.read_dir(&relative_path)
+    }
+
+    fn is_thread_safe(&self) -> bool {
+        let mounts = self.mounts.read().expect("Could not lock mounts");
+
+        mounts.iter().all(|mount| mount.file_system.is_thread_safe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::memory_file_system::MemoryFileSystem;
+
+    use super::*;
+
+    #[test]
+    fn godot_mounts_are_not_thread_safe() {
+        let file_system = VirtualFileSystem::new();
+
+        file_system.mount("", Arc::new(NativeFileSystem::new("")));
+        file_system.mount("mem://", Arc::new(MemoryFileSystem::new()));
+        assert!(file_system.is_thread_safe());
+
+        file_system.mount("res://", Arc::new(GodotFileSystem::new("res://")));
+        assert!(!file_system.is_thread_safe());
+
+        file_system.mount("res://", Arc::new(NativeFileSystem::new("game")));
+        assert!(file_system.is_thread_safe());
+    }
+
+    #[test]
+    fn strips_mount_prefixes_on_path_boundaries() {
+        assert_eq!(strip_mount_prefix("", "data/system.def"), Some("data/system.def"));
+        assert_eq!(strip_mount_prefix("res://", "res://data/system.def"), Some("data/system.def"));
+        assert_eq!(strip_mount_prefix("zip://kfm.zip", "zip://kfm.zip/kfm.def"), Some("kfm.def"));
+        assert_eq!(strip_mount_prefix("zip://kfm.zip", "zip://kfm.zip"), Some(""));
+        assert_eq!(strip_mount_prefix("zip://kfm.zip", "zip://kfm.zip2/kfm.def"), None);
+        assert_eq!(strip_mount_prefix("res://", "user://mugen.cfg"), None);
+    }
+
+    #[test]
+    fn resolves_paths_to_the_longest_mount() {
+        let file_system = VirtualFileSystem::new();
+        let outer = Arc::new(MemoryFileSystem::new());
+        let inner = Arc::new(MemoryFileSystem::new());
+
+        outer.insert_text("data/system.def", "outer");
+        outer.insert_text("chars/kfm.def", "outer");
+        inner.insert_text("kfm.def", "inner");
+
+        file_system.mount("mem://", outer);
+        file_system.mount("mem://chars", inner);
+
+        assert_eq!(file_system.read("mem://data/system.def").unwrap(), b"outer");
+        assert_eq!(file_system.read("mem://chars/kfm.def").unwrap(), b"inner");
+        assert!(!file_system.exists("other://data/system.def"));
+
+        file_system.unmount("mem://chars");
+        assert!(!file_system.is_mounted("mem://chars"));
+        assert_eq!(file_system.read("mem://chars/kfm.def").unwrap(), b"outer");
+    }
+
+    #[test]
+    fn remounting_a_prefix_replaces_it() {
+        let file_system = VirtualFileSystem::new();
+        let first = Arc::new(MemoryFileSystem::new());
+        let second = Arc::new(MemoryFileSystem::new());
+
+        first.insert_text("file.txt", "first");
+        second.insert_text("file.txt", "second");
+
+        file_system.mount("mem://", first);
+        file_system.mount("mem://", second);
+
+        assert_eq!(file_system.read("mem://file.txt").unwrap(), b"second");
+    }
+
+    #[test]
+    fn errors_keep_the_virtual_path() {
+        let file_system = VirtualFileSystem::new();
+
+        file_system.mount("mem://", Arc::new(MemoryFileSystem::new()));
+
+        let error = file_system.open("mem://missing.txt").err().unwrap();
+        assert_eq!(error.path.as_deref(), Some("mem://missing.txt"));
+        assert!(file_system.open("other://missing.txt").is_err());
+        assert!(file_system.write("other://file.txt", b"data").is_err());
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::sync::Mutex;
+
+use zip::ZipArchive;
+
+use crate::core::error::DataError;
+
+use super::{file_system::{FileStream, FileSystem}, path_resolver};
+
+// The size comes from the archive headers, larger files grow the buffer as they are read
+const MAX_PREALLOCATED_SIZE: u64 = 16 * 1024 * 1024;
+
+pub struct ZipFileSystem {
+    archive: Mutex<ZipArchive<Cursor<Vec<u8>>>>,
+    entries: HashMap<String, usize>,
+}
+
+impl ZipFileSystem {
+    pub fn new(data: Vec<u8>) -> Result<Self, DataError> {
+        let mut archive = ZipArchive::new(Cursor::new(data))
+            .map_err(|error| DataError::new(format!("Error reading zip archive: {}", error)))?;
+        let mut entries = HashMap::new();
+
+        for index in 0..archive.len() {
+            let file = archive.by_index(index)
+                .map_err(|error| DataError::new(format!("Error reading zip archive: {}", error)))?;
+
+            if file.is_dir() {
+                continue;
+            }
+
+            entries.insert(path_resolver::normalize_path(file.name()), index);
+        }
+
+        Ok(ZipFileSystem {
+            archive: Mutex::new(archive),
+            entries,
+        })
+    }
+}
+
+fn normalize_path(path: &str) -> String {
+    path_resolver::normalize_path(path).trim_start_matches('/').to_string()
+}
+
+impl FileSystem for ZipFileSystem {
+    fn exists(&self, path: &str) -> bool {
+        self.entries.contains_key(&normalize_path(path))
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn FileStream>, DataError> {
+        Ok(Box::new(Cursor::new(self.read(path)?)))
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>, DataError> {
+        let index = *self.entries.get(&normalize_path(path))
+            .ok_or_else(|| DataError::missing_file(path, "File not found in archive".to_string()))?;
+        let mut archive = self.archive.lock().expect("Could not lock zip archive");
+        let mut file = archive.by_index(index)
+            .map_err(|error| DataError::new(format!("Error opening file: {}, {}", path, error)))?;
+        let mut buffer = Vec::with_capacity(file.size().min(MAX_PREALLOCATED_SIZE) as usize);
+
+        file.read_to_end(&mut buffer)
+            .map_err(|error| DataError::new(format!("Error reading file: {}, {}", path, error)))?;
+
+        Ok(buffer)
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<String>, DataError> {
+        let directory = normalize_path(path);
+        let prefix = if directory.is_empty() { String::new() } else { format!("{}/", directory) };
+        let mut entries: Vec<String> = Vec::new();
+
+        for key in self.entries.keys() {
+            if let Some(rest) = key.strip_prefix(&prefix) {
+                let name = rest.split('/').next().unwrap_or("").to_string();
+
+                if !name.is_empty() && !entries.contains(&name) {
+                    entries.push(name);
+                }
+            }
+        }
+
+        if entries.is_empty() {
+            return Err(DataError::new(format!("Error reading directory: {}, directory not found in archive", path)));
+        }
+
+        Ok(entries)
+    }
+}
@@ -2,12 +2,12 @@ use gdnative::prelude::*;
 use crate::core::game::Game;
 
 mod animations;
-mod audio;
+pub mod audio;
 mod backgrounds;
-mod core;
-mod drawing;
+pub mod core;
+pub mod drawing;
 mod elements;
-mod io;
+pub mod io;
 mod menus;
 mod systems;
 mod profiles;
@@ -4,6 +4,7 @@ pub struct MenuData {
     pub motif_name: String,
     pub motif_author: String,
     pub font_map: FontMap,
+    pub font_paths: Vec<String>,
     pub sound_path: String,
     pub sprite_path: String,
     pub anim_path: String,
@@ -14,6 +15,7 @@ impl MenuData {
         motif_name: String,
         motif_author: String,
         font_map: FontMap,
+        font_paths: Vec<String>,
         sound_path: String,
         sprite_path: String,
         anim_path: String,
@@ -22,6 +24,7 @@ impl MenuData {
             motif_name,
             motif_author,
             font_map,
+            font_paths,
             sound_path,
             sprite_path,
             anim_path
@@ -1,3 +1,5 @@
+use std::sync::{Arc, RwLock};
+
 use crate::{animations::animation_manager::AnimationManager, backgrounds::{background::Background, background_group::BackgroundGroup}, core::{configuration::Configuration, error::DataError, regex::RegEx, regex::RegExFlags}, drawing::sprite_file::SpriteFile, io::{text_file::TextFile, text_section::TextSection}};
 
 #[derive(Clone)]
@@ -13,7 +15,7 @@ impl NonCombatScreen {
         configuration: &Configuration,
         textsection: &TextSection,
         textfile: &TextFile,
-        sprite_file: &mut SpriteFile,
+        sprite_file: &Arc<RwLock<SpriteFile>>,
         animation_manager: &AnimationManager,
     ) -> Result<NonCombatScreen, DataError> {
         Ok(NonCombatScreen {
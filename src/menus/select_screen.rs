@@ -1,6 +1,8 @@
-use gdnative::core_types::Point2;
+use std::sync::{Arc, RwLock};
 
-use crate::{elements::element::Element, io::text_file::TextFile, core::{error::DataError, configuration::Configuration}, drawing::sprite_file::SpriteFile, animations::animation_manager::AnimationManager};
+use gdnative::core_types::{Point2, Vector2};
+
+use crate::{elements::element::Element, io::{text_file::TextFile, from_text_section::FromTextSection}, core::{error::DataError, configuration::Configuration}, drawing::sprite_file::SpriteFile, animations::animation_manager::AnimationManager};
 
 use super::non_combat_screen::NonCombatScreen;
 
@@ -9,21 +11,62 @@ pub struct SelectScreen {
     pub non_combat_screen: NonCombatScreen,
     pub cellbg: Element,
     pub cellrandom: Element,
+    pub cursoractive: [Element; 2],
+    pub cursordone: [Element; 2],
+    pub columns: i32,
+    pub rows: i32,
+    pub wrapping: bool,
+    pub showemptyboxes: bool,
+    pub moveoveremptyboxes: bool,
+    pub grid_position: Point2,
+    pub cellsize: Point2,
+    pub cellspacing: i32,
+    pub players: [PlayerSelectInfo; 2],
+}
+
+#[derive(Clone, FromTextSection)]
+pub struct SelectScreenDefinition {
     pub columns: i32,
     pub rows: i32,
     pub wrapping: bool,
     pub showemptyboxes: bool,
     pub moveoveremptyboxes: bool,
+    #[text_section(key = "pos")]
     pub grid_position: Point2,
+    #[text_section(key = "cell.size")]
     pub cellsize: Point2,
+    #[text_section(key = "cell.spacing")]
     pub cellspacing: i32,
+    #[text_section(prefix = "p1")]
+    pub p1: PlayerSelectInfo,
+    #[text_section(prefix = "p2")]
+    pub p2: PlayerSelectInfo,
+}
+
+#[derive(Clone, FromTextSection)]
+pub struct PlayerSelectInfo {
+    #[text_section(key = "cursor.startcell")]
+    pub cursorstartcell: Point2,
+    #[text_section(key = "face.offset")]
+    pub faceoffset: Point2,
+    #[text_section(key = "face.scale", default = "Vector2::new(1.0, 1.0)")]
+    pub facescale: Vector2,
+    #[text_section(key = "face.facing", default = "1")]
+    pub facefacing: i32,
+}
+
+impl PlayerSelectInfo {
+    // MUGEN writes the start cell as row, column
+    pub fn start_cell(&self) -> (i32, i32) {
+        (self.cursorstartcell.y as i32, self.cursorstartcell.x as i32)
+    }
 }
 
 impl SelectScreen {
     pub fn build(
         configuration: &Configuration,
         textfile: &TextFile,
-        sprite_file: &mut SpriteFile,
+        sprite_file: &Arc<RwLock<SpriteFile>>,
         animation_manager: &AnimationManager,
     ) -> Result<SelectScreen, DataError> {
         let textsection = textfile.get_section("Select Info")?;
@@ -37,29 +80,33 @@ impl SelectScreen {
             animation_manager
         )?;
 
-        let cellbg = Element::build(&textsection, "cell.bg", sprite_file)?;
-        let cellrandom = Element::build(&textsection, "cell.random", sprite_file)?;
-        let columns = textsection.get_attribute_or_default("columns");
-        let rows = textsection.get_attribute_or_default("rows");
-        let wrapping = textsection.get_attribute_or_default("wrapping");
-        let showemptyboxes = textsection.get_attribute_or_default("showemptyboxes");
-        let moveoveremptyboxes = textsection.get_attribute_or_default("moveoveremptyboxes");
-        let grid_position = textsection.get_attribute_or_default("pos");
-        let cellsize = textsection.get_attribute_or_default("cell.size");
-        let cellspacing = textsection.get_attribute_or_default("cell.spacing");
+        let cellbg = Element::build(&textsection, "cell.bg", sprite_file, animation_manager)?;
+        let cellrandom = Element::build(&textsection, "cell.random", sprite_file, animation_manager)?;
+        let cursoractive = [
+            Element::build(&textsection, "p1.cursor.active", sprite_file, animation_manager)?,
+            Element::build(&textsection, "p2.cursor.active", sprite_file, animation_manager)?,
+        ];
+        let cursordone = [
+            Element::build(&textsection, "p1.cursor.done", sprite_file, animation_manager)?,
+            Element::build(&textsection, "p2.cursor.done", sprite_file, animation_manager)?,
+        ];
+        let definition = SelectScreenDefinition::from_text_section(&textsection, "")?;
 
         Ok(SelectScreen {
             non_combat_screen,
             cellbg,
             cellrandom,
-            columns,
-            rows,
-            wrapping,
-            showemptyboxes,
-            moveoveremptyboxes,
-            grid_position,
-            cellsize,
-            cellspacing,
+            cursoractive,
+            cursordone,
+            columns: definition.columns,
+            rows: definition.rows,
+            wrapping: definition.wrapping,
+            showemptyboxes: definition.showemptyboxes,
+            moveoveremptyboxes: definition.moveoveremptyboxes,
+            grid_position: definition.grid_position,
+            cellsize: definition.cellsize,
+            cellspacing: definition.cellspacing,
+            players: [definition.p1, definition.p2],
         })
     }
 }
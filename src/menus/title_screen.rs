@@ -1,8 +1,9 @@
 use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 
 use gdnative::core_types::Vector2;
 
-use crate::{animations::animation_manager::AnimationManager, core::{configuration::Configuration, enumerations::MainMenuOption, error::DataError, sound_id::SoundId}, drawing::{print_data::PrintData, sprite_file::SpriteFile}, io::{text_file::TextFile, text_section::TextSection}};
+use crate::{animations::animation_manager::AnimationManager, core::{configuration::Configuration, enumerations::MainMenuOption, error::DataError, sound_id::SoundId}, drawing::{print_data::PrintData, sprite_file::SpriteFile}, io::{from_text_section::FromTextSection, text_file::TextFile}};
 
 use super::non_combat_screen::NonCombatScreen;
 
@@ -32,15 +33,58 @@ pub struct TitleScreen {
     pub marginybottom: i32,
 }
 
+#[derive(Clone, FromTextSection)]
+pub struct TitleScreenDefinition {
+    #[text_section(key = "menu.pos", required)]
+    pub menuposition: Vector2,
+    #[text_section(key = "menu.item.font", required)]
+    pub mainfont: PrintData,
+    #[text_section(key = "menu.item.active.font", required)]
+    pub activefont: PrintData,
+    #[text_section(key = "menu.item.spacing", required)]
+    pub spacing: Vector2,
+    #[text_section(key = "menu.window.visibleitems", required)]
+    pub visiblemenuitems: i32,
+    #[text_section(key = "menu.boxcursor.visible", required)]
+    pub cursorvisible: bool,
+    #[text_section(key = "cursor.move.snd")]
+    pub soundcursormove: Option<SoundId>,
+    #[text_section(key = "cursor.done.snd")]
+    pub soundselect: Option<SoundId>,
+    #[text_section(key = "cancel.snd")]
+    pub soundcancel: Option<SoundId>,
+    #[text_section(key = "menu.window.margins.y")]
+    pub marginy: Vector2,
+    #[text_section(prefix = "menu.itemname")]
+    pub itemnames: MenuItemNames,
+}
+
+// Only the implemented menus are shown, see build_menu_text
+#[allow(dead_code)]
+#[derive(Clone, FromTextSection)]
+pub struct MenuItemNames {
+    pub arcade: String,
+    pub versus: String,
+    pub teamarcade: String,
+    pub teamversus: String,
+    pub teamcoop: String,
+    pub survival: String,
+    pub survivalcoop: String,
+    pub training: String,
+    pub watch: String,
+    pub options: String,
+    pub exit: String,
+}
+
 impl TitleScreen {
     pub fn build(
         configuration: &Configuration,
         textfile: &TextFile,
-        sprite_file: &mut SpriteFile,
+        sprite_file: &Arc<RwLock<SpriteFile>>,
         animation_manager: &AnimationManager,
     ) -> Result<TitleScreen, DataError> {
         let textsection = textfile.get_section("Title Info")?;
-        let marginy: Vector2 = textsection.get_attribute_or_default("menu.window.margins.y");
+        let definition = TitleScreenDefinition::from_text_section(&textsection, "")?;
         let non_combat_screen = NonCombatScreen::build(
             "Title",
             configuration,
@@ -52,35 +96,35 @@ impl TitleScreen {
 
         Ok(TitleScreen {
             non_combat_screen: non_combat_screen,
-			menuposition: textsection.get_attribute_or_fail("menu.pos")?,
-			mainfont: textsection.get_attribute_or_fail("menu.item.font")?,
-			activefont: textsection.get_attribute_or_fail("menu.item.active.font")?,
-			spacing: textsection.get_attribute_or_fail("menu.item.spacing")?,
-			visiblemenuitems: textsection.get_attribute_or_fail("menu.window.visibleitems")?,
-			cursorvisible: textsection.get_attribute_or_fail("menu.boxcursor.visible")?,
-			soundcursormove: textsection.get_attribute("cursor.move.snd"),
-			soundselect: textsection.get_attribute("cursor.done.snd"),
-			soundcancel: textsection.get_attribute("cancel.snd"),
-            menutext: build_menu_text(&textsection),
-            marginytop: marginy.x as i32,
-            marginybottom: marginy.y as i32,
+            menuposition: definition.menuposition,
+            mainfont: definition.mainfont,
+            activefont: definition.activefont,
+            spacing: definition.spacing,
+            visiblemenuitems: definition.visiblemenuitems,
+            cursorvisible: definition.cursorvisible,
+            soundcursormove: definition.soundcursormove,
+            soundselect: definition.soundselect,
+            soundcancel: definition.soundcancel,
+            menutext: build_menu_text(&definition.itemnames),
+            marginytop: definition.marginy.x as i32,
+            marginybottom: definition.marginy.y as i32,
         })
     }
 }
 
-fn build_menu_text(textsection: &TextSection) -> HashMap<MainMenuOption, String> {
+fn build_menu_text(itemnames: &MenuItemNames) -> HashMap<MainMenuOption, String> {
     let mut map = HashMap::new();
-    // map.insert(MainMenuOption::Arcade, textsection.get_attribute_or_default("menu.itemname.arcade"));
-    map.insert(MainMenuOption::Versus, textsection.get_attribute_or_default("menu.itemname.versus"));
-    // map.insert(MainMenuOption::TeamArcade, textsection.get_attribute_or_default("menu.itemname.teamarcade"));
-    // map.insert(MainMenuOption::TeamVersus, textsection.get_attribute_or_default("menu.itemname.teamversus"));
-    // map.insert(MainMenuOption::TeamCoop, textsection.get_attribute_or_default("menu.itemname.teamcoop"));
-    // map.insert(MainMenuOption::Survival, textsection.get_attribute_or_default("menu.itemname.survival"));
-    // map.insert(MainMenuOption::SurvivalCoop, textsection.get_attribute_or_default("menu.itemname.survivalcoop"));
-    map.insert(MainMenuOption::Training, textsection.get_attribute_or_default("menu.itemname.training"));
-    // map.insert(MainMenuOption::Watch, textsection.get_attribute_or_default("menu.itemname.watch"));
-    // map.insert(MainMenuOption::Options, textsection.get_attribute_or_default("menu.itemname.options"));
-    // map.insert(MainMenuOption::Quit, textsection.get_attribute_or_default("menu.itemname.exit"));
+    // map.insert(MainMenuOption::Arcade, itemnames.arcade.clone());
+    map.insert(MainMenuOption::Versus, itemnames.versus.clone());
+    // map.insert(MainMenuOption::TeamArcade, itemnames.teamarcade.clone());
+    // map.insert(MainMenuOption::TeamVersus, itemnames.teamversus.clone());
+    // map.insert(MainMenuOption::TeamCoop, itemnames.teamcoop.clone());
+    // map.insert(MainMenuOption::Survival, itemnames.survival.clone());
+    // map.insert(MainMenuOption::SurvivalCoop, itemnames.survivalcoop.clone());
+    map.insert(MainMenuOption::Training, itemnames.training.clone());
+    // map.insert(MainMenuOption::Watch, itemnames.watch.clone());
+    // map.insert(MainMenuOption::Options, itemnames.options.clone());
+    // map.insert(MainMenuOption::Quit, itemnames.exit.clone());
     // TODO: Implement missing menus
     // map.insert(MainMenuOption::TeamCoop, "NOT IMPLEMENTED".to_string());
     // map.insert(MainMenuOption::Survival, "NOT IMPLEMENTED".to_string());
@@ -2,7 +2,7 @@ use std::{collections::{BTreeMap}, sync::{Arc, RwLock}};
 
 use gdnative::core_types::Size2;
 
-use crate::{core::{error::DataError, enumerations::PlayerSelectType}, io::{file_system, text_section::TextSection}, drawing::{sprite_system::SpriteSystem, sprite_file::SpriteFile}};
+use crate::{core::{error::DataError, enumerations::PlayerSelectType}, io::{file_system, from_text_section::FromTextSection, text_section::TextSection}, drawing::{sprite_system::SpriteSystem, sprite_file::SpriteFile}};
 
 #[derive(Clone)]
 pub struct PlayerProfile {
@@ -27,6 +27,29 @@ pub struct PlayerProfile {
     pub sprite_file: Arc<RwLock<SpriteFile>>,
 }
 
+#[derive(Clone, FromTextSection)]
+pub struct PlayerInfoDefinition {
+    pub name: String,
+    pub displayname: String,
+    pub author: String,
+    pub versiondate: String,
+    pub mugenversion: String,
+    pub localcoord: Size2,
+    #[text_section(key = "pal.defaults")]
+    pub paldefaults: String,
+}
+
+// st, st1..stN and pal1..pal12 are numbered, see build_state_files and build_palette_files
+#[derive(Clone, FromTextSection)]
+pub struct PlayerFilesDefinition {
+    pub cmd: String,
+    pub cns: String,
+    pub stcommon: String,
+    pub sprite: String,
+    pub anim: String,
+    pub sound: String,
+}
+
 #[derive(Clone)]
 pub struct PlayerSelect {
     pub select_type: PlayerSelectType,
@@ -51,7 +74,9 @@ fn build_palette_order(input: String) -> Vec<usize> {
 
 fn combine_paths(base_path: &str, path: String) -> String {
     if path.len() > 0 {
-        file_system::combine_paths(base_path, &path)
+        let path = file_system::combine_paths(base_path, &path);
+
+        file_system::resolve_file(&path).unwrap_or(path)
     } else {
         String::new()
     }
@@ -117,16 +142,16 @@ pub fn build_palette_files(section: &TextSection, base_path: &str) -> BTreeMap<u
     let mut palettes = BTreeMap::new();
 
     for (key, value) in section.parsedlines.iter() {
-        let attribute_text = value.to_string();
+        let key = key.to_lowercase();
 
-        if !value.compare("pal", 0, 0, 3) {
+        if !key.starts_with("pal") {
             continue;
         }
 
         let counter = key[3..].parse::<usize>();
 
         if let Ok(counter) = counter {
-            let path = combine_paths(base_path, attribute_text);
+            let path = combine_paths(base_path, value.to_string());
             palettes.insert(counter, path);
         }
     }
@@ -143,25 +168,27 @@ impl PlayerProfile {
         let textfile = file_system::open_text_file(definition_path)?;
         let infosection = textfile.get_section("info")?;
         let filesection = textfile.get_section("files")?;
+        let info = PlayerInfoDefinition::from_text_section(&infosection, "")?;
+        let files = PlayerFilesDefinition::from_text_section(&filesection, "")?;
         let base_path = file_system::get_directory(&textfile.filepath);
-        let common_state_file = get_common_state_file(&base_path, filesection.get_attribute_or_default("stcommon"));
-        let command_path = combine_paths(&base_path, filesection.get_attribute_or_default("cmd"));
-        let sprite_path = combine_paths(&base_path, filesection.get_attribute_or_default("sprite"));
+        let common_state_file = get_common_state_file(&base_path, files.stcommon);
+        let command_path = combine_paths(&base_path, files.cmd);
+        let sprite_path = combine_paths(&base_path, files.sprite);
         let sprite_file = Arc::new(RwLock::new(sprite_system.get_sprite_file(&sprite_path)?));
 
         Ok(PlayerProfile {
-            player_name: infosection.get_attribute_or_default("name"),
-            display_name: infosection.get_attribute_or_default("displayname"),
-            author: infosection.get_attribute_or_default("author"),
-            version: infosection.get_attribute_or_default("versiondate"),
-            mugen_version: infosection.get_attribute_or_default("mugenversion"),
-            localcoord: infosection.get_attribute_or_default("localcoord"),
-            palette_order: build_palette_order(infosection.get_attribute_or_default("pal.defaults")),
-            constants_path: combine_paths(&base_path, filesection.get_attribute_or_default("cns")),
+            player_name: info.name,
+            display_name: info.displayname,
+            author: info.author,
+            version: info.versiondate,
+            mugen_version: info.mugenversion,
+            localcoord: info.localcoord,
+            palette_order: build_palette_order(info.paldefaults),
+            constants_path: combine_paths(&base_path, files.cns),
             state_files: build_state_files(&filesection, &base_path, &common_state_file, &command_path),
             sprite_path,
-            animation_path: combine_paths(&base_path, filesection.get_attribute_or_default("anim")),
-            sound_path: combine_paths(&base_path, filesection.get_attribute_or_default("sound")),
+            animation_path: combine_paths(&base_path, files.anim),
+            sound_path: combine_paths(&base_path, files.sound),
             stage_path: stage_path.to_string(),
             palette_files: build_palette_files(&filesection, &base_path),
             definition_path: definition_path.to_string(),
@@ -172,3 +199,31 @@ impl PlayerProfile {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::io::text_file::TextFile;
+
+    use super::*;
+
+    #[test]
+    fn palette_files_are_read_from_pal_keys() {
+        let text_file = TextFile::from_string("kfm.def".to_string(), [
+            "[Files]",
+            "cmd = kfm.cmd",
+            "st = kfm.cns",
+            "sprite = kfm.sff",
+            "pal1 = kfm.act",
+            "PAL12 = alt/kfm12.act",
+            "pal = x",
+            "palx = kfm2.act",
+            "sound = a",
+        ].join("\n"));
+        let section = text_file.get_section("Files").unwrap();
+        let palette_files = build_palette_files(&section, "chars/kfm");
+
+        assert_eq!(palette_files.keys().copied().collect::<Vec<usize>>(), vec![1, 12]);
+        assert_eq!(palette_files[&1], "chars/kfm/kfm.act");
+        assert_eq!(palette_files[&12], "chars/kfm/alt/kfm12.act");
+    }
+}
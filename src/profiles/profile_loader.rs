@@ -1,14 +1,22 @@
 use std::collections::HashMap;
 
-use crate::{io::{file_system, text_file::TextFile}, core::{error::DataError, constants::DATA_PATH, enumerations::PlayerSelectType}, menus::select_screen::SelectScreen, drawing::sprite_system::SpriteSystem};
+use crate::{io::{file_system, text_file::TextFile}, core::{diagnostics, error::DataError, constants::DATA_PATH, enumerations::PlayerSelectType}, menus::select_screen::SelectScreen};
 
 use super::{stage_profile::StageProfile, player_profile::{PlayerProfile, PlayerSelect}};
 
+#[derive(Clone)]
+pub struct ProfileRequest {
+    pub index: usize,
+    pub definition_path: String,
+    pub stage_path: String,
+}
+
 #[derive(Clone)]
 pub struct ProfileLoader {
     pub stages: Vec<StageProfile>,
     pub players: Vec<PlayerSelect>,
     player_map: Option<HashMap<(i32, i32), PlayerSelect>>,
+    pending_profiles: Vec<ProfileRequest>,
     select_screen: SelectScreen,
 }
 
@@ -22,10 +30,16 @@ fn parse_profile_line(line: &str) -> Option<(String, String)> {
     let mut stage_path: String = "".to_string();
 
     if pieces.len() >= 1 {
-        player_path = file_system::combine_paths(
-            DATA_PATH,
-            &format!("chars/{}/{}.def", pieces[0].trim(), pieces[0].trim()),
-        );
+        let name = pieces[0].trim();
+
+        if name.to_lowercase().ends_with(".zip") {
+            player_path = file_system::combine_paths(DATA_PATH, &format!("chars/{}", name));
+        } else {
+            player_path = file_system::combine_paths(
+                DATA_PATH,
+                &format!("chars/{}/{}.def", name, name),
+            );
+        }
     }
 
     if pieces.len() >= 2 {
@@ -35,19 +49,52 @@ fn parse_profile_line(line: &str) -> Option<(String, String)> {
         );
     }
 
-    if !file_system::does_file_exist(&player_path) {
-        return None
-    }
+    let player_path = resolve_definition_path(&player_path)?;
 
-    if stage_path.len() > 0 && !file_system::does_file_exist(&stage_path) {
-        return None
+    if stage_path.len() > 0 {
+        stage_path = resolve_definition_path(&stage_path)?;
     }
 
     Some((player_path, stage_path))
 }
 
+// Characters and stages can be shipped as zip archives holding their def file
+fn resolve_definition_path(path: &str) -> Option<String> {
+    if path.to_lowercase().ends_with(".zip") {
+        return get_archive_definition_path(path);
+    }
+
+    file_system::resolve_file(path)
+}
+
+fn get_archive_definition_path(archive_path: &str) -> Option<String> {
+    let archive_path = match file_system::mount_archive(archive_path) {
+        Ok(archive_path) => archive_path,
+        Err(error) => {
+            diagnostics::warn(error);
+            return None;
+        }
+    };
+
+    let name = file_system::get_name(&archive_path);
+    let name = &name[..name.len() - ".zip".len()];
+
+    for candidate in [format!("{}.def", name), format!("{}/{}.def", name, name)].iter() {
+        let definition_path = file_system::combine_paths(&archive_path, candidate);
+
+        if let Some(definition_path) = file_system::resolve_file(&definition_path) {
+            return Some(definition_path);
+        }
+    }
+
+    diagnostics::warn(DataError::missing_file(&archive_path, "No definition file found in archive".to_string()));
+
+    None
+}
+
 impl ProfileLoader {
-    pub fn build(select_screen: &SelectScreen, sprite_system: &SpriteSystem) -> Result<Self, DataError> {
+    // Player profiles are left empty, they are built from take_pending_profiles by the asset loader
+    pub fn build(select_screen: &SelectScreen) -> Result<Self, DataError> {
         let textfile = file_system::open_text_file(
             &file_system::combine_paths(DATA_PATH, "data/select.def")
         )?;
@@ -57,10 +104,11 @@ impl ProfileLoader {
             stages: Default::default(),
             players: Default::default(),
             player_map: Default::default(),
+            pending_profiles: Default::default(),
         };
 
         profile_loader.build_stage_profiles(&textfile)?;
-        profile_loader.build_player_profiles(&textfile, sprite_system)?;
+        profile_loader.build_player_profiles(&textfile)?;
         profile_loader.select_screen = select_screen.clone();
 
         Ok(profile_loader)
@@ -73,7 +121,15 @@ impl ProfileLoader {
 
         for line in textsection.lines.iter() {
             let line_text = line.to_string();
-            let stage_path = file_system::combine_paths(DATA_PATH, &line_text);
+            let mut stage_path = file_system::combine_paths(DATA_PATH, &line_text);
+
+            if stage_path.to_lowercase().ends_with(".zip") {
+                stage_path = match get_archive_definition_path(&stage_path) {
+                    Some(stage_path) => stage_path,
+                    None => continue,
+                };
+            }
+
             let stagetextfile = file_system::open_text_file(&stage_path)?;
             let name: String = stagetextfile.get_section("Info")?.get_attribute_or_default("name");
 
@@ -86,8 +142,9 @@ impl ProfileLoader {
         Ok(())
     }
 
-    fn build_player_profiles(&mut self, textfile: &TextFile, sprite_system: &SpriteSystem) -> Result<(), DataError> {
+    fn build_player_profiles(&mut self, textfile: &TextFile) -> Result<(), DataError> {
         self.players.clear();
+        self.pending_profiles.clear();
 
         let textsection = textfile.get_section("Characters")?;
 
@@ -104,9 +161,14 @@ impl ProfileLoader {
             }
 
             if let Some((player_path, stage_path)) = parse_profile_line(&line_text) {
+                self.pending_profiles.push(ProfileRequest {
+                    index: self.players.len(),
+                    definition_path: player_path,
+                    stage_path,
+                });
                 self.players.push(PlayerSelect {
                     select_type: PlayerSelectType::Profile,
-                    profile: Some(PlayerProfile::build(&player_path, &stage_path, &sprite_system)?),
+                    profile: None,
                 });
             }
         }
@@ -114,6 +176,17 @@ impl ProfileLoader {
         Ok(())
     }
 
+    pub fn take_pending_profiles(&mut self) -> Vec<ProfileRequest> {
+        std::mem::take(&mut self.pending_profiles)
+    }
+
+    pub fn set_player_profile(&mut self, index: usize, profile: PlayerProfile) {
+        if let Some(player) = self.players.get_mut(index) {
+            player.profile = Some(profile);
+            self.player_map = None;
+        }
+    }
+
     pub fn get_player_on_grid(&mut self, position: (i32, i32)) -> Option<PlayerSelect> {
         let player_map = &self.player_map;
 
@@ -144,3 +217,52 @@ impl ProfileLoader {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::{ZipWriter, write::FileOptions};
+
+    use crate::io::memory_file_system::test_mount;
+
+    use super::*;
+
+    fn write_archive(path: &str, files: &[&str]) {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+
+        for file in files.iter() {
+            writer.start_file(*file, FileOptions::default()).unwrap();
+            writer.write_all(b"[Info]\nname = \"Test\"\n").unwrap();
+        }
+
+        let data = writer.finish().unwrap().into_inner();
+        file_system::write_file(path, &data).unwrap();
+    }
+
+    #[test]
+    fn resolves_definitions_inside_archives() {
+        let mount = test_mount("profile_loader_tests");
+
+        let stage_path = format!("{}/stages/river.zip", mount);
+        let player_path = format!("{}/chars/kfm.zip", mount);
+
+        write_archive(&stage_path, &["river.def", "river.sff"]);
+        write_archive(&player_path, &["kfm/kfm.def", "kfm/kfm.sff"]);
+
+        assert_eq!(resolve_definition_path(&stage_path), Some(format!("{}/river.def", stage_path)));
+        assert_eq!(resolve_definition_path(&player_path), Some(format!("{}/kfm/kfm.def", player_path)));
+    }
+
+    #[test]
+    fn archives_without_definitions_are_skipped() {
+        let mount = test_mount("profile_loader_tests");
+
+        let path = format!("{}/stages/empty.zip", mount);
+
+        write_archive(&path, &["readme.txt"]);
+
+        assert_eq!(resolve_definition_path(&path), None);
+        assert_eq!(resolve_definition_path(&format!("{}/stages/missing.zip", mount)), None);
+    }
+}
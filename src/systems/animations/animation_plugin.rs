@@ -0,0 +1,26 @@
+use std::sync::{Arc, RwLock};
+
+use bevy_app::{AppBuilder, Plugin};
+use bevy_ecs::prelude::*;
+
+use crate::{core::diagnostics, elements::animated_image::AnimatedSprite, systems::visual_server::{material::Material, palette::PaletteHandle, sprite::Sprite, texture::Texture, time::DeltaTime}};
+
+fn update_animated_sprite(
+    delta_time: Res<DeltaTime>,
+    mut query: Query<(&mut AnimatedSprite, &mut Sprite, &mut Arc<Texture>, &mut Option<PaletteHandle>, &Option<Arc<RwLock<Material>>>)>
+) {
+    for (mut animated_sprite, sprite, texture, palette, material) in query.iter_mut() {
+        if let Err(error) = animated_sprite.update(sprite, texture, palette, material, delta_time.0) {
+            diagnostics::error(error);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(update_animated_sprite.system());
+    }
+}
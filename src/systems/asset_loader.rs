@@ -0,0 +1,342 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, mpsc::{self, Receiver, Sender}};
+use std::thread;
+
+use bevy_app::{AppBuilder, CoreStage, Plugin};
+use bevy_ecs::prelude::*;
+use gdnative::godot_warn;
+
+use crate::core::{diagnostics, error::DataError};
+use crate::io::file_system::{self, FileSystem};
+
+const MAX_WORKERS: usize = 4;
+
+type ApplyAsset = Box<dyn FnOnce(&mut World) + Send>;
+type Job = Box<dyn FnOnce() -> LoadResult + Send>;
+
+struct LoadResult {
+    generation: u64,
+    description: String,
+    result: Result<ApplyAsset, DataError>,
+}
+
+// Shown by loading screens, counts the assets requested since the last reset
+#[derive(Default, Clone, Debug)]
+pub struct LoadingProgress {
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub current: String,
+}
+
+impl LoadingProgress {
+    pub fn is_done(&self) -> bool {
+        self.completed + self.failed >= self.total
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+
+        (self.completed + self.failed) as f32 / self.total as f32
+    }
+}
+
+// Loads assets on worker threads. Loading must not touch Godot objects, anything that needs
+// them is created when the result is applied to the world on the main thread. While a Godot
+// file system is mounted, like res:// in exported games, loads run on the main thread instead.
+pub struct AssetLoader {
+    generation: u64,
+    jobs: Option<Mutex<Sender<Job>>>,
+    results_sender: Mutex<Sender<LoadResult>>,
+    results: Mutex<Receiver<LoadResult>>,
+}
+
+impl AssetLoader {
+    pub fn new() -> Self {
+        let (results_sender, results) = mpsc::channel::<LoadResult>();
+        let (jobs_sender, jobs) = mpsc::channel::<Job>();
+        let jobs = Arc::new(Mutex::new(jobs));
+        let workers = thread::available_parallelism().map_or(1, |count| count.get()).min(MAX_WORKERS);
+        let mut spawned = 0;
+
+        for index in 0..workers {
+            let jobs = jobs.clone();
+            let results_sender = results_sender.clone();
+            let worker = thread::Builder::new()
+                .name(format!("asset-loader-{}", index))
+                .spawn(move || run_worker(jobs, results_sender));
+
+            if worker.is_ok() {
+                spawned += 1;
+            }
+        }
+
+        // Platforms without threads load everything on the main thread
+        if spawned == 0 {
+            godot_warn!("Could not start asset loader threads, loading on the main thread");
+        }
+
+        AssetLoader {
+            generation: 0,
+            jobs: if spawned > 0 { Some(Mutex::new(jobs_sender)) } else { None },
+            results_sender: Mutex::new(results_sender),
+            results: Mutex::new(results),
+        }
+    }
+
+    // Results of loads requested before the reset are discarded
+    pub fn reset(&mut self, progress: &mut LoadingProgress) {
+        self.generation += 1;
+        *progress = LoadingProgress::default();
+    }
+
+    pub fn load<T, L, A>(&self, progress: &mut LoadingProgress, description: &str, load: L, apply: A)
+    where
+        T: Send + 'static,
+        L: FnOnce() -> Result<T, DataError> + Send + 'static,
+        A: FnOnce(&mut World, T) + Send + 'static,
+    {
+        let generation = self.generation;
+        let description = description.to_string();
+
+        progress.total += 1;
+
+        let job: Job = Box::new(move || {
+            let result = match panic::catch_unwind(AssertUnwindSafe(load)) {
+                Ok(result) => result,
+                Err(_) => Err(DataError::new(format!("Loading panicked: {}", description))),
+            };
+
+            LoadResult {
+                generation,
+                description,
+                result: result.map(|asset| Box::new(move |world: &mut World| apply(world, asset)) as ApplyAsset),
+            }
+        });
+
+        let job = match &self.jobs {
+            Some(jobs) if file_system::get_file_system().is_thread_safe() => match jobs.lock().expect("Could not lock asset jobs").send(job) {
+                Ok(_) => return,
+                Err(error) => error.0,
+            },
+            _ => job,
+        };
+
+        self.send_result(job());
+    }
+
+    fn send_result(&self, result: LoadResult) {
+        let _ = self.results_sender.lock().expect("Could not lock asset results").send(result);
+    }
+
+    fn take_results(&self) -> Vec<LoadResult> {
+        let generation = self.generation;
+
+        self.results.lock()
+            .expect("Could not lock asset results")
+            .try_iter()
+            .filter(|result| result.generation == generation)
+            .collect()
+    }
+}
+
+impl Default for AssetLoader {
+    fn default() -> Self {
+        AssetLoader::new()
+    }
+}
+
+fn run_worker(jobs: Arc<Mutex<Receiver<Job>>>, results_sender: Sender<LoadResult>) {
+    loop {
+        let job = match jobs.lock().expect("Could not lock asset jobs").recv() {
+            Ok(job) => job,
+            Err(_) => break,
+        };
+
+        if results_sender.send(job()).is_err() {
+            break;
+        }
+    }
+}
+
+fn apply_loaded_assets(world: &mut World) {
+    let results = match world.get_resource::<AssetLoader>() {
+        Some(asset_loader) => asset_loader.take_results(),
+        None => return,
+    };
+
+    for load_result in results {
+        let succeeded = match load_result.result {
+            Ok(apply) => {
+                apply(world);
+                true
+            },
+            Err(error) => {
+                diagnostics::warn(match error.path {
+                    Some(_) => error,
+                    None => error.with_path(&load_result.description),
+                });
+                false
+            }
+        };
+
+        if let Some(mut progress) = world.get_resource_mut::<LoadingProgress>() {
+            if succeeded {
+                progress.completed += 1;
+            } else {
+                progress.failed += 1;
+            }
+
+            progress.current = load_result.description;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AssetLoaderPlugin;
+
+impl Plugin for AssetLoaderPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app
+            .insert_resource(AssetLoader::new())
+            .insert_resource(LoadingProgress::default())
+            .add_system_to_stage(CoreStage::PreUpdate, apply_loaded_assets.exclusive_system().at_start());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::io::godot_file_system::GodotFileSystem;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Loaded(Vec<i32>);
+
+    fn world(asset_loader: AssetLoader) -> World {
+        let mut world = World::new();
+
+        world.insert_resource(asset_loader);
+        world.insert_resource(LoadingProgress::default());
+        world.insert_resource(Loaded::default());
+        world
+    }
+
+    // Runs every load on the calling thread, so results are ready as soon as they are requested
+    fn inline_loader() -> AssetLoader {
+        let (results_sender, results) = mpsc::channel::<LoadResult>();
+
+        AssetLoader {
+            generation: 0,
+            jobs: None,
+            results_sender: Mutex::new(results_sender),
+            results: Mutex::new(results),
+        }
+    }
+
+    fn load(world: &mut World, description: &str, load: impl FnOnce() -> Result<i32, DataError> + Send + 'static) {
+        let world = world.cell();
+        let asset_loader = world.get_resource::<AssetLoader>().unwrap();
+        let mut progress = world.get_resource_mut::<LoadingProgress>().unwrap();
+
+        asset_loader.load(&mut progress, description, load, |world, value| {
+            world.get_resource_mut::<Loaded>().unwrap().0.push(value);
+        });
+    }
+
+    fn wait_until_done(world: &mut World) {
+        let start = Instant::now();
+
+        loop {
+            apply_loaded_assets(world);
+
+            if world.get_resource::<LoadingProgress>().unwrap().is_done() {
+                break;
+            }
+
+            assert!(start.elapsed() < Duration::from_secs(10), "assets were not loaded in time");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn counts_completed_and_failed_loads() {
+        let mut world = world(AssetLoader::new());
+
+        for value in 0..3 {
+            load(&mut world, &format!("asset {}", value), move || Ok(value));
+        }
+
+        load(&mut world, "missing asset", || Err(DataError::new("Missing".to_string())));
+        wait_until_done(&mut world);
+
+        let progress = world.get_resource::<LoadingProgress>().unwrap();
+        let mut loaded = world.get_resource::<Loaded>().unwrap().0.clone();
+        loaded.sort_unstable();
+
+        assert_eq!((progress.total, progress.completed, progress.failed), (4, 3, 1));
+        assert_eq!(progress.fraction(), 1.0);
+        assert_eq!(loaded, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn reset_discards_older_loads() {
+        let mut world = world(inline_loader());
+
+        load(&mut world, "old asset", || Ok(1));
+
+        {
+            let world = world.cell();
+            let mut asset_loader = world.get_resource_mut::<AssetLoader>().unwrap();
+            let mut progress = world.get_resource_mut::<LoadingProgress>().unwrap();
+
+            asset_loader.reset(&mut progress);
+        }
+
+        load(&mut world, "new asset", || Ok(2));
+        apply_loaded_assets(&mut world);
+
+        let progress = world.get_resource::<LoadingProgress>().unwrap();
+
+        assert_eq!((progress.total, progress.completed, progress.failed), (1, 1, 0));
+        assert_eq!(progress.current, "new asset");
+        assert_eq!(world.get_resource::<Loaded>().unwrap().0, vec![2]);
+    }
+
+    #[test]
+    fn panicking_loads_are_reported_as_errors() {
+        let mut world = world(inline_loader());
+
+        load(&mut world, "asset_loader_tests/panicking.def", || panic!("Corrupt asset"));
+        apply_loaded_assets(&mut world);
+
+        let progress = world.get_resource::<LoadingProgress>().unwrap();
+        let report = diagnostics::get_diagnostics().report();
+
+        assert_eq!((progress.completed, progress.failed), (0, 1));
+        assert!(world.get_resource::<Loaded>().unwrap().0.is_empty());
+        assert!(report.entries.iter().any(|entry| entry.error.message.contains("Loading panicked: asset_loader_tests/panicking.def")));
+    }
+
+    #[test]
+    fn godot_mounts_load_on_the_main_thread() {
+        const MOUNT: &str = "godot://asset_loader_tests";
+
+        let mut world = world(AssetLoader::new());
+        let main_thread = thread::current().id();
+
+        file_system::get_file_system().mount(MOUNT, Arc::new(GodotFileSystem::new("res://")));
+        load(&mut world, "godot asset", move || Ok((thread::current().id() == main_thread) as i32));
+        file_system::get_file_system().unmount(MOUNT);
+
+        // Inline loads have their result queued before load returns
+        apply_loaded_assets(&mut world);
+
+        assert_eq!(world.get_resource::<LoadingProgress>().unwrap().completed, 1);
+        assert_eq!(world.get_resource::<Loaded>().unwrap().0, vec![1]);
+    }
+}
@@ -3,7 +3,7 @@ use bevy_ecs::prelude::*;
 use bevy_transform::hierarchy::BuildChildren;
 use gdnative::{api::{visual_server::{TextureFlags, PrimitiveType}, SurfaceTool}, core_types::{Point2, Vector2, Color, Rect2, Size2, Vector3, Transform2D, ToVariant}, godot_print};
 
-use crate::{core::{error::DataError, sprite_id::SpriteId}, drawing::{sprite_system::SpriteSystem}, systems::visual_server::{sprite::{Sprite, SpriteBundle}, text::{text_plugin::{TextBundle}, common::{TextStyle, Text, TextAlignment, HorizontalAlign}, font_loader::load_dynamic_font}, shader::Shader, material::Material, mesh_2d::{Mesh2dBundle, Mesh2d}, canvas_item::{ClipRect, ZIndex}}, audio::{snd_parser::read_sounds, structs::WavSound}, io::file_system};
+use crate::{core::{error::DataError, sprite_id::SpriteId}, drawing::{sprite_system::SpriteSystem}, systems::visual_server::{sprite::{Sprite, SpriteBundle}, text::{text_plugin::{TextBundle}, common::{TextStyle, Text, TextAlignment, HorizontalAlign}, font_loader::load_dynamic_font}, shader::Shader, material::Material, palette::PaletteHandle, mesh_2d::{Mesh2dBundle, Mesh2d}, canvas_item::{ClipRect, ZIndex}}, audio::{snd_parser::read_sounds, structs::WavSound}, io::file_system};
 
 use super::{log::handle_error, visual_server::{canvas_item::{Visible}}, input::Input, audio_server::audio::Audio};
 
@@ -18,14 +18,13 @@ fn setup(
     let mut sprite_file = sprite_system.get_sprite_file("res://data/data/system.sff")?;
     let sff_data = sprite_file.get_sprite(&SpriteId::new(0, 0))?;
     let texture = sff_data.create_monochromatic_texture(TextureFlags(0));
-    let palette_texture = sff_data.create_palette_texture(None);
     let size = texture.size;
     let offset = Point2::new(sff_data.x as f32, sff_data.y as f32);
     let sprite_shader_code = file_system::open_file_as_string("res://resources/sprite.glsl")?;
     let shader = Shader::allocate(&sprite_shader_code);
+    let palette = PaletteHandle::new(shader.clone(), sff_data.select_palette(None));
     let material = Material::allocate(shader);
     let mut material_write = material.write().expect("Could not lock material");
-    material_write.set_shader_texture("palette", palette_texture.clone());
     material_write.set_shader_param("blend_type", 1.to_variant());
     material_write.set_shader_param("blend_source", 1.0.to_variant());
     material_write.set_shader_param("blend_destination", 1.0.to_variant());
@@ -41,6 +40,7 @@ fn setup(
         },
         transform: Transform2D::translation(100.0, 100.0),
         material: Some(material.clone()),
+        palette: Some(palette),
         ..Default::default()
     }).with_children(|parent| {
         parent.spawn_bundle(SpriteBundle {
@@ -96,7 +96,7 @@ fn setup(
     //     text: Text::new(
     //         "ABC TEST\nSecond Line",
     //         TextStyle {
-    //             font: bitmap_font.get_color_bank(1),
+    //             font: bitmap_font.get_font(),
     //             font_size: bitmap_font.size,
     //             ..Default::default()
     //         },
@@ -0,0 +1,75 @@
+use bevy_app::{AppBuilder, CoreStage, EventWriter, Plugin};
+use bevy_ecs::prelude::*;
+use gdnative::api::OS;
+
+use crate::{core::constants::DATA_PATH, io::file_watcher::FileWatcher};
+
+use super::visual_server::time::DeltaTime;
+
+const POLL_INTERVAL: f64 = 1.0;
+
+pub struct HotReload {
+    pub enabled: bool,
+    watcher: FileWatcher,
+    elapsed: f64,
+}
+
+impl HotReload {
+    pub fn new(enabled: bool) -> Self {
+        HotReload {
+            enabled,
+            watcher: FileWatcher::default(),
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn watch(&mut self, filepath: &str) {
+        if self.enabled && filepath.starts_with(DATA_PATH) {
+            self.watcher.watch(filepath);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.watcher.clear();
+    }
+}
+
+pub struct FilesChangedEvent {
+    pub paths: Vec<String>,
+}
+
+fn poll_files(
+    delta_time: Res<DeltaTime>,
+    mut hot_reload: ResMut<HotReload>,
+    mut files_changed_event: EventWriter<FilesChangedEvent>,
+) {
+    if !hot_reload.enabled {
+        return;
+    }
+
+    hot_reload.elapsed += delta_time.0;
+
+    if hot_reload.elapsed < POLL_INTERVAL {
+        return;
+    }
+
+    hot_reload.elapsed = 0.0;
+
+    let paths = hot_reload.watcher.poll();
+
+    if !paths.is_empty() {
+        files_changed_event.send(FilesChangedEvent { paths });
+    }
+}
+
+#[derive(Default)]
+pub struct HotReloadPlugin;
+
+impl Plugin for HotReloadPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app
+            .insert_resource(HotReload::new(OS::godot_singleton().is_debug_build()))
+            .add_event::<FilesChangedEvent>()
+            .add_system_to_stage(CoreStage::First, poll_files.system());
+    }
+}
@@ -1,10 +1,18 @@
 use bevy_ecs::prelude::*;
-use gdnative::godot_error;
+use gdnative::{godot_error, godot_warn};
 
-use crate::core::error::DataError;
+use crate::core::{diagnostics::Diagnostics, error::DataError};
 
 pub fn handle_error(In(result): In<Result<(), DataError>>) {
     if let Err(e) = result {
         godot_error!("{}", e);
     }
 }
+
+pub fn print_diagnostics(diagnostics: Res<Diagnostics>) {
+    let report = diagnostics.take_report();
+
+    if !report.is_empty() {
+        godot_warn!("{}", report);
+    }
+}
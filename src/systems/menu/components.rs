@@ -1,3 +1,5 @@
 use crate::audio::sound_manager::SoundManager;
 
 pub struct MenuSoundManager(pub SoundManager);
+
+pub struct MenuReloadedEvent;
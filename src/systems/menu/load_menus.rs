@@ -1,71 +1,162 @@
 use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use bevy_app::{EventReader, EventWriter};
 use bevy_ecs::prelude::*;
-use gdnative::core_types::Size2;
 
 use crate::animations::animation_loader::AnimationLoader;
 use crate::animations::animation_manager::AnimationManager;
-use crate::audio::sound_manager::SoundManager;
+use crate::audio::{snd_parser::read_sound_data, sound_manager::SoundManager};
 use crate::core::configuration::Configuration;
 use crate::drawing::font_map::FontMap;
 use crate::drawing::mugen_font::MugenFont;
 use crate::menus::menu_data::MenuData;
 use crate::menus::select_screen::SelectScreen;
 use crate::menus::title_screen::TitleScreen;
-use crate::profiles::profile_loader::ProfileLoader;
+use crate::profiles::{player_profile::PlayerProfile, profile_loader::ProfileLoader};
 use crate::systems::visual_server::shader::Shader;
-use crate::{core::{constants::{MUGEN_10_SYSTEM_PATH, MUGEN_11_SYSTEM_PATH}, error::DataError}, drawing::sprite_system::SpriteSystem, io::{file_system, text_file::TextFile}};
+use crate::systems::asset_loader::{AssetLoader, LoadingProgress};
+use crate::systems::hot_reload::{FilesChangedEvent, HotReload};
+use crate::{core::{constants::{DATA_PATH, MUGEN_10_SYSTEM_PATH, MUGEN_11_SYSTEM_PATH}, error::DataError}, drawing::sprite_system::SpriteSystem, io::{file_system, text_file::TextFile}};
 
-use super::components::MenuSoundManager;
+use super::components::{MenuReloadedEvent, MenuSoundManager};
 
 pub fn load_menus(
     mut commands: Commands,
+    mut hot_reload: ResMut<HotReload>,
+    mut asset_loader: ResMut<AssetLoader>,
+    mut loading_progress: ResMut<LoadingProgress>,
     sprite_system: Res<SpriteSystem>,
 ) -> Result<(), DataError> {
     let sprite_shader_code = file_system::open_file_as_string("res://resources/sprite.glsl")?;
     let sprite_shader = Shader::allocate(&sprite_shader_code);
-    let configuration = Configuration {
-        screen_size: Size2::new(1280.0, 720.0),
-        sprite_shader
-    };
+    let configuration = Configuration::load(sprite_shader)?;
 
+    // Replaced once the sounds are loaded
+    commands.insert_resource(MenuSoundManager(SoundManager::default()));
+
+    insert_menu_resources(&mut commands, &mut hot_reload, &mut asset_loader, &mut loading_progress, &configuration, &sprite_system)?;
+
+    commands.insert_resource(configuration);
+
+    Ok(())
+}
+
+pub fn reload_menus(
+    mut commands: Commands,
+    mut files_changed_event: EventReader<FilesChangedEvent>,
+    mut menu_reloaded_event: EventWriter<MenuReloadedEvent>,
+    mut hot_reload: ResMut<HotReload>,
+    mut asset_loader: ResMut<AssetLoader>,
+    mut loading_progress: ResMut<LoadingProgress>,
+    configuration: Res<Configuration>,
+    sprite_system: Res<SpriteSystem>,
+) -> Result<(), DataError> {
+    let paths: Vec<String> = files_changed_event.iter()
+        .flat_map(|event| event.paths.iter().cloned())
+        .collect();
+
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    insert_menu_resources(&mut commands, &mut hot_reload, &mut asset_loader, &mut loading_progress, &configuration, &sprite_system)?;
+
+    menu_reloaded_event.send(MenuReloadedEvent);
+
+    Ok(())
+}
+
+fn insert_menu_resources(
+    commands: &mut Commands,
+    hot_reload: &mut HotReload,
+    asset_loader: &mut AssetLoader,
+    loading_progress: &mut LoadingProgress,
+    configuration: &Configuration,
+    sprite_system: &SpriteSystem,
+) -> Result<(), DataError> {
     let textfile = load_text_file()?;
-    let menu_data = load_menu_data(&sprite_system, &textfile)?;
-    let mut sprite_file = sprite_system.get_sprite_file(&menu_data.sprite_path)?;
+    let menu_data = load_menu_data(sprite_system, configuration, &textfile)?;
+    let sprite_file = Arc::new(RwLock::new(sprite_system.get_sprite_file(&menu_data.sprite_path)?));
     let animation_loader = AnimationLoader::new();
     let animations = animation_loader.load_animations(&menu_data.anim_path)?;
     let animation_manager = AnimationManager::new(&menu_data.anim_path, animations);
 
     // Screens
     let title_screen = TitleScreen::build(
-        &configuration,
+        configuration,
         &textfile,
-        &mut sprite_file,
+        &sprite_file,
         &animation_manager
     )?;
 
     let select_screen = SelectScreen::build(
-        &configuration,
+        configuration,
         &textfile,
-        &mut sprite_file,
+        &sprite_file,
         &animation_manager
     )?;
 
-    let profile_loader = ProfileLoader::build(&select_screen, &sprite_system)?;
+    let mut profile_loader = ProfileLoader::build(&select_screen)?;
+
+    asset_loader.reset(loading_progress);
+
+    for request in profile_loader.take_pending_profiles() {
+        let sprite_system = sprite_system.clone();
+        let index = request.index;
+        let description = request.definition_path.clone();
+
+        asset_loader.load(
+            loading_progress,
+            &description,
+            move || PlayerProfile::build(&request.definition_path, &request.stage_path, &sprite_system),
+            move |world, profile| {
+                if let Some(mut profile_loader) = world.get_resource_mut::<ProfileLoader>() {
+                    profile_loader.set_player_profile(index, profile);
+                }
+            }
+        );
+    }
+
+    let sound_path = menu_data.sound_path.clone();
 
-    let sound_manager = SoundManager::load(&menu_data.sound_path)?;
+    asset_loader.load(
+        loading_progress,
+        &menu_data.sound_path,
+        move || read_sound_data(&sound_path),
+        |world, sound_data| {
+            world.insert_resource(MenuSoundManager(SoundManager::from_sound_data(&sound_data)));
+        }
+    );
+
+    hot_reload.clear();
+    hot_reload.watch(&textfile.filepath);
+    hot_reload.watch(&menu_data.sprite_path);
+    hot_reload.watch(&menu_data.sound_path);
+    hot_reload.watch(&file_system::combine_paths(DATA_PATH, "data/select.def"));
+
+    for font_path in menu_data.font_paths.iter() {
+        hot_reload.watch(font_path);
+    }
 
     commands.insert_resource(profile_loader);
-    commands.insert_resource(MenuSoundManager(sound_manager));
     commands.insert_resource(menu_data);
     commands.insert_resource(title_screen);
     commands.insert_resource(select_screen);
-    commands.insert_resource(configuration);
+
+    Ok(())
+}
+
+pub fn save_configuration(configuration: Res<Configuration>) -> Result<(), DataError> {
+    if configuration.is_changed() && !configuration.is_added() {
+        configuration.save()?;
+    }
 
     Ok(())
 }
 
 fn load_menu_data(
-    sprite_system: &Res<SpriteSystem>,
+    sprite_system: &SpriteSystem,
+    configuration: &Configuration,
     text_file: &TextFile
 ) -> Result<MenuData, DataError> {
     let info = text_file.get_section("info")?;
@@ -74,29 +165,31 @@ fn load_menu_data(
     let motif_name = info.get_attribute_or_default::<String>("name");
     let motif_author = info.get_attribute_or_default::<String>("author");
     let mut font_hash_map = HashMap::<usize, MugenFont>::new();
+    let mut font_paths = Vec::new();
 
     for i in 1..32 as usize {
         if let Some(path) = files.get_attribute::<String>(&format!("font{}", i)) {
             let font_path = format!("font/{}", &path);
-            let font_path = file_system::get_path_by_refferrer(&font_path, &text_file.filepath);
-            let font = sprite_system.load_font(&font_path)?;
+            let font_path = file_system::find_path_by_refferrer(&font_path, &text_file.filepath)?;
+            let font = sprite_system.load_font(&font_path, configuration.sprite_shader.clone())?;
             font_hash_map.insert(i, font);
+            font_paths.push(font_path);
         }
     }
 
     let font_map = FontMap::new(font_hash_map);
 
-    let sound_path = file_system::get_path_by_refferrer(
+    let sound_path = file_system::find_path_by_refferrer(
         &files.get_attribute::<String>("snd")
                 .ok_or_else(|| DataError::new("Missing Files snd attribute".into()))?,
             &text_file.filepath,
-    );
+    )?;
 
-    let sprite_path = file_system::get_path_by_refferrer(
+    let sprite_path = file_system::find_path_by_refferrer(
         &files.get_attribute::<String>("spr")
                 .ok_or_else(|| DataError::new("Missing Files snd attribute".into()))?,
             &text_file.filepath,
-    );
+    )?;
 
     let anim_path = text_file.filepath.clone();
 
@@ -104,6 +197,7 @@ fn load_menu_data(
         motif_name,
         motif_author,
         font_map,
+        font_paths,
         sound_path,
         sprite_path,
         anim_path
@@ -1,9 +1,9 @@
 use bevy_ecs::prelude::*;
-use bevy_app::{AppBuilder, Plugin, StartupStage};
+use bevy_app::{AppBuilder, CoreStage, Plugin, StartupStage};
 
-use crate::{systems::log::handle_error, menus::menu_state::MenuState, core::enumerations::CombatMode};
+use crate::{systems::log::{handle_error, print_diagnostics}, menus::menu_state::MenuState, core::enumerations::CombatMode};
 
-use super::{load_menus::load_menus, title_screen_systems::TitleScreenPlugin, setup_layers::setup_layers, select_screen_systems::SelectScreenPlugin};
+use super::{load_menus::{load_menus, reload_menus, save_configuration}, components::MenuReloadedEvent, title_screen_systems::TitleScreenPlugin, setup_layers::setup_layers, select_screen_systems::SelectScreenPlugin};
 
 #[derive(Default)]
 pub struct MenuPlugin;
@@ -13,8 +13,13 @@ impl Plugin for MenuPlugin {
         app
             .add_state(MenuState::Title)
             .add_state(CombatMode::None)
+            .add_event::<MenuReloadedEvent>()
             .add_startup_system_to_stage(StartupStage::PreStartup, load_menus.system().chain(handle_error.system()))
             .add_startup_system(setup_layers.system())
+            .add_startup_system_to_stage(StartupStage::PostStartup, print_diagnostics.system())
+            .add_system_to_stage(CoreStage::PreUpdate, reload_menus.system().chain(handle_error.system()))
+            .add_system_to_stage(CoreStage::PostUpdate, print_diagnostics.system())
+            .add_system(save_configuration.system().chain(handle_error.system()))
             .add_plugin(TitleScreenPlugin::default())
             .add_plugin(SelectScreenPlugin::default());
     }
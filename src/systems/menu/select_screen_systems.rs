@@ -1,11 +1,13 @@
-use bevy_app::{AppBuilder, Plugin, EventWriter};
+use std::sync::Arc;
+
+use bevy_app::{AppBuilder, Plugin, EventReader, EventWriter};
 use bevy_ecs::prelude::*;
 use bevy_transform::{hierarchy::{BuildChildren, DespawnRecursiveExt}, components::Parent};
-use gdnative::{core_types::{Transform2D, Point2}, api::visual_server::TextureFlags};
+use gdnative::{core_types::{Transform2D, Point2, Size2}, api::visual_server::TextureFlags};
 
-use crate::{menus::{menu_state::MenuState, select_screen::SelectScreen}, systems::{backgrounds::events::BackgroundGroupEvent, visual_server::{canvas_item::CanvasItemBundle, sprite::{SpriteBundle, Sprite}}}, core::{constants, sprite_id::SpriteId, configuration::{Configuration, ScaleForScreen}}, profiles::profile_loader::ProfileLoader};
+use crate::{menus::{menu_state::MenuState, select_screen::SelectScreen}, systems::{asset_loader::LoadingProgress, backgrounds::events::BackgroundGroupEvent, input::Input, visual_server::{canvas_item::{CanvasItemBundle, Visible}, sprite::{SpriteBundle, Sprite}, palette::{PaletteCache, PaletteHandle}, texture::Texture}}, core::{constants, diagnostics, enumerations::CombatMode, sprite_id::SpriteId, configuration::{Configuration, ScaleForScreen}}, profiles::profile_loader::ProfileLoader, drawing::{texture_atlas::TextureAtlasBuilder, palette_manager::PaletteManager, sff::sff_common::SffData}};
 
-use super::setup_layers::HudLayer;
+use super::{setup_layers::HudLayer, components::MenuReloadedEvent};
 
 #[derive(Default)]
 pub struct SelectScreenPlugin;
@@ -13,15 +15,104 @@ pub struct SelectScreenPlugin;
 #[derive(Default)]
 struct ScreenMarker;
 
+const PLAYER_ACTIONS: [&str; 2] = ["P1", "P2"];
+
+// a, b, c, x, y and z pick palettes 1 to 6, holding start picks 7 to 12
+const PALETTE_BUTTONS: [&str; 6] = ["a", "b", "c", "x", "y", "z"];
+
+struct PlayerCursor {
+    player: usize,
+    cell: (i32, i32),
+    selected: bool,
+}
+
+// Cursor sprites swap from the active to the done image once the player picks a character
+struct CursorImage {
+    player: usize,
+    done: bool,
+}
+
+struct PlayerFace {
+    player: usize,
+    shown: Option<ShownFace>,
+}
+
+struct ShownFace {
+    definition_path: String,
+    palette: usize,
+    sff: Option<SffData>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum CellImage {
+    Background,
+    Portrait(i32, i32),
+}
+
 fn show_screen(
     mut commands: Commands,
     mut background_group_event: EventWriter<BackgroundGroupEvent>,
     mut profile_loader: ResMut<ProfileLoader>,
+    mut palette_manager: ResMut<PaletteManager>,
     configuration: Res<Configuration>,
+    combat_mode: Res<State<CombatMode>>,
     hud_layer_query: Query<Entity, With<HudLayer>>,
     select_screen: Res<SelectScreen>
 ) {
     let hud_entity = hud_layer_query.single().expect("HudLayer not found");
+
+    spawn_screen(&mut commands, &mut background_group_event, &mut profile_loader, &mut palette_manager, &configuration, player_count(&combat_mode), hud_entity, &select_screen);
+}
+
+fn respawn_screen(
+    mut commands: Commands,
+    mut menu_reloaded_event: EventReader<MenuReloadedEvent>,
+    mut background_group_event: EventWriter<BackgroundGroupEvent>,
+    mut profile_loader: ResMut<ProfileLoader>,
+    mut palette_manager: ResMut<PaletteManager>,
+    configuration: Res<Configuration>,
+    combat_mode: Res<State<CombatMode>>,
+    loading_progress: Res<LoadingProgress>,
+    hud_layer_query: Query<Entity, With<HudLayer>>,
+    screen_query: Query<Entity, With<ScreenMarker>>,
+    select_screen: Res<SelectScreen>
+) {
+    // Portraits of profiles loaded after the screen was shown
+    let finished_loading = loading_progress.is_changed()
+        && loading_progress.is_done()
+        && screen_query.iter().next().is_some();
+
+    if menu_reloaded_event.iter().count() == 0 && !finished_loading {
+        return;
+    }
+
+    for entity in screen_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let hud_entity = hud_layer_query.single().expect("HudLayer not found");
+
+    spawn_screen(&mut commands, &mut background_group_event, &mut profile_loader, &mut palette_manager, &configuration, player_count(&combat_mode), hud_entity, &select_screen);
+}
+
+fn player_count(combat_mode: &State<CombatMode>) -> usize {
+    match combat_mode.current() {
+        CombatMode::Versus | CombatMode::TeamVersus | CombatMode::TeamCoop | CombatMode::SurvivalCoop => 2,
+        _ => 1,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_screen(
+    commands: &mut Commands,
+    background_group_event: &mut EventWriter<BackgroundGroupEvent>,
+    profile_loader: &mut ProfileLoader,
+    palette_manager: &mut PaletteManager,
+    configuration: &Configuration,
+    players: usize,
+    hud_entity: Entity,
+    select_screen: &SelectScreen
+) {
     let background_group = &select_screen.non_combat_screen.background_group;
 
     let screen_entity = commands.spawn_bundle(CanvasItemBundle::default())
@@ -38,51 +129,328 @@ fn show_screen(
     });
 
     let cellbg = &select_screen.cellbg;
+    let mut atlas_builder = TextureAtlasBuilder::new();
+    let mut palette_cache = PaletteCache::new(configuration.sprite_shader.clone());
+    let mut portraits = Vec::new();
+    let mut cell_palette = None;
+
+    if let Some(sff) = &cellbg.sff {
+        atlas_builder.add_sprite(CellImage::Background, sff);
+
+        if sff.image.is_indexed() {
+            cell_palette = Some(palette_cache.get_or_create(&sff.select_palette(None)));
+        }
+    }
 
     for y in 0..select_screen.rows {
         for x in 0..select_screen.columns {
-            let mut location = select_screen.grid_position;
-            location.x += (select_screen.cellsize.x + select_screen.cellspacing as f32) * x as f32;
-            location.y += (select_screen.cellsize.y + select_screen.cellspacing as f32) * y as f32;
-
-            let cell_texture = cellbg.sff.create_texture(None, TextureFlags(0)).unwrap();
-            let sff_offset = cellbg.sff.offset();
-
-            commands.spawn_bundle(SpriteBundle {
-                texture: cell_texture.clone(),
-                sprite: Sprite {
-                    size: cell_texture.size,
-                    offset: Point2::new(sff_offset.x + cellbg.offset.x, sff_offset.y + cellbg.offset.y),
-                    ..Default::default()
-                },
-                transform: Transform2D::translation(location.x, location.y),
-                ..Default::default()
-            }).insert(Parent(screen_entity));
-
-            if let Some(select) = profile_loader.get_player_on_grid((x, y)) {
-                let profile = select.profile.unwrap();
+            // Random cells and profiles still loading have no portrait
+            if let Some(profile) = profile_loader.get_player_on_grid((x, y)).and_then(|select| select.profile) {
                 let mut sprite_file = profile.sprite_file.write().unwrap();
+
                 if let Ok(small_portrait) = sprite_file.get_sprite(&SpriteId::SMALL_PORTRAIT) {
-                    let small_portrait_texture = small_portrait.create_texture(None, TextureFlags(0)).unwrap();
-
-                    commands.spawn_bundle(SpriteBundle {
-                        texture: small_portrait_texture.clone(),
-                        sprite: Sprite {
-                            size: small_portrait_texture.size.scale_for_screen(&configuration, profile.localcoord),
-                            offset: small_portrait.offset().scale_for_screen(&configuration, profile.localcoord),
-                            ..Default::default()
-                        },
-                        transform: Transform2D::translation(location.x, location.y),
-                        ..Default::default()
-                    }).insert(Parent(screen_entity));
+                    let palette = if small_portrait.image.is_indexed() {
+                        Some(palette_cache.get_or_create(&small_portrait.select_palette(None)))
+                    } else {
+                        None
+                    };
+
+                    atlas_builder.add_sprite(CellImage::Portrait(x, y), &small_portrait);
+                    portraits.push((x, y, small_portrait.offset(), profile.localcoord, palette));
                 }
             }
         }
     }
 
+    let atlas = atlas_builder.build(TextureFlags(0));
+
+    // Only a sprite can be packed, animated cell backgrounds aren't supported
+    if let (Some(sff), Some((cell_texture, cell_rect))) = (&cellbg.sff, atlas.get(&CellImage::Background)) {
+        let sff_offset = sff.offset();
+
+        for y in 0..select_screen.rows {
+            for x in 0..select_screen.columns {
+                let location = cell_location(select_screen, x, y);
+
+                commands.spawn_bundle(SpriteBundle {
+                    texture: cell_texture.clone(),
+                    sprite: Sprite {
+                        size: cell_rect.size,
+                        rect: Some(cell_rect),
+                        offset: Point2::new(sff_offset.x + cellbg.offset.x, sff_offset.y + cellbg.offset.y),
+                        ..Default::default()
+                    },
+                    transform: Transform2D::translation(location.x, location.y),
+                    palette: cell_palette.clone(),
+                    ..Default::default()
+                }).insert(Parent(screen_entity));
+            }
+        }
+    }
+
+    for (x, y, offset, localcoord, palette) in portraits {
+        let location = cell_location(select_screen, x, y);
+        let (portrait_texture, portrait_rect) = atlas.get(&CellImage::Portrait(x, y)).expect("Portrait not packed");
+
+        commands.spawn_bundle(SpriteBundle {
+            texture: portrait_texture,
+            sprite: Sprite {
+                size: portrait_rect.size.scale_for_screen(configuration, localcoord),
+                rect: Some(portrait_rect),
+                offset: offset.scale_for_screen(configuration, localcoord),
+                ..Default::default()
+            },
+            transform: Transform2D::translation(location.x, location.y),
+            palette,
+            ..Default::default()
+        }).insert(Parent(screen_entity));
+    }
+
+    // Selections start over with the cursors
+    for (player, info) in select_screen.players.iter().enumerate().take(players) {
+        let cell = info.start_cell();
+        let location = cell_location(select_screen, cell.0, cell.1);
+
+        palette_manager.deselect(player);
+
+        let cursor_entity = commands.spawn_bundle(CanvasItemBundle {
+            transform: Transform2D::translation(location.x, location.y),
+            ..Default::default()
+        })
+            .insert(PlayerCursor { player, cell, selected: false })
+            .insert(Parent(screen_entity))
+            .id();
+
+        let images = [(&select_screen.cursoractive[player], false), (&select_screen.cursordone[player], true)];
+
+        for (element, done) in images.iter() {
+            match element.spawn(commands, &mut palette_cache, configuration.sprite_shader.clone()) {
+                Ok(Some(image_entity)) => {
+                    commands.entity(image_entity)
+                        .insert(CursorImage { player, done: *done })
+                        .insert(Visible { is_visible: !*done })
+                        .insert(Parent(cursor_entity));
+                },
+                Ok(None) => {},
+                Err(error) => diagnostics::warn(error),
+            }
+        }
+
+        commands.spawn_bundle(SpriteBundle {
+            transform: Transform2D::translation(info.faceoffset.x, info.faceoffset.y),
+            ..Default::default()
+        })
+            .insert(PlayerFace { player, shown: None })
+            .insert(Parent(screen_entity));
+    }
+
     commands.entity(hud_entity).push_children(&[screen_entity]);
 }
 
+fn move_cell(select_screen: &SelectScreen, cell: (i32, i32), direction: (i32, i32)) -> (i32, i32) {
+    let columns = select_screen.columns.max(1);
+    let rows = select_screen.rows.max(1);
+    let x = cell.0 + direction.0;
+    let y = cell.1 + direction.1;
+
+    if select_screen.wrapping {
+        (x.rem_euclid(columns), y.rem_euclid(rows))
+    } else {
+        (x.min(columns - 1).max(0), y.min(rows - 1).max(0))
+    }
+}
+
+fn move_cursors(
+    input: Res<Input>,
+    select_screen: Res<SelectScreen>,
+    mut query: Query<(&mut PlayerCursor, &mut Transform2D)>
+) {
+    for (mut cursor, mut transform) in query.iter_mut() {
+        if cursor.selected {
+            continue;
+        }
+
+        let prefix = PLAYER_ACTIONS[cursor.player];
+        let mut direction = (0, 0);
+
+        if input.just_pressed(&format!("{}_U", prefix)) { direction.1 -= 1; }
+        if input.just_pressed(&format!("{}_D", prefix)) { direction.1 += 1; }
+        if input.just_pressed(&format!("{}_B", prefix)) { direction.0 -= 1; }
+        if input.just_pressed(&format!("{}_F", prefix)) { direction.0 += 1; }
+
+        let cell = move_cell(&select_screen, cursor.cell, direction);
+
+        if cell != cursor.cell {
+            let location = cell_location(&select_screen, cell.0, cell.1);
+
+            cursor.cell = cell;
+            *transform = Transform2D::translation(location.x, location.y);
+        }
+    }
+}
+
+fn select_players(
+    input: Res<Input>,
+    mut profile_loader: ResMut<ProfileLoader>,
+    mut palette_manager: ResMut<PaletteManager>,
+    mut query: Query<&mut PlayerCursor>
+) {
+    for mut cursor in query.iter_mut() {
+        if cursor.selected {
+            continue;
+        }
+
+        let prefix = PLAYER_ACTIONS[cursor.player];
+        let button = PALETTE_BUTTONS.iter()
+            .position(|button| input.just_pressed(&format!("{}_{}", prefix, button)));
+
+        let button = match button {
+            Some(button) => button,
+            None => continue,
+        };
+
+        let palette = if input.pressed(&format!("{}_s", prefix)) { button + 7 } else { button + 1 };
+
+        // Random cells and profiles still loading can't be picked yet
+        let profile = match profile_loader.get_player_on_grid(cursor.cell).and_then(|select| select.profile) {
+            Some(profile) => profile,
+            None => continue,
+        };
+
+        match palette_manager.select(cursor.player, &profile, palette) {
+            Ok(_) => cursor.selected = true,
+            Err(error) => diagnostics::warn(error),
+        }
+    }
+}
+
+fn update_cursor_images(
+    cursor_query: Query<&PlayerCursor, Changed<PlayerCursor>>,
+    mut image_query: Query<(&CursorImage, &mut Visible)>
+) {
+    for cursor in cursor_query.iter() {
+        for (image, mut visible) in image_query.iter_mut() {
+            let is_visible = image.done == cursor.selected;
+
+            if image.player == cursor.player && visible.is_visible != is_visible {
+                visible.is_visible = is_visible;
+            }
+        }
+    }
+}
+
+// Big portrait of the character under the cursor, drawn with the palette the player picked
+fn update_faces(
+    configuration: Res<Configuration>,
+    select_screen: Res<SelectScreen>,
+    mut profile_loader: ResMut<ProfileLoader>,
+    mut palette_manager: ResMut<PaletteManager>,
+    cursor_query: Query<&PlayerCursor>,
+    mut face_query: Query<(&mut PlayerFace, &mut Sprite, &mut Arc<Texture>, &mut Option<PaletteHandle>)>
+) {
+    for (mut face, mut sprite, mut texture, mut palette) in face_query.iter_mut() {
+        let cursor = match cursor_query.iter().find(|cursor| cursor.player == face.player) {
+            Some(cursor) => cursor,
+            None => continue,
+        };
+
+        let profile = match profile_loader.get_player_on_grid(cursor.cell).and_then(|select| select.profile) {
+            Some(profile) => profile,
+            None => {
+                if face.shown.is_some() {
+                    face.shown = None;
+                    *texture = Arc::new(Texture::invalid());
+                    *palette = None;
+                }
+
+                continue;
+            }
+        };
+
+        let palettes = match palette_manager.load(&profile) {
+            Ok(palettes) => palettes,
+            Err(error) => {
+                diagnostics::warn(error);
+                continue;
+            }
+        };
+
+        let number = match palette_manager.get_selection(face.player) {
+            Some(selection) if cursor.selected => selection.palette,
+            _ => palettes.default_palette(),
+        };
+
+        if let Some(shown) = face.shown.as_mut() {
+            if shown.definition_path == profile.definition_path {
+                if shown.palette != number {
+                    if let (Some(handle), Some(sff)) = (palette.as_ref(), shown.sff.as_ref()) {
+                        palettes.apply(handle, sff, number);
+                    }
+
+                    shown.palette = number;
+                }
+
+                continue;
+            }
+        }
+
+        let sff = profile.sprite_file
+            .write()
+            .expect("Could not lock sprite file")
+            .get_sprite(&SpriteId::BIG_PORTRAIT);
+
+        let sff = match sff {
+            Ok(sff) => sff,
+            Err(error) => {
+                diagnostics::warn(error);
+
+                *texture = Arc::new(Texture::invalid());
+                *palette = None;
+                face.shown = Some(ShownFace { definition_path: profile.definition_path.clone(), palette: number, sff: None });
+                continue;
+            }
+        };
+
+        let info = &select_screen.players[face.player];
+        let face_texture = sff.create_monochromatic_texture(TextureFlags(0));
+        let size = face_texture.size.scale_for_screen(&configuration, profile.localcoord);
+        let mut offset = sff.offset().scale_for_screen(&configuration, profile.localcoord);
+
+        offset.x *= info.facescale.x;
+        offset.y *= info.facescale.y;
+
+        if info.facefacing < 0 {
+            offset.x = -offset.x;
+        }
+
+        *sprite = Sprite {
+            size: Size2::new(size.width * info.facescale.x, size.height * info.facescale.y),
+            offset,
+            flip_h: info.facefacing < 0,
+            ..Default::default()
+        };
+        *texture = face_texture;
+        *palette = if sff.image.is_indexed() {
+            Some(palettes.create_handle(configuration.sprite_shader.clone(), &sff, number))
+        } else {
+            None
+        };
+
+        face.shown = Some(ShownFace {
+            definition_path: profile.definition_path.clone(),
+            palette: number,
+            sff: Some(sff),
+        });
+    }
+}
+
+fn cell_location(select_screen: &SelectScreen, x: i32, y: i32) -> Point2 {
+    let mut location = select_screen.grid_position;
+    location.x += (select_screen.cellsize.x + select_screen.cellspacing as f32) * x as f32;
+    location.y += (select_screen.cellsize.y + select_screen.cellspacing as f32) * y as f32;
+    location
+}
+
 fn hide_screen(
     mut commands: Commands,
     query: Query<Entity, With<ScreenMarker>>,
@@ -99,6 +467,14 @@ impl Plugin for SelectScreenPlugin {
                 SystemSet::on_enter(MenuState::Select)
                     .with_system(show_screen.system())
             )
+            .add_system_set(
+                SystemSet::on_update(MenuState::Select)
+                    .with_system(respawn_screen.system())
+                    .with_system(move_cursors.system())
+                    .with_system(select_players.system())
+                    .with_system(update_faces.system())
+                    .with_system(update_cursor_images.system())
+            )
             .add_system_set(
                 SystemSet::on_exit(MenuState::Select)
                     .with_system(hide_screen.system())
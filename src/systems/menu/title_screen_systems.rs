@@ -1,11 +1,11 @@
-use bevy_app::{AppBuilder, Plugin, EventWriter};
+use bevy_app::{AppBuilder, Plugin, EventReader, EventWriter};
 use bevy_ecs::prelude::*;
 use bevy_transform::{hierarchy::{BuildChildren, DespawnRecursiveExt}};
 use gdnative::{core_types::{Rect2, Point2, Size2, Transform2D}, godot_warn};
 
-use crate::{menus::{title_screen::{TitleScreen, TitleScreenState}, menu_data::MenuData, menu_state::MenuState}, systems::{backgrounds::events::BackgroundGroupEvent, visual_server::{canvas_item::{CanvasItemBundle, ClipRect}, text::common::Text}, input::Input, audio_server::audio::Audio}, core::{enumerations::{MainMenuOption, CombatMode}, configuration::Configuration}, drawing::print_data::PrintData};
+use crate::{menus::{title_screen::{TitleScreen, TitleScreenState}, menu_data::MenuData, menu_state::MenuState}, systems::{backgrounds::events::BackgroundGroupEvent, visual_server::{canvas_item::{CanvasItemBundle, ClipRect}, text::common::Text, palette::PaletteHandle}, input::Input, audio_server::audio::Audio}, core::{enumerations::{MainMenuOption, CombatMode}, configuration::Configuration}, drawing::print_data::PrintData};
 
-use super::{setup_layers::HudLayer, components::MenuSoundManager};
+use super::{setup_layers::HudLayer, components::{MenuReloadedEvent, MenuSoundManager}};
 
 struct MenuOptionText {
     index: usize,
@@ -24,10 +24,45 @@ fn show_title_screen(
     title_screen: Res<TitleScreen>,
     configuration: Res<Configuration>,
     menu_data: Res<MenuData>,
+) {
+    let hud_entity = hud_layer_query.single().expect("HudLayer not found");
+
+    spawn_title_screen(&mut commands, &mut background_group_event, hud_entity, &title_screen, &configuration, &menu_data);
+}
+
+fn respawn_title_screen(
+    mut commands: Commands,
+    mut menu_reloaded_event: EventReader<MenuReloadedEvent>,
+    mut background_group_event: EventWriter<BackgroundGroupEvent>,
+    hud_layer_query: Query<Entity, With<HudLayer>>,
+    screen_query: Query<Entity, With<TitleScreenTag>>,
+    title_screen: Res<TitleScreen>,
+    configuration: Res<Configuration>,
+    menu_data: Res<MenuData>,
+) {
+    if menu_reloaded_event.iter().count() == 0 {
+        return;
+    }
+
+    for entity in screen_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let hud_entity = hud_layer_query.single().expect("HudLayer not found");
+
+    spawn_title_screen(&mut commands, &mut background_group_event, hud_entity, &title_screen, &configuration, &menu_data);
+}
+
+fn spawn_title_screen(
+    commands: &mut Commands,
+    background_group_event: &mut EventWriter<BackgroundGroupEvent>,
+    hud_entity: Entity,
+    title_screen: &TitleScreen,
+    configuration: &Configuration,
+    menu_data: &MenuData,
 ) {
     let background_group = &title_screen.non_combat_screen.background_group;
     let mut title_screen_state = TitleScreenState::default();
-    let hud_entity = hud_layer_query.single().expect("HudLayer not found");
     let mut menu_offset = 0;
     let height = title_screen.spacing.y * (title_screen.visiblemenuitems as f32 - 1.0) + title_screen.marginytop as f32 + title_screen.marginybottom as f32;
     let clip_rect = ClipRect::global(Rect2::new(Point2::new(0.0, 1.0 + title_screen.menuposition.y - title_screen.spacing.y), Size2::new(configuration.screen_size.width, height)));
@@ -59,7 +94,7 @@ fn show_title_screen(
         menu_offset += 1;
 
         let menu_text_entity_option = menu_data.font_map.insert_font(
-            &mut commands,
+            commands,
             print_data,
             location,
             ClipRect::default(),
@@ -97,7 +132,7 @@ fn update_active_menu_item(
     mut audio: ResMut<Audio>,
     mut menu_container_query: Query<&mut Transform2D, With<MenuContainer>>,
     mut state_query: Query<&mut TitleScreenState>,
-    mut menu_query: Query<(&mut MenuOptionText, &mut Text)>,
+    mut menu_query: Query<(&mut MenuOptionText, &mut Text, &mut Option<PaletteHandle>)>,
 ) {
     let mainfont = title_screen.mainfont;
     let activefont = title_screen.activefont;
@@ -182,13 +217,13 @@ fn update_active_menu_item(
     let mut transform = menu_container_query.single_mut().unwrap();
     *transform = Transform2D::translation(0.0, -state.verticalmenudrawoffset);
 
-    for (mut menu_option_text, text) in menu_query.iter_mut() {
+    for (mut menu_option_text, text, palette) in menu_query.iter_mut() {
         if menu_option_text.index == state.currentmenuitem && menu_option_text.print_data != activefont {
             menu_option_text.print_data = activefont.clone();
-            menu_data.font_map.update_font(activefont, text);
+            menu_data.font_map.update_font(activefont, text, palette);
         } else if menu_option_text.index != state.currentmenuitem && menu_option_text.print_data != mainfont {
             menu_option_text.print_data = mainfont.clone();
-            menu_data.font_map.update_font(mainfont, text);
+            menu_data.font_map.update_font(mainfont, text, palette);
         }
     }
 }
@@ -215,6 +250,7 @@ impl Plugin for TitleScreenPlugin {
             .add_system_set(
                 SystemSet::on_update(MenuState::Title)
                     .with_system(update_active_menu_item.system())
+                    .with_system(respawn_title_screen.system())
             )
             .add_system_set(
                 SystemSet::on_exit(MenuState::Title)
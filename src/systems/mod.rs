@@ -1,7 +1,10 @@
+pub mod animations;
 pub mod audio_server;
 pub mod backgrounds;
 pub mod menu;
 pub mod log;
 pub mod visual_server;
 pub mod debug;
+pub mod hot_reload;
+pub mod asset_loader;
 pub mod input;
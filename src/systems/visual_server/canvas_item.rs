@@ -97,7 +97,7 @@ pub struct CanvasItemBundle {
     pub modulate: Modulate,
 }
 
-type UpdateCanvasFilter = (Added<CanvasItem>, Changed<Sprite>, Changed<Arc<Texture>>, Changed<Mesh2d>, Changed<Text>, Changed<ClipRect>);
+type UpdateCanvasFilter = (Added<CanvasItem>, Changed<Sprite>, Changed<Arc<Texture>>, Changed<Mesh2d>, Changed<Text>, Changed<ClipRect>, Changed<Option<Arc<RwLock<Material>>>>);
 
 fn update_canvas_item(
     mut canvas_item_state:  ResMut<CanvasItemState>,
@@ -123,8 +123,9 @@ fn update_canvas_item(
 
         canvas_item.version += 1; // Other system watches for this to know they need to update the commands
 
-        if let Some(material) = material {
-            visual_server.canvas_item_set_material(canvas_item.rid, material.read().unwrap().rid);
+        match material {
+            Some(material) => visual_server.canvas_item_set_material(canvas_item.rid, material.read().unwrap().rid),
+            None => visual_server.canvas_item_set_material(canvas_item.rid, Rid::new())
         }
 
         visual_server.canvas_item_set_copy_to_backbuffer(canvas_item.rid, back_buffer_copy.enabled, back_buffer_copy.rect);
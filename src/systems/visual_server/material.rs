@@ -8,6 +8,7 @@ pub struct Material {
     pub rid: Rid,
     pub shader: Arc<Shader>,
     pub texture_parameters: HashMap<String, Arc<Texture>>,
+    pub shared: bool,
 }
 
 impl Material {
@@ -20,7 +21,8 @@ impl Material {
         Arc::new(RwLock::new(Self {
             rid,
             shader,
-            texture_parameters: HashMap::new()
+            texture_parameters: HashMap::new(),
+            shared: false,
         }))
     }
 
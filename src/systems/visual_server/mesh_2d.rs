@@ -9,7 +9,7 @@ use std::sync::Arc;
 use gdnative::{api::VisualServer, core_types::{Color, VariantArray, Rid}};
 
 use super::canvas_item::{ClipRect, CanvasItem, ZIndex, BackBufferCopy, GlobalTransform};
-use super::{root_node::RootNode, texture::Texture};
+use super::{root_node::RootNode, texture::Texture, palette::PaletteHandle};
 
 use crate::systems::visual_server::material::Material;
 use crate::systems::visual_server::canvas_item::Visible;
@@ -42,6 +42,7 @@ pub struct Mesh2dBundle {
     pub transform: Transform2D,
     pub global_transform: GlobalTransform,
     pub material: Option<Arc<RwLock<Material>>>,
+    pub palette: Option<PaletteHandle>,
     pub clip_rect: ClipRect,
     pub back_buffer_copy: BackBufferCopy,
     pub z_index: ZIndex,
@@ -59,6 +60,7 @@ impl Default for Mesh2dBundle {
             global_transform: GlobalTransform::default(),
             z_index: ZIndex::default(),
             material: None,
+            palette: None,
             clip_rect: ClipRect::default()
         }
     }
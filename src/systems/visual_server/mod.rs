@@ -5,6 +5,7 @@ pub mod root_node;
 pub mod enumerations;
 pub mod text;
 pub mod material;
+pub mod palette;
 pub mod shader;
 pub mod mesh_2d;
 pub mod canvas_item;
@@ -0,0 +1,136 @@
+use std::sync::{Arc, RwLock};
+
+use bevy_app::{AppBuilder, Plugin};
+use bevy_ecs::prelude::*;
+use gdnative::{api::VisualServer, core_types::ToVariant};
+
+use crate::drawing::sff::image::Palette;
+use crate::systems::visual_server::enumerations::VisualServerStage;
+
+use super::{material::Material, shader::Shader, texture::Texture};
+
+// Palette texture shared by every indexed sprite drawn with it. Updating the palette rewrites
+// the texture in place, so the sprites don't need to be touched.
+#[derive(Clone)]
+pub struct PaletteHandle {
+    texture: Arc<Texture>,
+    material: Arc<RwLock<Material>>,
+    palette: Arc<RwLock<Arc<Palette>>>,
+}
+
+impl PaletteHandle {
+    pub fn new(shader: Arc<Shader>, palette: Arc<Palette>) -> Self {
+        let texture = palette.create_texture();
+        let material = Material::allocate(shader);
+
+        {
+            let mut material_write = material.write().expect("Could not lock material");
+            material_write.shared = true;
+            configure_material(&mut material_write, &texture);
+        }
+
+        PaletteHandle {
+            texture,
+            material,
+            palette: Arc::new(RwLock::new(palette)),
+        }
+    }
+
+    pub fn texture(&self) -> Arc<Texture> {
+        self.texture.clone()
+    }
+
+    pub fn material(&self) -> Arc<RwLock<Material>> {
+        self.material.clone()
+    }
+
+    pub fn palette(&self) -> Arc<Palette> {
+        self.palette.read().expect("Could not lock palette").clone()
+    }
+
+    pub fn set_palette(&self, palette: Arc<Palette>) {
+        let visual_server = unsafe { VisualServer::godot_singleton() };
+
+        visual_server.texture_set_data(self.texture.rid, palette.create_image(), 0);
+
+        *self.palette.write().expect("Could not lock palette") = palette;
+    }
+
+    pub fn ptr_eq(&self, other: &PaletteHandle) -> bool {
+        Arc::ptr_eq(&self.texture, &other.texture)
+    }
+}
+
+// Reuses handles for equal palettes, so sprites sharing a palette share its texture
+pub struct PaletteCache {
+    shader: Arc<Shader>,
+    handles: Vec<PaletteHandle>,
+}
+
+impl PaletteCache {
+    pub fn new(shader: Arc<Shader>) -> Self {
+        PaletteCache {
+            shader,
+            handles: Vec::new(),
+        }
+    }
+
+    pub fn get_or_create(&mut self, palette: &Arc<Palette>) -> PaletteHandle {
+        let existing = self.handles.iter()
+            .find(|handle| handle.palette().equal(palette));
+
+        if let Some(handle) = existing {
+            return handle.clone();
+        }
+
+        let handle = PaletteHandle::new(self.shader.clone(), palette.clone());
+        self.handles.push(handle.clone());
+
+        handle
+    }
+}
+
+fn configure_material(material: &mut Material, palette_texture: &Arc<Texture>) {
+    material.set_shader_texture("palette", palette_texture.clone());
+    material.set_shader_param("use_palette", 1.to_variant());
+}
+
+fn apply_palette(
+    mut query: Query<
+        (&Option<PaletteHandle>, &mut Option<Arc<RwLock<Material>>>),
+        Changed<Option<PaletteHandle>>
+    >
+) {
+    for (palette, mut material) in query.iter_mut() {
+        let current = (*material).clone();
+        let is_shared = current.as_ref()
+            .map_or(false, |material| material.read().expect("Could not lock material").shared);
+
+        match (palette, current) {
+            (Some(palette), Some(current)) if !is_shared => {
+                configure_material(&mut current.write().expect("Could not lock material"), &palette.texture);
+            },
+            (Some(palette), _) => {
+                *material = Some(palette.material());
+            },
+            (None, Some(current)) => {
+                if is_shared {
+                    *material = None;
+                } else {
+                    current.write().expect("Could not lock material").set_shader_param("use_palette", 0.to_variant());
+                }
+            },
+            (None, None) => {}
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PalettePlugin;
+
+impl Plugin for PalettePlugin {
+    fn build(&self, builder: &mut AppBuilder) {
+        builder
+            .add_system_to_stage(VisualServerStage::CanvasItemUpdate, apply_palette.system().before("create"));
+    }
+}
@@ -10,9 +10,9 @@ use crate::systems::visual_server::canvas_item::Visible;
 
 use super::canvas_item::{CanvasItem, ZIndex, BackBufferCopy, GlobalTransform};
 use super::canvas_item::ClipRect;
-use super::{texture::Texture, material::Material};
+use super::{texture::Texture, material::Material, palette::PaletteHandle};
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, PartialEq)]
 pub struct Sprite {
     pub size: Size2,
     pub rect: Option<Rect2>,
@@ -32,6 +32,7 @@ pub struct SpriteBundle {
     pub global_transform: GlobalTransform,
     pub clip_rect: ClipRect,
     pub material: Option<Arc<RwLock<Material>>>,
+    pub palette: Option<PaletteHandle>,
     pub z_index: ZIndex,
 }
 
@@ -48,6 +49,7 @@ impl Default for SpriteBundle {
             z_index: ZIndex::default(),
             clip_rect: ClipRect::default(),
             material: None,
+            palette: None,
         }
     }
 }
@@ -3,9 +3,7 @@ use crate::{io::file_system, core::error::DataError};
 use super::{font::Font, vector_font::VectorFont};
 
 pub fn load_dynamic_font(path: &str) -> Result<Font, DataError> {
-    let file = file_system::open_file(path)?;
-    let typed_array = file.get_buffer(file.get_len());
-    let font_data: Vec<u8> = typed_array.read().iter().map(|v| *v).collect();
+    let font_data = file_system::read_file(path)?;
 
     let result = VectorFont::try_from_bytes(font_data);
 
@@ -8,6 +8,7 @@ use gdnative::api::{VisualServer};
 
 use crate::systems::visual_server::canvas_item::{Visible, ClipRect, CanvasItemState, CanvasItem, ZIndex, BackBufferCopy, GlobalTransform};
 use crate::systems::visual_server::material::Material;
+use crate::systems::visual_server::palette::PaletteHandle;
 use crate::{systems::visual_server::{enumerations::VisualServerStage, root_node::RootNode, texture::Texture}};
 
 use super::{common::Text, text_renderer::render_text, vector_font::VectorFontCacheKey};
@@ -29,6 +30,7 @@ pub struct TextBundle {
     pub global_transform: GlobalTransform,
     pub clip_rect: ClipRect,
     pub material: Option<Arc<RwLock<Material>>>,
+    pub palette: Option<PaletteHandle>,
     pub z_index: ZIndex,
 }
 
@@ -1,7 +1,7 @@
 use bevy_app::{AppBuilder, CoreStage, Plugin};
 use bevy_ecs::prelude::*;
 
-use super::{enumerations::VisualServerStage, sprite::SpritePlugin, text::text_plugin::TextPlugin, mesh_2d::Mesh2dPlugin, canvas_item::CanvasItemPlugin};
+use super::{enumerations::VisualServerStage, sprite::SpritePlugin, text::text_plugin::TextPlugin, mesh_2d::Mesh2dPlugin, canvas_item::CanvasItemPlugin, palette::PalettePlugin};
 
 
 #[derive(Default)]
@@ -15,6 +15,7 @@ impl Plugin for VisualServerPlugin {
             .add_stage_after(VisualServerStage::CanvasItemUpdate, VisualServerStage::Update, SystemStage::single_threaded())
             .add_stage_after(VisualServerStage::Update, VisualServerStage::Transform, SystemStage::single_threaded())
             .add_plugin(CanvasItemPlugin::default())
+            .add_plugin(PalettePlugin::default())
             .add_plugin(SpritePlugin::default())
             .add_plugin(TextPlugin::default())
             .add_plugin(Mesh2dPlugin::default());